@@ -1,4 +1,4 @@
-use crate::document_metadata::{is_artboard, DocumentMetadata, LayerNodeIdentifier};
+use crate::document_metadata::{is_artboard, DocumentMetadata, LayerNodeIdentifier, PersistentMetadata};
 use crate::intersection::Quad;
 use crate::layers::folder_layer::FolderLegacyLayer;
 use crate::layers::layer_info::{LayerData, LayerDataTypeDiscriminant, LegacyLayer, LegacyLayerType};
@@ -36,7 +36,8 @@ pub struct Document {
 	/// This identifier is not a hash and is not guaranteed to be equal for equivalent documents.
 	#[serde(skip)]
 	pub state_identifier: DefaultHasher,
-	#[serde(skip)]
+	/// Only the state that isn't read from the graph is saved, see [`DocumentMetadata::persistent_state`].
+	#[serde(default, with = "persistent_metadata")]
 	pub metadata: DocumentMetadata,
 }
 
@@ -118,12 +119,13 @@ impl Document {
 		self.metadata.selected_layers().filter(|&layer| self.layer_visible(layer))
 	}
 
-	/// Runs an intersection test with all layers and a viewport space quad
+	/// Runs an intersection test with all layers in the isolation scope and a viewport space quad
 	pub fn intersect_quad<'a>(&'a self, viewport_quad: graphene_core::renderer::Quad, network: &'a NodeNetwork) -> impl Iterator<Item = LayerNodeIdentifier> + 'a {
 		let document_quad = self.metadata.document_to_viewport.inverse() * viewport_quad;
 		self.metadata
 			.root()
 			.decendants(&self.metadata)
+			.filter(|&layer| self.metadata.is_in_isolation_scope(layer))
 			.filter(|&layer| self.layer_visible(layer))
 			.filter(|&layer| !is_artboard(layer, network))
 			.filter_map(|layer| self.metadata.click_target(layer).map(|targets| (layer, targets)))
@@ -131,21 +133,34 @@ impl Document {
 			.map(|(layer, _)| layer)
 	}
 
-	/// Find all of the layers that were clicked on from a viewport space location
+	/// Find all of the layers in the isolation scope that were clicked on from a viewport space location, starting with the layer of any UI region clicked on such as an artboard label
 	pub fn click_xray(&self, viewport_location: DVec2) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
 		let point = self.metadata.document_to_viewport.inverse().transform_point2(viewport_location);
-		self.metadata
-			.root()
-			.decendants(&self.metadata)
-			.filter(|&layer| self.layer_visible(layer))
-			.filter_map(|layer| self.metadata.click_target(layer).map(|targets| (layer, targets)))
-			.filter(move |(layer, target)| target.iter().any(|target: &ClickTarget| target.intersect_point(point, self.metadata.transform_to_document(*layer))))
-			.map(|(layer, _)| layer)
+		let chrome = self.chrome_click(viewport_location);
+		chrome.into_iter().chain(
+			self.metadata
+				.root()
+				.decendants(&self.metadata)
+				.filter(move |&layer| Some(layer) != chrome)
+				.filter(|&layer| self.metadata.is_in_isolation_scope(layer))
+				.filter(|&layer| self.layer_visible(layer))
+				.filter_map(|layer| self.metadata.click_target(layer).map(|targets| (layer, targets)))
+				.filter(move |(layer, target)| target.iter().any(|target: &ClickTarget| target.intersect_point(point, self.metadata.transform_to_document(*layer))))
+				.map(|(layer, _)| layer),
+		)
 	}
 
-	/// Find the layer that has been clicked on from a viewport space location
+	/// Find the layer that has been clicked on from a viewport space location. Clicking a UI region of an artboard, such as its label, picks the artboard
 	pub fn click(&self, viewport_location: DVec2, network: &NodeNetwork) -> Option<LayerNodeIdentifier> {
-		self.click_xray(viewport_location).find(|&layer| !is_artboard(layer, network))
+		self.chrome_click(viewport_location)
+			.or_else(|| self.click_xray(viewport_location).find(|&layer| !is_artboard(layer, network)))
+	}
+
+	/// The layer in the isolation scope with a visible UI region at the viewport space location, see [`DocumentMetadata::set_chrome_click_targets`]
+	fn chrome_click(&self, viewport_location: DVec2) -> Option<LayerNodeIdentifier> {
+		self.metadata
+			.chrome_region_at_point(viewport_location)
+			.filter(|&layer| self.metadata.is_in_isolation_scope(layer) && self.layer_visible(layer))
 	}
 
 	/// Get the combined bounding box of the click targets of the selected visible layers in viewport space
@@ -1087,3 +1102,18 @@ pub fn pick_layer_safe_imaginate_resolution(layer: &LegacyLayer, render_data: &R
 
 	graphene_std::imaginate::pick_safe_imaginate_resolution(layer_bounds_size)
 }
+
+mod persistent_metadata {
+	use super::{DocumentMetadata, PersistentMetadata};
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	pub fn serialize<S: Serializer>(metadata: &DocumentMetadata, serializer: S) -> Result<S::Ok, S::Error> {
+		metadata.persistent_state().serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DocumentMetadata, D::Error> {
+		let mut metadata = DocumentMetadata::default();
+		metadata.restore_persistent_state(PersistentMetadata::deserialize(deserializer)?);
+		Ok(metadata)
+	}
+}
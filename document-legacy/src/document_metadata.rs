@@ -1,21 +1,100 @@
-use graph_craft::document::{DocumentNode, NodeId, NodeNetwork};
+use graph_craft::document::value::TaggedValue;
+use graph_craft::document::{DocumentNode, NodeId, NodeInput, NodeNetwork};
+use graphene_core::raster::{BlendMode, Color};
 use graphene_core::renderer::ClickTarget;
-use graphene_core::renderer::Quad;
+use graphene_core::renderer::{PixelRounding, Quad};
 use graphene_core::transform::Footprint;
 use graphene_core::uuid::ManipulatorGroupId;
 
-use glam::{DAffine2, DVec2};
+use glam::{DAffine2, DVec2, IVec2};
 use std::collections::{HashMap, HashSet};
 use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex, Weak};
 
+#[cfg(feature = "journal")]
+pub mod journal;
+
+/// The per-layer data is kept behind [`Arc`]s shared with any [`DocumentMetadataReader`], and is only copied when it is modified while shared.
 #[derive(Debug, Clone)]
 pub struct DocumentMetadata {
-	upstream_transforms: HashMap<NodeId, (Footprint, DAffine2)>,
-	structure: HashMap<LayerNodeIdentifier, NodeRelations>,
-	artboards: HashSet<LayerNodeIdentifier>,
-	folders: HashSet<LayerNodeIdentifier>,
-	click_targets: HashMap<LayerNodeIdentifier, Vec<ClickTarget>>,
+	upstream_transforms: Arc<HashMap<NodeId, (Footprint, DAffine2)>>,
+	/// Counter incremented by each evaluation that updates the cached transforms, whether of all nodes or only some.
+	transforms_generation: u64,
+	/// The transforms generation of the last update of all the cached transforms.
+	full_transforms_generation: u64,
+	/// The transforms generation of cached transforms updated since the last full update, see [`Self::transform_generation`].
+	transform_generations: Arc<HashMap<NodeId, u64>>,
+	/// The `document_to_viewport` when the cached transforms were last updated while it was invertible, used to find document space transforms while it isn't.
+	evaluated_document_to_viewport: DAffine2,
+	structure: Arc<HashMap<LayerNodeIdentifier, NodeRelations>>,
+	artboards: Arc<HashSet<LayerNodeIdentifier>>,
+	folders: Arc<HashSet<LayerNodeIdentifier>>,
+	/// Layers with their own node disabled in the graph, not including their descendants.
+	hidden: Arc<HashSet<LayerNodeIdentifier>>,
+	classifications: Arc<HashMap<LayerNodeIdentifier, LayerClassification>>,
+	layers_by_kind: Arc<HashMap<LayerClassification, HashSet<LayerNodeIdentifier>>>,
+	names: Arc<HashMap<LayerNodeIdentifier, String>>,
+	/// Opacity of each layer's own Opacity node, not including that of its ancestors.
+	opacities: Arc<HashMap<LayerNodeIdentifier, f64>>,
+	blend_modes: Arc<HashMap<LayerNodeIdentifier, BlendMode>>,
+	/// Parameters of each artboard's Artboard node, read by [`Self::load_structure`] or [`Self::refresh_artboard_info`].
+	artboard_info: Arc<HashMap<LayerNodeIdentifier, ArtboardInfo>>,
+	/// Layers fed by each node, only built by [`Self::load_structure`] when enabled with [`Self::set_node_usage_indexing`].
+	node_usage: Option<Arc<HashMap<NodeId, Vec<LayerNodeIdentifier>>>>,
+	/// Only kept when enabled with [`Self::set_subtree_interval_indexing`].
+	subtree_intervals: Option<SubtreeIntervals>,
+	click_targets: Arc<HashMap<LayerNodeIdentifier, Arc<Vec<ClickTarget>>>>,
+	/// How far each layer draws outside its click targets in its own space, cleared along with the click targets of the layer.
+	render_padding: Arc<HashMap<LayerNodeIdentifier, [f64; 4]>>,
+	geometry_fingerprints: GeometryFingerprints,
+	/// Regions of text layers mapping to ranges of characters, cleared along with the click targets of the layer.
+	text_hit_regions: Arc<HashMap<LayerNodeIdentifier, Vec<TextHitRegion>>>,
+	/// Viewport space regions of UI drawn for layers, such as artboard labels, which are only valid for the `document_to_viewport` they were registered with.
+	chrome_click_targets: Arc<HashMap<LayerNodeIdentifier, Vec<Quad>>>,
+	chrome_document_to_viewport: DAffine2,
 	selected_nodes: Vec<NodeId>,
+	/// Where each of the selected nodes was most recently selected from.
+	selection_sources: HashMap<NodeId, SelectionSource>,
+	collapsed: Arc<HashSet<LayerNodeIdentifier>>,
+	/// Layers locked against being picked on the canvas, not including their descendants, which are kept when the structure is reloaded.
+	locked: Arc<HashSet<LayerNodeIdentifier>>,
+	/// Color labels given to layers, which are kept when the structure is reloaded.
+	tags: Arc<HashMap<LayerNodeIdentifier, LayerTag>>,
+	/// Named selections of layers, which may include layers that have since been deleted.
+	saved_selections: Arc<Vec<SavedSelection>>,
+	/// Named camera positions in the order they were first saved.
+	saved_views: Arc<Vec<SavedView>>,
+	/// The views jumped away from, see [`Self::remember_view`], most recent last.
+	view_history: Vec<DAffine2>,
+	/// Guides in the order they were added, which aren't part of the graph so are kept when the structure is reloaded.
+	guides: Arc<Vec<(GuideId, Guide)>>,
+	next_guide_id: u64,
+	/// The folder being edited on its own, see [`Self::set_isolation`].
+	isolation_root: Option<LayerNodeIdentifier>,
+	/// The [`StructureTransaction`] that is open, if any, which isn't carried over to clones.
+	structure_transaction: OpenTransaction,
+	/// Counter incremented once per update batch, used to tag which layers were modified by that batch.
+	generation: u64,
+	layer_generations: Arc<HashMap<LayerNodeIdentifier, u64>>,
+	/// Counter incremented whenever the layer tree is modified.
+	structure_generation: u64,
+	document_bounds_cache: DocumentBoundsCache,
+	/// Counter incremented whenever the selection is modified.
+	selection_generation: u64,
+	selected_descendants_cache: SelectedDescendantsCache,
+	/// Counter incremented whenever a folder is collapsed or expanded.
+	collapse_generation: u64,
+	visible_rows_cache: VisibleRowsCache,
+	/// Viewport bounds of each layer when [`Self::commit_frame`] was last called, which is `None` until then and after the structure is reloaded.
+	previous_frame_bounds: Option<Arc<HashMap<LayerNodeIdentifier, [DVec2; 2]>>>,
+	/// Reused buffer for the snapshot of descendants taken by [`Self::with_descendants_mut`].
+	descendants_scratch: Vec<LayerNodeIdentifier>,
+	scratch: ScratchBuffers,
+	/// Callbacks registered with [`Self::on_change`], which aren't carried over to clones.
+	observers: Observers,
+	/// Modifications of the layer tree and selection recorded since they were last drained, only kept when enabled with [`Self::set_journaling`].
+	#[cfg(feature = "journal")]
+	journal: Option<journal::Journal>,
 	/// Transform from document space to viewport space.
 	pub document_to_viewport: DAffine2,
 }
@@ -23,18 +102,306 @@ pub struct DocumentMetadata {
 impl Default for DocumentMetadata {
 	fn default() -> Self {
 		Self {
-			upstream_transforms: HashMap::new(),
-			click_targets: HashMap::new(),
-			structure: HashMap::from_iter([(LayerNodeIdentifier::ROOT, NodeRelations::default())]),
-			artboards: HashSet::new(),
-			folders: HashSet::new(),
+			upstream_transforms: Default::default(),
+			transforms_generation: 0,
+			full_transforms_generation: 0,
+			transform_generations: Default::default(),
+			evaluated_document_to_viewport: DAffine2::IDENTITY,
+			click_targets: Default::default(),
+			render_padding: Default::default(),
+			geometry_fingerprints: GeometryFingerprints::default(),
+			text_hit_regions: Default::default(),
+			chrome_click_targets: Default::default(),
+			chrome_document_to_viewport: DAffine2::IDENTITY,
+			structure: Arc::new(HashMap::from_iter([(LayerNodeIdentifier::ROOT, NodeRelations::default())])),
+			artboards: Default::default(),
+			folders: Default::default(),
+			hidden: Default::default(),
+			classifications: Default::default(),
+			layers_by_kind: Default::default(),
+			names: Default::default(),
+			opacities: Default::default(),
+			blend_modes: Default::default(),
+			artboard_info: Default::default(),
+			node_usage: None,
+			subtree_intervals: None,
 			selected_nodes: Vec::new(),
+			selection_sources: HashMap::new(),
+			collapsed: Default::default(),
+			locked: Default::default(),
+			tags: Default::default(),
+			saved_selections: Default::default(),
+			saved_views: Default::default(),
+			view_history: Vec::new(),
+			guides: Default::default(),
+			next_guide_id: 0,
+			isolation_root: None,
+			structure_transaction: OpenTransaction::default(),
+			generation: 0,
+			layer_generations: Default::default(),
+			structure_generation: 0,
+			document_bounds_cache: DocumentBoundsCache::default(),
+			selection_generation: 0,
+			selected_descendants_cache: SelectedDescendantsCache::default(),
+			collapse_generation: 0,
+			visible_rows_cache: VisibleRowsCache::default(),
+			previous_frame_bounds: None,
+			descendants_scratch: Vec::new(),
+			scratch: ScratchBuffers::default(),
+			observers: Observers::default(),
+			#[cfg(feature = "journal")]
+			journal: None,
 			document_to_viewport: DAffine2::IDENTITY,
 		}
 	}
 }
+
+/// All layers in the same order as [`DocumentMetadata::all_layers`].
+impl<'a> IntoIterator for &'a DocumentMetadata {
+	type Item = LayerNodeIdentifier;
+	type IntoIter = DecendantsIter<'a>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.all_layers()
+	}
+}
+
+/// The relations of a layer in the tree, panicking if it isn't in the tree. See [`DocumentMetadata::relations`] to check instead.
+impl std::ops::Index<LayerNodeIdentifier> for DocumentMetadata {
+	type Output = NodeRelations;
+
+	fn index(&self, layer: LayerNodeIdentifier) -> &Self::Output {
+		self.relations(layer).unwrap_or_else(|| panic!("{layer:?} is not in the layer tree"))
+	}
+}
+
 pub struct SelectionChanged;
 
+/// Which layers are included in [`DocumentMetadata::combined_bounds_document`] and how their bounds are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundsOptions {
+	/// Include the descendants of the layers, so folders are covered by the bounds of their contents.
+	pub use_subtree_for_folders: bool,
+	/// Include layers disabled in the graph, or inside a disabled folder.
+	pub include_hidden: bool,
+	/// Include artboards given in the layers. Their contents are still included by `use_subtree_for_folders`.
+	pub include_artboards: bool,
+	/// Find the extrema of the transformed curves, rather than transforming the bounding box of each layer in its own space which is larger when rotated.
+	pub tight: bool,
+	/// How closely the extrema of the transformed curves are found when `tight`.
+	pub precision: BoundsPrecision,
+	/// Leave out layers that draw nothing, see [`DocumentMetadata::is_non_rendering`].
+	pub exclude_non_rendering: bool,
+	/// Grow the bounds of each layer by what it draws outside its click targets, such as strokes and shadows, see [`DocumentMetadata::set_layer_render_padding`].
+	pub include_render_padding: bool,
+}
+
+impl Default for BoundsOptions {
+	/// The exact bounds of just the given layers, whether hidden or not.
+	fn default() -> Self {
+		Self {
+			use_subtree_for_folders: false,
+			include_hidden: true,
+			include_artboards: true,
+			tight: true,
+			precision: BoundsPrecision::Fast,
+			exclude_non_rendering: false,
+			include_render_padding: false,
+		}
+	}
+}
+
+/// How closely [`DocumentMetadata::bounding_box_with_precision`] finds the extrema of the transformed curves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundsPrecision {
+	/// The bounds of each transformed curve as found by `bezier_rs`, see [`DocumentMetadata::bounding_box_with_transform`].
+	#[default]
+	Fast,
+	/// The extrema of each transformed curve refined until they are tight to within floating point error, for small sets of layers such as the selection.
+	Exact,
+}
+
+/// Where nodes were selected from, so operations on the selection of one panel can ignore nodes selected in another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum SelectionSource {
+	LayerPanel,
+	NodeGraph,
+	Canvas,
+	/// Used by the selection methods without a source parameter.
+	#[default]
+	Api,
+}
+
+/// A read only snapshot of the [`DocumentMetadata`], such as for the render thread, which derefs to it for all of the query methods.
+#[derive(Debug, Clone)]
+pub struct DocumentMetadataReader(DocumentMetadata);
+
+impl std::ops::Deref for DocumentMetadataReader {
+	type Target = DocumentMetadata;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+/// The document bounds computed for each combination of space, artboard inclusion and whether only rendered layers are included, each tagged with the state it was computed from.
+#[derive(Debug, Default)]
+struct DocumentBoundsCache(Mutex<[Option<CachedDocumentBounds>; 8]>);
+
+impl Clone for DocumentBoundsCache {
+	fn clone(&self) -> Self {
+		Self(Mutex::new(*self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CachedDocumentBounds {
+	generation: u64,
+	structure_generation: u64,
+	document_to_viewport: DAffine2,
+	bounds: Option<[DVec2; 2]>,
+}
+
+/// The [`DocumentMetadata::folders_with_selected_descendants`], tagged with the structure and selection generations it was found from.
+#[derive(Debug, Default)]
+struct SelectedDescendantsCache(Mutex<Option<(u64, u64, SelectedDescendants)>>);
+
+impl Clone for SelectedDescendantsCache {
+	fn clone(&self) -> Self {
+		Self(Mutex::new(self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()))
+	}
+}
+
+type SelectedDescendants = Arc<HashSet<LayerNodeIdentifier>>;
+
+/// The [`DocumentMetadata::geometry_fingerprint`] of each layer, along with the click targets it was found from.
+///
+/// Holding a [`Weak`] means click targets modified in place through [`Arc::make_mut`] are moved to a new allocation, so a fingerprint is valid only while it still points to the layer's click targets.
+#[derive(Debug, Default)]
+struct GeometryFingerprints(Mutex<HashMap<LayerNodeIdentifier, Fingerprint>>);
+
+impl Clone for GeometryFingerprints {
+	fn clone(&self) -> Self {
+		Self(Mutex::new(self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()))
+	}
+}
+
+type Fingerprint = (Weak<Vec<ClickTarget>>, u64);
+
+/// The [`DocumentMetadata::visible_tree_iter`] in order, tagged with the structure and collapse generations it was found from.
+#[derive(Debug, Default)]
+struct VisibleRowsCache(Mutex<Option<(u64, u64, VisibleRows)>>);
+
+impl Clone for VisibleRowsCache {
+	fn clone(&self) -> Self {
+		Self(Mutex::new(self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()))
+	}
+}
+
+type VisibleRows = Arc<Vec<LayerNodeIdentifier>>;
+
+/// The [`DocumentMetadata::subtree_interval`] of every layer in the tree, tagged with the structure generation they were found from.
+#[derive(Debug, Default)]
+struct SubtreeIntervals(Mutex<Option<(u64, SubtreeIntervalMap)>>);
+
+impl Clone for SubtreeIntervals {
+	fn clone(&self) -> Self {
+		Self(Mutex::new(self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()))
+	}
+}
+
+type SubtreeIntervalMap = Arc<HashMap<LayerNodeIdentifier, (u32, u32)>>;
+
+/// Buffers reused by the queries that need temporary lists, so repeating them, such as hit testing while the pointer moves, doesn't grow new lists once the buffers have grown.
+/// Queries may still allocate for other temporary data, such as the maps built by [`DocumentMetadata::transforms_to_viewport`].
+#[derive(Debug, Default)]
+struct ScratchBuffers {
+	layers: ScratchPool<LayerNodeIdentifier>,
+	hit_candidates: ScratchPool<HitCandidate>,
+}
+
+/// The buffers aren't carried over to clones, which start with empty pools.
+impl Clone for ScratchBuffers {
+	fn clone(&self) -> Self {
+		Self::default()
+	}
+}
+
+/// A layer that may be hit by [`DocumentMetadata::nearest_layer`], with the lower bound of its distance, its z-index and its viewport bounds.
+type HitCandidate = (f64, usize, LayerNodeIdentifier, [DVec2; 2]);
+
+/// Buffers waiting to be reused. A query nested inside another takes a different buffer from the pool, or a new one if the pool is empty, rather than sharing one.
+#[derive(Debug)]
+struct ScratchPool<T> {
+	buffers: Mutex<Vec<Vec<T>>>,
+	#[cfg(any(test, feature = "scratch-stats"))]
+	stats: ScratchCounters,
+}
+
+impl<T> Default for ScratchPool<T> {
+	fn default() -> Self {
+		Self {
+			buffers: Mutex::new(Vec::new()),
+			#[cfg(any(test, feature = "scratch-stats"))]
+			stats: ScratchCounters::default(),
+		}
+	}
+}
+
+impl<T> ScratchPool<T> {
+	/// The most buffers kept for reuse, which is how deeply queries can be nested without allocating.
+	const MAX_POOLED: usize = 4;
+
+	/// Call the closure with an empty buffer, which goes back to the pool afterwards.
+	fn with<R>(&self, f: impl FnOnce(&mut Vec<T>) -> R) -> R {
+		let mut buffer = self.buffers.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop().unwrap_or_default();
+		#[cfg(any(test, feature = "scratch-stats"))]
+		let capacity = buffer.capacity();
+
+		let result = f(&mut buffer);
+
+		#[cfg(any(test, feature = "scratch-stats"))]
+		self.stats.record(buffer.capacity() > capacity);
+		buffer.clear();
+		let mut buffers = self.buffers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if buffers.len() < Self::MAX_POOLED {
+			buffers.push(buffer);
+		}
+		result
+	}
+
+	/// Drop the pooled buffers, releasing their capacity.
+	fn clear(&mut self) {
+		self.buffers.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+	}
+}
+
+#[cfg(any(test, feature = "scratch-stats"))]
+#[derive(Debug, Default)]
+struct ScratchCounters {
+	acquisitions: std::sync::atomic::AtomicU64,
+	allocations: std::sync::atomic::AtomicU64,
+}
+
+#[cfg(any(test, feature = "scratch-stats"))]
+impl ScratchCounters {
+	fn record(&self, allocated: bool) {
+		use std::sync::atomic::Ordering;
+		self.acquisitions.fetch_add(1, Ordering::Relaxed);
+		if allocated {
+			self.allocations.fetch_add(1, Ordering::Relaxed);
+		}
+	}
+
+	fn stats(&self) -> ScratchStats {
+		use std::sync::atomic::Ordering;
+		ScratchStats {
+			acquisitions: self.acquisitions.load(Ordering::Relaxed),
+			allocations: self.allocations.load(Ordering::Relaxed),
+		}
+	}
+}
+
 // layer iters
 impl DocumentMetadata {
 	/// Get the root layer from the document
@@ -42,44 +409,171 @@ impl DocumentMetadata {
 		LayerNodeIdentifier::ROOT
 	}
 
+	/// Take a snapshot for reading while this continues to be modified. Only the selection and small state is copied, the rest is shared until modified.
+	pub fn reader(&self) -> DocumentMetadataReader {
+		DocumentMetadataReader(self.clone())
+	}
+
 	pub fn all_layers(&self) -> DecendantsIter<'_> {
 		self.root().decendants(self)
 	}
 
+	/// Calls the closure with mutable access for each descendant of the layer (not including itself), as they were before any of the calls.
+	///
+	/// The descendants are snapshotted into a buffer reused between calls, so the closure is free to restructure the tree.
+	pub fn with_descendants_mut(&mut self, layer: LayerNodeIdentifier, mut f: impl FnMut(&mut DocumentMetadata, LayerNodeIdentifier)) {
+		let mut descendants = std::mem::take(&mut self.descendants_scratch);
+		descendants.clear();
+		descendants.extend(layer.decendants(self));
+		for &descendant in &descendants {
+			f(self, descendant);
+		}
+		descendants.clear();
+		// A nested call will have left its own buffer, keep whichever has more capacity
+		if descendants.capacity() > self.descendants_scratch.capacity() {
+			self.descendants_scratch = descendants;
+		}
+	}
+
+	/// All layers that are not artboards themselves. The contents of artboards are still yielded, see [`Self::layers_outside_artboards`] to exclude them.
 	pub fn all_layers_except_artboards(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
 		self.all_layers().filter(move |layer| !self.artboards.contains(layer))
 	}
 
+	/// All layers, including artboards but not the contents of artboards.
+	pub fn iter_skipping_artboard_subtrees(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		let mut layers = self.all_layers();
+		std::iter::from_fn(move || {
+			let layer = layers.next()?;
+			if self.is_artboard(layer) {
+				layers.skip_subtree(layer);
+			}
+			Some(layer)
+		})
+	}
+
+	/// Layers that are neither artboards nor inside of one, such as content that hasn't been placed on an artboard.
+	pub fn layers_outside_artboards(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.iter_skipping_artboard_subtrees().filter(move |&layer| !self.is_artboard(layer))
+	}
+
+	/// The [`Self::selected_layer_ids`] from the top of the layer tree to the bottom.
 	pub fn selected_layers(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
-		self.all_layers().filter(|layer| self.selected_nodes.contains(&layer.to_node()))
+		let selected = self.selected_layer_ids().collect::<HashSet<_>>();
+		self.all_layers().filter(move |layer| selected.contains(layer))
 	}
 
+	/// Selected layers that are not artboards themselves. Selected children of a selected artboard are still yielded.
 	pub fn selected_layers_except_artboards(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
 		self.selected_layers().filter(move |layer| !self.artboards.contains(layer))
 	}
 
+	/// Selected layers that are neither artboards nor inside a selected artboard, for operations where an artboard's contents come along with it.
+	///
+	/// Unlike [`Self::selected_layers_except_artboards`], a selected child of a selected artboard is not yielded.
+	pub fn selected_layers_excluding_artboard_contents(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.selected_layers_except_artboards()
+			.filter(move |&layer| !self.has_selected_ancestor(layer, |ancestor| self.artboards.contains(&ancestor)))
+	}
+
+	/// Filter out layers that are descendants of a selected layer, keeping only the outermost selected layers of any nested selection.
+	pub fn exclude_descendants_of_selected<'a>(&'a self, layers: impl Iterator<Item = LayerNodeIdentifier> + 'a) -> impl Iterator<Item = LayerNodeIdentifier> + 'a {
+		layers.filter(move |&layer| !self.has_selected_ancestor(layer, |_| true))
+	}
+
+	/// Is any ancestor of the layer (excluding itself) both selected and matching the predicate?
+	fn has_selected_ancestor(&self, layer: LayerNodeIdentifier, predicate: impl Fn(LayerNodeIdentifier) -> bool) -> bool {
+		layer.ancestors(self).skip(1).any(|ancestor| self.selected_nodes.contains(&ancestor.to_node()) && predicate(ancestor))
+	}
+
 	pub fn selected_layers_contains(&self, layer: LayerNodeIdentifier) -> bool {
 		self.selected_layers().any(|selected| selected == layer)
 	}
 
+	/// Selected layers along with all descendants of selected folders, without duplicates and in layer tree order.
+	pub fn selected_layers_expanded(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		let selected = self.selected_nodes.iter().copied().collect::<HashSet<_>>();
+		let mut expanded = HashSet::new();
+		// Parents are always visited before their children, so a single pass can propagate the selection downwards
+		self.all_layers().filter(move |&layer| {
+			let included = selected.contains(&layer.to_node()) || layer.parent(self).is_some_and(|parent| expanded.contains(&parent));
+			if included {
+				expanded.insert(layer);
+			}
+			included
+		})
+	}
+
+	/// Is the layer, or any of its ancestors, selected?
+	pub fn is_effectively_selected(&self, layer: LayerNodeIdentifier) -> bool {
+		layer.ancestors(self).any(|ancestor| self.selected_nodes.contains(&ancestor.to_node()))
+	}
+
 	pub fn selected_nodes(&self) -> core::slice::Iter<'_, NodeId> {
 		self.selected_nodes.iter()
 	}
 
+	#[deprecated(note = "selected nodes may not be layers, use `selected_nodes`, `selected_layer_ids` or `selected_non_layer_nodes` instead")]
 	pub fn selected_nodes_ref(&self) -> &Vec<NodeId> {
 		&self.selected_nodes
 	}
 
+	/// The selected nodes that are layers in the layer tree, in the order they were selected.
+	pub fn selected_layer_ids(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.selected_nodes
+			.iter()
+			.map(|&node| LayerNodeIdentifier::new_unchecked(node))
+			.filter(|layer| *layer != LayerNodeIdentifier::ROOT && self.structure.contains_key(layer))
+	}
+
+	/// The selected nodes that aren't layers in the layer tree, such as nodes selected in the node graph or layer nodes that aren't connected to the output.
+	pub fn selected_non_layer_nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+		self.selected_nodes
+			.iter()
+			.copied()
+			.filter(|&node| node == LayerNodeIdentifier::ROOT.to_node() || !self.structure.contains_key(&LayerNodeIdentifier::new_unchecked(node)))
+	}
+
 	pub fn has_selected_nodes(&self) -> bool {
 		!self.selected_nodes.is_empty()
 	}
 
+	/// Where the node was most recently selected from, if it is selected.
+	pub fn selection_source(&self, node: NodeId) -> Option<SelectionSource> {
+		self.selection_sources.get(&node).copied()
+	}
+
+	/// The selected nodes which were most recently selected from the source.
+	pub fn selected_nodes_from(&self, source: SelectionSource) -> impl Iterator<Item = &NodeId> + '_ {
+		self.selected_nodes.iter().filter(move |node| self.selection_source(**node) == Some(source))
+	}
+
+	/// The selected layers which were most recently selected from the source, in z-order. See [`Self::selected_layers`] for those from any source.
+	pub fn selected_layers_from(&self, source: SelectionSource) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.all_layers().filter(move |layer| self.selection_source(layer.to_node()) == Some(source))
+	}
+
 	pub fn layer_exists(&self, layer: LayerNodeIdentifier) -> bool {
 		self.structure.contains_key(&layer)
 	}
 
-	pub fn click_target(&self, layer: LayerNodeIdentifier) -> Option<&Vec<ClickTarget>> {
-		self.click_targets.get(&layer)
+	pub fn click_target(&self, layer: LayerNodeIdentifier) -> Option<&[ClickTarget]> {
+		self.click_targets.get(&layer).map(|click_targets| click_targets.as_slice())
+	}
+
+	/// The [`NodeRelations`] of a layer, or `None` if it isn't in the layer tree.
+	pub fn relations(&self, layer: LayerNodeIdentifier) -> Option<&NodeRelations> {
+		self.get_relations(layer)
+	}
+
+	/// The number of layers in the layer tree, not including the root.
+	pub fn len(&self) -> usize {
+		self.structure.len() - 1
+	}
+
+	/// Are there no layers other than the root?
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
 	}
 
 	/// Access the [`NodeRelations`] of a layer.
@@ -89,42 +583,67 @@ impl DocumentMetadata {
 
 	/// Mutably access the [`NodeRelations`] of a layer.
 	fn get_structure_mut(&mut self, node_identifier: LayerNodeIdentifier) -> &mut NodeRelations {
-		self.structure.entry(node_identifier).or_default()
+		self.structure_generation += 1;
+		Arc::make_mut(&mut self.structure).entry(node_identifier).or_default()
 	}
 
-	/// Layers excluding ones that are children of other layers in the list.
-	pub fn shallowest_unique_layers(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> Vec<Vec<LayerNodeIdentifier>> {
-		let mut sorted_layers = layers
-			.map(|layer| {
-				let mut layer_path = layer.ancestors(self).collect::<Vec<_>>();
-				layer_path.reverse();
-				layer_path
-			})
-			.collect::<Vec<_>>();
-		sorted_layers.sort();
-		// Sorting here creates groups of similar UUID paths
-		sorted_layers.dedup_by(|a, b| a.starts_with(b));
-		sorted_layers
+	/// The direct children of the root that contain each of the layers.
+	pub fn top_level_ancestors(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> HashSet<LayerNodeIdentifier> {
+		layers.filter_map(|layer| layer.child_of_root(self)).collect()
+	}
+
+	/// Layers excluding ones that are descendants of other layers in the list, in the order they were given with any repeats removed.
+	pub fn shallowest_unique_layers(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> Vec<LayerNodeIdentifier> {
+		self.scratch.layers.with(|given| {
+			given.extend(layers);
+			let included = given.iter().copied().collect::<HashSet<_>>();
+			let mut yielded = HashSet::with_capacity(included.len());
+			given
+				.iter()
+				.copied()
+				.filter(|&layer| !layer.ancestors(self).skip(1).any(|ancestor| included.contains(&ancestor)))
+				.filter(|&layer| yielded.insert(layer))
+				.collect()
+		})
 	}
 
 	/// Ancestor that is shared by all layers and that is deepest (more nested). Default may be the root.
 	pub fn deepest_common_ancestor(&self, layers: impl Iterator<Item = LayerNodeIdentifier>, include_self: bool) -> Option<LayerNodeIdentifier> {
-		layers
-			.map(|layer| {
-				let mut layer_path = layer.ancestors(self).collect::<Vec<_>>();
-				layer_path.reverse();
+		self.scratch.layers.with(|common| {
+			self.scratch.layers.with(|layer_path| {
+				for (index, layer) in layers.enumerate() {
+					layer_path.clear();
+					layer_path.extend(layer.ancestors(self));
+					layer_path.reverse();
 
-				if include_self || !self.folders.contains(&layer) {
-					layer_path.pop();
-				}
+					if include_self || !self.folders.contains(&layer) {
+						layer_path.pop();
+					}
 
-				layer_path
-			})
-			.reduce(|mut a, b| {
-				a.truncate(a.iter().zip(b.iter()).position(|(&a, &b)| a != b).unwrap_or_else(|| a.len().min(b.len())));
-				a
+					if index == 0 {
+						common.append(layer_path);
+					} else {
+						common.truncate(common.iter().zip(layer_path.iter()).position(|(&a, &b)| a != b).unwrap_or_else(|| common.len().min(layer_path.len())));
+					}
+				}
+				common.last().copied()
 			})
-			.and_then(|layer| layer.last().copied())
+		})
+	}
+
+	/// The [`Self::deepest_common_ancestor`], or its nearest ancestor which isn't excluded by the predicate, such as `|layer| self.is_artboard(layer)`.
+	pub fn deepest_common_ancestor_filtered(
+		&self,
+		layers: impl Iterator<Item = LayerNodeIdentifier>,
+		include_self: bool,
+		exclude: impl Fn(LayerNodeIdentifier) -> bool,
+	) -> Option<LayerNodeIdentifier> {
+		self.deepest_common_ancestor(layers, include_self)?.ancestors(self).find(|&ancestor| !exclude(ancestor))
+	}
+
+	/// The deepest folder (other than an artboard) that contains or is each of the layers, otherwise the root.
+	pub fn deepest_common_folder(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> Option<LayerNodeIdentifier> {
+		self.deepest_common_ancestor_filtered(layers, false, |layer| layer != LayerNodeIdentifier::ROOT && (!self.is_folder(layer) || self.is_artboard(layer)))
 	}
 
 	pub fn active_artboard(&self) -> LayerNodeIdentifier {
@@ -139,72 +658,314 @@ impl DocumentMetadata {
 		self.artboards.contains(&layer)
 	}
 
+	/// Is the layer or any of its ancestors disabled in the graph?
+	pub fn is_hidden(&self, layer: LayerNodeIdentifier) -> bool {
+		layer.ancestors(self).any(|layer| self.hidden.contains(&layer))
+	}
+
+	/// Is the layer or any of its ancestors locked with [`Self::set_layer_locked`]?
+	pub fn is_locked(&self, layer: LayerNodeIdentifier) -> bool {
+		layer.ancestors(self).any(|layer| self.locked.contains(&layer))
+	}
+
+	/// Lock or unlock a layer, which locks its descendants with it. Layers that aren't in the layer tree can't be locked.
+	pub fn set_layer_locked(&mut self, layer: LayerNodeIdentifier, locked: bool) {
+		if locked && self.layer_exists(layer) && layer != LayerNodeIdentifier::ROOT {
+			Arc::make_mut(&mut self.locked).insert(layer);
+		} else if !locked && self.locked.contains(&layer) {
+			Arc::make_mut(&mut self.locked).remove(&layer);
+		}
+	}
+
 	/// Filter out non folder layers
 	pub fn folders<'a>(&'a self, layers: impl Iterator<Item = LayerNodeIdentifier> + 'a) -> impl Iterator<Item = LayerNodeIdentifier> + 'a {
 		layers.filter(|layer| self.folders.contains(layer))
 	}
 
-	/// Folders sorted from most nested to least nested
+	/// Folders sorted from most nested to least nested, so each comes before its ancestors. Folders at the same depth are in z-order, then by id for those not in the layer tree.
 	pub fn folders_sorted_by_most_nested(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> Vec<LayerNodeIdentifier> {
-		let mut folders: Vec<_> = self.folders(layers).collect();
-		folders.sort_by_cached_key(|a| std::cmp::Reverse(a.ancestors(self).count()));
+		self.folders_sorted_by_depth(layers, true)
+	}
+
+	/// Folders sorted from least nested to most nested, so each comes after its ancestors, with the same order for those at the same depth as [`Self::folders_sorted_by_most_nested`].
+	pub fn folders_sorted_by_least_nested(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> Vec<LayerNodeIdentifier> {
+		self.folders_sorted_by_depth(layers, false)
+	}
+
+	fn folders_sorted_by_depth(&self, layers: impl Iterator<Item = LayerNodeIdentifier>, most_nested_first: bool) -> Vec<LayerNodeIdentifier> {
+		let mut folders = self.folders(layers).collect::<HashSet<_>>();
+		// Only the z-order of the given folders is needed, so the layer tree is only walked until they have all been found
+		let mut remaining = folders.iter().filter(|folder| self.layer_exists(**folder)).count();
+		let mut z_order = HashMap::with_capacity(remaining);
+		for (z_index, layer) in self.all_layers().enumerate() {
+			if remaining == 0 {
+				break;
+			}
+			if folders.contains(&layer) {
+				z_order.insert(layer, z_index);
+				remaining -= 1;
+			}
+		}
+
+		let mut folders = folders.drain().collect::<Vec<_>>();
+		folders.sort_by_key(|folder| {
+			let depth = folder.depth(self);
+			let depth = if most_nested_first { usize::MAX - depth } else { depth };
+			(depth, z_order.get(folder).copied().unwrap_or(usize::MAX), folder.to_node())
+		});
 		folders
 	}
 }
 
+// layer search
+impl DocumentMetadata {
+	/// The first layer matching the predicate in the order given by the options, which stops calling the predicate once it matches.
+	pub fn find_layer(&self, options: SearchOptions, mut predicate: impl FnMut(LayerNodeIdentifier) -> bool) -> Option<LayerNodeIdentifier> {
+		self.search_order(options).find(|&layer| predicate(layer))
+	}
+
+	/// All layers matching the predicate in the order given by the options, stopping once there are `limit` of them.
+	pub fn find_all_layers(&self, options: SearchOptions, mut predicate: impl FnMut(LayerNodeIdentifier) -> bool, limit: Option<usize>) -> Vec<LayerNodeIdentifier> {
+		self.search_order(options).filter(|&layer| predicate(layer)).take(limit.unwrap_or(usize::MAX)).collect()
+	}
+
+	fn search_order(&self, options: SearchOptions) -> Box<dyn Iterator<Item = LayerNodeIdentifier> + '_> {
+		let layers = options.within.unwrap_or(LayerNodeIdentifier::ROOT).decendants(self);
+		let layers: Box<dyn Iterator<Item = LayerNodeIdentifier>> = match (options.near, options.direction) {
+			(None, SearchDirection::TopDown) => Box::new(layers),
+			(None, SearchDirection::BottomUp) => Box::new(layers.rev()),
+			(Some(near), direction) => {
+				let layers = layers.collect::<Vec<_>>();
+				let Some(start) = layers.iter().position(|&layer| layer == near) else {
+					return self.search_order(SearchOptions { near: None, ..options });
+				};
+				// Alternate between each side of the hint, moving in the search direction first
+				let below = layers[start + 1..].to_vec();
+				let above = layers[..start].iter().rev().copied().collect::<Vec<_>>();
+				let (first, second) = if direction == SearchDirection::TopDown { (below, above) } else { (above, below) };
+				let mut order = Vec::with_capacity(layers.len());
+				order.push(near);
+				for index in 0..first.len().max(second.len()) {
+					order.extend(first.get(index));
+					order.extend(second.get(index));
+				}
+				Box::new(order.into_iter())
+			}
+		};
+		Box::new(layers.filter(move |&layer| (!options.skip_hidden || !self.is_hidden(layer)) && (!options.skip_locked || !self.is_locked(layer))))
+	}
+}
+
+/// Where [`DocumentMetadata::find_layer`] searches and in which order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+	pub direction: SearchDirection,
+	/// Only search the descendants of this layer, rather than the whole layer tree.
+	pub within: Option<LayerNodeIdentifier>,
+	/// Start from this layer and search outwards in z-order, alternating between the layers above and below it so that nearby layers are found first.
+	/// The search is ordinary if the layer isn't among those searched.
+	pub near: Option<LayerNodeIdentifier>,
+	/// Skip layers disabled in the graph, or inside a disabled folder.
+	pub skip_hidden: bool,
+	/// Skip layers locked with [`DocumentMetadata::set_layer_locked`], or inside a locked folder.
+	pub skip_locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SearchDirection {
+	/// From the top of the layer tree to the bottom, visiting folders before their contents.
+	#[default]
+	TopDown,
+	/// From the bottom of the layer tree to the top, visiting folders after their contents.
+	BottomUp,
+}
+
 // selected layer modifications
 impl DocumentMetadata {
 	#[must_use]
 	pub fn retain_selected_nodes(&mut self, f: impl FnMut(&NodeId) -> bool) -> SelectionChanged {
+		let before = self.selection_before_change();
 		self.selected_nodes.retain(f);
+		self.retain_selection_sources();
+		self.notify_selection_changed(before);
 		SelectionChanged
 	}
+	/// Replace the selection, as selected from [`SelectionSource::default`].
 	#[must_use]
 	pub fn set_selected_nodes(&mut self, new: Vec<NodeId>) -> SelectionChanged {
+		self.set_selected_nodes_from(new, SelectionSource::default())
+	}
+	/// Replace the selection from every source with nodes selected from this source.
+	#[must_use]
+	pub fn set_selected_nodes_from(&mut self, new: Vec<NodeId>, source: SelectionSource) -> SelectionChanged {
+		let before = self.selection_before_change();
+		self.selection_sources = new.iter().map(|&node| (node, source)).collect();
 		self.selected_nodes = new;
+		self.notify_selection_changed(before);
 		SelectionChanged
 	}
+	/// Add to the selection, as selected from [`SelectionSource::default`].
 	#[must_use]
 	pub fn add_selected_nodes(&mut self, iter: impl IntoIterator<Item = NodeId>) -> SelectionChanged {
-		self.selected_nodes.extend(iter);
+		self.add_selected_nodes_from(iter, SelectionSource::default())
+	}
+	/// Add to the selection from this source. Nodes which were already selected are now counted as selected from this source.
+	#[must_use]
+	pub fn add_selected_nodes_from(&mut self, iter: impl IntoIterator<Item = NodeId>, source: SelectionSource) -> SelectionChanged {
+		let before = self.selection_before_change();
+		for node in iter {
+			self.selection_sources.insert(node, source);
+			self.selected_nodes.push(node);
+		}
+		self.notify_selection_changed(before);
 		SelectionChanged
 	}
 	#[must_use]
 	pub fn clear_selected_nodes(&mut self) -> SelectionChanged {
 		self.set_selected_nodes(Vec::new())
 	}
+	/// Deselect the nodes most recently selected from this source, keeping those from the others.
+	#[must_use]
+	pub fn clear_selected_nodes_from(&mut self, source: SelectionSource) -> SelectionChanged {
+		let before = self.selection_before_change();
+		let sources = &self.selection_sources;
+		self.selected_nodes.retain(|node| sources.get(node) != Some(&source));
+		self.selection_sources.retain(|_, node_source| *node_source != source);
+		self.notify_selection_changed(before);
+		SelectionChanged
+	}
+
+	/// Forget the sources of nodes which are no longer selected.
+	fn retain_selection_sources(&mut self) {
+		let selected = self.selected_nodes.iter().collect::<HashSet<_>>();
+		self.selection_sources.retain(|node, _| selected.contains(node));
+	}
+
+	/// Select only the layers which exist in the layer tree, returning the rejected identifiers of those that don't.
+	#[must_use]
+	pub fn set_selected_layers(&mut self, layers: Vec<LayerNodeIdentifier>) -> (SelectionChanged, Vec<LayerNodeIdentifier>) {
+		let (layers, rejected) = self.partition_existing_layers(layers);
+		(self.set_selected_nodes(layers), rejected)
+	}
+
+	/// Add only the layers which exist in the layer tree to the selection, returning the rejected identifiers of those that don't.
+	#[must_use]
+	pub fn add_selected_layers(&mut self, layers: impl IntoIterator<Item = LayerNodeIdentifier>) -> (SelectionChanged, Vec<LayerNodeIdentifier>) {
+		let (layers, rejected) = self.partition_existing_layers(layers);
+		(self.add_selected_nodes(layers), rejected)
+	}
+
+	/// Deselect any layers that aren't in the current layer tree, such as layers deleted since the selection was made, without reloading the structure.
+	///
+	/// Selected nodes that aren't layers are kept while they are in the graph. The selection is only modified, and observers notified, if anything was deselected.
+	#[must_use]
+	pub fn prune_selection(&mut self, graph: &NodeNetwork) -> SelectionChanged {
+		let is_layer = |node: NodeId| node != LayerNodeIdentifier::ROOT.to_node() && self.layer_exists(LayerNodeIdentifier::new_unchecked(node));
+		let is_other_node = |node: NodeId| graph.nodes.get(&node).is_some_and(|node| !node.is_layer());
+		let kept = self.selected_nodes.iter().copied().filter(|&node| is_layer(node) || is_other_node(node)).collect::<Vec<_>>();
+		if kept.len() == self.selected_nodes.len() {
+			return SelectionChanged;
+		}
+		let before = self.selection_before_change();
+		self.selected_nodes = kept;
+		self.retain_selection_sources();
+		self.notify_selection_changed(before);
+		SelectionChanged
+	}
+
+	/// Split the layers into the nodes of those in the layer tree (excluding the root) and the identifiers of those that aren't.
+	fn partition_existing_layers(&self, layers: impl IntoIterator<Item = LayerNodeIdentifier>) -> (Vec<NodeId>, Vec<LayerNodeIdentifier>) {
+		let (existing, rejected): (Vec<_>, Vec<_>) = layers.into_iter().partition(|&layer| layer != LayerNodeIdentifier::ROOT && self.layer_exists(layer));
+		(existing.into_iter().map(LayerNodeIdentifier::to_node).collect(), rejected)
+	}
+
+	/// Loads the structure of layer nodes from a node graph, reporting any problems found with the graph.
+	pub fn load_structure(&mut self, graph: &NodeNetwork) -> LoadStructureReport {
+		self.load_structure_with_remap(graph, &HashMap::new())
+	}
+
+	/// Loads the structure like [`Self::load_structure`], after moving the selection, tags, collapsed and locked state, isolation and saved selections of each node in the map to the node it maps to.
+	///
+	/// This keeps them when a node is replaced by an equivalent one with a new id, such as when a shape is regenerated. The new node keeps any of its own state.
+	/// Only state kept by the metadata is moved: pivots are inputs of the nodes in the graph, so they need copying to the new node there, and hover state belongs to the tools.
+	pub fn load_structure_with_remap(&mut self, graph: &NodeNetwork, id_remap: &HashMap<NodeId, NodeId>) -> LoadStructureReport {
+		let selection_before = self.selection_before_change();
+		self.remap_node_ids(id_remap);
 
-	/// Loads the structure of layer nodes from a node graph.
-	pub fn load_structure(&mut self, graph: &NodeNetwork) {
-		self.structure = HashMap::from_iter([(LayerNodeIdentifier::ROOT, NodeRelations::default())]);
-		self.folders = HashSet::new();
-		self.artboards = HashSet::new();
+		self.structure = Arc::new(HashMap::from_iter([(LayerNodeIdentifier::ROOT, NodeRelations::default())]));
+		self.structure_generation += 1;
+		#[cfg(feature = "journal")]
+		self.record_cleared();
+		self.folders = Arc::default();
+		self.artboards = Arc::default();
+		self.classifications = Arc::default();
+		self.layers_by_kind = Arc::default();
+		self.names = Arc::default();
+		self.artboard_info = Arc::default();
+		self.hidden = Arc::default();
+		self.opacities = Arc::default();
+		self.blend_modes = Arc::default();
+		self.text_hit_regions = Arc::default();
+		if let Some(node_usage) = &mut self.node_usage {
+			Arc::make_mut(node_usage).clear();
+		}
 
 		let id = graph.outputs[0].node_id;
-		let Some(output_node) = graph.nodes.get(&id) else {
-			return;
-		};
-		let Some((layer_node, node_id)) = first_child_layer(graph, output_node) else {
-			return;
-		};
-		let parent = LayerNodeIdentifier::ROOT;
-		let mut stack = vec![(layer_node, node_id, parent)];
+		// An empty graph still reaches the end, so the rest of the cached data is pruned
+		let first_layer = graph.nodes.get(&id).and_then(|output_node| first_child_layer(graph, output_node));
+		let mut stack = Vec::from_iter(first_layer.map(|(layer_node, node_id)| (layer_node, node_id, LayerNodeIdentifier::ROOT)));
+		let mut duplicates = Vec::new();
 		while let Some((node, id, parent)) = stack.pop() {
 			let mut current = Some((node, id));
 			while let Some(&(current_node, current_id)) = current.as_ref() {
 				let current_identifier = LayerNodeIdentifier::new_unchecked(current_id);
-				if !self.structure.contains_key(&current_identifier) {
-					parent.push_child(self, current_identifier);
+				if self.structure.contains_key(&current_identifier) {
+					// The layers stacked below and inside the duplicate are the same as at its first position, which has already been visited
+					duplicates.push(current_id);
+					break;
+				}
+				// Folders and artboards are marked before the layer is added, so it is journaled as one
+				let artboard = is_artboard(current_identifier, graph);
+				let folder = is_folder(current_identifier, graph);
+				if artboard {
+					Arc::make_mut(&mut self.artboards).insert(current_identifier);
+				}
+				if folder {
+					Arc::make_mut(&mut self.folders).insert(current_identifier);
+				}
+				parent.push_child(self, current_identifier);
 
-					if let Some((child_node, child_id)) = first_child_layer(graph, current_node) {
-						stack.push((child_node, child_id, current_identifier));
-					}
+				if let Some((child_node, child_id)) = first_child_layer(graph, current_node) {
+					stack.push((child_node, child_id, current_identifier));
+				}
 
-					if is_artboard(current_identifier, graph) {
-						self.artboards.insert(current_identifier);
+				if artboard {
+					if let Some(info) = read_artboard_info(current_identifier, graph) {
+						Arc::make_mut(&mut self.artboard_info).insert(current_identifier, info);
 					}
-					if is_folder(current_identifier, graph) {
-						self.folders.insert(current_identifier);
+				}
+				if graph.disabled.contains(&current_id) {
+					Arc::make_mut(&mut self.hidden).insert(current_identifier);
+				}
+				let kind = match (artboard, folder) {
+					(true, _) => LayerClassification::Artboard,
+					(false, true) => LayerClassification::Folder,
+					(false, false) => layer_content_kind(current_identifier, graph),
+				};
+				Arc::make_mut(&mut self.classifications).insert(current_identifier, kind);
+				Arc::make_mut(&mut self.layers_by_kind).entry(kind).or_default().insert(current_identifier);
+				if !current_node.alias.is_empty() {
+					Arc::make_mut(&mut self.names).insert(current_identifier, current_node.alias.clone());
+				}
+				if let Some(&TaggedValue::F32(opacity)) = find_layer_node_input(current_identifier, graph, "Opacity", 1) {
+					Arc::make_mut(&mut self.opacities).insert(current_identifier, opacity as f64 / 100.);
+				}
+				if let Some(&TaggedValue::BlendMode(blend_mode)) = find_layer_node_input(current_identifier, graph, "Blend Mode", 1) {
+					Arc::make_mut(&mut self.blend_modes).insert(current_identifier, blend_mode);
+				}
+				if let Some(node_usage) = self.node_usage.as_mut().map(Arc::make_mut) {
+					for node in upstream_content_nodes(current_identifier, graph) {
+						node_usage.entry(node).or_default().push(current_identifier);
 					}
 				}
 
@@ -213,483 +974,8357 @@ impl DocumentMetadata {
 		}
 
 		self.selected_nodes.retain(|node| graph.nodes.contains_key(node));
-		self.upstream_transforms.retain(|node, _| graph.nodes.contains_key(node));
-		self.click_targets.retain(|layer, _| self.structure.contains_key(layer));
-	}
-}
+		self.retain_selection_sources();
+		Arc::make_mut(&mut self.upstream_transforms).retain(|node, _| graph.nodes.contains_key(node));
+		Arc::make_mut(&mut self.transform_generations).retain(|node, _| graph.nodes.contains_key(node));
+		self.previous_frame_bounds = None;
+		self.prune_auxiliary_state();
+		self.debug_assert_click_targets_in_structure();
+		debug_assert!(self.audit_auxiliary_state().is_empty(), "Auxiliary state left for layers not in the structure");
+		self.subtree_intervals();
 
-fn first_child_layer<'a>(graph: &'a NodeNetwork, node: &DocumentNode) -> Option<(&'a DocumentNode, NodeId)> {
-	graph.upstream_flow_back_from_nodes(vec![node.inputs[0].as_node()?], true).find(|(node, _)| node.is_layer())
-}
+		Arc::make_mut(&mut self.layer_generations).clear();
+		let layers = self.structure.keys().copied().collect::<Vec<_>>();
+		self.mark_layers_changed(layers);
 
-fn sibling_below<'a>(graph: &'a NodeNetwork, node: &DocumentNode) -> Option<(&'a DocumentNode, NodeId)> {
-	let construct_layer_node = &node.inputs[1];
-	construct_layer_node.as_node().and_then(|id| graph.nodes.get(&id).filter(|node| node.is_layer()).map(|node| (node, id)))
-}
+		self.notify(ChangeEvent::StructureReloaded);
+		self.notify_selection_changed(selection_before);
 
-// transforms
-impl DocumentMetadata {
-	/// Update the cached transforms of the layers
-	pub fn update_transforms(&mut self, new_upstream_transforms: HashMap<NodeId, (Footprint, DAffine2)>) {
-		self.upstream_transforms = new_upstream_transforms;
+		duplicates.sort_unstable();
+		duplicates.dedup();
+		LoadStructureReport { duplicates }
 	}
 
-	/// Access the cached transformation to document space from layer space
-	pub fn transform_to_document(&self, layer: LayerNodeIdentifier) -> DAffine2 {
-		self.document_to_viewport.inverse() * self.transform_to_viewport(layer)
-	}
+	fn remap_node_ids(&mut self, id_remap: &HashMap<NodeId, NodeId>) {
+		if id_remap.is_empty() {
+			return;
+		}
+		let node = |node: NodeId| id_remap.get(&node).copied().unwrap_or(node);
+		let layer = |layer: LayerNodeIdentifier| match id_remap.get(&layer.to_node()) {
+			Some(&new) if layer != LayerNodeIdentifier::ROOT => LayerNodeIdentifier::new_unchecked(new),
+			_ => layer,
+		};
 
-	pub fn transform_to_viewport(&self, layer: LayerNodeIdentifier) -> DAffine2 {
-		layer
-			.ancestors(self)
-			.filter_map(|layer| self.upstream_transforms.get(&layer.to_node()))
-			.copied()
-			.map(|(footprint, transform)| footprint.transform * transform)
-			.next()
-			.unwrap_or(self.document_to_viewport)
+		let mut seen = HashSet::new();
+		self.selected_nodes = self.selected_nodes.iter().map(|&selected| node(selected)).filter(|&selected| seen.insert(selected)).collect();
+		let (remapped, kept): (Vec<_>, Vec<_>) = self.selection_sources.drain().partition(|(selected, _)| id_remap.contains_key(selected));
+		self.selection_sources.extend(kept);
+		for (selected, source) in remapped {
+			self.selection_sources.entry(node(selected)).or_insert(source);
+		}
+
+		if self.collapsed.iter().any(|&collapsed| layer(collapsed) != collapsed) {
+			self.collapsed = Arc::new(self.collapsed.iter().map(|&collapsed| layer(collapsed)).collect());
+		}
+		if self.locked.iter().any(|&locked| layer(locked) != locked) {
+			self.locked = Arc::new(self.locked.iter().map(|&locked| layer(locked)).collect());
+		}
+		if self.tags.keys().any(|&tagged| layer(tagged) != tagged) {
+			let (remapped, kept): (Vec<_>, Vec<_>) = self.tags.iter().map(|(&tagged, tag)| (tagged, tag.clone())).partition(|&(tagged, _)| layer(tagged) != tagged);
+			let mut tags = kept.into_iter().collect::<HashMap<_, _>>();
+			for (tagged, tag) in remapped {
+				tags.entry(layer(tagged)).or_insert(tag);
+			}
+			self.tags = Arc::new(tags);
+		}
+		self.isolation_root = self.isolation_root.map(layer);
+		if self.saved_selections.iter().flat_map(|saved| &saved.layers).any(|&saved| layer(saved) != saved) {
+			for saved in Arc::make_mut(&mut self.saved_selections) {
+				let mut seen = HashSet::new();
+				saved.layers = saved.layers.iter().map(|&saved| layer(saved)).filter(|&saved| seen.insert(saved)).collect();
+			}
+		}
 	}
 
-	pub fn upstream_transform(&self, node_id: NodeId) -> DAffine2 {
-		self.upstream_transforms.get(&node_id).copied().map(|(_, transform)| transform).unwrap_or(DAffine2::IDENTITY)
+	/// Layer nodes in the graph that aren't connected to the output, so were not found by [`Self::load_structure`], sorted by id.
+	pub fn find_unreachable_layers(&self, graph: &NodeNetwork) -> Vec<NodeId> {
+		let mut unreachable = graph
+			.nodes
+			.iter()
+			.filter(|(&id, node)| node.is_layer() && !self.structure.contains_key(&LayerNodeIdentifier::new_unchecked(id)))
+			.map(|(&id, _)| id)
+			.collect::<Vec<_>>();
+		unreachable.sort_unstable();
+		unreachable
 	}
+}
 
-	pub fn downstream_transform_to_viewport(&self, layer: LayerNodeIdentifier) -> DAffine2 {
-		self.upstream_transforms
-			.get(&layer.to_node())
-			.copied()
-			.map(|(footprint, _)| footprint.transform)
-			.unwrap_or_else(|| self.transform_to_viewport(layer))
+/// Divide the distance by the geometric mean of the scale of the transform along each of its axes.
+fn px_to_units(transform: DAffine2, px: f64) -> f64 {
+	let scale = (transform.matrix2.x_axis.length() * transform.matrix2.y_axis.length()).sqrt();
+	if scale > 0. {
+		px / scale
+	} else {
+		f64::INFINITY
 	}
 }
 
-pub fn is_artboard(layer: LayerNodeIdentifier, network: &NodeNetwork) -> bool {
-	network.upstream_flow_back_from_nodes(vec![layer.to_node()], true).any(|(node, _)| node.is_artboard())
-}
-
-pub fn is_folder(layer: LayerNodeIdentifier, network: &NodeNetwork) -> bool {
-	network.nodes.get(&layer.to_node()).and_then(|node| node.inputs.first()).is_some_and(|input| input.as_node().is_none())
-		|| network
-			.upstream_flow_back_from_nodes(vec![layer.to_node()], true)
-			.skip(1)
-			.any(|(node, _)| node.is_artboard() || node.is_layer())
+/// Problems found in the graph by [`DocumentMetadata::load_structure`], such as to prompt the user to repair the document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[must_use = "the report should be checked for problems with the graph"]
+pub struct LoadStructureReport {
+	/// Layer nodes reached from more than one position in the layer stacks, such as after a bad merge of documents, sorted by id.
+	/// Only the first position of each is kept in the layer tree.
+	pub duplicates: Vec<NodeId>,
 }
 
-// click targets
+// auxiliary state
 impl DocumentMetadata {
-	/// Update the cached click targets of the layers
-	pub fn update_click_targets(&mut self, new_click_targets: HashMap<LayerNodeIdentifier, Vec<ClickTarget>>) {
-		self.click_targets = new_click_targets;
-	}
-
-	/// Get the bounding box of the click target of the specified layer in the specified transform space
-	pub fn bounding_box_with_transform(&self, layer: LayerNodeIdentifier, transform: DAffine2) -> Option<[DVec2; 2]> {
-		self.click_targets
-			.get(&layer)?
-			.iter()
-			.filter_map(|click_target| click_target.subpath.bounding_box_with_transform(transform))
-			.reduce(Quad::combine_bounds)
-	}
-
-	/// Calculate the corners of the bounding box but with a nonzero size.
+	/// Forget everything kept for layers that aren't in the layer tree, such as the tags and collapsed state of deleted layers, returning how much was forgotten of each kind.
 	///
-	/// If the layer bounds are `0` in either axis then they are changed to be `1`.
-	pub fn nonzero_bounding_box(&self, layer: LayerNodeIdentifier) -> [DVec2; 2] {
-		let [bounds_min, mut bounds_max] = self.bounding_box_with_transform(layer, DAffine2::IDENTITY).unwrap_or_default();
-
-		let bounds_size = bounds_max - bounds_min;
-		if bounds_size.x < 1e-10 {
-			bounds_max.x = bounds_min.x + 1.;
+	/// This is the one place that keeps each kind of [`AuxiliaryState`] to the layers in the tree, and is called by [`Self::load_structure`].
+	/// Saved selections aren't included, as they keep missing layers until restored so a layer missing only until an undo is restored with them.
+	pub fn prune_auxiliary_state(&mut self) -> PruneReport {
+		fn prune_map<V: Clone>(map: &mut Arc<HashMap<LayerNodeIdentifier, V>>, structure: &HashMap<LayerNodeIdentifier, NodeRelations>) -> usize {
+			let stale = map.keys().filter(|layer| !structure.contains_key(layer)).count();
+			if stale > 0 {
+				Arc::make_mut(map).retain(|layer, _| structure.contains_key(layer));
+			}
+			stale
 		}
-		if bounds_size.y < 1e-10 {
-			bounds_max.y = bounds_min.y + 1.;
+		fn prune_set(set: &mut Arc<HashSet<LayerNodeIdentifier>>, structure: &HashMap<LayerNodeIdentifier, NodeRelations>) -> usize {
+			let stale = set.iter().filter(|layer| !structure.contains_key(layer)).count();
+			if stale > 0 {
+				Arc::make_mut(set).retain(|layer| structure.contains_key(layer));
+			}
+			stale
 		}
 
-		[bounds_min, bounds_max]
+		let structure = self.structure.clone();
+		let mut removed = HashMap::new();
+		let mut record = |state, count| {
+			if count > 0 {
+				removed.insert(state, count);
+			}
+		};
+		record(AuxiliaryState::ClickTargets, prune_map(&mut self.click_targets, &structure));
+		record(AuxiliaryState::TextHitRegions, prune_map(&mut self.text_hit_regions, &structure));
+		record(AuxiliaryState::RenderPadding, prune_map(&mut self.render_padding, &structure));
+		record(AuxiliaryState::ChromeClickTargets, prune_map(&mut self.chrome_click_targets, &structure));
+		record(AuxiliaryState::Names, prune_map(&mut self.names, &structure));
+		record(AuxiliaryState::Opacities, prune_map(&mut self.opacities, &structure));
+		record(AuxiliaryState::BlendModes, prune_map(&mut self.blend_modes, &structure));
+		record(AuxiliaryState::ArtboardInfo, prune_map(&mut self.artboard_info, &structure));
+		record(AuxiliaryState::Tags, prune_map(&mut self.tags, &structure));
+		record(AuxiliaryState::LayerGenerations, prune_map(&mut self.layer_generations, &structure));
+		record(AuxiliaryState::Hidden, prune_set(&mut self.hidden, &structure));
+		record(AuxiliaryState::Collapsed, prune_set(&mut self.collapsed, &structure));
+		record(AuxiliaryState::Locked, prune_set(&mut self.locked, &structure));
+		if let Some(previous_frame_bounds) = &mut self.previous_frame_bounds {
+			record(AuxiliaryState::PreviousFrameBounds, prune_map(previous_frame_bounds, &structure));
+		}
+		let fingerprints = self.geometry_fingerprints.0.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let fingerprint_count = fingerprints.len();
+		fingerprints.retain(|layer, _| structure.contains_key(layer));
+		record(AuxiliaryState::GeometryFingerprints, fingerprint_count - fingerprints.len());
+		if self.isolation_root.is_some_and(|layer| !structure.contains_key(&layer)) {
+			self.isolation_root = None;
+			record(AuxiliaryState::IsolationRoot, 1);
+		}
+		PruneReport { removed }
 	}
 
-	/// Get the bounding box of the click target of the specified layer in document space
-	pub fn bounding_box_document(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
-		self.bounding_box_with_transform(layer, self.transform_to_document(layer))
+	/// Everything kept for layers that aren't in the layer tree, which [`Self::prune_auxiliary_state`] would forget, in no particular order.
+	pub fn audit_auxiliary_state(&self) -> Vec<AuxiliaryLeak> {
+		let stale = |state: AuxiliaryState, layers: &mut dyn Iterator<Item = LayerNodeIdentifier>| {
+			layers
+				.filter(|layer| !self.structure.contains_key(layer))
+				.map(move |layer| AuxiliaryLeak { state, layer })
+				.collect::<Vec<_>>()
+		};
+		let fingerprints = self.geometry_fingerprints.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let previous_frame_bounds = self.previous_frame_bounds.iter().flat_map(|bounds| bounds.keys().copied());
+		[
+			stale(AuxiliaryState::ClickTargets, &mut self.click_targets.keys().copied()),
+			stale(AuxiliaryState::TextHitRegions, &mut self.text_hit_regions.keys().copied()),
+			stale(AuxiliaryState::RenderPadding, &mut self.render_padding.keys().copied()),
+			stale(AuxiliaryState::ChromeClickTargets, &mut self.chrome_click_targets.keys().copied()),
+			stale(AuxiliaryState::Names, &mut self.names.keys().copied()),
+			stale(AuxiliaryState::Opacities, &mut self.opacities.keys().copied()),
+			stale(AuxiliaryState::BlendModes, &mut self.blend_modes.keys().copied()),
+			stale(AuxiliaryState::ArtboardInfo, &mut self.artboard_info.keys().copied()),
+			stale(AuxiliaryState::Tags, &mut self.tags.keys().copied()),
+			stale(AuxiliaryState::LayerGenerations, &mut self.layer_generations.keys().copied()),
+			stale(AuxiliaryState::Hidden, &mut self.hidden.iter().copied()),
+			stale(AuxiliaryState::Collapsed, &mut self.collapsed.iter().copied()),
+			stale(AuxiliaryState::Locked, &mut self.locked.iter().copied()),
+			stale(AuxiliaryState::PreviousFrameBounds, &mut previous_frame_bounds.into_iter()),
+			stale(AuxiliaryState::GeometryFingerprints, &mut fingerprints.keys().copied()),
+			stale(AuxiliaryState::IsolationRoot, &mut self.isolation_root.into_iter()),
+		]
+		.concat()
 	}
+}
 
-	/// Get the bounding box of the click target of the specified layer in viewport space
-	pub fn bounding_box_viewport(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
-		self.bounding_box_with_transform(layer, self.transform_to_viewport(layer))
-	}
+/// Each kind of data kept for layers alongside the layer tree, see [`DocumentMetadata::prune_auxiliary_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuxiliaryState {
+	ClickTargets,
+	TextHitRegions,
+	RenderPadding,
+	ChromeClickTargets,
+	Names,
+	Opacities,
+	BlendModes,
+	ArtboardInfo,
+	Tags,
+	LayerGenerations,
+	Hidden,
+	Collapsed,
+	Locked,
+	PreviousFrameBounds,
+	GeometryFingerprints,
+	IsolationRoot,
+}
 
-	/// Calculates the document bounds in viewport space
-	pub fn document_bounds_viewport_space(&self) -> Option<[DVec2; 2]> {
-		self.all_layers().filter_map(|layer| self.bounding_box_viewport(layer)).reduce(Quad::combine_bounds)
+/// How many layers each kind of state was forgotten for by [`DocumentMetadata::prune_auxiliary_state`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+	/// Only kinds with anything forgotten are included.
+	pub removed: HashMap<AuxiliaryState, usize>,
+}
+
+impl PruneReport {
+	/// How many layers the kind of state was forgotten for.
+	pub fn removed(&self, state: AuxiliaryState) -> usize {
+		self.removed.get(&state).copied().unwrap_or_default()
 	}
 
-	/// Calculates the document bounds in document space
-	pub fn document_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
-		self.all_layers()
-			.filter(|&layer| include_artboards || !self.is_artboard(layer))
-			.filter_map(|layer| self.bounding_box_document(layer))
-			.reduce(Quad::combine_bounds)
+	/// Was anything forgotten?
+	pub fn is_empty(&self) -> bool {
+		self.removed.is_empty()
 	}
+}
 
-	/// Calculates the selected layer bounds in document space
-	pub fn selected_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
-		self.selected_layers()
-			.filter(|&layer| include_artboards || !self.is_artboard(layer))
-			.filter_map(|layer| self.bounding_box_document(layer))
-			.reduce(Quad::combine_bounds)
+/// State kept for a layer that isn't in the layer tree, see [`DocumentMetadata::audit_auxiliary_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuxiliaryLeak {
+	pub state: AuxiliaryState,
+	pub layer: LayerNodeIdentifier,
+}
+
+// persistence
+impl DocumentMetadata {
+	/// The state that isn't read from the graph by [`Self::load_structure`], to be saved with the document.
+	pub fn persistent_state(&self) -> PersistentMetadata {
+		let mut tags: Vec<_> = self.tags.iter().map(|(&layer, tag)| (layer, tag.clone())).collect();
+		tags.sort_unstable_by_key(|&(layer, _)| layer.to_node());
+		let mut collapsed: Vec<_> = self.collapsed.iter().copied().collect();
+		collapsed.sort_unstable_by_key(|layer| layer.to_node());
+		let mut locked: Vec<_> = self.locked.iter().copied().collect();
+		locked.sort_unstable_by_key(|layer| layer.to_node());
+		PersistentMetadata {
+			tags,
+			collapsed,
+			locked,
+			saved_selections: self.saved_selections.to_vec(),
+			saved_views: self.saved_views.to_vec(),
+			guides: self.guides.to_vec(),
+		}
 	}
 
-	pub fn layer_outline<'a>(&'a self, layer: LayerNodeIdentifier) -> impl Iterator<Item = &'a bezier_rs::Subpath<ManipulatorGroupId>> {
-		static EMPTY: Vec<ClickTarget> = Vec::new();
-		let click_targets = self.click_targets.get(&layer).unwrap_or(&EMPTY);
-		click_targets.iter().map(|click_target| &click_target.subpath)
+	/// Restore the state saved with the document by [`Self::persistent_state`].
+	///
+	/// This is usually called before the layers are loaded, so state for layers that don't exist is kept until [`Self::load_structure`] prunes it.
+	pub fn restore_persistent_state(&mut self, state: PersistentMetadata) {
+		self.tags = Arc::new(state.tags.into_iter().collect());
+		self.set_collapsed_folders(state.collapsed);
+		self.locked = Arc::new(state.locked.into_iter().collect());
+		self.saved_selections = Arc::new(state.saved_selections);
+		self.saved_views = Arc::new(state.saved_views);
+		self.view_history.clear();
+		self.set_guides(state.guides);
 	}
 }
 
-/// Id of a layer node
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
-pub struct LayerNodeIdentifier(NonZeroU64);
+/// The state of the [`DocumentMetadata`] saved with the document, see [`DocumentMetadata::persistent_state`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PersistentMetadata {
+	#[serde(default)]
+	pub tags: Vec<(LayerNodeIdentifier, LayerTag)>,
+	/// The folders collapsed in the layer panel.
+	#[serde(default)]
+	pub collapsed: Vec<LayerNodeIdentifier>,
+	/// The layers locked with [`DocumentMetadata::set_layer_locked`].
+	#[serde(default)]
+	pub locked: Vec<LayerNodeIdentifier>,
+	#[serde(default)]
+	pub saved_selections: Vec<SavedSelection>,
+	/// The views saved with [`DocumentMetadata::save_view`]. The current view is saved by the navigation of the editor.
+	#[serde(default)]
+	pub saved_views: Vec<SavedView>,
+	#[serde(default)]
+	pub guides: Vec<(GuideId, Guide)>,
+}
 
-impl Default for LayerNodeIdentifier {
-	fn default() -> Self {
-		Self::ROOT
-	}
+/// How the click targets of the layers are stored, see [`DocumentMetadata::click_target_sharing`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClickTargetSharing {
+	/// Number of distinct click target allocations.
+	pub unique_allocations: usize,
+	/// Number of layers referencing an allocation that other layers reference too.
+	pub shared_references: usize,
+	/// Number of layers referencing an allocation of their own.
+	pub unshared_references: usize,
 }
 
-impl core::fmt::Debug for LayerNodeIdentifier {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.debug_tuple("LayerNodeIdentifier").field(&self.to_node()).finish()
+/// The cached transforms at a point in time, see [`DocumentMetadata::snapshot_transforms`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformsSnapshot(Arc<HashMap<NodeId, (Footprint, DAffine2)>>);
+
+/// Split a path from [`DocumentMetadata::layer_path_string`] into the unescaped names and the node id following a trailing unescaped `#`, or `None` if it is malformed.
+fn parse_layer_path(path: &str) -> Option<(Vec<String>, Option<NodeId>)> {
+	let mut components = vec![String::new()];
+	let mut node_id: Option<String> = None;
+	let mut characters = path.chars();
+	while let Some(character) = characters.next() {
+		match (character, &mut node_id) {
+			(character, Some(node_id)) => node_id.push(character),
+			('#', None) => node_id = Some(String::new()),
+			('/', None) => components.push(String::new()),
+			('\\', None) => components.last_mut()?.push(characters.next()?),
+			(character, None) => components.last_mut()?.push(character),
+		}
 	}
+	let node_id = match node_id {
+		Some(node_id) => Some(node_id.parse().ok()?),
+		None => None,
+	};
+	Some((components, node_id))
 }
 
-impl core::fmt::Display for LayerNodeIdentifier {
-	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.write_fmt(format_args!("Layer(node_id={})", self.to_node()))
-	}
+fn first_child_layer<'a>(graph: &'a NodeNetwork, node: &DocumentNode) -> Option<(&'a DocumentNode, NodeId)> {
+	graph.upstream_flow_back_from_nodes(vec![node.inputs[0].as_node()?], true).find(|(node, _)| node.is_layer())
 }
 
-impl LayerNodeIdentifier {
-	pub const ROOT: Self = LayerNodeIdentifier::new_unchecked(0);
+fn sibling_below<'a>(graph: &'a NodeNetwork, node: &DocumentNode) -> Option<(&'a DocumentNode, NodeId)> {
+	let construct_layer_node = &node.inputs[1];
+	construct_layer_node.as_node().and_then(|id| graph.nodes.get(&id).filter(|node| node.is_layer()).map(|node| (node, id)))
+}
 
-	/// Construct a [`LayerNodeIdentifier`] without checking if it is a layer node
-	pub const fn new_unchecked(node_id: NodeId) -> Self {
-		// Safety: will always be >=1
-		Self(unsafe { NonZeroU64::new_unchecked(node_id + 1) })
+// saved selections
+impl DocumentMetadata {
+	/// Save the currently selected layers under the name, replacing any saved selection with the same name.
+	pub fn save_selection(&mut self, name: String) {
+		let layers = self.selected_layer_ids().collect();
+		let saved_selections = Arc::make_mut(&mut self.saved_selections);
+		match saved_selections.iter_mut().find(|saved| saved.name == name) {
+			Some(saved) => saved.layers = layers,
+			None => saved_selections.push(SavedSelection { name, layers }),
+		}
 	}
 
-	/// Construct a [`LayerNodeIdentifier`], debug asserting that it is a layer node
-	#[track_caller]
-	pub fn new(node_id: NodeId, network: &NodeNetwork) -> Self {
-		debug_assert!(
-			is_layer_node(node_id, network),
-			"Layer identifier constructed from non layer node {node_id}: {:#?}",
-			network.nodes.get(&node_id)
-		);
-		Self::new_unchecked(node_id)
+	/// Select the layers saved under the name, or return `None` if there is no such saved selection.
+	///
+	/// Layers that no longer exist are deselected and forgotten from the saved selection. They aren't pruned when the structure is loaded, so a layer missing only until an undo is restored with it.
+	#[must_use]
+	pub fn restore_selection(&mut self, name: &str) -> Option<SelectionChanged> {
+		let index = self.saved_selections.iter().position(|saved| saved.name == name)?;
+		let structure = &self.structure;
+		let saved = &mut Arc::make_mut(&mut self.saved_selections)[index];
+		saved.layers.retain(|layer| structure.contains_key(layer));
+		let layers = saved.layers.iter().copied().map(LayerNodeIdentifier::to_node).collect();
+		Some(self.set_selected_nodes(layers))
 	}
 
-	pub fn from_path(path: &[u64], network: &NodeNetwork) -> Self {
-		Self::new(*path.last().unwrap(), network)
+	/// The name of each saved selection in the order they were first saved, along with how many of its layers still exist.
+	pub fn saved_selections(&self) -> impl Iterator<Item = (&str, usize)> {
+		self.saved_selections
+			.iter()
+			.map(|saved| (saved.name.as_str(), saved.layers.iter().filter(|&&layer| self.layer_exists(layer)).count()))
 	}
 
-	/// Access the node id of this layer
-	pub fn to_node(self) -> NodeId {
-		u64::from(self.0) - 1
+	/// Forget the saved selection, returning whether it existed.
+	pub fn delete_saved_selection(&mut self, name: &str) -> bool {
+		let Some(index) = self.saved_selections.iter().position(|saved| saved.name == name) else {
+			return false;
+		};
+		Arc::make_mut(&mut self.saved_selections).remove(index);
+		true
 	}
 
-	/// Convert layer to layer path
-	pub fn to_path(self) -> Vec<NodeId> {
-		vec![self.to_node()]
+	/// All of the saved selections, to be saved with the document.
+	pub fn saved_selection_list(&self) -> &[SavedSelection] {
+		&self.saved_selections
 	}
 
-	/// Access the parent layer if possible
-	pub fn parent(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
-		document_metadata.get_relations(self).and_then(|relations| relations.parent)
+	/// Restore the saved selections saved with the document. Missing layers are kept until the selection is restored.
+	pub fn set_saved_selection_list(&mut self, saved_selections: Vec<SavedSelection>) {
+		self.saved_selections = Arc::new(saved_selections);
 	}
+}
 
-	/// Access the previous sibling of this layer (up the layer tree)
-	pub fn previous_sibling(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
-		document_metadata.get_relations(self).and_then(|relations| relations.previous_sibling)
+/// Layers selected together and saved under a name, see [`DocumentMetadata::save_selection`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct SavedSelection {
+	pub name: String,
+	pub layers: Vec<LayerNodeIdentifier>,
+}
+
+// saved views
+impl DocumentMetadata {
+	/// Save the current `document_to_viewport` under the name, replacing any saved view with the same name.
+	pub fn save_view(&mut self, name: String) {
+		let transform = self.document_to_viewport.to_cols_array();
+		let saved_views = Arc::make_mut(&mut self.saved_views);
+		match saved_views.iter_mut().find(|saved| saved.name == name) {
+			Some(saved) => saved.transform = transform,
+			None => saved_views.push(SavedView { name, transform }),
+		}
 	}
 
-	/// Access the next sibling of this layer (down the layer tree)
-	pub fn next_sibling(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
-		document_metadata.get_relations(self).and_then(|relations| relations.next_sibling)
+	/// Jump to the view saved under the name, remembering the current view first like [`Self::remember_view`]. Returns `false` if there is no such view.
+	///
+	/// The viewport space caches are found again for the restored `document_to_viewport`, which the navigation of the editor should then be updated to.
+	pub fn restore_view(&mut self, name: &str) -> bool {
+		let Some(saved) = self.saved_views.iter().find(|saved| saved.name == name).map(|saved| DAffine2::from_cols_array(&saved.transform)) else {
+			return false;
+		};
+		self.remember_view();
+		self.document_to_viewport = saved;
+		true
 	}
 
-	/// Access the first child of this layer (top most in layer tree)
-	pub fn first_child(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
-		document_metadata.get_relations(self).and_then(|relations| relations.first_child)
+	/// The names of the saved views in the order they were first saved.
+	pub fn views(&self) -> impl Iterator<Item = &str> {
+		self.saved_views.iter().map(|saved| saved.name.as_str())
 	}
 
-	/// Access the last child of this layer (bottom most in layer tree)
-	pub fn last_child(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
-		document_metadata.get_relations(self).and_then(|relations| relations.last_child)
+	/// Remember the current `document_to_viewport` before jumping away from it, such as when zooming to fit, so it can be returned to with [`Self::restore_previous_view`].
+	///
+	/// Continuous changes such as panning don't need to be remembered.
+	pub fn remember_view(&mut self) {
+		const MAX_HISTORY: usize = 32;
+
+		if self.view_history.last() == Some(&self.document_to_viewport) {
+			return;
+		}
+		if self.view_history.len() == MAX_HISTORY {
+			self.view_history.remove(0);
+		}
+		self.view_history.push(self.document_to_viewport);
 	}
 
-	/// Does the layer have children?
-	pub fn has_children(self, document_metadata: &DocumentMetadata) -> bool {
-		self.first_child(document_metadata).is_some()
+	/// Jump back to the view last remembered with [`Self::remember_view`], which is then forgotten. Returns `false` if there is no view to return to.
+	pub fn restore_previous_view(&mut self) -> bool {
+		let Some(previous) = self.view_history.pop() else {
+			return false;
+		};
+		self.document_to_viewport = previous;
+		true
 	}
 
-	/// Iterator over all direct children (excluding self and recursive children)
-	pub fn children(self, document_metadata: &DocumentMetadata) -> AxisIter {
-		AxisIter {
-			layer_node: self.first_child(document_metadata),
-			next_node: Self::next_sibling,
-			document_metadata,
-		}
+	/// Keep the saved views and the views to return to of another metadata, such as the one replaced by undo, since changing the view isn't undone.
+	pub fn take_views_from(&mut self, other: &mut Self) {
+		self.saved_views = std::mem::take(&mut other.saved_views);
+		self.view_history = std::mem::take(&mut other.view_history);
 	}
+}
 
-	/// All ancestors of this layer, including self, going to the document root
-	pub fn ancestors(self, document_metadata: &DocumentMetadata) -> AxisIter {
-		AxisIter {
-			layer_node: Some(self),
-			next_node: Self::parent,
-			document_metadata,
-		}
+/// A `document_to_viewport` saved under a name, see [`DocumentMetadata::save_view`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct SavedView {
+	pub name: String,
+	/// The columns of the transform, see [`DAffine2::to_cols_array`].
+	pub transform: [f64; 6],
+}
+
+// transforms
+impl DocumentMetadata {
+	/// Update the cached transforms of the layers
+	pub fn update_transforms(&mut self, new_upstream_transforms: HashMap<NodeId, (Footprint, DAffine2)>) {
+		self.update_transforms_shared(Arc::new(new_upstream_transforms));
 	}
 
-	/// Iterator through all the last children, starting from self
-	pub fn last_children(self, document_metadata: &DocumentMetadata) -> AxisIter {
-		AxisIter {
-			layer_node: Some(self),
-			next_node: Self::last_child,
-			document_metadata,
+	fn update_transforms_shared(&mut self, new_upstream_transforms: Arc<HashMap<NodeId, (Footprint, DAffine2)>>) {
+		if Arc::ptr_eq(&self.upstream_transforms, &new_upstream_transforms) {
+			return;
 		}
+		let removed = self.upstream_transforms.keys().filter(|node| !new_upstream_transforms.contains_key(node));
+		let modified = new_upstream_transforms
+			.iter()
+			.filter(|&(node, value)| self.upstream_transforms.get(node) != Some(value))
+			.map(|(node, _)| node);
+		let changed = removed.chain(modified).copied().collect::<Vec<_>>();
+		self.upstream_transforms = new_upstream_transforms;
+		self.transforms_generation += 1;
+		self.full_transforms_generation = self.transforms_generation;
+		self.transform_generations = Arc::default();
+		self.record_evaluated_document_to_viewport();
+		self.mark_layers_changed(changed.iter().copied().map(LayerNodeIdentifier::new_unchecked));
+		self.notify_transforms_updated(changed);
 	}
 
-	/// Iterator through all decendants, including recursive children (not including self)
-	pub fn decendants(self, document_metadata: &DocumentMetadata) -> DecendantsIter {
-		DecendantsIter {
-			front: self.first_child(document_metadata),
-			back: self.last_child(document_metadata).and_then(|child| child.last_children(document_metadata).last()),
-			document_metadata,
-		}
+	/// Update the cached transforms of only the specified nodes from a partial evaluation, keeping the rest.
+	///
+	/// The cached transforms that were kept are older than those merged, so they are stale inside of merged layers, see [`Self::transform_to_viewport`].
+	pub fn merge_transforms(&mut self, new_upstream_transforms: HashMap<NodeId, (Footprint, DAffine2)>) {
+		self.transforms_generation += 1;
+		let generation = self.transforms_generation;
+		Arc::make_mut(&mut self.transform_generations).extend(new_upstream_transforms.keys().map(|&node| (node, generation)));
+		let merged = new_upstream_transforms.keys().copied().map(LayerNodeIdentifier::new_unchecked).filter(|layer| layer.exists(self));
+		// The descendants may now resolve to the transform of a merged ancestor instead of their own stale one
+		let changed = merged.flat_map(|layer| std::iter::once(layer).chain(layer.decendants(self))).collect::<Vec<_>>();
+		let updated = new_upstream_transforms.keys().copied().collect();
+		Arc::make_mut(&mut self.upstream_transforms).extend(new_upstream_transforms);
+		self.record_evaluated_document_to_viewport();
+		self.mark_layers_changed(changed);
+		self.notify_transforms_updated(updated);
 	}
 
-	/// Add a child towards the top of the layer tree
-	pub fn push_front_child(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
-		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
-		let parent = document_metadata.get_structure_mut(self);
-		let old_first_child = parent.first_child.replace(new);
-		parent.last_child.get_or_insert(new);
-		if let Some(old_first_child) = old_first_child {
-			document_metadata.get_structure_mut(old_first_child).previous_sibling = Some(new);
+	fn record_evaluated_document_to_viewport(&mut self) {
+		if is_invertible(self.document_to_viewport) {
+			self.evaluated_document_to_viewport = self.document_to_viewport;
 		}
-		document_metadata.get_structure_mut(new).next_sibling = old_first_child;
-		document_metadata.get_structure_mut(new).parent = Some(self);
 	}
 
-	/// Add a child towards the bottom of the layer tree
-	pub fn push_child(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
-		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
-		let parent = document_metadata.get_structure_mut(self);
-		let old_last_child = parent.last_child.replace(new);
-		parent.first_child.get_or_insert(new);
-		if let Some(old_last_child) = old_last_child {
-			document_metadata.get_structure_mut(old_last_child).next_sibling = Some(new);
+	/// Set a cached transform from a change made without evaluating the graph, which is as up to date as the newest evaluated transforms.
+	fn set_upstream_transform(&mut self, node: NodeId, value: (Footprint, DAffine2)) {
+		Arc::make_mut(&mut self.upstream_transforms).insert(node, value);
+		if self.transforms_generation != self.full_transforms_generation {
+			Arc::make_mut(&mut self.transform_generations).insert(node, self.transforms_generation);
 		}
-		document_metadata.get_structure_mut(new).previous_sibling = old_last_child;
-		document_metadata.get_structure_mut(new).parent = Some(self);
 	}
 
-	/// Add sibling above in the layer tree
-	pub fn add_before(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
-		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
-		document_metadata.get_structure_mut(new).next_sibling = Some(self);
-		document_metadata.get_structure_mut(new).parent = self.parent(document_metadata);
-		let old_previous_sibling = document_metadata.get_structure_mut(self).previous_sibling.replace(new);
-		if let Some(old_previous_sibling) = old_previous_sibling {
-			document_metadata.get_structure_mut(old_previous_sibling).next_sibling = Some(new);
-			document_metadata.get_structure_mut(new).previous_sibling = Some(old_previous_sibling);
-		} else if let Some(structure) = self
-			.parent(document_metadata)
-			.map(|parent| document_metadata.get_structure_mut(parent))
-			.filter(|structure| structure.first_child == Some(self))
-		{
-			structure.first_child = Some(new);
+	/// The transforms generation that the cached transform of the node was last updated in, or `None` if it has no cached transform.
+	fn transform_generation(&self, node: NodeId) -> Option<u64> {
+		self.upstream_transforms
+			.contains_key(&node)
+			.then(|| self.transform_generations.get(&node).copied().unwrap_or(self.full_transforms_generation))
+	}
+
+	/// Move the cached transforms of the layers by a viewport space transform, such as to preview a drag before the graph is evaluated again.
+	///
+	/// Layers inside another of the layers are only moved once. Descendants without a cached transform of their own follow their ancestor, and those with one are moved along with it.
+	pub fn apply_transform_delta(&mut self, layers: &[LayerNodeIdentifier], delta_viewport: DAffine2) {
+		let top_layers = layers
+			.iter()
+			.copied()
+			.filter(|layer| !layer.ancestors(self).skip(1).any(|ancestor| layers.contains(&ancestor)))
+			.collect::<Vec<_>>();
+		let moved = top_layers.iter().flat_map(|&layer| std::iter::once(layer).chain(layer.decendants(self))).collect::<Vec<_>>();
+
+		let mut changed = Vec::new();
+		for layer in moved {
+			let own_transform = self.upstream_transforms.get(&layer.to_node()).copied();
+			// Dragged layers without a cached transform get one, describing where they are inherited to be, whereas their descendants can keep inheriting
+			let is_top_layer = top_layers.contains(&layer);
+			let Some((footprint, transform)) = own_transform.or_else(|| {
+				is_top_layer.then(|| {
+					let footprint = Footprint {
+						transform: self.transform_to_viewport(layer),
+						..Default::default()
+					};
+					(footprint, DAffine2::IDENTITY)
+				})
+			}) else {
+				continue;
+			};
+			// A layer with a downstream scale of zero has no area to move
+			if footprint.transform.matrix2.determinant() == 0. {
+				continue;
+			}
+			// The viewport transform is `footprint.transform * transform`, so the delta is conjugated into the local space of the layer
+			let transform = footprint.transform.inverse() * delta_viewport * footprint.transform * transform;
+			self.set_upstream_transform(layer.to_node(), (footprint, transform));
+			changed.push(layer);
 		}
+		let nodes = changed.iter().map(|layer| layer.to_node()).collect();
+		self.mark_layers_changed(changed);
+		self.notify_transforms_updated(nodes);
 	}
 
-	/// Add sibling below in the layer tree
-	pub fn add_after(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
-		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
-		document_metadata.get_structure_mut(new).previous_sibling = Some(self);
-		document_metadata.get_structure_mut(new).parent = self.parent(document_metadata);
-		let old_next_sibling = document_metadata.get_structure_mut(self).next_sibling.replace(new);
-		if let Some(old_next_sibling) = old_next_sibling {
-			document_metadata.get_structure_mut(old_next_sibling).previous_sibling = Some(new);
-			document_metadata.get_structure_mut(new).next_sibling = Some(old_next_sibling);
-		} else if let Some(structure) = self
-			.parent(document_metadata)
-			.map(|parent| document_metadata.get_structure_mut(parent))
-			.filter(|structure| structure.last_child == Some(self))
-		{
-			structure.last_child = Some(new);
+	/// Push the folder's cached transform down into the cached transforms of its direct children and reset its own to identity, mirroring flattening its transform in the graph.
+	///
+	/// The children inherit the folder's footprint, so the viewport transforms of all descendants are unchanged as long as the cache is consistent.
+	pub fn bake_transform_into_children(&mut self, folder: LayerNodeIdentifier) {
+		let Some((footprint, folder_transform)) = self.upstream_transforms.get(&folder.to_node()).copied() else {
+			return;
+		};
+		let children = folder.children(self).collect::<Vec<_>>();
+		for &child in &children {
+			// Children without their own cached transform were inheriting the folder's, so now they need their own
+			let transform = self.upstream_transforms.get(&child.to_node()).map_or(DAffine2::IDENTITY, |&(_, transform)| transform);
+			self.set_upstream_transform(child.to_node(), (footprint, folder_transform * transform));
 		}
+		self.set_upstream_transform(folder.to_node(), (footprint, DAffine2::IDENTITY));
+		let nodes = std::iter::once(folder).chain(children.iter().copied()).map(LayerNodeIdentifier::to_node).collect();
+		self.mark_layers_changed(std::iter::once(folder).chain(children));
+		self.notify_transforms_updated(nodes);
 	}
 
-	/// Delete layer and all children
-	pub fn delete(self, document_metadata: &mut DocumentMetadata) {
+	/// Capture the cached transforms so they can be returned to with [`Self::restore_transforms`].
+	pub fn snapshot_transforms(&self) -> TransformsSnapshot {
+		TransformsSnapshot(self.upstream_transforms.clone())
+	}
+
+	/// Return the cached transforms to an earlier snapshot, such as when a drag is cancelled.
+	pub fn restore_transforms(&mut self, snapshot: TransformsSnapshot) {
+		self.update_transforms_shared(snapshot.0);
+	}
+
+	/// Access the cached transformation to document space from layer space
+	///
+	/// While `document_to_viewport` isn't invertible, such as when the viewport has no size, the cached transforms are brought back to document space
+	/// with the `document_to_viewport` they were last updated with instead. Layers without any cached transforms are in document space either way.
+	pub fn transform_to_document(&self, layer: LayerNodeIdentifier) -> DAffine2 {
+		let Some(transform_to_viewport) = self.newest_cached_transform_to_viewport(layer) else {
+			return DAffine2::IDENTITY;
+		};
+		let document_to_viewport = if is_invertible(self.document_to_viewport) {
+			self.document_to_viewport
+		} else {
+			self.evaluated_document_to_viewport
+		};
+		document_to_viewport.inverse() * transform_to_viewport
+	}
+
+	/// The transform from the local space of the layer to viewport space, resolved from the cached transforms of the layer and its ancestors.
+	///
+	/// The footprint of each cached transform already accumulates the transforms downstream of the node, so only one cached transform is used: the newest one,
+	/// preferring the deepest of equally new ones. After a full evaluation this is the layer's own, or that of its nearest ancestor with one. After a partial evaluation
+	/// that updated a folder but not its children, the children's stale cached transforms are ignored in favour of the folder's, treating their transforms relative to it as identity
+	/// until they are evaluated again. Without any cached transforms, the layer is in document space.
+	///
+	/// Unlike [`Self::downstream_transform_to_viewport`], this includes the transform of the layer itself.
+	pub fn transform_to_viewport(&self, layer: LayerNodeIdentifier) -> DAffine2 {
+		self.newest_cached_transform_to_viewport(layer).unwrap_or(self.document_to_viewport)
+	}
+
+	/// The viewport transform from the cached transform that [`Self::transform_to_viewport`] resolves the layer to, or `None` if neither it nor its ancestors have one.
+	fn newest_cached_transform_to_viewport(&self, layer: LayerNodeIdentifier) -> Option<DAffine2> {
+		// Ancestors are walked from the layer up, so the deepest is kept unless a strictly newer one is found
+		let newest = layer
+			.ancestors(self)
+			.filter_map(|layer| self.cached_transform_to_viewport(layer.to_node()))
+			.fold(
+				None,
+				|newest: Option<(u64, DAffine2)>, candidate| if newest.is_some_and(|newest| newest.0 >= candidate.0) { newest } else { Some(candidate) },
+			);
+		newest.map(|(_, transform)| transform)
+	}
+
+	/// The transforms generation and viewport transform of the cached transform of the node.
+	fn cached_transform_to_viewport(&self, node: NodeId) -> Option<(u64, DAffine2)> {
+		let (footprint, transform) = self.upstream_transforms.get(&node)?;
+		Some((self.transform_generation(node)?, footprint.transform * *transform))
+	}
+
+	/// The [`Self::transform_to_viewport`] of many layers at once, where the ancestors shared between layers are only walked once.
+	pub fn transforms_to_viewport(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> HashMap<LayerNodeIdentifier, DAffine2> {
+		self.transforms_to_viewport_with(layers, |node| self.cached_transform_to_viewport(node))
+	}
+
+	fn transforms_to_viewport_with(
+		&self,
+		layers: impl Iterator<Item = LayerNodeIdentifier>,
+		mut cached_transform: impl FnMut(NodeId) -> Option<(u64, DAffine2)>,
+	) -> HashMap<LayerNodeIdentifier, DAffine2> {
+		// The cached transform that each layer walked so far resolves to, including ancestors of the requested layers
+		let mut known: HashMap<LayerNodeIdentifier, Option<(u64, DAffine2)>> = HashMap::new();
+		let mut transforms = HashMap::new();
+		self.scratch.layers.with(|chain| {
+			for layer in layers {
+				let mut newest = None;
+				for ancestor in layer.ancestors(self) {
+					if let Some(&known_newest) = known.get(&ancestor) {
+						newest = known_newest;
+						break;
+					}
+					chain.push(ancestor);
+				}
+				// Resolve down from the topmost unknown ancestor, where a deeper cached transform only wins when it is at least as new
+				for ancestor in chain.drain(..).rev() {
+					if let Some(candidate) = cached_transform(ancestor.to_node()).filter(|candidate| newest.map_or(true, |newest: (u64, DAffine2)| candidate.0 >= newest.0)) {
+						newest = Some(candidate);
+					}
+					known.insert(ancestor, newest);
+				}
+				transforms.insert(layer, known[&layer].map_or(self.document_to_viewport, |(_, transform)| transform));
+			}
+		});
+		transforms
+	}
+
+	/// Transform from the local space of `layer` to the local space of `space`, or `None` if the space has a non-invertible transform.
+	pub fn transform_from_layer_to_layer(&self, layer: LayerNodeIdentifier, space: LayerNodeIdentifier) -> Option<DAffine2> {
+		let space_to_viewport = self.transform_to_viewport(space);
+		(space_to_viewport.matrix2.determinant() != 0.).then(|| space_to_viewport.inverse() * self.transform_to_viewport(layer))
+	}
+
+	/// The document space transform that moves the placement of `a` onto that of `b`, such as from a layer to its duplicate so the offset can be repeated.
+	/// Returns `None` if `a` has a non-invertible transform.
+	pub fn relative_offset_between(&self, a: LayerNodeIdentifier, b: LayerNodeIdentifier) -> Option<DAffine2> {
+		let a_to_document = self.transform_to_document(a);
+		(a_to_document.matrix2.determinant() != 0.).then(|| self.transform_to_document(b) * a_to_document.inverse())
+	}
+
+	/// The [`Self::relative_offset_between`] the layers split into its translation, rotation in radians and scale, so each can be applied again on its own.
+	pub fn relative_translation_rotation_scale(&self, a: LayerNodeIdentifier, b: LayerNodeIdentifier) -> Option<(DVec2, f64, DVec2)> {
+		let (scale, rotation, translation) = self.relative_offset_between(a, b)?.to_scale_angle_translation();
+		Some((translation, rotation, scale))
+	}
+
+	/// Convert a distance in viewport pixels, such as a click tolerance, to the local units of the layer so it stays the same size on screen at any zoom.
+	///
+	/// The scale is the geometric mean of the lengths of the x and y axes of [`Self::transform_to_viewport`], so it is exact for uniform scales and an average
+	/// for layers stretched more along one axis. Returns infinity for transforms that collapse the layer to no size.
+	pub fn viewport_px_to_layer_units(&self, layer: LayerNodeIdentifier, px: f64) -> f64 {
+		px_to_units(self.transform_to_viewport(layer), px)
+	}
+
+	/// Convert a distance in viewport pixels to document space units, with the scale of `document_to_viewport` found as in [`Self::viewport_px_to_layer_units`].
+	pub fn viewport_px_to_document_units(&self, px: f64) -> f64 {
+		px_to_units(self.document_to_viewport, px)
+	}
+
+	/// The `document_to_viewport` transform that centers the document space bounds in the viewport, zoomed to fit inside `margin_px` from each edge.
+	///
+	/// Any rotation of the current `document_to_viewport` is kept. The corners of the bounds may be in either order. Returns `None` if the bounds have no area or the margin leaves no room.
+	pub fn transform_to_fit(&self, bounds_document: [DVec2; 2], viewport_size: DVec2, margin_px: f64) -> Option<DAffine2> {
+		let [min, max] = (self.document_to_viewport * Quad::from_box_normalized(bounds_document)).bounding_box();
+		let available = viewport_size - DVec2::splat(2. * margin_px);
+		if available.cmple(DVec2::ZERO).any() || (max - min).cmple(DVec2::ZERO).any() {
+			return None;
+		}
+		let scale = (available / (max - min)).min_element();
+		Some(DAffine2::from_translation(viewport_size / 2.) * DAffine2::from_scale(DVec2::splat(scale)) * DAffine2::from_translation(-(min + max) / 2.) * self.document_to_viewport)
+	}
+
+	/// The `document_to_viewport` transform that brings the layer (with its descendants) fully into view, at least `margin_px` from each edge of the viewport.
+	///
+	/// This is the current transform if the layer is already visible, otherwise it is panned as little as possible, or zoomed with [`Self::transform_to_fit`] if the layer is too large to fit.
+	pub fn transform_to_reveal(&self, layer: LayerNodeIdentifier, viewport_size: DVec2, margin_px: f64) -> Option<DAffine2> {
+		let [min, max] = self.subtree_bounding_box_viewport(layer)?;
+		let [visible_min, visible_max] = [DVec2::splat(margin_px), viewport_size - DVec2::splat(margin_px)];
+		if (max - min).cmpgt(visible_max - visible_min).any() {
+			return self.transform_to_fit(self.subtree_bounding_box_document(layer)?, viewport_size, margin_px);
+		}
+		let offset = (visible_min - min).max(DVec2::ZERO) + (visible_max - max).min(DVec2::ZERO);
+		Some(DAffine2::from_translation(offset) * self.document_to_viewport)
+	}
+
+	/// Does the layer's transform to document space only scale (including flips) and translate, with no rotation or skew, within `epsilon`?
+	///
+	/// Rotations by multiples of 90° other than 180° swap the axes so they are not considered axis aligned, even though they map rectangles onto rectangles.
+	pub fn layer_transform_is_axis_aligned(&self, layer: LayerNodeIdentifier, epsilon: f64) -> bool {
+		let matrix2 = self.transform_to_document(layer).matrix2;
+		matrix2.x_axis.y.abs() <= epsilon && matrix2.y_axis.x.abs() <= epsilon
+	}
+
+	/// Is the layer's transform to document space only a translation by a whole number of units, within `epsilon`?
+	pub fn layer_transform_is_integer_translation(&self, layer: LayerNodeIdentifier, epsilon: f64) -> bool {
+		let transform = self.transform_to_document(layer);
+		transform.matrix2.abs_diff_eq(glam::DMat2::IDENTITY, epsilon) && transform.translation.abs_diff_eq(transform.translation.round(), epsilon)
+	}
+
+	/// The raw cached transform of the node relative to its footprint, or identity if it doesn't have one. This is only what the node was last evaluated with,
+	/// so is stale after a partial evaluation that skipped it. See [`Self::layer_relative_transform`] for a layer's transform relative to its parent layer.
+	pub fn upstream_transform(&self, node_id: NodeId) -> DAffine2 {
+		self.upstream_transforms.get(&node_id).copied().map(|(_, transform)| transform).unwrap_or(DAffine2::IDENTITY)
+	}
+
+	/// The transform of the layer relative to its parent layer, such as to show and edit the position and rotation that were given to the layer itself.
+	///
+	/// This removes the parent's transform to viewport from the layer's, both resolved from the cached transforms like [`Self::transform_to_viewport`]. If the parent isn't the root
+	/// and has no cached transform of its own, or one that can't be inverted, its contribution isn't known, so the layer's accumulated transform to document space is given instead.
+	pub fn layer_relative_transform(&self, layer: LayerNodeIdentifier) -> LayerRelativeTransform {
+		let accumulated = LayerRelativeTransform {
+			transform: self.transform_to_document(layer),
+			relative_to_parent: false,
+		};
+		let Some(transform_to_viewport) = self.newest_cached_transform_to_viewport(layer) else {
+			// Without any cached transforms, the layer and its parent are both in document space
+			return LayerRelativeTransform {
+				transform: DAffine2::IDENTITY,
+				relative_to_parent: true,
+			};
+		};
+		let parent_to_viewport = match layer.parent(self) {
+			None | Some(LayerNodeIdentifier::ROOT) => {
+				return LayerRelativeTransform {
+					relative_to_parent: true,
+					..accumulated
+				}
+			}
+			Some(parent) => self.cached_transform_to_viewport(parent.to_node()).map(|(_, transform)| transform),
+		};
+		match parent_to_viewport.filter(|&transform| is_invertible(transform)) {
+			Some(parent_to_viewport) => LayerRelativeTransform {
+				transform: parent_to_viewport.inverse() * transform_to_viewport,
+				relative_to_parent: true,
+			},
+			None => accumulated,
+		}
+	}
+
+	/// The transform from the space the layer is placed in to viewport space, which excludes the transform of the layer itself unlike [`Self::transform_to_viewport`].
+	///
+	/// This is the footprint of the layer's own cached transform, which may be stale after a partial evaluation. Layers without a cached transform fall back to their full transform to viewport.
+	pub fn downstream_transform_to_viewport(&self, layer: LayerNodeIdentifier) -> DAffine2 {
+		self.upstream_transforms
+			.get(&layer.to_node())
+			.copied()
+			.map(|(footprint, _)| footprint.transform)
+			.unwrap_or_else(|| self.transform_to_viewport(layer))
+	}
+}
+
+/// A layer's transform relative to its parent layer, see [`DocumentMetadata::layer_relative_transform`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerRelativeTransform {
+	pub transform: DAffine2,
+	/// Is the transform relative to the parent layer? Otherwise the parent's transform wasn't cached, so this is the layer's accumulated transform to document space.
+	pub relative_to_parent: bool,
+}
+
+/// The parameters of the artboard's Artboard node, if its location and dimensions are given as values rather than by other nodes.
+fn read_artboard_info(artboard: LayerNodeIdentifier, network: &NodeNetwork) -> Option<ArtboardInfo> {
+	let input = |index| find_layer_node_input(artboard, network, "Artboard", index);
+	let (Some(&TaggedValue::IVec2(location)), Some(&TaggedValue::IVec2(dimensions))) = (input(1), input(2)) else {
+		return None;
+	};
+	Some(ArtboardInfo {
+		origin_document: location.as_dvec2(),
+		size: dimensions.as_dvec2(),
+		background: match input(3) {
+			Some(&TaggedValue::Color(color)) => Some(color),
+			_ => None,
+		},
+		clip_content: matches!(input(4), Some(&TaggedValue::Bool(true))),
+	})
+}
+
+pub fn is_artboard(layer: LayerNodeIdentifier, network: &NodeNetwork) -> bool {
+	network.upstream_flow_back_from_nodes(vec![layer.to_node()], true).any(|(node, _)| node.is_artboard())
+}
+
+/// Nodes feeding into the layer through any of their inputs, stopping at other layers
+fn upstream_content_nodes(layer: LayerNodeIdentifier, network: &NodeNetwork) -> Vec<NodeId> {
+	// The second input of a layer is the layer below it, rather than its content
+	let mut stack = network.nodes.get(&layer.to_node()).and_then(|node| node.inputs.first()?.as_node()).into_iter().collect::<Vec<_>>();
+	let mut visited = HashSet::new();
+	while let Some(node_id) = stack.pop() {
+		let Some(node) = network.nodes.get(&node_id).filter(|node| !node.is_layer()) else { continue };
+		if visited.insert(node_id) {
+			stack.extend(node.inputs.iter().filter_map(|input| input.as_node()));
+		}
+	}
+	visited.into_iter().collect()
+}
+
+/// Find an input value of the named node within the layer's primary flow, up until the next layer is reached
+fn find_layer_node_input<'a>(layer: LayerNodeIdentifier, network: &'a NodeNetwork, node_name: &str, index: usize) -> Option<&'a TaggedValue> {
+	network
+		.upstream_flow_back_from_nodes(vec![layer.to_node()], true)
+		.skip(1)
+		.take_while(|(node, _)| !node.is_layer())
+		.find(|(node, _)| node.name == node_name)
+		.and_then(|(node, _)| node.inputs.get(index))
+		.and_then(NodeInput::as_value)
+}
+
+/// Classify a non-folder layer by the first node generating its content, found along its primary flow before reaching another layer
+fn layer_content_kind(layer: LayerNodeIdentifier, network: &NodeNetwork) -> LayerClassification {
+	network
+		.upstream_flow_back_from_nodes(vec![layer.to_node()], true)
+		.skip(1)
+		.take_while(|(node, _)| !node.is_layer())
+		.find_map(|(node, _)| match node.name.as_str() {
+			"Text" => Some(LayerClassification::Text),
+			"Image" => Some(LayerClassification::Raster),
+			"Shape" => Some(LayerClassification::Vector),
+			_ => None,
+		})
+		.unwrap_or(LayerClassification::Other)
+}
+
+pub fn is_folder(layer: LayerNodeIdentifier, network: &NodeNetwork) -> bool {
+	network.nodes.get(&layer.to_node()).and_then(|node| node.inputs.first()).is_some_and(|input| input.as_node().is_none())
+		|| network
+			.upstream_flow_back_from_nodes(vec![layer.to_node()], true)
+			.skip(1)
+			.any(|(node, _)| node.is_artboard() || node.is_layer())
+}
+
+// click targets
+impl DocumentMetadata {
+	/// Update the cached click targets of the layers
+	///
+	/// Layers missing from the layer tree, such as from a graph newer than the last [`Self::load_structure`], are left out and returned sorted so they can be sent again once the structure catches up.
+	pub fn update_click_targets(&mut self, new_click_targets: HashMap<LayerNodeIdentifier, impl Into<Arc<Vec<ClickTarget>>>>) -> Vec<LayerNodeIdentifier> {
+		self.update_click_targets_shared(new_click_targets.into_iter().map(|(layer, click_targets)| (layer, click_targets.into())).collect())
+	}
+
+	/// Update the cached click targets of the layers, where layers with identical geometry may share a single allocation. Layers missing from the layer tree are rejected like [`Self::update_click_targets`].
+	pub fn update_click_targets_shared(&mut self, mut new_click_targets: HashMap<LayerNodeIdentifier, Arc<Vec<ClickTarget>>>) -> Vec<LayerNodeIdentifier> {
+		let rejected = self.reject_click_targets_outside_structure(&mut new_click_targets);
+		let removed = self.click_targets.keys().filter(|layer| !new_click_targets.contains_key(layer));
+		let modified = new_click_targets
+			.iter()
+			.filter(|(layer, click_targets)| !self.click_targets.get(layer).is_some_and(|old| Arc::ptr_eq(old, click_targets)))
+			.map(|(layer, _)| layer);
+		let changed = removed.chain(modified).copied().collect::<Vec<_>>();
+		self.click_targets = Arc::new(new_click_targets);
+		self.clear_text_hit_regions(&changed);
+		self.clear_render_padding(&changed);
+		self.mark_layers_changed(changed);
+		self.debug_assert_click_targets_in_structure();
+		rejected
+	}
+
+	/// Update the cached click targets of only the specified layers, keeping the rest. Layers missing from the layer tree are rejected like [`Self::update_click_targets`].
+	pub fn update_click_targets_partial(&mut self, new_click_targets: HashMap<LayerNodeIdentifier, impl Into<Arc<Vec<ClickTarget>>>>) -> Vec<LayerNodeIdentifier> {
+		let mut new_click_targets = new_click_targets.into_iter().map(|(layer, click_targets)| (layer, click_targets.into())).collect();
+		let rejected = self.reject_click_targets_outside_structure(&mut new_click_targets);
+		let changed = new_click_targets.keys().copied().collect::<Vec<_>>();
+		Arc::make_mut(&mut self.click_targets).extend(new_click_targets);
+		self.clear_text_hit_regions(&changed);
+		self.clear_render_padding(&changed);
+		self.mark_layers_changed(changed);
+		self.debug_assert_click_targets_in_structure();
+		rejected
+	}
+
+	/// Remove the click targets of layers missing from the layer tree, returning those layers sorted.
+	fn reject_click_targets_outside_structure(&self, new_click_targets: &mut HashMap<LayerNodeIdentifier, Arc<Vec<ClickTarget>>>) -> Vec<LayerNodeIdentifier> {
+		let mut rejected = new_click_targets.keys().copied().filter(|layer| !self.structure.contains_key(layer)).collect::<Vec<_>>();
+		for layer in &rejected {
+			new_click_targets.remove(layer);
+		}
+		rejected.sort_unstable();
+		rejected
+	}
+
+	/// Every layer with click targets is in the layer tree, so code going through the click targets can rely on [`Self::layer_exists`].
+	fn debug_assert_click_targets_in_structure(&self) {
+		debug_assert!(
+			self.click_targets.keys().all(|layer| self.structure.contains_key(layer)),
+			"click targets are stored for layers missing from the layer tree"
+		);
+	}
+
+	/// Modify the cached click targets of a single layer in place, marking the layer as changed. Click targets shared with other layers or readers are copied first.
+	pub fn click_targets_mut(&mut self, layer: LayerNodeIdentifier) -> Option<&mut Vec<ClickTarget>> {
+		if !self.click_targets.contains_key(&layer) {
+			return None;
+		}
+		self.mark_layers_changed([layer]);
+		Arc::make_mut(&mut self.click_targets).get_mut(&layer).map(Arc::make_mut)
+	}
+
+	/// Replace the cached click targets of a single layer, keeping the rest. A layer missing from the layer tree is rejected like [`Self::update_click_targets`], returning whether the click targets were inserted.
+	pub fn insert_click_targets(&mut self, layer: LayerNodeIdentifier, click_targets: impl Into<Arc<Vec<ClickTarget>>>) -> bool {
+		let mut new_click_targets = HashMap::from([(layer, click_targets.into())]);
+		if !self.reject_click_targets_outside_structure(&mut new_click_targets).is_empty() {
+			return false;
+		}
+		Arc::make_mut(&mut self.click_targets).extend(new_click_targets);
+		self.clear_text_hit_regions(&[layer]);
+		self.clear_render_padding(&[layer]);
+		self.mark_layers_changed([layer]);
+		self.debug_assert_click_targets_in_structure();
+		true
+	}
+
+	/// Remove the cached click targets of a single layer
+	pub fn remove_click_targets(&mut self, layer: LayerNodeIdentifier) {
+		if self.click_targets.contains_key(&layer) {
+			Arc::make_mut(&mut self.click_targets).remove(&layer);
+			self.clear_text_hit_regions(&[layer]);
+			self.clear_render_padding(&[layer]);
+			self.mark_layers_changed([layer]);
+		}
+	}
+
+	/// Set how far the layer draws outside its click targets on every side in its own space, such as half the width of its stroke. It is cleared when its click targets are replaced.
+	pub fn set_layer_render_padding(&mut self, layer: LayerNodeIdentifier, padding: f64) {
+		self.set_layer_render_padding_sides(layer, [padding; 4]);
+	}
+
+	/// Set how far the layer draws outside its click targets on the left, top, right and bottom sides in its own space, such as for a drop shadow. Negative padding is treated as none.
+	pub fn set_layer_render_padding_sides(&mut self, layer: LayerNodeIdentifier, sides: [f64; 4]) {
+		let sides = sides.map(|side| if side.is_finite() { side.max(0.) } else { 0. });
+		if self.layer_render_padding(layer) == sides || !self.structure.contains_key(&layer) {
+			return;
+		}
+		if sides == [0.; 4] {
+			Arc::make_mut(&mut self.render_padding).remove(&layer);
+		} else {
+			Arc::make_mut(&mut self.render_padding).insert(layer, sides);
+		}
+		self.mark_layers_changed([layer]);
+	}
+
+	/// The padding on the left, top, right and bottom sides of the layer set by [`Self::set_layer_render_padding_sides`], which is none unless set.
+	pub fn layer_render_padding(&self, layer: LayerNodeIdentifier) -> [f64; 4] {
+		self.render_padding.get(&layer).copied().unwrap_or_default()
+	}
+
+	fn clear_render_padding(&mut self, layers: &[LayerNodeIdentifier]) {
+		if layers.iter().any(|layer| self.render_padding.contains_key(layer)) {
+			let render_padding = Arc::make_mut(&mut self.render_padding);
+			for layer in layers {
+				render_padding.remove(layer);
+			}
+		}
+	}
+
+	/// Number of distinct click target allocations, which is lower than the number of layers with click targets when they are shared
+	pub fn click_target_memory_usage(&self) -> usize {
+		self.click_target_sharing().unique_allocations
+	}
+
+	/// How many layers reference click targets, split into those with an allocation of their own and those sharing one with other layers.
+	pub fn click_target_sharing(&self) -> ClickTargetSharing {
+		let mut references = HashMap::<_, usize>::new();
+		for click_targets in self.click_targets.values() {
+			*references.entry(Arc::as_ptr(click_targets)).or_default() += 1;
+		}
+		let unique_allocations = references.len();
+		let (shared_references, unshared_references) = references
+			.into_values()
+			.fold((0, 0), |(shared, unshared), count| if count > 1 { (shared + count, unshared) } else { (shared, unshared + 1) });
+		ClickTargetSharing {
+			unique_allocations,
+			shared_references,
+			unshared_references,
+		}
+	}
+
+	/// Get the bounding box of the click target of the specified layer in the specified transform space.
+	///
+	/// The control points are transformed before finding the extrema of each curve, so the bounds stay tight under rotation.
+	/// Click targets with non-finite bounds, such as from a degenerate transform, are left out.
+	pub fn bounding_box_with_transform(&self, layer: LayerNodeIdentifier, transform: DAffine2) -> Option<[DVec2; 2]> {
+		self.click_targets
+			.get(&layer)?
+			.iter()
+			.filter_map(|click_target| click_target.subpath.bounding_box_with_transform(transform))
+			.filter(|&bounds| Quad::bounds_are_valid(bounds))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// The bounding box like [`Self::bounding_box_with_transform`], with the extrema of the transformed curves found as closely as the precision asks for.
+	pub fn bounding_box_with_precision(&self, layer: LayerNodeIdentifier, transform: DAffine2, precision: BoundsPrecision) -> Option<[DVec2; 2]> {
+		if precision == BoundsPrecision::Fast {
+			return self.bounding_box_with_transform(layer, transform);
+		}
+		self.click_targets
+			.get(&layer)?
+			.iter()
+			.filter_map(|click_target| {
+				let curves = click_target
+					.subpath
+					.iter()
+					.map(|bezier| exact_bezier_bounds(bezier.apply_transformation(|point| transform.transform_point2(point))));
+				curves.reduce(Quad::combine_bounds)
+			})
+			.filter(|&bounds| Quad::bounds_are_valid(bounds))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// Calculate the corners of the bounding box but with a nonzero size.
+	///
+	/// If the layer bounds are `0` in either axis then they are changed to be `1`.
+	pub fn nonzero_bounding_box(&self, layer: LayerNodeIdentifier) -> [DVec2; 2] {
+		let [bounds_min, mut bounds_max] = self.bounding_box_with_transform(layer, DAffine2::IDENTITY).unwrap_or_default();
+
+		let bounds_size = bounds_max - bounds_min;
+		if bounds_size.x < 1e-10 {
+			bounds_max.x = bounds_min.x + 1.;
+		}
+		if bounds_size.y < 1e-10 {
+			bounds_max.y = bounds_min.y + 1.;
+		}
+
+		[bounds_min, bounds_max]
+	}
+
+	/// Like [`Self::nonzero_bounding_box`], but padding the axes with no size to cover `min_size_viewport_px` in the viewport, so the padding looks the same at any zoom or layer scale.
+	///
+	/// Axes that are collapsed in the viewport, where no padding in layer space could be seen, are padded by one unit.
+	pub fn nonzero_bounding_box_with_min(&self, layer: LayerNodeIdentifier, min_size_viewport_px: f64) -> [DVec2; 2] {
+		let [bounds_min, mut bounds_max] = self.bounding_box_with_transform(layer, DAffine2::IDENTITY).unwrap_or_default();
+		let transform = self.transform_to_viewport(layer);
+		let min_size = |axis: DVec2| {
+			let size = min_size_viewport_px / axis.length();
+			if size.is_finite() {
+				size
+			} else {
+				1.
+			}
+		};
+
+		let bounds_size = bounds_max - bounds_min;
+		if bounds_size.x < 1e-10 {
+			bounds_max.x = bounds_min.x + min_size(transform.matrix2.x_axis);
+		}
+		if bounds_size.y < 1e-10 {
+			bounds_max.y = bounds_min.y + min_size(transform.matrix2.y_axis);
+		}
+
+		[bounds_min, bounds_max]
+	}
+
+	/// The rotated rectangle of least area around the anchors of the layer's click targets in document space, along with its rotation in radians from `-π/4` to `π/4`.
+	///
+	/// One side of the minimal rectangle always lies along an edge of the convex hull, so each edge is tried in turn. Curves bulging beyond their anchors are not included.
+	/// Layers with fewer than three anchors that aren't in a line give their axis aligned bounds, made at least one unit in size, with a rotation of `0`.
+	pub fn min_area_bounding_quad(&self, layer: LayerNodeIdentifier) -> Option<(Quad, f64)> {
+		let transform = self.transform_to_document(layer);
+		let points = self
+			.click_targets
+			.get(&layer)?
+			.iter()
+			.flat_map(|click_target| click_target.subpath.anchors())
+			.map(|point| transform.transform_point2(point));
+		let hull = convex_hull(points.collect());
+		let [first, ..] = hull[..] else {
+			return None;
+		};
+
+		if hull.len() < 3 {
+			let [min, mut max] = hull.iter().fold([first, first], |[min, max], &point| [min.min(point), max.max(point)]);
+			let size = max - min;
+			if size.x < 1e-10 {
+				max.x = min.x + 1.;
+			}
+			if size.y < 1e-10 {
+				max.y = min.y + 1.;
+			}
+			return Some((Quad::from_box([min, max]), 0.));
+		}
+
+		let mut best: Option<(f64, f64, [DVec2; 2])> = None;
+		for (index, &start) in hull.iter().enumerate() {
+			let edge = hull[(index + 1) % hull.len()] - start;
+			// The rectangles along perpendicular edges are the same, so the angle is kept within a quarter turn
+			let angle = (edge.y.atan2(edge.x) + std::f64::consts::FRAC_PI_4).rem_euclid(std::f64::consts::FRAC_PI_2) - std::f64::consts::FRAC_PI_4;
+			let rotation = DAffine2::from_angle(-angle);
+			let rotated = hull.iter().map(|&point| rotation.transform_point2(point));
+			let [min, max] = rotated.fold([DVec2::INFINITY, DVec2::NEG_INFINITY], |[min, max], point| [min.min(point), max.max(point)]);
+			let area = (max - min).x * (max - min).y;
+			if best.map_or(true, |(best_area, ..)| area < best_area) {
+				best = Some((area, angle, [min, max]));
+			}
+		}
+		let (_, angle, bounds) = best?;
+		Some((DAffine2::from_angle(angle) * Quad::from_box(bounds), angle))
+	}
+
+	/// Get the bounding box of the click target of the specified layer in document space
+	pub fn bounding_box_document(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.bounding_box_with_transform(layer, self.transform_to_document(layer))
+	}
+
+	/// Get the bounding box of the click target of the specified layer in viewport space
+	pub fn bounding_box_viewport(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.bounding_box_with_transform(layer, self.transform_to_viewport(layer))
+	}
+
+	/// The tiles of a repeating grid that the document space bounds of the layer overlap, each with the part of the bounds within it in the tile's local space, as used to preview tiled patterns.
+	///
+	/// The grid has a tile corner at `origin`. Layers larger than a tile are split across every tile they overlap, see [`Quad::split_bounds_into_tiles`].
+	pub fn layer_tile_coverage(&self, layer: LayerNodeIdentifier, tile_size: DVec2, origin: DVec2) -> Vec<(IVec2, [DVec2; 2])> {
+		self.bounding_box_document(layer)
+			.map(|bounds| Quad::split_bounds_into_tiles(bounds, tile_size, origin))
+			.unwrap_or_default()
+	}
+
+	/// The [`Self::bounding_box_viewport`] of many layers at once, sharing the work of finding their transforms. Layers without bounds are left out.
+	pub fn bounding_boxes_viewport(&self, layers: impl Iterator<Item = LayerNodeIdentifier>) -> HashMap<LayerNodeIdentifier, [DVec2; 2]> {
+		self.transforms_to_viewport(layers)
+			.into_iter()
+			.filter_map(|(layer, transform)| self.bounding_box_with_transform(layer, transform).map(|bounds| (layer, bounds)))
+			.collect()
+	}
+
+	/// Get the bounding box of the click target of the specified layer in the local space of another layer, which need not be one of its ancestors.
+	///
+	/// The click targets themselves are transformed, so the box stays tight if the layers are rotated relative to each other.
+	pub fn bounding_box_in_layer_space(&self, layer: LayerNodeIdentifier, space: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.bounding_box_with_transform(layer, self.transform_from_layer_to_layer(layer, space)?)
+	}
+
+	/// Get the local bounding box of the click target of the specified layer as a quad in the local space of another layer.
+	pub fn bounding_quad_in_layer_space(&self, layer: LayerNodeIdentifier, space: LayerNodeIdentifier) -> Option<Quad> {
+		let bounds = self.bounding_box_with_transform(layer, DAffine2::IDENTITY)?;
+		Some(self.transform_from_layer_to_layer(layer, space)? * Quad::from_box(bounds))
+	}
+
+	/// Get the bounding box of the click targets of the specified layer and all its descendants in document space
+	pub fn subtree_bounding_box_document(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		std::iter::once(layer)
+			.chain(layer.decendants(self))
+			.filter_map(|layer| self.bounding_box_document(layer))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// Get the bounding box of the click targets of the specified layer and all its descendants in viewport space
+	pub fn subtree_bounding_box_viewport(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		std::iter::once(layer)
+			.chain(layer.decendants(self))
+			.filter_map(|layer| self.bounding_box_viewport(layer))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// Calculates the document bounds in viewport space
+	pub fn document_bounds_viewport_space(&self) -> Option<[DVec2; 2]> {
+		self.cached_document_bounds(true, true, false)
+	}
+
+	/// Calculates the document bounds in viewport space, excluding artboards
+	pub fn document_bounds_viewport_space_except_artboards(&self) -> Option<[DVec2; 2]> {
+		self.cached_document_bounds(true, false, false)
+	}
+
+	/// Calculates the document bounds in document space
+	pub fn document_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
+		self.cached_document_bounds(false, include_artboards, false)
+	}
+
+	/// Calculates the document bounds in document space of only the layers that draw something, see [`Self::is_non_rendering`], so exports don't get empty margins.
+	/// Unlike the other document bounds, these include the render padding of each layer so exports don't clip strokes and shadows.
+	pub fn rendered_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
+		self.cached_document_bounds(false, include_artboards, true)
+	}
+
+	/// Reuses the document bounds from a previous call unless the transforms, click targets, layer tree or viewport have changed since.
+	/// If `rendered_only`, layers that draw nothing are left out and the render padding of the others is included.
+	fn cached_document_bounds(&self, viewport_space: bool, include_artboards: bool, rendered_only: bool) -> Option<[DVec2; 2]> {
+		let key = CachedDocumentBounds {
+			generation: self.generation,
+			structure_generation: self.structure_generation,
+			document_to_viewport: self.document_to_viewport,
+			bounds: None,
+		};
+		let slot = viewport_space as usize * 4 + rendered_only as usize * 2 + include_artboards as usize;
+		let mut cache = self.document_bounds_cache.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some(cached) = cache[slot].filter(|cached| CachedDocumentBounds { bounds: None, ..*cached } == key) {
+			return cached.bounds;
+		}
+
+		let options = BoundsOptions {
+			include_artboards,
+			exclude_non_rendering: rendered_only,
+			include_render_padding: rendered_only,
+			..Default::default()
+		};
+		let bounds = self.combined_bounds(self.all_layers(), options, viewport_space);
+		// Bounds found while the viewport transform is collapsed are only as good as the cached transforms, so they are found again once it recovers
+		if is_invertible(self.document_to_viewport) {
+			cache[slot] = Some(CachedDocumentBounds { bounds, ..key });
+		}
+		bounds
+	}
+
+	/// Calculates the selected layer bounds in document space
+	pub fn selected_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
+		self.combined_bounds_document(
+			self.selected_layers(),
+			BoundsOptions {
+				include_artboards,
+				precision: BoundsPrecision::Exact,
+				..Default::default()
+			},
+		)
+	}
+
+	/// The combined bounds of the layers in document space, see [`BoundsOptions`] for which layers are included and how.
+	pub fn combined_bounds_document(&self, layers: impl Iterator<Item = LayerNodeIdentifier>, options: BoundsOptions) -> Option<[DVec2; 2]> {
+		self.combined_bounds(layers, options, false)
+	}
+
+	/// The combined bounds of the layers in viewport space, see [`BoundsOptions`] for which layers are included and how.
+	pub fn combined_bounds_viewport(&self, layers: impl Iterator<Item = LayerNodeIdentifier>, options: BoundsOptions) -> Option<[DVec2; 2]> {
+		self.combined_bounds(layers, options, true)
+	}
+
+	fn combined_bounds(&self, layers: impl Iterator<Item = LayerNodeIdentifier>, options: BoundsOptions, viewport_space: bool) -> Option<[DVec2; 2]> {
+		let layers = layers.filter(|&layer| options.include_artboards || !self.is_artboard(layer));
+		let layers = layers.flat_map(|layer| {
+			let descendants = options.use_subtree_for_folders.then(|| layer.decendants(self));
+			std::iter::once(layer).chain(descendants.into_iter().flatten())
+		});
+		layers
+			.filter(|&layer| options.include_hidden || !self.is_hidden(layer))
+			.filter(|&layer| !options.exclude_non_rendering || !self.is_non_rendering(layer))
+			.filter_map(|layer| {
+				let transform = if viewport_space { self.transform_to_viewport(layer) } else { self.transform_to_document(layer) };
+				let padding = options.include_render_padding.then(|| self.render_padding.get(&layer).copied()).flatten();
+				if options.tight {
+					let bounds = self.bounding_box_with_precision(layer, transform, options.precision)?;
+					Some(padding.map_or(bounds, |padding| pad_bounds(bounds, padding, transform)))
+				} else {
+					self.bounding_box_with_transform(layer, DAffine2::IDENTITY)
+						.map(|bounds| (transform * Quad::from_box(bounds)).bounding_box())
+						.map(|bounds| padding.map_or(bounds, |padding| pad_bounds(bounds, padding, transform)))
+				}
+			})
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// Are the document space bounds of the layer inside those of any artboard, allowing for rounding error? Layers without bounds have no content to be outside.
+	pub fn is_within_some_artboard(&self, layer: LayerNodeIdentifier) -> bool {
+		let Some(bounds) = self.bounding_box_document(layer) else {
+			return true;
+		};
+		self.artboards
+			.iter()
+			.filter_map(|&artboard| self.artboard_bounds_document(artboard))
+			.any(|artboard_bounds| bounds_within(bounds, artboard_bounds))
+	}
+
+	/// The bounds and contents of each artboard in the order of the layer tree, for exporting them separately.
+	///
+	/// The contents of an artboard nested in another belong only to the innermost one.
+	pub fn artboard_export_info(&self) -> Vec<ArtboardExportInfo> {
+		let mut artboards = Vec::new();
+		let mut artboard_indices = HashMap::new();
+		for layer in self.all_layers() {
+			if self.is_artboard(layer) {
+				artboard_indices.insert(layer, artboards.len());
+				artboards.push(ArtboardExportInfo {
+					artboard: layer,
+					bounds_document: self.artboard_bounds_document(layer),
+					content_layers: Vec::new(),
+					has_clipped_content: false,
+				});
+				continue;
+			}
+			// Artboards come before their contents in the layer tree, so the innermost one has already been added
+			let Some(&index) = layer.ancestors(self).skip(1).find_map(|ancestor| artboard_indices.get(&ancestor)) else {
+				continue;
+			};
+			let info = &mut artboards[index];
+			info.content_layers.push(layer);
+			if let (Some(bounds), Some(artboard_bounds)) = (self.bounding_box_document(layer), info.bounds_document) {
+				info.has_clipped_content |= !bounds_within(bounds, artboard_bounds);
+			}
+		}
+		artboards
+	}
+
+	/// The combined document space bounds of the visible layers that are not within any artboard, for warning that they are cropped from an export.
+	///
+	/// This is `None` if everything fits, or if there are no artboards to fit in.
+	pub fn content_outside_artboards_bounds(&self) -> Option<[DVec2; 2]> {
+		if self.artboards.is_empty() {
+			return None;
+		}
+		self.all_layers_except_artboards()
+			.filter(|&layer| !self.is_hidden(layer) && !self.is_within_some_artboard(layer))
+			.filter_map(|layer| self.bounding_box_document(layer))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// The bounds of the layer relative to the top left corner of the innermost artboard containing it, along with that artboard, such as for the position shown in the properties panel.
+	///
+	/// The bounds are in the artboard's own space, so for a rotated or scaled artboard they follow its axes rather than being offset from the corner of its document space bounding box.
+	/// Layers outside every artboard give their document space bounds with the root.
+	pub fn bounds_relative_to_artboard(&self, layer: LayerNodeIdentifier) -> Option<([DVec2; 2], LayerNodeIdentifier)> {
+		let (document_to_artboard, artboard) = self.document_to_artboard_relative(layer)?;
+		let bounds = self.bounding_box_with_transform(layer, document_to_artboard * self.transform_to_document(layer))?;
+		Some((bounds, artboard))
+	}
+
+	/// A document space point relative to the top left corner of the innermost artboard containing the layer, in the same space as [`Self::bounds_relative_to_artboard`].
+	pub fn point_relative_to_artboard(&self, layer: LayerNodeIdentifier, point_document: DVec2) -> Option<DVec2> {
+		let (document_to_artboard, _) = self.document_to_artboard_relative(layer)?;
+		Some(document_to_artboard.transform_point2(point_document))
+	}
+
+	/// The transform from document space to the space of the innermost artboard containing the layer, shifted to put the top left corner of the artboard at the origin.
+	///
+	/// This is the identity with the root for layers outside every artboard, and `None` if the artboard's transform can't be inverted.
+	fn document_to_artboard_relative(&self, layer: LayerNodeIdentifier) -> Option<(DAffine2, LayerNodeIdentifier)> {
+		let Some(artboard) = layer.ancestors(self).skip(1).find(|&ancestor| self.is_artboard(ancestor)) else {
+			return Some((DAffine2::IDENTITY, LayerNodeIdentifier::ROOT));
+		};
+		let artboard_to_document = self.transform_to_document(artboard);
+		if artboard_to_document.matrix2.determinant() == 0. {
+			return None;
+		}
+		let [corner, _] = self.artboard_bounds_local(artboard).unwrap_or_default();
+		Some((DAffine2::from_translation(-corner) * artboard_to_document.inverse(), artboard))
+	}
+
+	/// The center of the layer's bounds in document space, covering the whole subtree of folders.
+	pub fn layer_center_document(&self, layer: LayerNodeIdentifier) -> Option<DVec2> {
+		let options = BoundsOptions {
+			use_subtree_for_folders: true,
+			..Default::default()
+		};
+		let [min, max] = self.combined_bounds_document(std::iter::once(layer), options)?;
+		Some((min + max) / 2.)
+	}
+
+	/// The mean of the [`Self::layer_center_document`] of each selected layer except artboards, such as for distributing by centers.
+	pub fn selection_centroid_document(&self) -> Option<DVec2> {
+		let centers = self.selected_layers_except_artboards().filter_map(|layer| self.layer_center_document(layer)).collect::<Vec<_>>();
+		(!centers.is_empty()).then(|| centers.iter().sum::<DVec2>() / centers.len() as f64)
+	}
+
+	/// The center of the combined bounds of the selected layers except artboards, which unlike [`Self::selection_centroid_document`] isn't weighted towards clusters of layers.
+	pub fn selection_bounds_center_document(&self) -> Option<DVec2> {
+		let options = BoundsOptions {
+			use_subtree_for_folders: true,
+			include_artboards: false,
+			..Default::default()
+		};
+		let [min, max] = self.combined_bounds_document(self.selected_layers(), options)?;
+		Some((min + max) / 2.)
+	}
+
+	/// A footprint covering the viewport extent of the selected layers (including the contents of selected folders), padded by some viewport pixels.
+	///
+	/// The output resolution is the padded viewport size multiplied by `viewport_resolution_scale`, and is at least 1x1 pixels even for zero area selections.
+	pub fn selection_footprint(&self, viewport_resolution_scale: f64, padding_px: f64) -> Option<Footprint> {
+		let options = BoundsOptions {
+			use_subtree_for_folders: true,
+			..Default::default()
+		};
+		let [min, max] = self.combined_bounds_viewport(self.selected_layers(), options)?;
+		let [min, max] = [min - DVec2::splat(padding_px), max + DVec2::splat(padding_px)];
+
+		let resolution = ((max - min) * viewport_resolution_scale).ceil().max(DVec2::ONE);
+		let transform = DAffine2::from_scale(DVec2::splat(viewport_resolution_scale)) * DAffine2::from_translation(-min) * self.document_to_viewport;
+		Some(Footprint {
+			transform,
+			resolution: resolution.as_uvec2(),
+			..Default::default()
+		})
+	}
+
+	/// A square region of document space framing the layer (with the contents of folders) for its thumbnail, so thumbnails of differently shaped layers are framed alike.
+	///
+	/// The tight bounds, including any render padding, are grown along their shorter axis to a square around their center, then by `margin_fraction` of the square's side on each edge.
+	/// Bounds with no size are framed as a one unit square, like [`Self::nonzero_bounding_box`].
+	pub fn thumbnail_bounds_document(&self, layer: LayerNodeIdentifier, margin_fraction: f64) -> Option<[DVec2; 2]> {
+		let options = BoundsOptions {
+			use_subtree_for_folders: true,
+			include_render_padding: true,
+			..Default::default()
+		};
+		let [min, max] = self.combined_bounds_document(std::iter::once(layer), options)?;
+		let side = (max - min).max_element();
+		let side = if side < 1e-10 { 1. } else { side };
+		let half_extent = side * (0.5 + margin_fraction.max(0.));
+		let center = (min + max) / 2.;
+		Some([center - DVec2::splat(half_extent), center + DVec2::splat(half_extent)])
+	}
+
+	/// A footprint rendering the [`Self::thumbnail_bounds_document`] of the layer, with a small margin, into a square of `output_px` pixels.
+	pub fn thumbnail_footprint(&self, layer: LayerNodeIdentifier, output_px: u32) -> Option<Footprint> {
+		const MARGIN_FRACTION: f64 = 0.05;
+
+		let [min, max] = self.thumbnail_bounds_document(layer, MARGIN_FRACTION)?;
+		let output_px = output_px.max(1);
+		let scale = output_px as f64 / (max.x - min.x);
+		Some(Footprint {
+			transform: DAffine2::from_scale(DVec2::splat(scale)) * DAffine2::from_translation(-min),
+			resolution: glam::UVec2::splat(output_px),
+			..Default::default()
+		})
+	}
+
+	/// The viewport bounds of the selected layers in physical pixels, as the minimum corner and size, such as for taking a screenshot of the selection.
+	///
+	/// Folders are covered by the bounds of their contents. Returns `None` if nothing selected has bounds, see [`Quad::to_pixel_rect`].
+	pub fn selection_pixel_rect(&self, device_pixel_ratio: f64, mode: PixelRounding) -> Option<([i64; 2], [i64; 2])> {
+		let options = BoundsOptions {
+			use_subtree_for_folders: true,
+			..Default::default()
+		};
+		let bounds = self.combined_bounds_viewport(self.selected_layers(), options)?;
+		Quad::from_box(bounds).to_pixel_rect(device_pixel_ratio, mode)
+	}
+
+	/// The viewport space quad of the transform gizmo around the selected layers (with the contents of selected folders), rotated into the frame chosen by the basis.
+	///
+	/// The frame is rotated by the document space rotation of the basis layer, as it appears in the viewport, and the quad is the smallest one in that frame
+	/// enclosing the tight bounds of every selected layer. It is axis aligned in the viewport if the basis layer has no cached transform of its own or from its ancestors.
+	pub fn selection_gizmo_quad(&self, basis: GizmoBasis) -> Option<Quad> {
+		let basis_layer = match basis {
+			GizmoBasis::Viewport => None,
+			GizmoBasis::FirstSelected => self.selected_layer_ids().next(),
+			GizmoBasis::LastSelected => self.selected_layer_ids().last(),
+			GizmoBasis::ActiveLayer(layer) => Some(layer),
+		};
+		let direction_document = basis_layer
+			.filter(|&layer| self.newest_cached_transform_to_viewport(layer).is_some())
+			.and_then(|layer| self.transform_to_document(layer).matrix2.x_axis.try_normalize());
+		let direction_viewport = direction_document.and_then(|direction| self.document_to_viewport.transform_vector2(direction).try_normalize());
+		let frame = direction_viewport.map_or(DAffine2::IDENTITY, |direction| DAffine2::from_angle(direction.y.atan2(direction.x)));
+
+		let viewport_to_frame = frame.inverse();
+		let bounds = self
+			.selected_layers()
+			.flat_map(|layer| std::iter::once(layer).chain(layer.decendants(self)))
+			.filter_map(|layer| self.bounding_box_with_transform(layer, viewport_to_frame * self.transform_to_viewport(layer)))
+			.reduce(Quad::combine_bounds)?;
+		Some(frame * Quad::from_box(bounds))
+	}
+
+	pub fn layer_outline<'a>(&'a self, layer: LayerNodeIdentifier) -> impl Iterator<Item = &'a bezier_rs::Subpath<ManipulatorGroupId>> {
+		static EMPTY: Vec<ClickTarget> = Vec::new();
+		let click_targets = self.click_targets.get(&layer).map_or(&EMPTY, Arc::as_ref);
+		click_targets.iter().map(|click_target| &click_target.subpath)
+	}
+
+	/// Does the layer have any cached click targets to draw or hit test?
+	pub fn has_click_targets(&self, layer: LayerNodeIdentifier) -> bool {
+		self.click_targets.get(&layer).is_some_and(|click_targets| !click_targets.is_empty())
+	}
+
+	/// The layer's outline like [`Self::layer_outline`], or a rectangle of the [`Self::nonzero_bounding_box`] if it has no click targets (such as while it is loading), so there is always something to draw.
+	pub fn layer_outline_or_bounds(&self, layer: LayerNodeIdentifier) -> impl Iterator<Item = bezier_rs::Subpath<ManipulatorGroupId>> + '_ {
+		let bounds = (!self.has_click_targets(layer)).then(|| bezier_rs::Subpath::from_anchors(Quad::from_box(self.nonzero_bounding_box(layer)).0, true));
+		self.layer_outline(layer).cloned().chain(bounds)
+	}
+
+	/// Find where the edges of a viewport space quad, such as the selection marquee, cross the click targets of each layer except artboards.
+	///
+	/// The points are in viewport space and layers the quad doesn't cross are omitted.
+	pub fn selection_quad_intersections(&self, quad: Quad) -> Vec<(LayerNodeIdentifier, Vec<DVec2>)> {
+		self.all_layers_except_artboards()
+			.filter_map(|layer| self.click_targets.get(&layer).map(|click_targets| (layer, click_targets)))
+			.map(|(layer, click_targets)| {
+				let transform = self.transform_to_viewport(layer);
+				let points = click_targets
+					.iter()
+					.flat_map(|click_target| quad.intersections_with_subpath(&click_target.subpath, transform))
+					.collect::<Vec<_>>();
+				(layer, points)
+			})
+			.filter(|(_, points)| !points.is_empty())
+			.collect()
+	}
+
+	/// Find the layers, except artboards, selected by a viewport space marquee quad, in z-order.
+	///
+	/// Folders are tested as a rectangle covering the bounds of their whole subtree.
+	pub fn layers_in_marquee(&self, quad: Quad, mode: MarqueeMode, scope: HitScope) -> Vec<LayerNodeIdentifier> {
+		let in_scope = self.hit_scope_filter(scope);
+		self.all_layers_except_artboards()
+			.filter(|&layer| in_scope(layer))
+			.filter(|&layer| {
+				let bounds = if self.is_folder(layer) {
+					self.subtree_bounding_box_viewport(layer)
+				} else {
+					self.bounding_box_viewport(layer)
+				};
+				let Some(bounds) = bounds else { return false };
+				match mode {
+					MarqueeMode::Touch if self.is_folder(layer) => {
+						let click_target = ClickTarget {
+							subpath: bezier_rs::Subpath::new_rect(bounds[0], bounds[1]),
+							stroke_width: 0.,
+						};
+						click_target.intersect_rectangle(quad, DAffine2::IDENTITY)
+					}
+					MarqueeMode::Touch => {
+						let transform = self.transform_to_viewport(layer);
+						self.click_targets
+							.get(&layer)
+							.is_some_and(|click_targets| click_targets.iter().any(|click_target| click_target.intersect_rectangle(quad, transform)))
+					}
+					MarqueeMode::Contain => Quad::from_box(bounds).0.into_iter().all(|corner| quad.contains(corner)),
+					MarqueeMode::CenterInside => quad.contains((bounds[0] + bounds[1]) / 2.),
+				}
+			})
+			.collect()
+	}
+
+	/// Find the layers, except artboards, selected by a viewport space lasso polygon, in z-order. The polygon is closed back to its first point and may cross itself,
+	/// in which case the parts enclosed an odd number of times are inside it.
+	///
+	/// Layers with bounds outside those of the polygon are rejected and layers with bounds fully inside the polygon are accepted without looking at their click targets,
+	/// so only the layers touching the outline of the polygon are intersected with their curves. Folders are tested as a rectangle covering the bounds of their whole subtree.
+	pub fn lasso_select(&self, polygon_viewport: &[DVec2], mode: MarqueeMode) -> Vec<LayerNodeIdentifier> {
+		if polygon_viewport.len() < 3 {
+			return Vec::new();
+		}
+		let polygon_bounds = polygon_viewport
+			.iter()
+			.fold([DVec2::INFINITY, DVec2::NEG_INFINITY], |[min, max], &point| [min.min(point), max.max(point)]);
+		self.all_layers_except_artboards()
+			.filter(|&layer| {
+				let bounds = if self.is_folder(layer) {
+					self.subtree_bounding_box_viewport(layer)
+				} else {
+					self.bounding_box_viewport(layer)
+				};
+				let Some(bounds) = bounds else { return false };
+				if !bounds_overlap(bounds, polygon_bounds) {
+					return false;
+				}
+				let bounds_inside = || {
+					let corners = Quad::from_box(bounds).0;
+					corners.iter().all(|&corner| polygon_contains(polygon_viewport, corner))
+						&& !polygon_edges(polygon_viewport).any(|edge| quad_edges(corners).any(|side| segments_intersect(edge, side)))
+				};
+				match mode {
+					MarqueeMode::Touch if bounds_inside() => true,
+					MarqueeMode::Touch if self.is_folder(layer) => {
+						let corners = Quad::from_box(bounds).0;
+						polygon_edges(polygon_viewport).any(|edge| quad_edges(corners).any(|side| segments_intersect(edge, side)))
+							|| polygon_contains(polygon_viewport, corners[0])
+							|| Quad::from_box(bounds).contains(polygon_viewport[0])
+					}
+					MarqueeMode::Touch => self.click_targets_touch_polygon(layer, polygon_viewport),
+					MarqueeMode::Contain => bounds_inside(),
+					MarqueeMode::CenterInside => polygon_contains(polygon_viewport, (bounds[0] + bounds[1]) / 2.),
+				}
+			})
+			.collect()
+	}
+
+	/// Do the click targets of the layer cross the outline of the viewport space polygon, lie inside it, or enclose it?
+	fn click_targets_touch_polygon(&self, layer: LayerNodeIdentifier, polygon_viewport: &[DVec2]) -> bool {
+		let Some(click_targets) = self.click_targets.get(&layer) else { return false };
+		let transform = self.transform_to_viewport(layer);
+		if transform.matrix2.determinant().abs() <= f64::EPSILON {
+			return false;
+		}
+		let inverse = transform.inverse();
+		let polygon = polygon_viewport.iter().map(|&point| inverse.transform_point2(point)).collect::<Vec<_>>();
+		let edges = polygon_edges(&polygon).map(|(start, end)| bezier_rs::Bezier::from_linear_dvec2(start, end)).collect::<Vec<_>>();
+		click_targets.iter().any(|click_target| {
+			let subpath = &click_target.subpath;
+			subpath.iter().any(|segment| edges.iter().any(|edge| !segment.intersections(edge, None, None).is_empty()))
+				|| subpath.manipulator_groups().first().is_some_and(|group| polygon_contains(&polygon, group.anchor))
+				|| (subpath.closed() && subpath.contains_point(polygon[0]))
+		})
+	}
+
+	/// Find the layer to move the selection to in a direction from a layer on the canvas, such as with the arrow keys, comparing the centers of their bounds in document space.
+	///
+	/// Only layers with a center within `max_angle` radians either side of the direction are considered, and only visible and unlocked layers other than artboards, ancestors and descendants of `from`.
+	/// The candidate with the lowest `distance * (1 + 2 * deviation)` wins, where the deviation is the angle in radians away from the direction, so a layer slightly off to the side and
+	/// slightly closer loses to one straight ahead. Ties are won by the topmost layer.
+	pub fn nearest_layer_in_direction(&self, from: LayerNodeIdentifier, direction: DVec2, max_angle: f64) -> Option<LayerNodeIdentifier> {
+		const DEVIATION_WEIGHT: f64 = 2.;
+
+		let origin = self.layer_center_document(from)?;
+		if direction == DVec2::ZERO {
+			return None;
+		}
+		let mut best: Option<(f64, LayerNodeIdentifier)> = None;
+		for layer in self.all_layers_except_artboards() {
+			if layer.starts_with(from, self) || from.starts_with(layer, self) || self.is_hidden(layer) || self.is_locked(layer) {
+				continue;
+			}
+			let Some(offset) = self.layer_center_document(layer).map(|center| center - origin) else {
+				continue;
+			};
+			if offset == DVec2::ZERO {
+				continue;
+			}
+			let deviation = direction.angle_between(offset).abs();
+			if deviation > max_angle {
+				continue;
+			}
+			let score = offset.length() * (1. + DEVIATION_WEIGHT * deviation);
+			if !best.is_some_and(|(best_score, _)| score >= best_score) {
+				best = Some((score, layer));
+			}
+		}
+		best.map(|(_, layer)| layer)
+	}
+
+	/// Did the layer overlap the footprint it was last evaluated with, which covers the viewport from the origin to its resolution?
+	///
+	/// Returns `None` for layers without their own cached footprint or any click targets.
+	pub fn layer_was_in_footprint(&self, layer: LayerNodeIdentifier) -> Option<bool> {
+		let (footprint, _) = self.upstream_transforms.get(&layer.to_node())?;
+		let [min, max] = self.bounding_box_viewport(layer)?;
+		Some(max.cmpge(DVec2::ZERO).all() && min.cmple(footprint.resolution.as_dvec2()).all())
+	}
+
+	/// Layers entirely outside of the footprint they were last evaluated with, so were culled from rendering, see [`Self::layer_was_in_footprint`].
+	pub fn culled_layers(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.all_layers().filter(|&layer| self.layer_was_in_footprint(layer) == Some(false))
+	}
+
+	/// Find the layer with the click target closest to a viewport space point, with its distance, if within `max_distance` pixels.
+	///
+	/// The distances are measured in viewport space, so the tolerance covers the same distance on screen at any zoom. Use [`Self::viewport_px_to_layer_units`] to compare with distances within a layer.
+	///
+	/// A point inside a region from [`Self::chrome_region_at_point`] hits that layer at a distance of `0` before any click targets are tested, even for artboards.
+	/// Points inside a closed click target are at a distance of `0`. Artboards are excluded and the policy picks between the layers that are hit, see [`HitTestPolicy`].
+	pub fn nearest_layer(&self, point_viewport: DVec2, max_distance: f64, scope: HitScope, policy: HitTestPolicy) -> Option<(LayerNodeIdentifier, f64)> {
+		let in_scope = self.hit_scope_filter(scope);
+		if let Some(layer) = self.chrome_region_at_point(point_viewport).filter(|&layer| in_scope(layer)) {
+			return Some((layer, 0.));
+		}
+		let hits = self
+			.all_layers_except_artboards()
+			.enumerate()
+			.filter(|&(_, layer)| in_scope(layer))
+			.filter_map(|(z_index, layer)| self.bounding_box_viewport(layer).map(|bounds| (distance_to_bounds(bounds, point_viewport), z_index, layer, bounds)))
+			.filter(|&(lower_bound, ..)| lower_bound <= max_distance);
+		self.scratch.hit_candidates.with(|candidates| {
+			candidates.extend(hits);
+
+			if policy != HitTestPolicy::Topmost {
+				// Candidates are in z-order, so the first of any with an equal key is the topmost
+				let key = |&(_, _, layer, [min, max]): &HitCandidate| match policy {
+					HitTestPolicy::SmallestArea => (max - min).x * (max - min).y,
+					_ => -(layer.ancestors(self).count() as f64),
+				};
+				return candidates
+					.iter()
+					.filter_map(|candidate| Some((candidate, self.click_target_distance(candidate.2, point_viewport)?)))
+					.filter(|&(_, distance)| distance <= max_distance)
+					.fold(None, |best: Option<(&_, f64)>, (candidate, distance)| match best {
+						Some((best_candidate, _)) if key(best_candidate) <= key(candidate) => best,
+						_ => Some((candidate, distance)),
+					})
+					.map(|(candidate, distance)| (candidate.2, distance));
+			}
+
+			// The distance to a layer's bounding box is a lower bound on the distance to its click targets, so layers are visited best-first
+			// Layers with a lower bound equal to the best distance are still visited, as they may be at the same distance and above the best layer
+			candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+			let mut best: Option<(f64, usize, LayerNodeIdentifier)> = None;
+			for &(lower_bound, z_index, layer, _) in candidates.iter() {
+				if best.is_some_and(|(best_distance, ..)| lower_bound > best_distance) {
+					break;
+				}
+				let Some(distance) = self.click_target_distance(layer, point_viewport) else { continue };
+				if distance <= max_distance && !best.is_some_and(|(best_distance, best_z_index, _)| (best_distance, best_z_index) <= (distance, z_index)) {
+					best = Some((distance, z_index, layer));
+				}
+			}
+			best.map(|(distance, _, layer)| (layer, distance))
+		})
+	}
+
+	/// Distance in viewport space from a point to the nearest click target of the layer
+	fn click_target_distance(&self, layer: LayerNodeIdentifier, point_viewport: DVec2) -> Option<f64> {
+		let transform = self.transform_to_viewport(layer);
+		let point_layer = transform.inverse().transform_point2(point_viewport);
+		self.click_targets
+			.get(&layer)?
+			.iter()
+			.map(|click_target| {
+				if click_target.subpath.closed() && click_target.subpath.contains_point(point_layer) {
+					return 0.;
+				}
+				click_target
+					.subpath
+					.iter()
+					.map(|bezier| bezier.apply_transformation(|point| transform.transform_point2(point)))
+					.map(|bezier| bezier.evaluate(bezier_rs::TValue::Parametric(bezier.project(point_viewport, None))).distance(point_viewport))
+					.fold(f64::INFINITY, f64::min)
+			})
+			.reduce(f64::min)
+	}
+}
+
+/// An artboard and its contents, see [`DocumentMetadata::artboard_export_info`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, specta::Type)]
+pub struct ArtboardExportInfo {
+	pub artboard: LayerNodeIdentifier,
+	#[serde(rename = "boundsDocument")]
+	pub bounds_document: Option<[DVec2; 2]>,
+	/// The layers inside the artboard, excluding other artboards nested in it and their contents.
+	#[serde(rename = "contentLayers")]
+	pub content_layers: Vec<LayerNodeIdentifier>,
+	/// Does any of the content extend beyond the artboard, so would be cropped by exporting it?
+	#[serde(rename = "hasClippedContent")]
+	pub has_clipped_content: bool,
+}
+
+/// Which rotation the transform gizmo of the selection is aligned to, see [`DocumentMetadata::selection_gizmo_quad`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoBasis {
+	/// Aligned to the axes of the viewport.
+	#[default]
+	Viewport,
+	/// Aligned to the layer selected first.
+	FirstSelected,
+	/// Aligned to the layer selected most recently.
+	LastSelected,
+	/// Aligned to the given layer, which doesn't need to be selected.
+	ActiveLayer(LayerNodeIdentifier),
+}
+
+/// Is the point inside the polygon, counting the parts enclosed an odd number of times as inside?
+fn polygon_contains(polygon: &[DVec2], point: DVec2) -> bool {
+	polygon_edges(polygon)
+		.filter(|&(start, end)| (start.y > point.y) != (end.y > point.y))
+		.filter(|&(start, end)| point.x < start.x + (point.y - start.y) / (end.y - start.y) * (end.x - start.x))
+		.count()
+		% 2 == 1
+}
+
+/// Each edge of the polygon, including the one closing it back to its first point.
+fn polygon_edges(polygon: &[DVec2]) -> impl Iterator<Item = (DVec2, DVec2)> + '_ {
+	polygon.iter().copied().zip(polygon.iter().copied().cycle().skip(1))
+}
+
+fn quad_edges(corners: [DVec2; 4]) -> impl Iterator<Item = (DVec2, DVec2)> {
+	(0..4).map(move |index| (corners[index], corners[(index + 1) % 4]))
+}
+
+/// Do the line segments cross or touch?
+fn segments_intersect((a_start, a_end): (DVec2, DVec2), (b_start, b_end): (DVec2, DVec2)) -> bool {
+	let side = |start: DVec2, end: DVec2, point: DVec2| (end - start).perp_dot(point - start);
+	let on_segment = |start: DVec2, end: DVec2, point: DVec2| point.cmpge(start.min(end)).all() && point.cmple(start.max(end)).all();
+	let [d1, d2] = [side(b_start, b_end, a_start), side(b_start, b_end, a_end)];
+	let [d3, d4] = [side(a_start, a_end, b_start), side(a_start, a_end, b_end)];
+	if ((d1 > 0. && d2 < 0.) || (d1 < 0. && d2 > 0.)) && ((d3 > 0. && d4 < 0.) || (d3 < 0. && d4 > 0.)) {
+		return true;
+	}
+	(d1 == 0. && on_segment(b_start, b_end, a_start))
+		|| (d2 == 0. && on_segment(b_start, b_end, a_end))
+		|| (d3 == 0. && on_segment(a_start, a_end, b_start))
+		|| (d4 == 0. && on_segment(a_start, a_end, b_end))
+}
+
+/// Does the transform have a finite inverse?
+fn is_invertible(transform: DAffine2) -> bool {
+	let determinant = transform.matrix2.determinant();
+	determinant.is_finite() && determinant.abs() > f64::EPSILON && transform.translation.is_finite()
+}
+
+/// Grow bounds already transformed into another space by the left, top, right and bottom padding of the layer, which is laid out in the layer's own space before being transformed.
+///
+/// The padding reaches around the corners of the shape like a stroke does, so it is rounded there rather than square.
+/// This way a uniform padding grows the bounds of a rotated layer by the padding itself rather than by the diagonal of a square of padding.
+fn pad_bounds([min, max]: [DVec2; 2], [left, top, right, bottom]: [f64; 4], transform: DAffine2) -> [DVec2; 2] {
+	// How far the padding reaches along a direction in the layer's space
+	let reach = |direction: DVec2| {
+		let horizontal = direction.x * if direction.x < 0. { -left } else { right };
+		let vertical = direction.y * if direction.y < 0. { -top } else { bottom };
+		horizontal.hypot(vertical)
+	};
+	// Each axis of the other space is measured along the matching row of the linear part of the transform in the layer's space
+	let [x_axis, y_axis] = [transform.matrix2.row(0), transform.matrix2.row(1)];
+	let padding_min = DVec2::new(reach(-x_axis), reach(-y_axis));
+	let padding_max = DVec2::new(reach(x_axis), reach(y_axis));
+	[min - padding_min, max + padding_max]
+}
+
+/// Do the bounds overlap or touch?
+fn bounds_overlap([min, max]: [DVec2; 2], [other_min, other_max]: [DVec2; 2]) -> bool {
+	min.cmple(other_max).all() && other_min.cmple(max).all()
+}
+
+/// Are the inner bounds inside the outer bounds, allowing for rounding error?
+fn bounds_within([min, max]: [DVec2; 2], [outer_min, outer_max]: [DVec2; 2]) -> bool {
+	const EPSILON: f64 = 1e-6;
+	min.cmpge(outer_min - EPSILON).all() && max.cmple(outer_max + EPSILON).all()
+}
+
+// geometry fingerprints
+impl DocumentMetadata {
+	/// A hash of the shape of the layer's click targets in its own space, so layers drawn with the same shape have the same fingerprint wherever they are transformed to.
+	///
+	/// Coordinates are rounded to a millionth of a unit first so tiny floating point differences don't change it. This is `None` for layers without click targets.
+	pub fn geometry_fingerprint(&self, layer: LayerNodeIdentifier) -> Option<u64> {
+		use std::hash::{Hash, Hasher};
+
+		let click_targets = self.click_targets.get(&layer).filter(|click_targets| !click_targets.is_empty())?;
+		let mut fingerprints = self.geometry_fingerprints.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some(&(_, fingerprint)) = fingerprints.get(&layer).filter(|(found_from, _)| std::ptr::eq(found_from.as_ptr(), Arc::as_ptr(click_targets))) {
+			return Some(fingerprint);
+		}
+
+		let mut hasher = std::collections::hash_map::DefaultHasher::new();
+		quantized_geometry(click_targets).for_each(|value| value.hash(&mut hasher));
+		let fingerprint = hasher.finish();
+		fingerprints.insert(layer, (Arc::downgrade(click_targets), fingerprint));
+		Some(fingerprint)
+	}
+
+	/// The other layers with click targets of the same shape as the layer in their own spaces, such as copies of an icon, in layer tree order.
+	///
+	/// Layers with the same [`Self::geometry_fingerprint`] are compared in full, so ones that only share a hash aren't included.
+	pub fn layers_matching_geometry(&self, layer: LayerNodeIdentifier) -> Vec<LayerNodeIdentifier> {
+		let Some(fingerprint) = self.geometry_fingerprint(layer) else { return Vec::new() };
+		let geometry = |layer| self.click_targets.get(&layer).map_or(&[][..], |click_targets| &click_targets[..]);
+		self.all_layers()
+			.filter(|&other| other != layer && self.geometry_fingerprint(other) == Some(fingerprint))
+			.filter(|&other| quantized_geometry(geometry(layer)).eq(quantized_geometry(geometry(other))))
+			.collect()
+	}
+}
+
+/// The coordinates of the anchors and handles of the click targets rounded for [`DocumentMetadata::geometry_fingerprint`], with the structure of the subpaths.
+fn quantized_geometry(click_targets: &[ClickTarget]) -> impl Iterator<Item = i64> + '_ {
+	const EPSILON: f64 = 1e-6;
+
+	let quantize = |point: Option<DVec2>| point.map_or([i64::MIN; 2], |point| [(point.x / EPSILON).round() as i64, (point.y / EPSILON).round() as i64]);
+	click_targets.iter().flat_map(move |click_target| {
+		let groups = click_target.subpath.manipulator_groups();
+		let points = groups
+			.iter()
+			.flat_map(move |group| [Some(group.anchor), group.in_handle, group.out_handle].into_iter().flat_map(quantize));
+		[click_target.subpath.closed() as i64, groups.len() as i64].into_iter().chain(points)
+	})
+}
+
+// artboard info
+impl DocumentMetadata {
+	/// The parameters of the artboard, such as for drawing its name and background, without reading the node network.
+	pub fn artboard_info(&self, artboard: LayerNodeIdentifier) -> Option<&ArtboardInfo> {
+		self.artboard_info.get(&artboard)
+	}
+
+	/// Read the parameters of the artboard again after its Artboard node's inputs are changed without reloading the structure, returning whether they changed.
+	pub fn refresh_artboard_info(&mut self, artboard: LayerNodeIdentifier, graph: &NodeNetwork) -> bool {
+		let info = self.is_artboard(artboard).then(|| read_artboard_info(artboard, graph)).flatten();
+		if self.artboard_info.get(&artboard) == info.as_ref() {
+			return false;
+		}
+		match info {
+			Some(info) => Arc::make_mut(&mut self.artboard_info).insert(artboard, info),
+			None => Arc::make_mut(&mut self.artboard_info).remove(&artboard),
+		};
+		// The bounds of the artboard may have changed
+		self.mark_layers_changed([artboard]);
+		true
+	}
+
+	/// The rectangle of the artboard in its own space from its parameters, or from its click targets if they aren't known.
+	fn artboard_bounds_local(&self, artboard: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		match self.artboard_info(artboard) {
+			Some(info) => Some(Quad::sort_bounds([info.origin_document, info.origin_document + info.size])),
+			None => self.bounding_box_with_transform(artboard, DAffine2::IDENTITY),
+		}
+	}
+
+	fn artboard_bounds_document(&self, artboard: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		match self.artboard_info(artboard) {
+			Some(_) => Some((self.transform_to_document(artboard) * Quad::from_box(self.artboard_bounds_local(artboard)?)).bounding_box()),
+			None => self.bounding_box_document(artboard),
+		}
+	}
+}
+
+/// The parameters of an artboard, see [`DocumentMetadata::artboard_info`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArtboardInfo {
+	/// The corner of the artboard from its Location input, which is in document space unless the artboard is transformed.
+	pub origin_document: DVec2,
+	pub size: DVec2,
+	/// The background color, or `None` if it is given by another node.
+	pub background: Option<Color>,
+	/// Is the content of the artboard clipped to its bounds?
+	pub clip_content: bool,
+}
+
+// previous frame bounds
+impl DocumentMetadata {
+	/// Remember the current viewport bounds of every layer, to be compared against by [`Self::bounds_changed_significantly`] once the layers have been updated for the next frame.
+	pub fn commit_frame(&mut self) {
+		let bounds = self.bounding_boxes_viewport(self.all_layers());
+		self.previous_frame_bounds = Some(Arc::new(bounds));
+	}
+
+	/// The viewport bounds of the layer when [`Self::commit_frame`] was last called.
+	pub fn previous_bounding_box_viewport(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.previous_frame_bounds.as_ref()?.get(&layer).copied()
+	}
+
+	/// Has any edge of the layer's viewport bounds moved by more than `threshold_px` since the last [`Self::commit_frame`], so overlays can ignore sub-pixel jitter?
+	///
+	/// Layers gaining or losing their bounds have changed significantly, as have all layers with bounds before the first frame is committed.
+	pub fn bounds_changed_significantly(&self, layer: LayerNodeIdentifier, threshold_px: f64) -> bool {
+		match (self.previous_bounding_box_viewport(layer), self.bounding_box_viewport(layer)) {
+			(Some([previous_min, previous_max]), Some([min, max])) => (min - previous_min).abs().max((max - previous_max).abs()).max_element() > threshold_px,
+			(None, None) => false,
+			_ => true,
+		}
+	}
+}
+
+// chrome click targets
+impl DocumentMetadata {
+	/// Set the viewport space regions of UI drawn for the layer, such as an artboard's name above it, which select the layer when clicked.
+	///
+	/// They are forgotten for every layer once `document_to_viewport` changes, so should be registered again after each layout of the UI.
+	pub fn set_chrome_click_targets(&mut self, layer: LayerNodeIdentifier, regions: Vec<Quad>) {
+		if self.chrome_document_to_viewport != self.document_to_viewport {
+			self.chrome_document_to_viewport = self.document_to_viewport;
+			self.chrome_click_targets = Arc::default();
+		}
+		if !regions.is_empty() && self.layer_exists(layer) {
+			Arc::make_mut(&mut self.chrome_click_targets).insert(layer, regions);
+		} else if self.chrome_click_targets.contains_key(&layer) {
+			Arc::make_mut(&mut self.chrome_click_targets).remove(&layer);
+		}
+	}
+
+	/// The topmost layer with a UI region registered by [`Self::set_chrome_click_targets`] containing the viewport space point.
+	pub fn chrome_region_at_point(&self, point_viewport: DVec2) -> Option<LayerNodeIdentifier> {
+		if self.chrome_click_targets.is_empty() || self.chrome_document_to_viewport != self.document_to_viewport {
+			return None;
+		}
+		self.all_layers()
+			.find(|layer| self.chrome_click_targets.get(layer).is_some_and(|regions| regions.iter().any(|region| region.contains(point_viewport))))
+	}
+}
+
+// text hit regions
+impl DocumentMetadata {
+	/// Set the regions of a text layer in its own space that map to ranges of characters, such as one per line. They are cleared when its click targets are replaced.
+	pub fn set_text_hit_regions(&mut self, layer: LayerNodeIdentifier, regions: Vec<TextHitRegion>) {
+		Arc::make_mut(&mut self.text_hit_regions).insert(layer, regions);
+	}
+
+	/// The regions of a text layer set by [`Self::set_text_hit_regions`], for drawing the highlight of selected text.
+	pub fn text_hit_regions(&self, layer: LayerNodeIdentifier) -> &[TextHitRegion] {
+		self.text_hit_regions.get(&layer).map_or(&[], Vec::as_slice)
+	}
+
+	fn clear_text_hit_regions(&mut self, layers: &[LayerNodeIdentifier]) {
+		if layers.iter().any(|layer| self.text_hit_regions.contains_key(layer)) {
+			let text_hit_regions = Arc::make_mut(&mut self.text_hit_regions);
+			layers.iter().for_each(|layer| drop(text_hit_regions.remove(layer)));
+		}
+	}
+
+	/// The caret position in the text of the layer at a point in viewport space.
+	///
+	/// This is found within the region containing the point by the horizontal fraction along it, rounded to the nearest gap between characters.
+	pub fn text_position_at_point(&self, layer: LayerNodeIdentifier, point_viewport: DVec2) -> Option<usize> {
+		let transform = self.transform_to_viewport(layer);
+		if transform.matrix2.determinant().abs() <= f64::EPSILON {
+			return None;
+		}
+		let point = transform.inverse().transform_point2(point_viewport);
+		let region = self.text_hit_regions.get(&layer)?.iter().find(|region| region.bounds.contains(point))?;
+
+		let [start, end] = [region.bounds.0[0], region.bounds.0[1]];
+		let direction = end - start;
+		let fraction = ((point - start).dot(direction) / direction.length_squared()).clamp(0., 1.);
+		let characters = region.char_range.len();
+		Some(region.char_range.start + (fraction * characters as f64).round() as usize)
+	}
+}
+
+/// A region of a text layer in its own space, with the characters laid out from the first to the second corner of its bounds.
+#[derive(Debug, Clone)]
+pub struct TextHitRegion {
+	pub bounds: Quad,
+	pub char_range: std::ops::Range<usize>,
+}
+
+/// How a marquee selects layers, see [`DocumentMetadata::layers_in_marquee`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum MarqueeMode {
+	/// Layers that the marquee crosses or that enclose or are enclosed by it.
+	#[default]
+	Touch,
+	/// Layers with bounds fully inside the marquee.
+	Contain,
+	/// Layers with the center of their bounds inside the marquee.
+	CenterInside,
+}
+
+/// How [`DocumentMetadata::nearest_layer`] picks between the layers within range of the point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum HitTestPolicy {
+	/// The layer with the nearest click target, with ties won by the topmost layer.
+	#[default]
+	Topmost,
+	/// The layer with the smallest bounds, so small layers stacked on large ones can still be picked. Ties are won by the topmost layer.
+	SmallestArea,
+	/// The layer most deeply nested in folders. Ties are won by the topmost layer.
+	DeepestInTree,
+}
+
+/// Which layers can be hit by [`DocumentMetadata::nearest_layer`] and [`DocumentMetadata::layers_in_marquee`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum HitScope {
+	/// Any layer in the document.
+	#[default]
+	All,
+	/// Only layers in the isolation scope, see [`DocumentMetadata::is_in_isolation_scope`].
+	Isolation,
+	/// Only descendants of the layer, not including the layer itself, as used by [`DocumentMetadata::scoped`].
+	Subtree(LayerNodeIdentifier),
+}
+
+// isolation
+impl DocumentMetadata {
+	/// Edit only the folder and its contents, or stop with `None`. Layers that aren't in the layer tree also stop isolation.
+	///
+	/// Only hit tests with [`HitScope::Isolation`] and those of [`crate::document::Document`], such as its `click`, are restricted. Bounds are still found for every layer.
+	pub fn set_isolation(&mut self, layer: Option<LayerNodeIdentifier>) {
+		self.isolation_root = layer.filter(|&layer| self.layer_exists(layer));
+	}
+
+	/// The layer being edited on its own, if any.
+	pub fn isolation_root(&self) -> Option<LayerNodeIdentifier> {
+		self.isolation_root
+	}
+
+	/// Is the layer the isolation root or one of its descendants? All layers are in scope while nothing is isolated.
+	pub fn is_in_isolation_scope(&self, layer: LayerNodeIdentifier) -> bool {
+		self.isolation_root.map_or(true, |root| layer.starts_with(root, self))
+	}
+
+	fn is_in_hit_scope(&self, layer: LayerNodeIdentifier, scope: HitScope) -> bool {
+		match scope {
+			HitScope::All => true,
+			HitScope::Isolation => self.is_in_isolation_scope(layer),
+			HitScope::Subtree(root) => root.is_ancestor_of(layer, self),
+		}
+	}
+
+	/// Like [`Self::is_in_hit_scope`] for testing many layers, with the subtree intervals and the interval of the scope's root looked up once rather than for each layer.
+	fn hit_scope_filter(&self, scope: HitScope) -> impl Fn(LayerNodeIdentifier) -> bool + '_ {
+		let (root, include_root) = match scope {
+			HitScope::All => (None, true),
+			HitScope::Isolation => (self.isolation_root, true),
+			HitScope::Subtree(root) => (Some(root), false),
+		};
+		let intervals = root.and_then(|_| self.subtree_intervals());
+		let root_interval = root.zip(intervals.as_ref()).and_then(|(root, intervals)| intervals.get(&root).copied());
+		move |layer| {
+			let Some(root) = root else { return true };
+			if layer == root {
+				return include_root;
+			}
+			match (&intervals, root_interval) {
+				(Some(intervals), Some((start, end))) => intervals.get(&layer).is_some_and(|&(position, _)| (start..=end).contains(&position)),
+				_ => layer.starts_with(root, self),
+			}
+		}
+	}
+}
+
+// scoped views
+impl DocumentMetadata {
+	/// Treat the layer as the root of the document, such as while editing a symbol on its own, with queries restricted to its descendants. See [`ScopedMetadata`].
+	pub fn scoped(&self, root: LayerNodeIdentifier) -> ScopedMetadata<'_> {
+		ScopedMetadata { metadata: self, root }
+	}
+}
+
+/// A view of the [`DocumentMetadata`] where the descendants of a layer are the whole document, see [`DocumentMetadata::scoped`].
+///
+/// The document space of the view is the local space of the root, while viewport space is unchanged. Layers outside the scope are never returned,
+/// so a selection built from the hit tests of the view stays within it.
+#[derive(Debug, Clone, Copy)]
+pub struct ScopedMetadata<'a> {
+	metadata: &'a DocumentMetadata,
+	root: LayerNodeIdentifier,
+}
+
+impl<'a> ScopedMetadata<'a> {
+	/// The layer acting as the root of the document.
+	pub fn root(&self) -> LayerNodeIdentifier {
+		self.root
+	}
+
+	/// The whole document, outside of the scope.
+	pub fn metadata(&self) -> &'a DocumentMetadata {
+		self.metadata
+	}
+
+	/// Is the layer a descendant of the root?
+	pub fn contains(&self, layer: LayerNodeIdentifier) -> bool {
+		self.metadata.is_in_hit_scope(layer, HitScope::Subtree(self.root))
+	}
+
+	/// The descendants of the root in the same order as [`DocumentMetadata::all_layers`].
+	pub fn all_layers(&self) -> DecendantsIter<'a> {
+		self.root.decendants(self.metadata)
+	}
+
+	/// The selected layers within the scope, from the top of the layer tree to the bottom.
+	pub fn selected_layers(&self) -> impl Iterator<Item = LayerNodeIdentifier> + 'a {
+		let selected = self.metadata.selected_layer_ids().collect::<HashSet<_>>();
+		self.all_layers().filter(move |layer| selected.contains(layer))
+	}
+
+	/// The transform from the layer's local space to the local space of the root, or `None` for layers outside the scope or a root with a degenerate transform.
+	pub fn transform_to_document(&self, layer: LayerNodeIdentifier) -> Option<DAffine2> {
+		self.contains(layer).then(|| self.metadata.transform_from_layer_to_layer(layer, self.root)).flatten()
+	}
+
+	/// Get the bounding box of the click target of the layer in the local space of the root.
+	pub fn bounding_box_document(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.metadata.bounding_box_with_transform(layer, self.transform_to_document(layer)?)
+	}
+
+	/// Get the bounding box of the click target of the layer in viewport space.
+	pub fn bounding_box_viewport(&self, layer: LayerNodeIdentifier) -> Option<[DVec2; 2]> {
+		self.contains(layer).then(|| self.metadata.bounding_box_viewport(layer)).flatten()
+	}
+
+	/// The combined bounds of the layers in the scope in the local space of the root.
+	pub fn document_bounds_document_space(&self, include_artboards: bool) -> Option<[DVec2; 2]> {
+		self.all_layers()
+			.filter(|&layer| include_artboards || !self.metadata.is_artboard(layer))
+			.filter_map(|layer| self.bounding_box_document(layer))
+			.reduce(Quad::combine_bounds)
+	}
+
+	/// The combined bounds of the layers in the scope in viewport space.
+	pub fn document_bounds_viewport_space(&self) -> Option<[DVec2; 2]> {
+		self.all_layers().filter_map(|layer| self.bounding_box_viewport(layer)).reduce(Quad::combine_bounds)
+	}
+
+	/// The [`DocumentMetadata::nearest_layer`] within the scope.
+	pub fn nearest_layer(&self, point_viewport: DVec2, max_distance: f64, policy: HitTestPolicy) -> Option<(LayerNodeIdentifier, f64)> {
+		self.metadata.nearest_layer(point_viewport, max_distance, HitScope::Subtree(self.root), policy)
+	}
+
+	/// The [`DocumentMetadata::layers_in_marquee`] within the scope.
+	pub fn layers_in_marquee(&self, quad: Quad, mode: MarqueeMode) -> Vec<LayerNodeIdentifier> {
+		self.metadata.layers_in_marquee(quad, mode, HitScope::Subtree(self.root))
+	}
+}
+
+// layer panel
+impl DocumentMetadata {
+	/// Is the folder collapsed in the layer panel?
+	pub fn is_collapsed(&self, layer: LayerNodeIdentifier) -> bool {
+		self.collapsed.contains(&layer)
+	}
+
+	/// Collapse or expand a folder in the layer panel.
+	pub fn set_collapsed(&mut self, layer: LayerNodeIdentifier, collapsed: bool) {
+		self.collapse_generation += 1;
+		if collapsed {
+			Arc::make_mut(&mut self.collapsed).insert(layer);
+		} else {
+			Arc::make_mut(&mut self.collapsed).remove(&layer);
+		}
+	}
+
+	/// The collapsed folders, in no particular order.
+	pub fn collapsed_folders(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.collapsed.iter().copied()
+	}
+
+	/// Collapse exactly these folders, such as to keep the collapsed folders when the document is replaced by an undo.
+	pub fn set_collapsed_folders(&mut self, collapsed: impl IntoIterator<Item = LayerNodeIdentifier>) {
+		self.collapse_generation += 1;
+		self.collapsed = Arc::new(collapsed.into_iter().collect());
+	}
+
+	/// The folders containing a selected layer, which are marked in the layer panel while collapsed.
+	///
+	/// Selected folders with any contents are included, since their contents are selected with them as in [`Self::selected_layers_expanded`].
+	pub fn folders_with_selected_descendants(&self) -> HashSet<LayerNodeIdentifier> {
+		self.selected_descendant_folders().as_ref().clone()
+	}
+
+	/// Does the folder contain a selected layer? See [`Self::folders_with_selected_descendants`].
+	pub fn has_selected_descendant(&self, layer: LayerNodeIdentifier) -> bool {
+		self.selected_descendant_folders().contains(&layer)
+	}
+
+	/// Reuses the folders from a previous call unless the selection or the layer tree have changed since.
+	fn selected_descendant_folders(&self) -> Arc<HashSet<LayerNodeIdentifier>> {
+		let mut cache = self.selected_descendants_cache.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some((_, _, folders)) = cache
+			.as_ref()
+			.filter(|&&(structure_generation, selection_generation, _)| (structure_generation, selection_generation) == (self.structure_generation, self.selection_generation))
+		{
+			return folders.clone();
+		}
+
+		let mut folders = HashSet::new();
+		for layer in self.selected_layer_ids().filter(|&layer| self.layer_exists(layer)) {
+			let start = if layer.has_children(self) { Some(layer) } else { layer.parent(self) };
+			// Once a folder has been added, so have all of its ancestors
+			for ancestor in start.into_iter().flat_map(|start| start.ancestors(self)) {
+				if ancestor == LayerNodeIdentifier::ROOT || !folders.insert(ancestor) {
+					break;
+				}
+			}
+		}
+		let folders = Arc::new(folders);
+		*cache = Some((self.structure_generation, self.selection_generation, folders.clone()));
+		folders
+	}
+
+	/// The layers shown as rows of the layer panel from top to bottom, which are all the layers except the contents of collapsed folders.
+	pub fn visible_tree_iter(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		let mut layers = self.all_layers();
+		std::iter::from_fn(move || {
+			let layer = layers.next()?;
+			if self.is_collapsed(layer) {
+				layers.skip_subtree(layer);
+			}
+			Some(layer)
+		})
+	}
+
+	/// The rows of the layer panel in a window of the [`Self::visible_tree_iter`], so a large document can be scrolled through without sending every row to the frontend.
+	///
+	/// The window is cut short at the last row, and the rows are found again only after the layer tree changes or a folder is collapsed or expanded.
+	pub fn layer_panel_slice(&self, start_flattened_index: usize, count: usize, options: PanelSliceOptions) -> LayerPanelSlice {
+		let visible_rows;
+		let (rows, total_rows): (Box<dyn Iterator<Item = LayerNodeIdentifier>>, usize) = if options.expand_all {
+			(Box::new(self.all_layers().skip(start_flattened_index).take(count)), self.structure.len() - 1)
+		} else {
+			visible_rows = self.visible_rows();
+			let window = visible_rows.iter().copied().skip(start_flattened_index).take(count);
+			(Box::new(window), visible_rows.len())
+		};
+		let rows = rows
+			.map(|layer| LayerPanelRow {
+				id: layer,
+				depth: layer.depth(self),
+				has_children: layer.has_children(self),
+				collapsed: self.is_collapsed(layer),
+				selected: self.selected_nodes.contains(&layer.to_node()),
+				kind: self.classification(layer),
+				name: self.layer_name(layer).map(str::to_string),
+			})
+			.collect();
+		LayerPanelSlice {
+			start_flattened_index,
+			rows,
+			total_rows,
+		}
+	}
+
+	/// Reuses the rows from a previous call unless the layer tree has changed or a folder was collapsed or expanded since.
+	fn visible_rows(&self) -> Arc<Vec<LayerNodeIdentifier>> {
+		let mut cache = self.visible_rows_cache.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some((_, _, rows)) = cache
+			.as_ref()
+			.filter(|&&(structure_generation, collapse_generation, _)| (structure_generation, collapse_generation) == (self.structure_generation, self.collapse_generation))
+		{
+			return rows.clone();
+		}
+
+		let rows = Arc::new(self.visible_tree_iter().collect::<Vec<_>>());
+		*cache = Some((self.structure_generation, self.collapse_generation, rows.clone()));
+		rows
+	}
+
+	/// Build the full layer tree, starting from the root, so it can be sent to the frontend layer panel in one message.
+	pub fn serialize_tree(&self) -> LayerTreeNode {
+		self.serialize_subtree(self.root(), None)
+	}
+
+	/// Build the layer tree from the root, omitting the children of layers nested deeper than `max_depth`. A `max_depth` of `0` yields only the root.
+	pub fn serialize_tree_to_depth(&self, max_depth: usize) -> LayerTreeNode {
+		self.serialize_subtree(self.root(), Some(max_depth))
+	}
+
+	/// Build the layer tree starting from an arbitrary layer, optionally limited to a maximum depth below that layer.
+	pub fn serialize_subtree(&self, layer: LayerNodeIdentifier, max_depth: Option<usize>) -> LayerTreeNode {
+		let children = match max_depth {
+			Some(0) => Vec::new(),
+			_ => layer.children(self).map(|child| self.serialize_subtree(child, max_depth.map(|depth| depth - 1))).collect(),
+		};
+		LayerTreeNode {
+			id: layer,
+			children,
+			is_folder: self.is_folder(layer),
+			is_artboard: self.is_artboard(layer),
+			selected: self.selected_nodes.contains(&layer.to_node()),
+			expanded: layer.has_children(self).then(|| !self.is_collapsed(layer)),
+		}
+	}
+}
+
+// node usage
+impl DocumentMetadata {
+	/// Choose whether [`Self::load_structure`] records which layers each node feeds into, for [`Self::layers_using_node`]. This takes effect at the next load.
+	pub fn set_node_usage_indexing(&mut self, enabled: bool) {
+		match (enabled, &self.node_usage) {
+			(true, None) => self.node_usage = Some(Arc::default()),
+			(false, Some(_)) => self.node_usage = None,
+			_ => {}
+		}
+	}
+
+	/// Layers whose content is fed by the node, in layer tree order. The node does not cross into other layers, so ancestors of the yielded layers are affected too.
+	///
+	/// Nothing is yielded unless enabled with [`Self::set_node_usage_indexing`] before the structure was loaded.
+	pub fn layers_using_node(&self, node: NodeId) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		let layers = self.node_usage.as_ref().and_then(|node_usage| node_usage.get(&node));
+		self.all_layers().filter(move |layer| layers.is_some_and(|layers| layers.contains(layer)))
+	}
+}
+
+// subtree intervals
+impl DocumentMetadata {
+	/// Choose whether to number the layers in tree order so [`LayerNodeIdentifier::starts_with`] can compare two numbers rather than walking up the ancestors, such as for the many checks of a marquee selection.
+	/// The numbers are found after the structure is loaded, and again by the hit tests or [`Self::subtree_interval`] after the tree is modified.
+	pub fn set_subtree_interval_indexing(&mut self, enabled: bool) {
+		match (enabled, &self.subtree_intervals) {
+			(true, None) => {
+				self.subtree_intervals = Some(SubtreeIntervals::default());
+				self.subtree_intervals();
+			}
+			(false, Some(_)) => self.subtree_intervals = None,
+			_ => {}
+		}
+	}
+
+	/// The position of the layer in a walk of the layer tree from the top, where the root is `0`, and the position of the last of its descendants, or of itself if it has none.
+	/// Its descendants are the layers with positions between the two.
+	///
+	/// This is `None` for layers not in the tree, or unless enabled with [`Self::set_subtree_interval_indexing`].
+	pub fn subtree_interval(&self, layer: LayerNodeIdentifier) -> Option<(u32, u32)> {
+		self.subtree_intervals()?.get(&layer).copied()
+	}
+
+	/// The intervals if indexing is enabled, numbering the layers again first if the tree was modified since they were last numbered.
+	fn subtree_intervals(&self) -> Option<SubtreeIntervalMap> {
+		let mut cache = self.subtree_intervals.as_ref()?.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some((_, intervals)) = cache.as_ref().filter(|&&(structure_generation, _)| structure_generation == self.structure_generation) {
+			return Some(intervals.clone());
+		}
+
+		let mut intervals = HashMap::with_capacity(self.structure.len());
+		for (position, layer) in std::iter::once(self.root()).chain(self.all_layers()).enumerate() {
+			intervals.insert(layer, (position as u32, position as u32));
+		}
+		// The last descendant of a layer is the deepest of its chain of last children
+		for layer in self.all_layers() {
+			let Some(last_descendant) = layer.last_children(self).last() else { continue };
+			let end = intervals[&last_descendant].0;
+			if let Some(interval) = intervals.get_mut(&layer) {
+				interval.1 = end;
+			}
+		}
+		intervals.insert(self.root(), (0, intervals.len() as u32 - 1));
+
+		let intervals = Arc::new(intervals);
+		*cache = Some((self.structure_generation, intervals.clone()));
+		Some(intervals)
+	}
+
+	/// The intervals if indexing is enabled and the tree hasn't been modified since they were numbered, so checks made while modifying the tree don't number it each time.
+	fn valid_subtree_intervals(&self) -> Option<SubtreeIntervalMap> {
+		let cache = self.subtree_intervals.as_ref()?.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let (structure_generation, intervals) = cache.as_ref()?;
+		(*structure_generation == self.structure_generation).then(|| intervals.clone())
+	}
+}
+
+// layer names
+impl DocumentMetadata {
+	/// The name given to the layer, if it has been named.
+	pub fn layer_name(&self, layer: LayerNodeIdentifier) -> Option<&str> {
+		self.names.get(&layer).map(String::as_str)
+	}
+
+	/// Update the name of a layer without reloading the structure. An empty name removes it.
+	pub fn set_layer_name(&mut self, layer: LayerNodeIdentifier, name: String) {
+		if name.is_empty() {
+			Arc::make_mut(&mut self.names).remove(&layer);
+		} else if self.layer_exists(layer) {
+			Arc::make_mut(&mut self.names).insert(layer, name);
+		}
+	}
+
+	/// The name of the layer, or its node id for unnamed layers
+	fn path_component(&self, layer: LayerNodeIdentifier) -> String {
+		self.layer_name(layer).map_or_else(|| layer.to_node().to_string(), str::to_string)
+	}
+
+	/// The names of the ancestors of the layer and itself, from the outermost.
+	fn path_components(&self, layer: LayerNodeIdentifier) -> Vec<String> {
+		let mut components = layer
+			.ancestors(self)
+			.filter(|&ancestor| ancestor != LayerNodeIdentifier::ROOT)
+			.map(|ancestor| self.path_component(ancestor))
+			.collect::<Vec<_>>();
+		components.reverse();
+		components
+	}
+
+	/// A human readable path to the layer, made of the names of its ancestors and itself, followed by `#` and the node id of the layer.
+	///
+	/// For example `Artboard 1/Group A/Rectangle#348` with a separator of `/`, which [`Self::resolve_layer_path`] converts back to the layer.
+	/// Any `\`, `/` or `#` within a name is escaped with a `\`, so `Layer #2` becomes `Layer \#2`.
+	pub fn layer_path_string(&self, layer: LayerNodeIdentifier, separator: &str) -> String {
+		let escape = |name: String| name.replace('\\', "\\\\").replace('/', "\\/").replace('#', "\\#");
+		let components = self.path_components(layer).into_iter().map(escape).collect::<Vec<_>>();
+		format!("{}#{}", components.join(separator), layer.to_node())
+	}
+
+	/// Find a layer from a `/` separated path of layer names, as produced by [`Self::layer_path_string`].
+	///
+	/// Names are matched case sensitively against the children at each level, taking the topmost where several siblings share a name.
+	/// A trailing `#<node_id>` instead picks the layer with that id, provided its path matches. A `/` or `#` within a name must be escaped as `\/` or `\#`.
+	pub fn resolve_layer_path(&self, path: &str) -> Option<LayerNodeIdentifier> {
+		let (components, node_id) = parse_layer_path(path)?;
+		if let Some(node_id) = node_id {
+			let layer = LayerNodeIdentifier::new_unchecked(node_id);
+			let matches = node_id != LayerNodeIdentifier::ROOT.to_node() && self.layer_exists(layer) && self.path_components(layer) == components;
+			return matches.then_some(layer);
+		}
+
+		components
+			.iter()
+			.try_fold(LayerNodeIdentifier::ROOT, |parent, name| parent.children(self).find(|&child| &self.path_component(child) == name))
+			.filter(|&layer| layer != LayerNodeIdentifier::ROOT)
+	}
+}
+
+// layer tags
+impl DocumentMetadata {
+	/// The color label given to the layer, if any.
+	pub fn layer_tag(&self, layer: LayerNodeIdentifier) -> Option<&LayerTag> {
+		self.tags.get(&layer)
+	}
+
+	/// Give the layer a color label, or remove it with `None`.
+	pub fn set_layer_tag(&mut self, layer: LayerNodeIdentifier, tag: Option<LayerTag>) {
+		if let Some(tag) = tag {
+			if self.layer_exists(layer) {
+				Arc::make_mut(&mut self.tags).insert(layer, tag);
+			}
+		} else if self.tags.contains_key(&layer) {
+			Arc::make_mut(&mut self.tags).remove(&layer);
+		}
+	}
+
+	/// Layers with the color label, from the top of the layer tree to the bottom.
+	pub fn layers_with_tag<'a>(&'a self, tag: &'a LayerTag) -> impl Iterator<Item = LayerNodeIdentifier> + 'a {
+		self.all_layers().filter(move |layer| self.tags.get(layer) == Some(tag))
+	}
+
+	/// All of the color labels, to be saved with the document.
+	pub fn layer_tags(&self) -> &HashMap<LayerNodeIdentifier, LayerTag> {
+		&self.tags
+	}
+
+	/// Restore the color labels saved with the document, ignoring those of layers that don't exist.
+	pub fn set_layer_tags(&mut self, tags: HashMap<LayerNodeIdentifier, LayerTag>) {
+		self.tags = Arc::new(tags.into_iter().filter(|&(layer, _)| self.layer_exists(layer)).collect());
+	}
+}
+
+/// A color label for organizing layers, see [`DocumentMetadata::set_layer_tag`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum LayerTag {
+	Red,
+	Orange,
+	Yellow,
+	Green,
+	Blue,
+	Purple,
+	Gray,
+	Custom(String),
+}
+
+// layer opacity and blending
+impl DocumentMetadata {
+	/// The opacity the layer is drawn with from `0` to `1`, which is the product of the values in the Opacity nodes of the layer and its ancestors.
+	///
+	/// Like the other cached layer data this is read during [`Self::load_structure`], so values driven by other nodes aren't taken into account.
+	pub fn layer_opacity(&self, layer: LayerNodeIdentifier) -> f64 {
+		layer.ancestors(self).filter_map(|layer| self.opacities.get(&layer)).product()
+	}
+
+	/// The blend mode from the layer's own Blend Mode node, or [`BlendMode::Normal`] if it has none.
+	pub fn layer_blend_mode(&self, layer: LayerNodeIdentifier) -> BlendMode {
+		self.blend_modes.get(&layer).copied().unwrap_or_default()
+	}
+
+	/// Does the layer draw nothing because it, or one of its ancestors, is fully transparent or only masks what is below it?
+	pub fn is_non_rendering(&self, layer: LayerNodeIdentifier) -> bool {
+		const MIN_OPACITY: f64 = 1e-4;
+		self.layer_opacity(layer) < MIN_OPACITY || layer.ancestors(self).any(|layer| self.is_mask_only(layer))
+	}
+
+	/// Does the layer only change the alpha of what is below it rather than drawing its own colors, because of the blend mode found by [`Self::load_structure`]?
+	pub fn is_mask_only(&self, layer: LayerNodeIdentifier) -> bool {
+		matches!(self.layer_blend_mode(layer), BlendMode::Erase | BlendMode::Restore | BlendMode::MultiplyAlpha)
+	}
+
+	/// All layers that draw nothing in layer tree order, such as to mark them in the layer panel.
+	pub fn non_rendering_layers(&self) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		self.all_layers().filter(|&layer| self.is_non_rendering(layer))
+	}
+
+	/// Optimistically show a new opacity for the layer (from `0` to `1`) until the structure is next loaded from the graph.
+	pub fn set_layer_opacity_hint(&mut self, layer: LayerNodeIdentifier, opacity: f64) {
+		Arc::make_mut(&mut self.opacities).insert(layer, opacity);
+		// The document bounds leave out transparent layers, so they depend on the opacity
+		self.mark_layers_changed([layer]);
+	}
+}
+
+/// A node of the layer tree, as sent to the frontend layer panel.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct LayerTreeNode {
+	pub id: LayerNodeIdentifier,
+	pub children: Vec<LayerTreeNode>,
+	#[serde(rename = "isFolder")]
+	pub is_folder: bool,
+	#[serde(rename = "isArtboard")]
+	pub is_artboard: bool,
+	pub selected: bool,
+	/// Left out for layers without children, which can't be expanded.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub expanded: Option<bool>,
+}
+
+/// Which rows are included by [`DocumentMetadata::layer_panel_slice`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct PanelSliceOptions {
+	/// Show the contents of collapsed folders too, such as while searching the layer panel.
+	#[serde(rename = "expandAll")]
+	pub expand_all: bool,
+}
+
+/// A window of the rows of the layer panel, see [`DocumentMetadata::layer_panel_slice`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct LayerPanelSlice {
+	#[serde(rename = "startFlattenedIndex")]
+	pub start_flattened_index: usize,
+	pub rows: Vec<LayerPanelRow>,
+	/// The number of rows in the whole layer panel, for sizing the scrollbar.
+	#[serde(rename = "totalRows")]
+	pub total_rows: usize,
+}
+
+/// A row of the layer panel, see [`DocumentMetadata::layer_panel_slice`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct LayerPanelRow {
+	pub id: LayerNodeIdentifier,
+	/// The indentation of the row, which is `1` for layers at the top level.
+	pub depth: usize,
+	#[serde(rename = "hasChildren")]
+	pub has_children: bool,
+	pub collapsed: bool,
+	pub selected: bool,
+	pub kind: Option<LayerClassification>,
+	pub name: Option<String>,
+}
+
+// layer classification
+impl DocumentMetadata {
+	/// The kind of the layer, as determined when the structure was last loaded from the node network.
+	pub fn classification(&self, layer: LayerNodeIdentifier) -> Option<LayerClassification> {
+		self.classifications.get(&layer).copied()
+	}
+
+	/// All layers of the specified kind, in layer tree order.
+	pub fn layers_of_kind(&self, kind: LayerClassification) -> impl Iterator<Item = LayerNodeIdentifier> + '_ {
+		let layers = self.layers_by_kind.get(&kind);
+		self.all_layers().filter(move |layer| layers.is_some_and(|layers| layers.contains(layer)))
+	}
+
+	/// The number of layers of each kind in the document.
+	pub fn layer_kind_counts(&self) -> LayerKindCounts {
+		let count = |kind| self.layers_by_kind.get(&kind).map_or(0, HashSet::len);
+		LayerKindCounts {
+			artboards: count(LayerClassification::Artboard),
+			folders: count(LayerClassification::Folder),
+			text: count(LayerClassification::Text),
+			raster: count(LayerClassification::Raster),
+			vector: count(LayerClassification::Vector),
+			other: count(LayerClassification::Other),
+		}
+	}
+
+	/// Summarize the selected layers in a single pass, for display in the status bar.
+	pub fn selection_summary(&self) -> SelectionSummary {
+		let mut summary = SelectionSummary::default();
+		let mut artboards = HashSet::new();
+		for layer in self.selected_layers() {
+			if let Some(artboard) = layer.ancestors(self).find(|&ancestor| self.is_artboard(ancestor)) {
+				artboards.insert(artboard);
+				if artboard == layer {
+					continue;
+				}
+			}
+
+			summary.layer_count += 1;
+			let kind = self
+				.classification(layer)
+				.unwrap_or(if self.is_folder(layer) { LayerClassification::Folder } else { LayerClassification::Other });
+			*summary.kinds.get_mut(kind) += 1;
+			if let Some(bounds) = self.bounding_box_document(layer) {
+				summary.bounds = Some(summary.bounds.map_or(bounds, |existing| Quad::combine_bounds(existing, bounds)));
+			}
+		}
+		summary.size = summary.bounds.map(|[min, max]| max - min);
+		summary.spans_multiple_artboards = artboards.len() > 1;
+		summary
+	}
+}
+
+/// What a layer contains. Artboards and folders take priority over the kind of content they are fed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum LayerClassification {
+	Artboard,
+	Folder,
+	Text,
+	Raster,
+	Vector,
+	Other,
+}
+
+/// The number of layers of each [`LayerClassification`], as shown in the document statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct LayerKindCounts {
+	pub artboards: usize,
+	pub folders: usize,
+	pub text: usize,
+	pub raster: usize,
+	pub vector: usize,
+	pub other: usize,
+}
+
+impl LayerKindCounts {
+	pub fn get(&self, kind: LayerClassification) -> usize {
+		match kind {
+			LayerClassification::Artboard => self.artboards,
+			LayerClassification::Folder => self.folders,
+			LayerClassification::Text => self.text,
+			LayerClassification::Raster => self.raster,
+			LayerClassification::Vector => self.vector,
+			LayerClassification::Other => self.other,
+		}
+	}
+
+	fn get_mut(&mut self, kind: LayerClassification) -> &mut usize {
+		match kind {
+			LayerClassification::Artboard => &mut self.artboards,
+			LayerClassification::Folder => &mut self.folders,
+			LayerClassification::Text => &mut self.text,
+			LayerClassification::Raster => &mut self.raster,
+			LayerClassification::Vector => &mut self.vector,
+			LayerClassification::Other => &mut self.other,
+		}
+	}
+
+	pub fn total(&self) -> usize {
+		self.artboards + self.folders + self.text + self.raster + self.vector + self.other
+	}
+}
+
+/// An overview of the selected layers, excluding artboards, as shown in the status bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, specta::Type)]
+pub struct SelectionSummary {
+	#[serde(rename = "layerCount")]
+	pub layer_count: usize,
+	pub kinds: LayerKindCounts,
+	/// The combined bounds of the selected layers in document space.
+	pub bounds: Option<[DVec2; 2]>,
+	pub size: Option<DVec2>,
+	/// Whether the selected layers, or selected artboards themselves, belong to more than one artboard.
+	#[serde(rename = "spansMultipleArtboards")]
+	pub spans_multiple_artboards: bool,
+}
+
+// statistics
+impl DocumentMetadata {
+	/// Totals describing the document for the document properties dialog.
+	pub fn statistics(&self) -> DocumentStatistics {
+		use std::mem::size_of;
+
+		let mut layer_count = 0;
+		let mut max_depth = 0;
+		let mut stack = self.root().children(self).map(|child| (child, 1)).collect::<Vec<_>>();
+		while let Some((layer, depth)) = stack.pop() {
+			layer_count += 1;
+			max_depth = max_depth.max(depth);
+			stack.extend(layer.children(self).map(|child| (child, depth + 1)));
+		}
+
+		// Shared click targets are only counted once
+		let mut seen = HashSet::new();
+		let unique_click_targets = self
+			.click_targets
+			.values()
+			.filter(|click_targets| seen.insert(Arc::as_ptr(click_targets)))
+			.flat_map(|click_targets| click_targets.iter());
+		let (click_target_count, click_target_anchor_count) = unique_click_targets.fold((0, 0), |(count, anchors), click_target| (count + 1, anchors + click_target.subpath.len()));
+
+		let layer_entry = size_of::<LayerNodeIdentifier>();
+		let memory_usage = self.structure.len() * (layer_entry + size_of::<NodeRelations>())
+			+ self.upstream_transforms.len() * (size_of::<NodeId>() + size_of::<(Footprint, DAffine2)>())
+			+ self.click_targets.len() * (layer_entry + size_of::<Arc<Vec<ClickTarget>>>())
+			+ click_target_count * size_of::<ClickTarget>()
+			+ click_target_anchor_count * size_of::<bezier_rs::ManipulatorGroup<ManipulatorGroupId>>()
+			+ self.names.values().map(|name| layer_entry + size_of::<String>() + name.len()).sum::<usize>()
+			+ (self.classifications.len() + self.opacities.len() + self.blend_modes.len() + self.layer_generations.len()) * (layer_entry + size_of::<u64>())
+			+ (self.artboards.len() + self.folders.len() + self.hidden.len() + self.collapsed.len() + self.locked.len()) * layer_entry;
+
+		DocumentStatistics {
+			layer_count,
+			folder_count: self.folders.len(),
+			artboard_count: self.artboards.len(),
+			max_depth,
+			click_target_anchor_count,
+			memory_usage,
+		}
+	}
+
+	/// How many times queries have used the reusable scratch buffers, and how many of those times a buffer had to allocate, to check that repeated queries reuse them.
+	/// Only the scratch buffers are counted, not any other allocations made by the queries.
+	#[cfg(any(test, feature = "scratch-stats"))]
+	pub fn scratch_stats(&self) -> ScratchStats {
+		let pools = [self.scratch.layers.stats.stats(), self.scratch.hit_candidates.stats.stats()];
+		pools.into_iter().fold(ScratchStats::default(), |total, pool| ScratchStats {
+			acquisitions: total.acquisitions + pool.acquisitions,
+			allocations: total.allocations + pool.allocations,
+		})
+	}
+
+	/// Release the spare capacity of the caches, such as after deleting many layers. Caches shared with a [`DocumentMetadataReader`] are left as they are rather than copied.
+	pub fn shrink_to_fit(&mut self) {
+		fn shrink<T>(cache: &mut Arc<T>, shrink: impl FnOnce(&mut T)) {
+			if let Some(cache) = Arc::get_mut(cache) {
+				shrink(cache);
+			}
+		}
+		shrink(&mut self.upstream_transforms, HashMap::shrink_to_fit);
+		shrink(&mut self.transform_generations, HashMap::shrink_to_fit);
+		shrink(&mut self.structure, HashMap::shrink_to_fit);
+		shrink(&mut self.artboards, HashSet::shrink_to_fit);
+		shrink(&mut self.folders, HashSet::shrink_to_fit);
+		shrink(&mut self.hidden, HashSet::shrink_to_fit);
+		shrink(&mut self.locked, HashSet::shrink_to_fit);
+		shrink(&mut self.classifications, HashMap::shrink_to_fit);
+		shrink(&mut self.layers_by_kind, |layers_by_kind| {
+			layers_by_kind.values_mut().for_each(HashSet::shrink_to_fit);
+			layers_by_kind.shrink_to_fit();
+		});
+		shrink(&mut self.names, HashMap::shrink_to_fit);
+		shrink(&mut self.artboard_info, HashMap::shrink_to_fit);
+		shrink(&mut self.opacities, HashMap::shrink_to_fit);
+		shrink(&mut self.blend_modes, HashMap::shrink_to_fit);
+		if let Some(node_usage) = &mut self.node_usage {
+			shrink(node_usage, HashMap::shrink_to_fit);
+		}
+		if let Some(previous_frame_bounds) = &mut self.previous_frame_bounds {
+			shrink(previous_frame_bounds, HashMap::shrink_to_fit);
+		}
+		shrink(&mut self.click_targets, HashMap::shrink_to_fit);
+		shrink(&mut self.text_hit_regions, HashMap::shrink_to_fit);
+		shrink(&mut self.render_padding, HashMap::shrink_to_fit);
+		shrink(&mut self.chrome_click_targets, HashMap::shrink_to_fit);
+		shrink(&mut self.collapsed, HashSet::shrink_to_fit);
+		shrink(&mut self.tags, HashMap::shrink_to_fit);
+		shrink(&mut self.layer_generations, HashMap::shrink_to_fit);
+		self.selected_nodes.shrink_to_fit();
+		self.selection_sources.shrink_to_fit();
+		self.descendants_scratch.shrink_to_fit();
+		self.scratch.layers.clear();
+		self.scratch.hit_candidates.clear();
+	}
+}
+
+/// Uses of the scratch buffers, see [`DocumentMetadata::scratch_stats`].
+#[cfg(any(test, feature = "scratch-stats"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScratchStats {
+	pub acquisitions: u64,
+	/// Uses where the buffer wasn't big enough, or none was free to reuse.
+	pub allocations: u64,
+}
+
+/// Totals describing the document, see [`DocumentMetadata::statistics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, specta::Type)]
+pub struct DocumentStatistics {
+	/// The number of layers, including folders and artboards.
+	#[serde(rename = "layerCount")]
+	pub layer_count: usize,
+	#[serde(rename = "folderCount")]
+	pub folder_count: usize,
+	#[serde(rename = "artboardCount")]
+	pub artboard_count: usize,
+	/// The most layers nested inside each other, which is `1` when no layers are inside folders and `0` for an empty document.
+	#[serde(rename = "maxDepth")]
+	pub max_depth: usize,
+	/// The number of anchors in all the click targets, counting shared click targets once.
+	#[serde(rename = "clickTargetAnchorCount")]
+	pub click_target_anchor_count: usize,
+	/// An estimate of the bytes used by the cached data, not including spare capacity.
+	#[serde(rename = "memoryUsage")]
+	pub memory_usage: usize,
+}
+
+// modification generations
+impl DocumentMetadata {
+	/// The generation of the most recent update batch. Pass this to [`Self::layers_changed_since`] later to find what changed in the meantime.
+	pub fn generation(&self) -> u64 {
+		self.generation
+	}
+
+	/// The generation of the last update batch that modified this layer or any of its descendants.
+	pub fn layer_generation(&self, layer: LayerNodeIdentifier) -> u64 {
+		self.layer_generations.get(&layer).copied().unwrap_or_default()
+	}
+
+	/// Layers (in layer tree order) modified, directly or through a descendant, by an update batch newer than the specified generation.
+	pub fn layers_changed_since(&self, generation: u64) -> Vec<LayerNodeIdentifier> {
+		self.all_layers().filter(|&layer| self.layer_generation(layer) > generation).collect()
+	}
+
+	/// Start a new update batch and tag the layers and all of their ancestors with it, since a change to a child also affects its folders.
+	///
+	/// Identifiers not in the layer structure (such as non-layer nodes with cached transforms) are ignored.
+	fn mark_layers_changed(&mut self, layers: impl IntoIterator<Item = LayerNodeIdentifier>) {
+		self.generation += 1;
+		for layer in layers {
+			if !self.structure.contains_key(&layer) {
+				continue;
+			}
+			let mut current = Some(layer);
+			while let Some(layer) = current {
+				// Once an ancestor has been tagged by this batch, so have all of its own ancestors
+				if Arc::make_mut(&mut self.layer_generations).insert(layer, self.generation) == Some(self.generation) {
+					break;
+				}
+				current = layer.parent(self);
+			}
+		}
+	}
+}
+
+// change observers
+impl DocumentMetadata {
+	/// Register a callback that is called with each change matching the mask straight after it is made. It is given the metadata to read, but can't modify it.
+	///
+	/// Clones of the metadata start without observers, so when the metadata is replaced by another copy, such as by undo, they need moving over with [`Self::take_observers_from`].
+	pub fn on_change(&mut self, mask: ChangeMask, callback: ChangeObserver) -> ObserverId {
+		let id = ObserverId(self.observers.next_id);
+		self.observers.next_id += 1;
+		self.observers.list().push((id, mask, callback));
+		id
+	}
+
+	/// Unregister a callback, returning whether it was registered.
+	pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+		let observers = self.observers.list();
+		let count = observers.len();
+		observers.retain(|&(observer, ..)| observer != id);
+		observers.len() != count
+	}
+
+	/// Move the observers registered with `other` over to this metadata, keeping their ids, so they continue to be notified after this replaces it.
+	pub fn take_observers_from(&mut self, other: &mut Self) {
+		let taken = std::mem::take(other.observers.list());
+		self.observers.next_id = self.observers.next_id.max(other.observers.next_id);
+		self.observers.list().extend(taken);
+	}
+
+	fn notify(&mut self, event: ChangeEvent) {
+		let mask = event.mask();
+		if !self.observers.list().iter().any(|(_, observer_mask, _)| observer_mask.intersects(mask)) {
+			return;
+		}
+		// Callbacks only get shared access, so they can't register or remove observers while they are taken out
+		let mut observers = std::mem::take(self.observers.list());
+		for (_, observer_mask, callback) in &mut observers {
+			if observer_mask.intersects(mask) {
+				callback(&event, self);
+			}
+		}
+		*self.observers.list() = observers;
+	}
+
+	/// A copy of the selection to compare with after a change, only if anything is observing selection changes.
+	fn selection_before_change(&mut self) -> Option<Vec<NodeId>> {
+		let observed = self.observers.list().iter().any(|(_, mask, _)| mask.intersects(ChangeMask::SELECTION));
+		observed.then(|| self.selected_nodes.clone())
+	}
+
+	fn notify_selection_changed(&mut self, before: Option<Vec<NodeId>>) {
+		// Every modification of the selection ends here, whether or not it is observed
+		self.selection_generation += 1;
+		#[cfg(feature = "journal")]
+		self.record_selection();
+		let Some(before) = before else {
+			return;
+		};
+		let [before_set, after_set] = [&before, &self.selected_nodes].map(|nodes| nodes.iter().copied().collect::<HashSet<_>>());
+		let mut added = self.selected_nodes.iter().copied().filter(|node| !before_set.contains(node)).collect::<Vec<_>>();
+		let mut removed = before.into_iter().filter(|node| !after_set.contains(node)).collect::<Vec<_>>();
+		added.dedup();
+		removed.dedup();
+		if !added.is_empty() || !removed.is_empty() {
+			self.notify(ChangeEvent::SelectionChanged { added, removed });
+		}
+	}
+
+	fn notify_transforms_updated(&mut self, nodes: Vec<NodeId>) {
+		if !nodes.is_empty() {
+			self.notify(ChangeEvent::TransformsUpdated(nodes));
+		}
+	}
+}
+
+/// A callback registered with [`DocumentMetadata::on_change`].
+pub type ChangeObserver = Box<dyn FnMut(&ChangeEvent, &DocumentMetadata) + Send>;
+
+/// Identifies a callback registered with [`DocumentMetadata::on_change`], for removing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+#[derive(Default)]
+struct Observers {
+	next_id: u64,
+	list: Mutex<Vec<(ObserverId, ChangeMask, ChangeObserver)>>,
+}
+
+impl Observers {
+	fn list(&mut self) -> &mut Vec<(ObserverId, ChangeMask, ChangeObserver)> {
+		self.list.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+impl Clone for Observers {
+	/// Observers are notified of changes to the metadata they were registered with, not to copies of it.
+	fn clone(&self) -> Self {
+		Self::default()
+	}
+}
+
+impl core::fmt::Debug for Observers {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let count = self.list.lock().map_or(0, |list| list.len());
+		f.debug_struct("Observers").field("count", &count).finish()
+	}
+}
+
+/// Which kinds of [`ChangeEvent`] an observer is notified of, which can be combined with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChangeMask(u8);
+
+impl ChangeMask {
+	pub const STRUCTURE: Self = Self(1 << 0);
+	pub const DELETION: Self = Self(1 << 1);
+	pub const SELECTION: Self = Self(1 << 2);
+	pub const TRANSFORMS: Self = Self(1 << 3);
+	pub const ALL: Self = Self(0b1111);
+
+	/// Is any kind of change in both masks?
+	pub const fn intersects(self, other: Self) -> bool {
+		self.0 & other.0 != 0
+	}
+}
+
+impl core::ops::BitOr for ChangeMask {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self::Output {
+		Self(self.0 | rhs.0)
+	}
+}
+
+/// A change made to the [`DocumentMetadata`], see [`DocumentMetadata::on_change`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent {
+	/// The layer tree was loaded from the graph again.
+	StructureReloaded,
+	/// The layers were deleted from the layer tree, starting with the deleted layer followed by its descendants.
+	LayersDeleted(Vec<LayerNodeIdentifier>),
+	/// Deleted layers were put back in the layer tree with their data by [`StructureTransaction::rollback`], in tree order.
+	LayersRestored(Vec<LayerNodeIdentifier>),
+	/// Nodes were selected or deselected, in the order they are or were in the selection.
+	SelectionChanged { added: Vec<NodeId>, removed: Vec<NodeId> },
+	/// The cached transforms of the nodes were updated or removed.
+	TransformsUpdated(Vec<NodeId>),
+}
+
+impl ChangeEvent {
+	pub fn mask(&self) -> ChangeMask {
+		match self {
+			Self::StructureReloaded => ChangeMask::STRUCTURE,
+			Self::LayersDeleted(_) | Self::LayersRestored(_) => ChangeMask::DELETION,
+			Self::SelectionChanged { .. } => ChangeMask::SELECTION,
+			Self::TransformsUpdated(_) => ChangeMask::TRANSFORMS,
+		}
+	}
+}
+
+// measurement
+impl DocumentMetadata {
+	/// Measure the spacing between two layers in document space, for the measurement overlay. Folders are measured by the bounds of their contents.
+	pub fn measure_between(&self, a: LayerNodeIdentifier, b: LayerNodeIdentifier) -> Option<LayerMeasurement> {
+		let [a_bounds, b_bounds] = [self.subtree_bounding_box_document(a)?, self.subtree_bounding_box_document(b)?];
+		let center = |[min, max]: [DVec2; 2]| (min + max) / 2.;
+		Some(LayerMeasurement {
+			gap: a_bounds[0].max(b_bounds[0]) - a_bounds[1].min(b_bounds[1]),
+			center_delta: center(b_bounds) - center(a_bounds),
+			outline_distance: self.outline_distance(a, b),
+		})
+	}
+
+	/// The shortest distance in document space between the click target outlines of two layers, which is `0` where they cross.
+	///
+	/// Curves are approximated by points along them, so this can slightly overestimate the distance between curved outlines.
+	pub fn outline_distance(&self, a: LayerNodeIdentifier, b: LayerNodeIdentifier) -> Option<f64> {
+		const SAMPLES_PER_CURVE: usize = 16;
+
+		let document_beziers = |layer: LayerNodeIdentifier| {
+			let transform = self.transform_to_document(layer);
+			let beziers = self.click_targets.get(&layer).into_iter().flat_map(|click_targets| click_targets.iter());
+			let beziers = beziers
+				.flat_map(|click_target| click_target.subpath.iter())
+				.map(move |bezier| bezier.apply_transformation(|point| transform.transform_point2(point)));
+			beziers.map(|bezier| (bezier.bounding_box(), bezier)).collect::<Vec<_>>()
+		};
+		let [a_beziers, b_beziers] = [document_beziers(a), document_beziers(b)];
+
+		let mut best: Option<f64> = None;
+		for (a_bounds, a_bezier) in &a_beziers {
+			for (b_bounds, b_bezier) in &b_beziers {
+				// The gap between the bounding boxes is a lower bound on the distance between the curves
+				let gap = (a_bounds[0].max(b_bounds[0]) - a_bounds[1].min(b_bounds[1])).max(DVec2::ZERO).length();
+				if best.is_some_and(|best| gap >= best) {
+					continue;
+				}
+				if gap == 0. && !a_bezier.intersections(b_bezier, None, None).is_empty() {
+					return Some(0.);
+				}
+				let closest = |from: &bezier_rs::Bezier, to: &bezier_rs::Bezier| {
+					(0..=SAMPLES_PER_CURVE)
+						.map(|sample| from.evaluate(bezier_rs::TValue::Parametric(sample as f64 / SAMPLES_PER_CURVE as f64)))
+						.map(|point| to.evaluate(bezier_rs::TValue::Parametric(to.project(point, None))).distance(point))
+						.fold(f64::INFINITY, f64::min)
+				};
+				let distance = closest(a_bezier, b_bezier).min(closest(b_bezier, a_bezier));
+				best = Some(best.map_or(distance, |best| best.min(distance)));
+			}
+		}
+		best
+	}
+
+	/// The shortest translation in document space that moves the bounding quad of the first layer out of the bounding quad of the second, or `None` if they don't overlap.
+	pub fn resolve_overlap(&self, a: LayerNodeIdentifier, b: LayerNodeIdentifier) -> Option<DVec2> {
+		let document_quad = |layer| Some(self.transform_to_document(layer) * Quad::from_box(self.bounding_box_with_transform(layer, DAffine2::IDENTITY)?));
+		document_quad(a)?.separation_vector(document_quad(b)?)
+	}
+
+	/// The smallest and largest projections of the layer onto the direction of a document space axis, such as along the edge of another layer for smart guides.
+	///
+	/// With `tight` the click targets are projected exactly, otherwise the corners of the layer's bounding box in its own space are, which is quicker but larger when rotated relative to the axis.
+	/// Returns `None` for layers without bounds or an axis with no direction.
+	pub fn layer_extent_along(&self, layer: LayerNodeIdentifier, axis_document: DVec2, tight: bool) -> Option<(f64, f64)> {
+		let axis = axis_document.try_normalize()?;
+		let angle = axis.y.atan2(axis.x);
+		if tight {
+			// Rotating the axis onto the x axis makes the projection the x coordinate
+			let [min, max] = self.bounding_box_with_transform(layer, DAffine2::from_angle(-angle) * self.transform_to_document(layer))?;
+			Some((min.x, max.x))
+		} else {
+			let bounds = self.bounding_box_with_transform(layer, DAffine2::IDENTITY)?;
+			Some((self.transform_to_document(layer) * Quad::from_box(bounds)).extent_along(axis_document))
+		}
+	}
+}
+
+/// The spacing between two layers in document space, see [`DocumentMetadata::measure_between`].
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, specta::Type)]
+pub struct LayerMeasurement {
+	/// The empty space between the bounds of the layers along each axis, which is negative where they overlap.
+	pub gap: DVec2,
+	/// The offset from the center of the first layer's bounds to the center of the second's.
+	#[serde(rename = "centerDelta")]
+	pub center_delta: DVec2,
+	#[serde(rename = "outlineDistance")]
+	pub outline_distance: Option<f64>,
+}
+
+// fill tests
+impl DocumentMetadata {
+	/// The direction each click target subpath of the layer winds in document space, in the order of the click targets.
+	///
+	/// Curves are included exactly, and a mirroring transform reverses the winding. Open subpaths and closed ones enclosing no area give [`Winding::None`].
+	pub fn subpath_windings(&self, layer: LayerNodeIdentifier) -> Vec<Winding> {
+		self.document_click_target_beziers(layer)
+			.map(|(closed, beziers)| {
+				if !closed {
+					return Winding::None;
+				}
+				let area = beziers.map(|bezier| cubic_signed_area(&bezier)).sum::<f64>();
+				if area > 0. {
+					Winding::CounterClockwise
+				} else if area < 0. {
+					Winding::Clockwise
+				} else {
+					Winding::None
+				}
+			})
+			.collect()
+	}
+
+	/// Would a document space point be filled by the click targets of the layer with the fill rule, like a fill of the whole outline drawn as one shape? Open subpaths fill nothing.
+	pub fn point_fill_test(&self, layer: LayerNodeIdentifier, point_document: DVec2, rule: FillRule) -> bool {
+		let winding_number = self
+			.document_click_target_beziers(layer)
+			.filter(|&(closed, _)| closed)
+			.flat_map(|(_, beziers)| beziers)
+			.map(|bezier| bezier.winding(point_document))
+			.sum::<i32>();
+		match rule {
+			FillRule::NonZero => winding_number != 0,
+			FillRule::EvenOdd => winding_number % 2 != 0,
+		}
+	}
+
+	/// Whether each click target subpath of the layer is closed, with its curves in document space, including the closing curve of closed subpaths.
+	fn document_click_target_beziers(&self, layer: LayerNodeIdentifier) -> impl Iterator<Item = (bool, impl Iterator<Item = bezier_rs::Bezier> + '_)> + '_ {
+		let transform = self.transform_to_document(layer);
+		let click_targets = self.click_targets.get(&layer).into_iter().flat_map(|click_targets| click_targets.iter());
+		click_targets.map(move |click_target| {
+			let beziers = click_target.subpath.iter().map(move |bezier| bezier.apply_transformation(|point| transform.transform_point2(point)));
+			(click_target.subpath.closed(), beziers)
+		})
+	}
+}
+
+/// The area enclosed between the curve and the origin, positive where it turns counter-clockwise around the origin when the y axis points up.
+/// Summed around a closed path this gives the area within it, from the integral of `(x dy - y dx) / 2`.
+fn cubic_signed_area(bezier: &bezier_rs::Bezier) -> f64 {
+	let [p0, p1, p2, p3] = match bezier.to_cubic().handles {
+		bezier_rs::BezierHandles::Cubic { handle_start, handle_end } => [bezier.start, handle_start, handle_end, bezier.end],
+		_ => return bezier.start.perp_dot(bezier.end) / 2.,
+	};
+	(6. * p0.perp_dot(p1) + 3. * p0.perp_dot(p2) + p0.perp_dot(p3) + 3. * p1.perp_dot(p2) + 3. * p1.perp_dot(p3) + 6. * p2.perp_dot(p3)) / 20.
+}
+
+/// The direction a closed path turns, see [`DocumentMetadata::subpath_windings`].
+///
+/// The directions are as seen with the y axis pointing up, so they appear the other way around on the canvas where it points down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum Winding {
+	/// A positive signed area.
+	CounterClockwise,
+	Clockwise,
+	/// An open path, or one enclosing no area.
+	None,
+}
+
+/// How overlapping subpaths decide whether a point is filled, matching the SVG `fill-rule` property.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum FillRule {
+	/// Filled where the subpaths wind around the point any number of times other than zero, counting windings in opposite directions against each other.
+	#[default]
+	NonZero,
+	/// Filled where the point is within an odd number of subpaths.
+	EvenOdd,
+}
+
+// guides and snapping
+impl DocumentMetadata {
+	/// The most lines of the grid along each axis that [`Self::snap_candidates`] enumerates.
+	pub const MAX_GRID_SNAP_LINES: usize = 256;
+
+	/// Add a guide to the document, returning the identifier to remove it with.
+	pub fn add_guide(&mut self, guide: Guide) -> GuideId {
+		let id = GuideId(self.next_guide_id);
+		self.next_guide_id += 1;
+		Arc::make_mut(&mut self.guides).push((id, guide));
+		id
+	}
+
+	/// Remove a guide, returning it if it existed.
+	pub fn remove_guide(&mut self, id: GuideId) -> Option<Guide> {
+		let index = self.guides.iter().position(|&(guide_id, _)| guide_id == id)?;
+		Some(Arc::make_mut(&mut self.guides).remove(index).1)
+	}
+
+	/// All of the guides in the order they were added, to be saved with the document.
+	pub fn guides(&self) -> &[(GuideId, Guide)] {
+		&self.guides
+	}
+
+	/// Restore the guides saved with the document. Guides added afterwards are given identifiers after the largest restored one.
+	pub fn set_guides(&mut self, guides: Vec<(GuideId, Guide)>) {
+		self.next_guide_id = guides.iter().map(|&(GuideId(id), _)| id + 1).max().unwrap_or_default().max(self.next_guide_id);
+		self.guides = Arc::new(guides);
+	}
+
+	/// The points within the document space rectangle that the pointer can snap to, as used by the snapping of tools around the pointer.
+	///
+	/// These are the corners and centers of the bounds of visible layers, intersections between guides, the points where guides cross the lines of the grid, and the points of the grid.
+	/// Only the part of the grid within the rectangle is enumerated, so it should be kept to the area around the pointer.
+	/// At most [`Self::MAX_GRID_SNAP_LINES`] lines of the grid nearest the center of the rectangle are enumerated along each axis, and the grid is left out where it is too far from the origin for its lines to be told apart.
+	pub fn snap_candidates(&self, query_document: [DVec2; 2], grid: Option<GridSettings>) -> Vec<SnapCandidate> {
+		let [min, max] = Quad::sort_bounds(query_document);
+		let inside = |point: DVec2| point.cmpge(min).all() && point.cmple(max).all();
+		let mut candidates = Vec::new();
+
+		for layer in self.all_layers().filter(|&layer| !self.is_hidden(layer)) {
+			let Some([layer_min, layer_max]) = self.bounding_box_document(layer) else { continue };
+			let points = [
+				layer_min,
+				DVec2::new(layer_max.x, layer_min.y),
+				layer_max,
+				DVec2::new(layer_min.x, layer_max.y),
+				(layer_min + layer_max) / 2.,
+			];
+			let source = SnapSource::Layer(layer);
+			candidates.extend(points.into_iter().filter(|&point| inside(point)).map(|point| SnapCandidate { point, source }));
+		}
+
+		let guides_along = |axis: GuideAxis| self.guides.iter().filter(move |(_, guide)| guide.axis == axis);
+		for &(vertical_id, vertical) in guides_along(GuideAxis::Vertical) {
+			for &(horizontal_id, horizontal) in guides_along(GuideAxis::Horizontal) {
+				let point = DVec2::new(vertical.position_document, horizontal.position_document);
+				if inside(point) {
+					let source = SnapSource::GuideIntersection(vertical_id, horizontal_id);
+					candidates.push(SnapCandidate { point, source });
+				}
+			}
+		}
+
+		let Some(GridSettings { origin, spacing }) = grid.filter(|grid| grid.spacing.cmpgt(DVec2::ZERO).all() && grid.spacing.is_finite()) else {
+			return candidates;
+		};
+		// The lines of the grid crossing the rectangle along each axis, kept to those nearest its center
+		let center = (((min + max) / 2. - origin) / spacing).round();
+		let first = ((min - origin) / spacing).ceil().max(center - (Self::MAX_GRID_SNAP_LINES / 2) as f64);
+		let last = ((max - origin) / spacing).floor().min(first + (Self::MAX_GRID_SNAP_LINES - 1) as f64);
+		// Beyond this the indices of neighboring lines can't all be represented
+		let max_index = (1_u64 << f64::MANTISSA_DIGITS) as f64;
+		if !first.is_finite() || !last.is_finite() || first.abs().max(last.abs()).max_element() > max_index {
+			return candidates;
+		}
+		let lines = |axis: usize| (first[axis] as i64..=last[axis] as i64).map(move |index| origin[axis] + index as f64 * spacing[axis]);
+
+		for &(id, guide) in self.guides.iter() {
+			let position = guide.position_document;
+			let source = SnapSource::GuideOnGrid(id);
+			match guide.axis {
+				GuideAxis::Vertical if (min.x..=max.x).contains(&position) => candidates.extend(lines(1).map(|y| SnapCandidate {
+					point: DVec2::new(position, y),
+					source,
+				})),
+				GuideAxis::Horizontal if (min.y..=max.y).contains(&position) => candidates.extend(lines(0).map(|x| SnapCandidate {
+					point: DVec2::new(x, position),
+					source,
+				})),
+				_ => {}
+			}
+		}
+		for y in lines(1) {
+			candidates.extend(lines(0).map(|x| SnapCandidate {
+				point: DVec2::new(x, y),
+				source: SnapSource::Grid,
+			}));
+		}
+		candidates
+	}
+}
+
+/// Identifies a guide added with [`DocumentMetadata::add_guide`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct GuideId(u64);
+
+/// A line across the whole document that can be snapped to.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct Guide {
+	pub axis: GuideAxis,
+	/// The x coordinate of a vertical guide, or the y coordinate of a horizontal guide, in document space.
+	#[serde(rename = "positionDocument")]
+	pub position_document: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum GuideAxis {
+	Horizontal,
+	Vertical,
+}
+
+/// A grid of points in document space with one at `origin`, see [`DocumentMetadata::snap_candidates`].
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct GridSettings {
+	pub origin: DVec2,
+	pub spacing: DVec2,
+}
+
+/// A point in document space that can be snapped to, and what it comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapCandidate {
+	pub point: DVec2,
+	pub source: SnapSource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SnapSource {
+	/// A corner or the center of the bounds of the layer.
+	Layer(LayerNodeIdentifier),
+	/// Where a vertical and a horizontal guide cross.
+	GuideIntersection(GuideId, GuideId),
+	/// Where the guide crosses a line of the grid.
+	GuideOnGrid(GuideId),
+	Grid,
+}
+
+// structure modifications
+impl DocumentMetadata {
+	/// Restack the children of the layer into the order given from top to bottom, which must contain each of the current children exactly once.
+	pub fn reorder_children(&mut self, parent: LayerNodeIdentifier, new_order: &[LayerNodeIdentifier]) -> Result<(), ReorderError> {
+		let children = parent.children(self).collect::<HashSet<_>>();
+		let mut seen = HashSet::new();
+		let extra = new_order.iter().copied().filter(|child| !children.contains(child) || !seen.insert(*child)).collect::<Vec<_>>();
+		let missing = parent.children(self).filter(|child| !seen.contains(child)).collect::<Vec<_>>();
+		if !missing.is_empty() || !extra.is_empty() {
+			return Err(ReorderError { missing, extra });
+		}
+		if new_order.is_empty() {
+			return Ok(());
+		}
+
+		for (index, &child) in new_order.iter().enumerate() {
+			let relations = self.get_structure_mut(child);
+			relations.previous_sibling = index.checked_sub(1).map(|previous| new_order[previous]);
+			relations.next_sibling = new_order.get(index + 1).copied();
+		}
+		let relations = self.get_structure_mut(parent);
+		relations.first_child = new_order.first().copied();
+		relations.last_child = new_order.last().copied();
+		#[cfg(feature = "journal")]
+		self.record_reordered(parent);
+		Ok(())
+	}
+
+	/// The parent and index among its children (from the top) that the dragged layers would be inserted at when dropped onto the hovered layer.
+	///
+	/// Dropping inside a layer that is not a folder drops below it instead. The index is where the dragged layers end up once they have been removed from their current position,
+	/// so it is shifted up by any dragged siblings above the insertion point. Returns `None` if a dragged layer would be moved into itself.
+	pub fn resolve_drop_target(&self, hovered_layer: LayerNodeIdentifier, position: DropPosition, dragged: &[LayerNodeIdentifier]) -> Option<(LayerNodeIdentifier, usize)> {
+		let position = match position {
+			DropPosition::Inside if hovered_layer != LayerNodeIdentifier::ROOT && !self.is_folder(hovered_layer) => DropPosition::Below,
+			position => position,
+		};
+		let (parent, index) = match position {
+			DropPosition::Inside => (hovered_layer, 0),
+			DropPosition::Above | DropPosition::Below => {
+				let parent = hovered_layer.parent(self)?;
+				let index = parent.children(self).position(|child| child == hovered_layer)?;
+				(parent, if position == DropPosition::Above { index } else { index + 1 })
+			}
+		};
+		if parent.ancestors(self).any(|ancestor| dragged.contains(&ancestor)) {
+			return None;
+		}
+
+		let dragged_above = parent.children(self).take(index).filter(|child| dragged.contains(child)).count();
+		Some((parent, index - dragged_above))
+	}
+
+	/// The parent and index among its children (from the top) that a newly drawn layer should be inserted at. The first of these that applies is used:
+	///
+	/// 1. The top of the deepest selected folder or artboard, with ties won by the topmost.
+	/// 2. Directly above the topmost selected layer, in its parent.
+	/// 3. The top of the topmost artboard containing the document space cursor.
+	/// 4. The top of the active artboard.
+	/// 5. The top of the root.
+	///
+	/// Selected layers that aren't in the layer tree are ignored, so the result is always a parent in the tree and an index within its children.
+	pub fn new_layer_insertion_target(&self, cursor_document: Option<DVec2>) -> (LayerNodeIdentifier, usize) {
+		let mut topmost_selected = None;
+		let mut deepest_folder: Option<(LayerNodeIdentifier, usize)> = None;
+		for layer in self.selected_layers() {
+			topmost_selected = topmost_selected.or(Some(layer));
+			if !self.is_folder(layer) && !self.is_artboard(layer) {
+				continue;
+			}
+			let depth = layer.depth(self);
+			if !deepest_folder.is_some_and(|(_, deepest)| deepest >= depth) {
+				deepest_folder = Some((layer, depth));
+			}
+		}
+		if let Some((folder, _)) = deepest_folder {
+			return (folder, 0);
+		}
+		if let Some(layer) = topmost_selected {
+			if let Some(parent) = layer.parent(self) {
+				let index = parent.children(self).position(|child| child == layer).unwrap_or_default();
+				return (parent, index);
+			}
+		}
+
+		let under_cursor = cursor_document.and_then(|cursor| {
+			self.all_layers()
+				.filter(|&layer| self.is_artboard(layer))
+				.find(|&artboard| self.artboard_bounds_document(artboard).is_some_and(|[min, max]| cursor.cmpge(min).all() && cursor.cmple(max).all()))
+		});
+		if let Some(artboard) = under_cursor {
+			return (artboard, 0);
+		}
+		let active = self.active_artboard();
+		if active != LayerNodeIdentifier::ROOT && active.parent(self).is_some() {
+			return (active, 0);
+		}
+		(LayerNodeIdentifier::ROOT, 0)
+	}
+
+	/// Copy the layer and its descendants to the nodes they were duplicated to in the graph, given by the map from each original node to its copy, so the copies
+	/// can be used before the structure is reloaded. The copy is placed above the layer, and its cached data such as click targets and tags come from the originals.
+	///
+	/// Returns the copy of the layer, or `None` without modifying anything if the layer is the root, the map is missing any of the layers, or the copies already exist.
+	pub fn duplicate_subtree(&mut self, layer: LayerNodeIdentifier, id_map: &HashMap<NodeId, NodeId>) -> Option<LayerNodeIdentifier> {
+		if layer == LayerNodeIdentifier::ROOT || !self.layer_exists(layer) {
+			return None;
+		}
+		let originals = std::iter::once(layer).chain(layer.decendants(self)).collect::<Vec<_>>();
+		let copies = originals
+			.iter()
+			.map(|original| id_map.get(&original.to_node()).map(|&id| LayerNodeIdentifier::new_unchecked(id)))
+			.collect::<Option<Vec<_>>>()?;
+		if copies.iter().any(|&copy| self.layer_exists(copy) || copy == LayerNodeIdentifier::ROOT) || copies.iter().collect::<HashSet<_>>().len() != copies.len() {
+			return None;
+		}
+		let copy_of = originals.iter().copied().zip(copies.iter().copied()).collect::<HashMap<_, _>>();
+
+		// The data is copied before the copies are added, so they are journaled as folders and artboards like the originals
+		for (&original, &copy) in originals.iter().zip(&copies) {
+			self.copy_layer_data(original, copy);
+		}
+		layer.add_before(self, copies[0]);
+		// Descendants are in depth first order, so each parent is copied before its children, which are pushed in order
+		for (&original, &copy) in originals.iter().zip(&copies).skip(1) {
+			let parent = original.parent(self).map_or(LayerNodeIdentifier::ROOT, |parent| copy_of[&parent]);
+			parent.push_child(self, copy);
+		}
+		self.mark_layers_changed(copies.iter().copied());
+		Some(copies[0])
+	}
+
+	/// Take a copy of the layer tree, folders, artboards, selection and the data of each layer to return to with [`StructureTransaction::rollback`] if a multi step modification fails partway.
+	///
+	/// The copy shares the data until it is modified, so is cheap to take. Only one transaction can be open at a time, and it must be committed, rolled back or dropped before another is begun.
+	pub fn begin_structure_transaction(&mut self) -> Result<StructureTransaction, StructureError> {
+		if self.structure_transaction.0.strong_count() > 0 {
+			return Err(StructureError::TransactionInProgress);
+		}
+		let token = Arc::new(());
+		self.structure_transaction = OpenTransaction(Arc::downgrade(&token));
+		Ok(StructureTransaction {
+			token,
+			structure: self.structure.clone(),
+			folders: self.folders.clone(),
+			artboards: self.artboards.clone(),
+			selected_nodes: self.selected_nodes.clone(),
+			selection_sources: self.selection_sources.clone(),
+			layer_data: Box::new(LayerDataSnapshot {
+				names: self.names.clone(),
+				artboard_info: self.artboard_info.clone(),
+				hidden: self.hidden.clone(),
+				locked: self.locked.clone(),
+				opacities: self.opacities.clone(),
+				blend_modes: self.blend_modes.clone(),
+				click_targets: self.click_targets.clone(),
+				text_hit_regions: self.text_hit_regions.clone(),
+				render_padding: self.render_padding.clone(),
+				chrome_click_targets: self.chrome_click_targets.clone(),
+				chrome_document_to_viewport: self.chrome_document_to_viewport,
+				tags: self.tags.clone(),
+				previous_frame_bounds: self.previous_frame_bounds.clone(),
+				isolation_root: self.isolation_root,
+				classifications: self.classifications.clone(),
+			}),
+		})
+	}
+
+	/// Run the modification in a transaction, rolling back to the state before it if it fails.
+	fn in_structure_transaction<T>(&mut self, modify: impl FnOnce(&mut Self) -> Result<T, StructureError>) -> Result<T, StructureError> {
+		let transaction = self.begin_structure_transaction()?;
+		let result = modify(self);
+		match result {
+			Ok(_) => transaction.commit(self),
+			Err(_) => transaction.rollback(self),
+		}
+		result
+	}
+
+	/// Move the layers in the order given into a new folder, which is placed where the first layer was, and select it. This mirrors grouping in the graph so the folder can be used before the structure is reloaded.
+	///
+	/// If any of the layers can't be moved, such as if it doesn't exist or contains the first layer, nothing is modified.
+	pub fn group_layers(&mut self, layers: &[LayerNodeIdentifier], folder: LayerNodeIdentifier) -> Result<(), StructureError> {
+		self.in_structure_transaction(|document_metadata| {
+			if folder == LayerNodeIdentifier::ROOT || document_metadata.layer_exists(folder) {
+				return Err(StructureError::LayerExists(folder));
+			}
+			let Some(&first) = layers.first() else { return Ok(()) };
+			if first == LayerNodeIdentifier::ROOT || !document_metadata.layer_exists(first) {
+				return Err(StructureError::MissingLayer(first));
+			}
+			Arc::make_mut(&mut document_metadata.folders).insert(folder);
+			first.link_before(document_metadata, folder);
+			#[cfg(feature = "journal")]
+			document_metadata.record_added(folder);
+
+			for &layer in layers {
+				if layer == LayerNodeIdentifier::ROOT || !document_metadata.layer_exists(layer) {
+					return Err(StructureError::MissingLayer(layer));
+				}
+				if folder.starts_with(layer, document_metadata) {
+					return Err(StructureError::InvalidMove(layer));
+				}
+				layer.reparent(document_metadata, folder);
+			}
+			let _ = document_metadata.set_selected_nodes(vec![folder.to_node()]);
+			Ok(())
+		})
+	}
+
+	/// Move the children of the folder to where it is in its parent, delete it, and select the children, which are returned.
+	pub fn ungroup(&mut self, folder: LayerNodeIdentifier) -> Result<Vec<LayerNodeIdentifier>, StructureError> {
+		self.in_structure_transaction(|document_metadata| {
+			if folder == LayerNodeIdentifier::ROOT || !document_metadata.layer_exists(folder) {
+				return Err(StructureError::MissingLayer(folder));
+			}
+			if !document_metadata.is_folder(folder) || document_metadata.is_artboard(folder) {
+				return Err(StructureError::NotAFolder(folder));
+			}
+			let children = folder.children(document_metadata).collect::<Vec<_>>();
+			for &child in &children {
+				child.detach(document_metadata);
+				folder.link_before(document_metadata, child);
+				child.update_descendant_relations(document_metadata);
+			}
+			Arc::make_mut(&mut document_metadata.folders).remove(&folder);
+			folder.delete(document_metadata);
+			let _ = document_metadata.set_selected_nodes(children.iter().copied().map(LayerNodeIdentifier::to_node).collect());
+			Ok(children)
+		})
+	}
+
+	/// Move the dragged layers, keeping their order, to where [`Self::resolve_drop_target`] puts them when dropped onto the hovered layer.
+	///
+	/// If the drop isn't allowed or any of the dragged layers don't exist, nothing is modified.
+	pub fn drop_layers(&mut self, hovered_layer: LayerNodeIdentifier, position: DropPosition, dragged: &[LayerNodeIdentifier]) -> Result<(), StructureError> {
+		let mut seen = HashSet::new();
+		for &layer in dragged {
+			if layer == LayerNodeIdentifier::ROOT || !self.layer_exists(layer) {
+				return Err(StructureError::MissingLayer(layer));
+			}
+			if !seen.insert(layer) {
+				return Err(StructureError::InvalidMove(layer));
+			}
+		}
+		// Descendants of dragged folders move along inside them rather than being taken out
+		let dragged = self.shallowest_unique_layers(dragged.iter().copied());
+		let (parent, index) = self.resolve_drop_target(hovered_layer, position, &dragged).ok_or(StructureError::InvalidMove(hovered_layer))?;
+		self.in_structure_transaction(|document_metadata| {
+			for &layer in &dragged {
+				layer.detach(document_metadata);
+			}
+			let below = parent.children(document_metadata).nth(index);
+			for &layer in &dragged {
+				match below {
+					Some(below) => below.link_before(document_metadata, layer),
+					None => parent.link_last_child(document_metadata, layer),
+				}
+				layer.update_descendant_relations(document_metadata);
+			}
+			Ok(())
+		})
+	}
+
+	fn copy_layer_data(&mut self, original: LayerNodeIdentifier, copy: LayerNodeIdentifier) {
+		fn copy_member(set: &mut Arc<HashSet<LayerNodeIdentifier>>, original: LayerNodeIdentifier, copy: LayerNodeIdentifier) {
+			if set.contains(&original) {
+				Arc::make_mut(set).insert(copy);
+			}
+		}
+		fn copy_entry<K: Eq + std::hash::Hash + Clone, V: Clone>(map: &mut Arc<HashMap<K, V>>, original: K, copy: K) {
+			if let Some(value) = map.get(&original).cloned() {
+				Arc::make_mut(map).insert(copy, value);
+			}
+		}
+		copy_member(&mut self.artboards, original, copy);
+		copy_member(&mut self.folders, original, copy);
+		copy_member(&mut self.hidden, original, copy);
+		copy_member(&mut self.collapsed, original, copy);
+		copy_member(&mut self.locked, original, copy);
+		copy_entry(&mut self.names, original, copy);
+		copy_entry(&mut self.opacities, original, copy);
+		copy_entry(&mut self.blend_modes, original, copy);
+		copy_entry(&mut self.click_targets, original, copy);
+		copy_entry(&mut self.text_hit_regions, original, copy);
+		copy_entry(&mut self.render_padding, original, copy);
+		copy_entry(&mut self.tags, original, copy);
+		copy_entry(&mut self.upstream_transforms, original.to_node(), copy.to_node());
+		copy_entry(&mut self.transform_generations, original.to_node(), copy.to_node());
+		if let Some(kind) = self.classifications.get(&original).copied() {
+			Arc::make_mut(&mut self.classifications).insert(copy, kind);
+			Arc::make_mut(&mut self.layers_by_kind).entry(kind).or_default().insert(copy);
+		}
+	}
+}
+
+/// Where layers are dropped relative to the hovered layer, see [`DocumentMetadata::resolve_drop_target`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub enum DropPosition {
+	Above,
+	Below,
+	Inside,
+}
+
+/// The order given to [`DocumentMetadata::reorder_children`] was not a permutation of the children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorderError {
+	/// Children of the layer that were not in the new order.
+	pub missing: Vec<LayerNodeIdentifier>,
+	/// Layers in the new order that are not children of the layer, or were repeated.
+	pub extra: Vec<LayerNodeIdentifier>,
+}
+
+/// The layer tree, folders, artboards, selection and data of each layer before a modification, see [`DocumentMetadata::begin_structure_transaction`].
+///
+/// The transaction is closed when this is dropped, so dropping it without committing or rolling back keeps the modifications.
+#[must_use = "the transaction must be committed or rolled back"]
+pub struct StructureTransaction {
+	/// Shared with the [`OpenTransaction`] of the metadata it was begun on, which only stays open while this is alive.
+	token: Arc<()>,
+	structure: Arc<HashMap<LayerNodeIdentifier, NodeRelations>>,
+	folders: Arc<HashSet<LayerNodeIdentifier>>,
+	artboards: Arc<HashSet<LayerNodeIdentifier>>,
+	selected_nodes: Vec<NodeId>,
+	selection_sources: HashMap<NodeId, SelectionSource>,
+	layer_data: Box<LayerDataSnapshot>,
+}
+
+impl StructureTransaction {
+	/// Is this the transaction that is open on the metadata, rather than on a clone of it or another document?
+	fn is_open_on(&self, document_metadata: &DocumentMetadata) -> bool {
+		std::ptr::eq(document_metadata.structure_transaction.0.as_ptr(), Arc::as_ptr(&self.token))
+	}
+
+	/// Keep the modifications made since the transaction began.
+	pub fn commit(self, document_metadata: &mut DocumentMetadata) {
+		debug_assert!(self.is_open_on(document_metadata), "Committed a structure transaction on metadata it wasn't begun on");
+	}
+
+	/// Undo the modifications to the layer tree, folders, artboards and selection made since the transaction began, and restore the data of layers that were deleted.
+	///
+	/// Observers are told about the restored layers with [`ChangeEvent::LayersRestored`]. Nothing is modified if the transaction was begun on other metadata.
+	pub fn rollback(self, document_metadata: &mut DocumentMetadata) {
+		if !self.is_open_on(document_metadata) {
+			warn!("Rolled back a structure transaction on metadata it wasn't begun on");
+			return;
+		}
+		let selection_before = document_metadata.selection_before_change();
+		let deleted = self.structure.keys().copied().filter(|&layer| !document_metadata.layer_exists(layer)).collect::<HashSet<_>>();
+		document_metadata.structure = self.structure;
+		document_metadata.folders = self.folders;
+		document_metadata.artboards = self.artboards;
+		document_metadata.selected_nodes = self.selected_nodes;
+		document_metadata.selection_sources = self.selection_sources;
+		self.layer_data.restore(document_metadata, &deleted);
+		document_metadata.structure_generation += 1;
+		#[cfg(feature = "journal")]
+		document_metadata.record_resync();
+		let restored = document_metadata.root().decendants(document_metadata).filter(|layer| deleted.contains(layer)).collect::<Vec<_>>();
+		document_metadata.mark_layers_changed(restored.iter().copied());
+		if !restored.is_empty() {
+			document_metadata.notify(ChangeEvent::LayersRestored(restored));
+		}
+		document_metadata.notify_selection_changed(selection_before);
+	}
+}
+
+/// Marks the metadata as having a [`StructureTransaction`] open for as long as the transaction is alive.
+#[derive(Debug, Default)]
+struct OpenTransaction(Weak<()>);
+
+impl Clone for OpenTransaction {
+	/// A transaction only rolls back the metadata it was begun on, so clones start without one.
+	fn clone(&self) -> Self {
+		Self::default()
+	}
+}
+
+/// The data that deleting a layer removes, taken when a [`StructureTransaction`] begins.
+struct LayerDataSnapshot {
+	names: Arc<HashMap<LayerNodeIdentifier, String>>,
+	artboard_info: Arc<HashMap<LayerNodeIdentifier, ArtboardInfo>>,
+	hidden: Arc<HashSet<LayerNodeIdentifier>>,
+	locked: Arc<HashSet<LayerNodeIdentifier>>,
+	opacities: Arc<HashMap<LayerNodeIdentifier, f64>>,
+	blend_modes: Arc<HashMap<LayerNodeIdentifier, BlendMode>>,
+	click_targets: Arc<HashMap<LayerNodeIdentifier, Arc<Vec<ClickTarget>>>>,
+	text_hit_regions: Arc<HashMap<LayerNodeIdentifier, Vec<TextHitRegion>>>,
+	render_padding: Arc<HashMap<LayerNodeIdentifier, [f64; 4]>>,
+	chrome_click_targets: Arc<HashMap<LayerNodeIdentifier, Vec<Quad>>>,
+	chrome_document_to_viewport: DAffine2,
+	tags: Arc<HashMap<LayerNodeIdentifier, LayerTag>>,
+	previous_frame_bounds: Option<Arc<HashMap<LayerNodeIdentifier, [DVec2; 2]>>>,
+	isolation_root: Option<LayerNodeIdentifier>,
+	classifications: Arc<HashMap<LayerNodeIdentifier, LayerClassification>>,
+}
+
+impl LayerDataSnapshot {
+	/// Put back the data of the deleted layers, keeping that of the other layers as it is now.
+	fn restore(&self, document_metadata: &mut DocumentMetadata, deleted: &HashSet<LayerNodeIdentifier>) {
+		fn restore_members(set: &mut Arc<HashSet<LayerNodeIdentifier>>, before: &HashSet<LayerNodeIdentifier>, deleted: &HashSet<LayerNodeIdentifier>) {
+			if before.iter().any(|layer| deleted.contains(layer)) {
+				Arc::make_mut(set).extend(before.iter().filter(|layer| deleted.contains(layer)));
+			}
+		}
+		fn restore_entries<V: Clone>(map: &mut Arc<HashMap<LayerNodeIdentifier, V>>, before: &HashMap<LayerNodeIdentifier, V>, deleted: &HashSet<LayerNodeIdentifier>) {
+			let entries = deleted.iter().filter_map(|layer| before.get(layer).map(|value| (*layer, value.clone()))).collect::<Vec<_>>();
+			if !entries.is_empty() {
+				Arc::make_mut(map).extend(entries);
+			}
+		}
+		if deleted.is_empty() {
+			return;
+		}
+		restore_entries(&mut document_metadata.names, &self.names, deleted);
+		restore_entries(&mut document_metadata.artboard_info, &self.artboard_info, deleted);
+		restore_members(&mut document_metadata.hidden, &self.hidden, deleted);
+		restore_members(&mut document_metadata.locked, &self.locked, deleted);
+		restore_entries(&mut document_metadata.opacities, &self.opacities, deleted);
+		restore_entries(&mut document_metadata.blend_modes, &self.blend_modes, deleted);
+		restore_entries(&mut document_metadata.click_targets, &self.click_targets, deleted);
+		restore_entries(&mut document_metadata.text_hit_regions, &self.text_hit_regions, deleted);
+		restore_entries(&mut document_metadata.render_padding, &self.render_padding, deleted);
+		// Regions registered for another viewport transform have been cleared since
+		if document_metadata.chrome_document_to_viewport == self.chrome_document_to_viewport {
+			restore_entries(&mut document_metadata.chrome_click_targets, &self.chrome_click_targets, deleted);
+		}
+		restore_entries(&mut document_metadata.tags, &self.tags, deleted);
+		if let (Some(previous_frame_bounds), Some(before)) = (&mut document_metadata.previous_frame_bounds, &self.previous_frame_bounds) {
+			restore_entries(previous_frame_bounds, before, deleted);
+		}
+		if document_metadata.isolation_root.is_none() && self.isolation_root.is_some_and(|layer| deleted.contains(&layer)) {
+			document_metadata.isolation_root = self.isolation_root;
+		}
+		for layer in deleted {
+			if let Some(&kind) = self.classifications.get(layer) {
+				Arc::make_mut(&mut document_metadata.classifications).insert(*layer, kind);
+				Arc::make_mut(&mut document_metadata.layers_by_kind).entry(kind).or_default().insert(*layer);
+			}
+		}
+	}
+}
+
+/// Why a modification of the layer tree failed, in which case it was left as it was before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StructureError {
+	/// Another [`StructureTransaction`] is still open.
+	TransactionInProgress,
+	/// The layer isn't in the layer tree, or is the root.
+	MissingLayer(LayerNodeIdentifier),
+	/// The layer to be added is already in the layer tree.
+	LayerExists(LayerNodeIdentifier),
+	NotAFolder(LayerNodeIdentifier),
+	/// The layer can't be moved there, such as into itself.
+	InvalidMove(LayerNodeIdentifier),
+}
+
+/// The corners of the convex hull of the points anticlockwise, excluding points along its edges, found with Andrew's monotone chain algorithm.
+fn convex_hull(mut points: Vec<DVec2>) -> Vec<DVec2> {
+	points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+	points.dedup();
+	if points.len() < 3 {
+		return points;
+	}
+
+	// Add the points in turn, removing previous points that no longer make a left turn
+	let half_hull = |points: &mut dyn Iterator<Item = DVec2>| {
+		let mut hull: Vec<DVec2> = Vec::new();
+		for point in points {
+			while let [.., a, b] = hull[..] {
+				if (b - a).perp_dot(point - a) > 0. {
+					break;
+				}
+				hull.pop();
+			}
+			hull.push(point);
+		}
+		// The last point is the first of the other half
+		hull.pop();
+		hull
+	};
+	let mut hull = half_hull(&mut points.iter().copied());
+	hull.extend(half_hull(&mut points.iter().rev().copied()));
+	hull
+}
+
+/// The bounds of the curve from its endpoints and extrema, with each extremum refined by Newton's method on the derivative along its axis.
+fn exact_bezier_bounds(bezier: bezier_rs::Bezier) -> [DVec2; 2] {
+	let mut bounds = [bezier.start.min(bezier.end), bezier.start.max(bezier.end)];
+	let derivative = bezier.derivative();
+	let second_derivative = derivative.as_ref().and_then(bezier_rs::Bezier::derivative);
+	for (axis, extrema) in bezier.local_extrema().into_iter().enumerate() {
+		for mut t in extrema {
+			if let (Some(derivative), Some(second_derivative)) = (&derivative, &second_derivative) {
+				for _ in 0..4 {
+					let slope = derivative.evaluate(bezier_rs::TValue::Parametric(t))[axis];
+					let curvature = second_derivative.evaluate(bezier_rs::TValue::Parametric(t))[axis];
+					if curvature.abs() < 1e-12 {
+						break;
+					}
+					t = (t - slope / curvature).clamp(0., 1.);
+				}
+			}
+			let point = bezier.evaluate(bezier_rs::TValue::Parametric(t));
+			bounds = [bounds[0].min(point), bounds[1].max(point)];
+		}
+	}
+	bounds
+}
+
+/// Distance from a point to an axis aligned rectangle, which is `0` for points inside the rectangle.
+fn distance_to_bounds([min, max]: [DVec2; 2], point: DVec2) -> f64 {
+	point.clamp(min, max).distance(point)
+}
+
+/// Id of a layer node
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize, specta::Type)]
+pub struct LayerNodeIdentifier(NonZeroU64);
+
+impl Default for LayerNodeIdentifier {
+	fn default() -> Self {
+		Self::ROOT
+	}
+}
+
+impl core::fmt::Debug for LayerNodeIdentifier {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_tuple("LayerNodeIdentifier").field(&self.to_node()).finish()
+	}
+}
+
+impl core::fmt::Display for LayerNodeIdentifier {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_fmt(format_args!("Layer(node_id={})", self.to_node()))
+	}
+}
+
+impl LayerNodeIdentifier {
+	pub const ROOT: Self = LayerNodeIdentifier::new_unchecked(0);
+
+	/// Construct a [`LayerNodeIdentifier`] without checking if it is a layer node
+	pub const fn new_unchecked(node_id: NodeId) -> Self {
+		// Safety: will always be >=1
+		Self(unsafe { NonZeroU64::new_unchecked(node_id + 1) })
+	}
+
+	/// Construct a [`LayerNodeIdentifier`], debug asserting that it is a layer node
+	#[track_caller]
+	pub fn new(node_id: NodeId, network: &NodeNetwork) -> Self {
+		debug_assert!(
+			is_layer_node(node_id, network),
+			"Layer identifier constructed from non layer node {node_id}: {:#?}",
+			network.nodes.get(&node_id)
+		);
+		Self::new_unchecked(node_id)
+	}
+
+	pub fn from_path(path: &[u64], network: &NodeNetwork) -> Self {
+		Self::new(*path.last().unwrap(), network)
+	}
+
+	/// Access the node id of this layer
+	pub fn to_node(self) -> NodeId {
+		u64::from(self.0) - 1
+	}
+
+	/// Convert layer to layer path
+	pub fn to_path(self) -> Vec<NodeId> {
+		vec![self.to_node()]
+	}
+
+	/// Access the parent layer if possible
+	pub fn parent(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
+		document_metadata.get_relations(self).and_then(|relations| relations.parent)
+	}
+
+	/// Access the previous sibling of this layer (up the layer tree)
+	pub fn previous_sibling(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
+		document_metadata.get_relations(self).and_then(|relations| relations.previous_sibling)
+	}
+
+	/// Access the next sibling of this layer (down the layer tree)
+	pub fn next_sibling(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
+		document_metadata.get_relations(self).and_then(|relations| relations.next_sibling)
+	}
+
+	/// Access the first child of this layer (top most in layer tree)
+	pub fn first_child(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
+		document_metadata.get_relations(self).and_then(|relations| relations.first_child)
+	}
+
+	/// Access the last child of this layer (bottom most in layer tree)
+	pub fn last_child(self, document_metadata: &DocumentMetadata) -> Option<LayerNodeIdentifier> {
+		document_metadata.get_relations(self).and_then(|relations| relations.last_child)
+	}
+
+	/// Does the layer have children?
+	pub fn has_children(self, document_metadata: &DocumentMetadata) -> bool {
+		self.first_child(document_metadata).is_some()
+	}
+
+	/// The number of direct children of this layer
+	pub fn child_count(self, document_metadata: &DocumentMetadata) -> usize {
+		document_metadata.get_relations(self).map_or(0, |relations| relations.child_count)
+	}
+
+	/// Iterator over all direct children (excluding self and recursive children)
+	pub fn children(self, document_metadata: &DocumentMetadata) -> ChildrenIter {
+		ChildrenIter {
+			front: self.first_child(document_metadata),
+			back: self.last_child(document_metadata),
+			document_metadata,
+		}
+	}
+
+	/// Iterator over all direct children, starting from the bottom most in the layer tree
+	pub fn children_rev(self, document_metadata: &DocumentMetadata) -> std::iter::Rev<ChildrenIter> {
+		self.children(document_metadata).rev()
+	}
+
+	/// Access the direct child at the index, walking from whichever end of the children is closer
+	pub fn nth_child(self, document_metadata: &DocumentMetadata, index: usize) -> Option<LayerNodeIdentifier> {
+		let child_count = self.child_count(document_metadata);
+		if index >= child_count {
+			return None;
+		}
+		if index < child_count / 2 {
+			self.children(document_metadata).nth(index)
+		} else {
+			self.children_rev(document_metadata).nth(child_count - 1 - index)
+		}
+	}
+
+	/// All ancestors of this layer, including self, going to the document root
+	pub fn ancestors(self, document_metadata: &DocumentMetadata) -> AxisIter {
+		AxisIter {
+			layer_node: Some(self),
+			next_node: Self::parent,
+			document_metadata,
+		}
+	}
+
+	/// Iterator through all the last children, starting from self
+	pub fn last_children(self, document_metadata: &DocumentMetadata) -> AxisIter {
+		AxisIter {
+			layer_node: Some(self),
+			next_node: Self::last_child,
+			document_metadata,
+		}
+	}
+
+	/// Iterator through all decendants, including recursive children (not including self)
+	pub fn decendants(self, document_metadata: &DocumentMetadata) -> DecendantsIter {
+		DecendantsIter {
+			front: self.first_child(document_metadata),
+			back: self.last_child(document_metadata).and_then(|child| child.last_children(document_metadata).last()),
+			document_metadata,
+		}
+	}
+
+	/// All decendants collected into a list, for holding onto across modifications of the layer tree
+	pub fn descendants_vec(self, document_metadata: &DocumentMetadata) -> Vec<LayerNodeIdentifier> {
+		self.decendants(document_metadata).collect()
+	}
+
+	/// Add a child towards the top of the layer tree
+	pub fn push_front_child(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
+		let parent = document_metadata.get_structure_mut(self);
+		parent.child_count += 1;
+		let old_first_child = parent.first_child.replace(new);
+		parent.last_child.get_or_insert(new);
+		if let Some(old_first_child) = old_first_child {
+			document_metadata.get_structure_mut(old_first_child).previous_sibling = Some(new);
+		}
+		document_metadata.get_structure_mut(new).next_sibling = old_first_child;
+		new.set_parent(document_metadata, Some(self));
+		#[cfg(feature = "journal")]
+		document_metadata.record_added(new);
+	}
+
+	/// Add a child towards the bottom of the layer tree
+	pub fn push_child(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
+		self.link_last_child(document_metadata, new);
+		#[cfg(feature = "journal")]
+		document_metadata.record_added(new);
+	}
+
+	fn link_last_child(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		let parent = document_metadata.get_structure_mut(self);
+		parent.child_count += 1;
+		let old_last_child = parent.last_child.replace(new);
+		parent.first_child.get_or_insert(new);
+		if let Some(old_last_child) = old_last_child {
+			document_metadata.get_structure_mut(old_last_child).next_sibling = Some(new);
+		}
+		document_metadata.get_structure_mut(new).previous_sibling = old_last_child;
+		new.set_parent(document_metadata, Some(self));
+	}
+
+	/// Set the parent of a layer without children, along with its cached [`Self::child_of_root`] and [`Self::depth`].
+	fn set_parent(self, document_metadata: &mut DocumentMetadata, parent: Option<LayerNodeIdentifier>) {
+		let top_level_ancestor = match parent {
+			Some(LayerNodeIdentifier::ROOT) => Some(self),
+			Some(parent) => parent.child_of_root(document_metadata),
+			None => None,
+		};
+		let depth = parent.map_or(0, |parent| parent.depth(document_metadata) + 1);
+		let structure = document_metadata.get_structure_mut(self);
+		structure.parent = parent;
+		structure.top_level_ancestor = top_level_ancestor;
+		structure.depth = depth;
+	}
+
+	/// Move the layer along with all its children to the bottom of a new parent
+	pub fn reparent(self, document_metadata: &mut DocumentMetadata, new_parent: LayerNodeIdentifier) {
+		assert!(!new_parent.starts_with(self, document_metadata), "Cannot move a layer into itself");
+		self.detach(document_metadata);
+		new_parent.link_last_child(document_metadata, self);
+		self.update_descendant_relations(document_metadata);
+	}
+
+	/// Remove the layer from its siblings and parent, keeping its children, so it can be linked elsewhere.
+	fn detach(self, document_metadata: &mut DocumentMetadata) {
+		self.unlink(document_metadata);
+		let structure = document_metadata.get_structure_mut(self);
+		structure.previous_sibling = None;
+		structure.next_sibling = None;
+		structure.parent = None;
+	}
+
+	/// Set the cached [`Self::child_of_root`] and [`Self::depth`] of the descendants after the layer has been moved.
+	fn update_descendant_relations(self, document_metadata: &mut DocumentMetadata) {
+		let top_level_ancestor = self.child_of_root(document_metadata);
+		// Descendants are visited after their parents, so each parent's depth is already updated
+		for descendant in self.descendants_vec(document_metadata) {
+			let depth = descendant.parent(document_metadata).map_or(0, |parent| parent.depth(document_metadata) + 1);
+			let structure = document_metadata.get_structure_mut(descendant);
+			structure.top_level_ancestor = top_level_ancestor;
+			structure.depth = depth;
+		}
+		#[cfg(feature = "journal")]
+		document_metadata.record_moved(self);
+	}
+
+	/// Add sibling above in the layer tree
+	pub fn add_before(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
+		self.link_before(document_metadata, new);
+		#[cfg(feature = "journal")]
+		document_metadata.record_added(new);
+	}
+
+	fn link_before(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		document_metadata.get_structure_mut(new).next_sibling = Some(self);
+		new.set_parent(document_metadata, self.parent(document_metadata));
+		if let Some(parent) = self.parent(document_metadata) {
+			document_metadata.get_structure_mut(parent).child_count += 1;
+		}
+		let old_previous_sibling = document_metadata.get_structure_mut(self).previous_sibling.replace(new);
+		if let Some(old_previous_sibling) = old_previous_sibling {
+			document_metadata.get_structure_mut(old_previous_sibling).next_sibling = Some(new);
+			document_metadata.get_structure_mut(new).previous_sibling = Some(old_previous_sibling);
+		} else if let Some(structure) = self
+			.parent(document_metadata)
+			.map(|parent| document_metadata.get_structure_mut(parent))
+			.filter(|structure| structure.first_child == Some(self))
+		{
+			structure.first_child = Some(new);
+		}
+	}
+
+	/// Add sibling below in the layer tree
+	pub fn add_after(self, document_metadata: &mut DocumentMetadata, new: LayerNodeIdentifier) {
+		assert!(!document_metadata.structure.contains_key(&new), "Cannot add already existing layer");
+		document_metadata.get_structure_mut(new).previous_sibling = Some(self);
+		new.set_parent(document_metadata, self.parent(document_metadata));
+		if let Some(parent) = self.parent(document_metadata) {
+			document_metadata.get_structure_mut(parent).child_count += 1;
+		}
+		let old_next_sibling = document_metadata.get_structure_mut(self).next_sibling.replace(new);
+		if let Some(old_next_sibling) = old_next_sibling {
+			document_metadata.get_structure_mut(old_next_sibling).previous_sibling = Some(new);
+			document_metadata.get_structure_mut(new).next_sibling = Some(old_next_sibling);
+		} else if let Some(structure) = self
+			.parent(document_metadata)
+			.map(|parent| document_metadata.get_structure_mut(parent))
+			.filter(|structure| structure.last_child == Some(self))
+		{
+			structure.last_child = Some(new);
+		}
+		#[cfg(feature = "journal")]
+		document_metadata.record_added(new);
+	}
+
+	/// Delete layer and all children
+	pub fn delete(self, document_metadata: &mut DocumentMetadata) {
+		self.unlink(document_metadata);
+
+		let mut delete = vec![self];
+		delete.extend(self.decendants(document_metadata));
+		for &node in &delete {
+			Arc::make_mut(&mut document_metadata.structure).remove(&node);
+			Arc::make_mut(&mut document_metadata.names).remove(&node);
+			Arc::make_mut(&mut document_metadata.artboard_info).remove(&node);
+			Arc::make_mut(&mut document_metadata.hidden).remove(&node);
+			Arc::make_mut(&mut document_metadata.locked).remove(&node);
+			Arc::make_mut(&mut document_metadata.opacities).remove(&node);
+			Arc::make_mut(&mut document_metadata.blend_modes).remove(&node);
+			Arc::make_mut(&mut document_metadata.click_targets).remove(&node);
+			Arc::make_mut(&mut document_metadata.text_hit_regions).remove(&node);
+			Arc::make_mut(&mut document_metadata.render_padding).remove(&node);
+			Arc::make_mut(&mut document_metadata.chrome_click_targets).remove(&node);
+			Arc::make_mut(&mut document_metadata.tags).remove(&node);
+			if let Some(previous_frame_bounds) = &mut document_metadata.previous_frame_bounds {
+				Arc::make_mut(previous_frame_bounds).remove(&node);
+			}
+			if document_metadata.isolation_root == Some(node) {
+				document_metadata.isolation_root = None;
+			}
+			if let Some(kind) = Arc::make_mut(&mut document_metadata.classifications).remove(&node) {
+				Arc::make_mut(&mut document_metadata.layers_by_kind).entry(kind).or_default().remove(&node);
+			}
+		}
+		document_metadata.structure_generation += 1;
+		#[cfg(feature = "journal")]
+		document_metadata.record_deleted(self);
+		document_metadata.notify(ChangeEvent::LayersDeleted(delete));
+	}
+
+	/// Remove the layer from its siblings and parent, keeping its children
+	fn unlink(self, document_metadata: &mut DocumentMetadata) {
 		let previous_sibling = self.previous_sibling(document_metadata);
 		let next_sibling = self.next_sibling(document_metadata);
 
-		if let Some(previous_sibling) = previous_sibling.map(|node| document_metadata.get_structure_mut(node)) {
-			previous_sibling.next_sibling = next_sibling;
-		}
+		if let Some(previous_sibling) = previous_sibling.map(|node| document_metadata.get_structure_mut(node)) {
+			previous_sibling.next_sibling = next_sibling;
+		}
+
+		if let Some(next_sibling) = next_sibling.map(|node| document_metadata.get_structure_mut(node)) {
+			next_sibling.previous_sibling = previous_sibling;
+		}
+		let mut parent = self.parent(document_metadata).map(|parent| document_metadata.get_structure_mut(parent));
+		if let Some(structure) = parent.as_mut() {
+			structure.child_count -= 1;
+		}
+		if let Some(structure) = parent.as_mut().filter(|structure| structure.first_child == Some(self)) {
+			structure.first_child = next_sibling;
+		}
+		if let Some(structure) = parent.as_mut().filter(|structure| structure.last_child == Some(self)) {
+			structure.last_child = previous_sibling;
+		}
+	}
+
+	pub fn exists(&self, document_metadata: &DocumentMetadata) -> bool {
+		document_metadata.get_relations(*self).is_some()
+	}
+
+	pub fn starts_with(&self, other: Self, document_metadata: &DocumentMetadata) -> bool {
+		if let Some(intervals) = document_metadata.valid_subtree_intervals() {
+			if let (Some(&(position, _)), Some(&(start, end))) = (intervals.get(self), intervals.get(&other)) {
+				return (start..=end).contains(&position);
+			}
+		}
+		self.ancestors(document_metadata).any(|parent| parent == other)
+	}
+
+	/// Is the other layer a descendant of this one, not including this layer itself?
+	pub fn is_ancestor_of(&self, other: Self, document_metadata: &DocumentMetadata) -> bool {
+		*self != other && other.starts_with(*self, document_metadata)
+	}
+
+	/// The ancestor of the layer (or the layer itself) that is a direct child of the root, which is `None` for the root and layers not in the tree.
+	pub fn child_of_root(&self, document_metadata: &DocumentMetadata) -> Option<Self> {
+		document_metadata.get_relations(*self).and_then(|relations| relations.top_level_ancestor)
+	}
+
+	/// The number of ancestors of the layer, not including itself, so `0` for the root and `1` for its children. Layers not in the tree are also `0`.
+	pub fn depth(&self, document_metadata: &DocumentMetadata) -> usize {
+		document_metadata.get_relations(*self).map_or(0, |relations| relations.depth)
+	}
+}
+
+impl From<NodeId> for LayerNodeIdentifier {
+	fn from(node_id: NodeId) -> Self {
+		Self::new_unchecked(node_id)
+	}
+}
+
+impl From<LayerNodeIdentifier> for NodeId {
+	fn from(identifier: LayerNodeIdentifier) -> Self {
+		identifier.to_node()
+	}
+}
+
+/// Iterator over specified axis.
+#[derive(Clone)]
+pub struct AxisIter<'a> {
+	layer_node: Option<LayerNodeIdentifier>,
+	next_node: fn(LayerNodeIdentifier, &DocumentMetadata) -> Option<LayerNodeIdentifier>,
+	document_metadata: &'a DocumentMetadata,
+}
+
+impl<'a> Iterator for AxisIter<'a> {
+	type Item = LayerNodeIdentifier;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let layer_node = self.layer_node.take();
+		self.layer_node = layer_node.and_then(|node| (self.next_node)(node, self.document_metadata));
+		layer_node
+	}
+}
+
+/// Iterator over the direct children of a layer, from either end.
+#[derive(Clone)]
+pub struct ChildrenIter<'a> {
+	front: Option<LayerNodeIdentifier>,
+	back: Option<LayerNodeIdentifier>,
+	document_metadata: &'a DocumentMetadata,
+}
+
+impl<'a> Iterator for ChildrenIter<'a> {
+	type Item = LayerNodeIdentifier;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.front == self.back {
+			self.back = None;
+			self.front.take()
+		} else {
+			let layer_node = self.front.take();
+			self.front = layer_node.and_then(|layer_node| layer_node.next_sibling(self.document_metadata));
+			layer_node
+		}
+	}
+}
+impl<'a> DoubleEndedIterator for ChildrenIter<'a> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		if self.front == self.back {
+			self.front = None;
+			self.back.take()
+		} else {
+			let layer_node = self.back.take();
+			self.back = layer_node.and_then(|layer_node| layer_node.previous_sibling(self.document_metadata));
+			layer_node
+		}
+	}
+}
+
+#[derive(Clone)]
+pub struct DecendantsIter<'a> {
+	front: Option<LayerNodeIdentifier>,
+	back: Option<LayerNodeIdentifier>,
+	document_metadata: &'a DocumentMetadata,
+}
+
+impl<'a> Iterator for DecendantsIter<'a> {
+	type Item = LayerNodeIdentifier;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.front == self.back {
+			self.back = None;
+			self.front.take()
+		} else {
+			let layer_node = self.front.take();
+			if let Some(layer_node) = layer_node {
+				self.front = layer_node
+					.first_child(self.document_metadata)
+					.or_else(|| layer_node.ancestors(self.document_metadata).find_map(|ancestor| ancestor.next_sibling(self.document_metadata)));
+			}
+			layer_node
+		}
+	}
+}
+impl<'a> DecendantsIter<'a> {
+	/// Don't visit the descendants of the layer most recently returned by [`Iterator::next`].
+	pub fn skip_subtree(&mut self, layer: LayerNodeIdentifier) {
+		if !self.front.is_some_and(|front| front.starts_with(layer, self.document_metadata)) {
+			return;
+		}
+		if self.back.is_some_and(|back| back.starts_with(layer, self.document_metadata)) {
+			self.front = None;
+			self.back = None;
+		} else {
+			self.front = layer.ancestors(self.document_metadata).find_map(|ancestor| ancestor.next_sibling(self.document_metadata));
+		}
+	}
+}
+
+impl<'a> DoubleEndedIterator for DecendantsIter<'a> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		if self.front == self.back {
+			self.front = None;
+			self.back.take()
+		} else {
+			let layer_node = self.back.take();
+			if let Some(layer_node) = layer_node {
+				self.back = layer_node
+					.previous_sibling(self.document_metadata)
+					.and_then(|sibling| sibling.last_children(self.document_metadata).last())
+					.or_else(|| layer_node.parent(self.document_metadata));
+			}
+
+			layer_node
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NodeRelations {
+	parent: Option<LayerNodeIdentifier>,
+	previous_sibling: Option<LayerNodeIdentifier>,
+	next_sibling: Option<LayerNodeIdentifier>,
+	first_child: Option<LayerNodeIdentifier>,
+	last_child: Option<LayerNodeIdentifier>,
+	child_count: usize,
+	/// Cached [`LayerNodeIdentifier::child_of_root`]
+	top_level_ancestor: Option<LayerNodeIdentifier>,
+	/// Cached [`LayerNodeIdentifier::depth`]
+	depth: usize,
+}
+
+impl NodeRelations {
+	pub fn parent(&self) -> Option<LayerNodeIdentifier> {
+		self.parent
+	}
+
+	/// The sibling above this layer.
+	pub fn previous_sibling(&self) -> Option<LayerNodeIdentifier> {
+		self.previous_sibling
+	}
+
+	/// The sibling below this layer.
+	pub fn next_sibling(&self) -> Option<LayerNodeIdentifier> {
+		self.next_sibling
+	}
+
+	pub fn first_child(&self) -> Option<LayerNodeIdentifier> {
+		self.first_child
+	}
+
+	pub fn last_child(&self) -> Option<LayerNodeIdentifier> {
+		self.last_child
+	}
+
+	pub fn child_count(&self) -> usize {
+		self.child_count
+	}
+}
+
+fn is_layer_node(node: NodeId, network: &NodeNetwork) -> bool {
+	node == LayerNodeIdentifier::ROOT.to_node() || network.nodes.get(&node).is_some_and(|node| node.is_layer())
+}
+
+#[test]
+fn test_tree() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let document_metadata = &mut document_metadata;
+	root.push_child(document_metadata, LayerNodeIdentifier::new_unchecked(3));
+	assert_eq!(root.children(document_metadata).collect::<Vec<_>>(), vec![LayerNodeIdentifier::new_unchecked(3)]);
+	root.push_child(document_metadata, LayerNodeIdentifier::new_unchecked(6));
+	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![3, 6]);
+	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![3, 6]);
+	LayerNodeIdentifier::new_unchecked(3).add_after(document_metadata, LayerNodeIdentifier::new_unchecked(4));
+	LayerNodeIdentifier::new_unchecked(3).add_before(document_metadata, LayerNodeIdentifier::new_unchecked(2));
+	LayerNodeIdentifier::new_unchecked(6).add_before(document_metadata, LayerNodeIdentifier::new_unchecked(5));
+	LayerNodeIdentifier::new_unchecked(6).add_after(document_metadata, LayerNodeIdentifier::new_unchecked(9));
+	LayerNodeIdentifier::new_unchecked(6).push_child(document_metadata, LayerNodeIdentifier::new_unchecked(8));
+	LayerNodeIdentifier::new_unchecked(6).push_front_child(document_metadata, LayerNodeIdentifier::new_unchecked(7));
+	root.push_front_child(document_metadata, LayerNodeIdentifier::new_unchecked(1));
+	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 9]);
+	assert_eq!(
+		root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(),
+		vec![1, 2, 3, 4, 5, 6, 7, 8, 9]
+	);
+	assert_eq!(
+		root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).rev().collect::<Vec<_>>(),
+		vec![9, 8, 7, 6, 5, 4, 3, 2, 1]
+	);
+	assert!(root.children(document_metadata).all(|child| child.parent(document_metadata) == Some(root)));
+	LayerNodeIdentifier::new_unchecked(6).delete(document_metadata);
+	LayerNodeIdentifier::new_unchecked(1).delete(document_metadata);
+	LayerNodeIdentifier::new_unchecked(9).push_child(document_metadata, LayerNodeIdentifier::new_unchecked(10));
+	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![2, 3, 4, 5, 9]);
+	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![2, 3, 4, 5, 9, 10]);
+	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).rev().collect::<Vec<_>>(), vec![10, 9, 5, 4, 3, 2]);
+}
+
+#[test]
+fn selected_artboard_contents() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, child, stray] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	artboard.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, stray);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	let _ = document_metadata.set_selected_nodes(vec![1, 2, 3]);
+
+	assert_eq!(document_metadata.selected_layers_except_artboards().collect::<Vec<_>>(), vec![child, stray]);
+	assert_eq!(document_metadata.selected_layers_excluding_artboard_contents().collect::<Vec<_>>(), vec![stray]);
+	assert_eq!(
+		document_metadata.exclude_descendants_of_selected(document_metadata.all_layers()).collect::<Vec<_>>(),
+		vec![artboard, stray]
+	);
+}
+
+#[test]
+fn serialize_tree_snapshot() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	for id in [2, 3, 9] {
+		root.push_child(&mut document_metadata, LayerNodeIdentifier::new_unchecked(id));
+	}
+	let folder = LayerNodeIdentifier::new_unchecked(9);
+	folder.push_child(&mut document_metadata, LayerNodeIdentifier::new_unchecked(10));
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	document_metadata.set_collapsed(folder, true);
+	let _ = document_metadata.set_selected_nodes(vec![3]);
+
+	let leaf = |id: u64, selected: bool| serde_json::json!({ "id": id + 1, "children": [], "isFolder": false, "isArtboard": false, "selected": selected });
+	let expected = serde_json::json!({
+		"id": 1,
+		"children": [
+			leaf(2, false),
+			leaf(3, true),
+			{ "id": 10, "children": [leaf(10, false)], "isFolder": true, "isArtboard": false, "selected": false, "expanded": false },
+		],
+		"isFolder": false,
+		"isArtboard": false,
+		"selected": false,
+		"expanded": true,
+	});
+	assert_eq!(serde_json::to_value(document_metadata.serialize_tree()).unwrap(), expected);
+
+	let shallow = document_metadata.serialize_tree_to_depth(1);
+	assert_eq!(shallow.children.len(), 3);
+	assert!(shallow.children.iter().all(|child| child.children.is_empty()));
+	assert_eq!(document_metadata.serialize_subtree(folder, None).children[0].id, LayerNodeIdentifier::new_unchecked(10));
+	// Only layers with children can be expanded
+	assert_eq!(document_metadata.serialize_subtree(folder, None).expanded, Some(false));
+	assert_eq!(document_metadata.serialize_subtree(LayerNodeIdentifier::new_unchecked(2), None).expanded, None);
+}
+
+#[test]
+fn layer_generations() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, leaf, sibling, other] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, leaf);
+	folder.push_child(&mut document_metadata, sibling);
+	root.push_child(&mut document_metadata, other);
+
+	let before = document_metadata.generation();
+	document_metadata.merge_transforms(HashMap::from_iter([(leaf.to_node(), (Footprint::default(), DAffine2::IDENTITY))]));
+	let after = document_metadata.generation();
+	assert_eq!(after, before + 1);
+	for layer in [root, folder, leaf] {
+		assert_eq!(document_metadata.layer_generation(layer), after);
+	}
+	assert_eq!(document_metadata.layer_generation(sibling), 0);
+	assert_eq!(document_metadata.layer_generation(other), 0);
+	assert_eq!(document_metadata.layers_changed_since(before), vec![folder, leaf]);
+	assert!(document_metadata.layers_changed_since(after).is_empty());
+
+	document_metadata.update_click_targets_partial(HashMap::from_iter([(sibling, Vec::new())]));
+	assert_eq!(document_metadata.layers_changed_since(after), vec![folder, sibling]);
+	assert_eq!(document_metadata.layer_generation(leaf), after);
+}
+
+#[test]
+fn nearest_layer() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [line, near, far] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [line, near, far] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let click_target = |subpath| vec![ClickTarget { subpath, stroke_width: 0. }];
+	document_metadata.update_click_targets(HashMap::from_iter([
+		// The point is inside this line's bounding box, but 40√2 away from the line itself
+		(line, click_target(bezier_rs::Subpath::new_line(DVec2::ZERO, DVec2::splat(100.)))),
+		(near, click_target(bezier_rs::Subpath::new_rect(DVec2::new(120., 0.), DVec2::new(140., 20.)))),
+		(far, click_target(bezier_rs::Subpath::new_rect(DVec2::new(80., 60.), DVec2::new(100., 70.)))),
+	]));
+
+	let point = DVec2::new(90., 10.);
+	let (layer, distance) = document_metadata.nearest_layer(point, 100., HitScope::All, HitTestPolicy::Topmost).unwrap();
+	assert_eq!(layer, near);
+	assert!((distance - 30.).abs() < 1e-6);
+	assert_eq!(document_metadata.nearest_layer(point, 25., HitScope::All, HitTestPolicy::Topmost), None);
+	assert_eq!(document_metadata.nearest_layer(DVec2::new(130., 10.), 100., HitScope::All, HitTestPolicy::Topmost), Some((near, 0.)));
+	let (layer, distance) = document_metadata.nearest_layer(DVec2::new(50., 60.), 100., HitScope::All, HitTestPolicy::Topmost).unwrap();
+	assert_eq!(layer, line);
+	assert!((distance - 5. * 2_f64.sqrt()).abs() < 1e-3);
+}
+
+#[test]
+fn nearest_layer_equal_distance() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [above, below] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, above);
+	root.push_child(&mut document_metadata, below);
+	let line = |y: f64| ClickTarget {
+		subpath: bezier_rs::Subpath::new_line(DVec2::new(-10., y), DVec2::new(10., y)),
+		stroke_width: 0.,
+	};
+	document_metadata.update_click_targets(HashMap::from_iter([
+		// Both are 10 away from the origin, but the bounding box of the lines below overlaps it so is visited first
+		(above, rect_click_targets(DVec2::new(10., -5.), DVec2::new(20., 5.))),
+		(below, vec![line(-10.), line(10.)]),
+	]));
+
+	for policy in [HitTestPolicy::Topmost, HitTestPolicy::SmallestArea] {
+		assert_eq!(document_metadata.nearest_layer(DVec2::ZERO, 20., HitScope::All, policy), Some((above, 10.)));
+	}
+	// Without the tie, the layer below is nearest
+	let (layer, distance) = document_metadata.nearest_layer(DVec2::new(-1., 0.), 20., HitScope::All, HitTestPolicy::Topmost).unwrap();
+	assert_eq!(layer, below);
+	assert!((distance - 10.).abs() < 1e-3);
+}
+
+#[test]
+fn selected_layers_expanded() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, child, nested_folder, nested_child, unrelated] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	folder.push_child(&mut document_metadata, nested_folder);
+	nested_folder.push_child(&mut document_metadata, nested_child);
+	root.push_child(&mut document_metadata, unrelated);
+	// Both the folder and some of its descendants are explicitly selected
+	let _ = document_metadata.set_selected_nodes(vec![nested_child.to_node(), folder.to_node(), nested_folder.to_node()]);
+
+	assert_eq!(document_metadata.selected_layers_expanded().collect::<Vec<_>>(), vec![folder, child, nested_folder, nested_child]);
+	assert!(document_metadata.is_effectively_selected(child));
+	assert!(!document_metadata.is_effectively_selected(unrelated));
+
+	let _ = document_metadata.set_selected_nodes(vec![nested_folder.to_node()]);
+	assert_eq!(document_metadata.selected_layers_expanded().collect::<Vec<_>>(), vec![nested_folder, nested_child]);
+	assert!(!document_metadata.is_effectively_selected(child));
+}
+
+#[test]
+fn selection_footprint() {
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_scale_angle_translation(DVec2::splat(2.), 0., DVec2::new(5., -3.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [folder, child, point, unselected] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, point);
+	root.push_child(&mut document_metadata, unselected);
+	let click_target = |subpath| vec![ClickTarget { subpath, stroke_width: 0. }];
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(child, click_target(bezier_rs::Subpath::new_rect(DVec2::new(10., 10.), DVec2::new(20., 30.)))),
+		(point, click_target(bezier_rs::Subpath::new_line(DVec2::new(50., 5.), DVec2::new(50., 5.)))),
+		(unselected, click_target(bezier_rs::Subpath::new_rect(DVec2::splat(-100.), DVec2::splat(-50.)))),
+	]));
+
+	let document_to_viewport = document_metadata.document_to_viewport;
+	let contains = |footprint: Footprint, bounds: [DVec2; 2]| {
+		let local = footprint.viewport_bounds_in_local_space();
+		let [start, end] = [local.start, local.end].map(|point| document_to_viewport.transform_point2(point));
+		start.cmple(bounds[0] + 1e-9).all() && end.cmpge(bounds[1] - 1e-9).all()
+	};
+
+	let _ = document_metadata.set_selected_nodes(vec![folder.to_node(), point.to_node()]);
+	let footprint = document_metadata.selection_footprint(1.5, 4.).unwrap();
+	for layer in [child, point] {
+		assert!(contains(footprint, document_metadata.bounding_box_viewport(layer).unwrap()));
+	}
+	assert!(!contains(footprint, document_metadata.bounding_box_viewport(unselected).unwrap()));
+
+	// A zero area selection still produces a footprint
+	let _ = document_metadata.set_selected_nodes(vec![point.to_node()]);
+	let footprint = document_metadata.selection_footprint(1., 0.).unwrap();
+	assert_eq!(footprint.resolution, glam::UVec2::ONE);
+	assert!(contains(footprint, document_metadata.bounding_box_viewport(point).unwrap()));
+
+	let _ = document_metadata.clear_selected_nodes();
+	assert!(document_metadata.selection_footprint(1., 0.).is_none());
+}
+
+#[test]
+fn shared_click_targets() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let layers = (1..=100).map(LayerNodeIdentifier::new_unchecked).collect::<Vec<_>>();
+	for &layer in &layers {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let shared = Arc::new(vec![ClickTarget {
+		subpath: bezier_rs::Subpath::new_rect(DVec2::ZERO, DVec2::ONE),
+		stroke_width: 1.,
+	}]);
+	document_metadata.update_click_targets_shared(layers.iter().map(|&layer| (layer, shared.clone())).collect());
+	assert_eq!(document_metadata.click_target_memory_usage(), 1);
+	let sharing = ClickTargetSharing {
+		unique_allocations: 1,
+		shared_references: 100,
+		unshared_references: 0,
+	};
+	assert_eq!(document_metadata.click_target_sharing(), sharing);
+	assert!(layers.iter().all(|&layer| document_metadata.click_target(layer).is_some_and(|targets| targets.len() == 1)));
+	assert_eq!(document_metadata.layer_outline(layers[42]).count(), 1);
+
+	// Reinserting the same allocation doesn't mark the layers as modified
+	let generation = document_metadata.generation();
+	document_metadata.update_click_targets_shared(layers.iter().map(|&layer| (layer, shared.clone())).collect());
+	assert!(document_metadata.layers_changed_since(generation).is_empty());
+
+	// Owned click targets are each their own allocation
+	document_metadata.update_click_targets(layers.iter().map(|&layer| (layer, shared.as_ref().clone())).collect::<HashMap<_, _>>());
+	assert_eq!(document_metadata.click_target_memory_usage(), 100);
+	let sharing = ClickTargetSharing {
+		unique_allocations: 100,
+		shared_references: 0,
+		unshared_references: 100,
+	};
+	assert_eq!(document_metadata.click_target_sharing(), sharing);
+
+	// Sharing again between all but one layer
+	document_metadata.update_click_targets_partial(layers[1..].iter().map(|&layer| (layer, shared.clone())).collect());
+	let sharing = ClickTargetSharing {
+		unique_allocations: 2,
+		shared_references: 99,
+		unshared_references: 1,
+	};
+	assert_eq!(document_metadata.click_target_sharing(), sharing);
+}
+
+#[test]
+fn document_bounds_cache() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, layer, added] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	root.push_child(&mut document_metadata, layer);
+	root.push_child(&mut document_metadata, added);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	let click_target = |start, end| Arc::new(rect_click_targets(start, end));
+	document_metadata.update_click_targets_shared(HashMap::from_iter([
+		(artboard, click_target(DVec2::ZERO, DVec2::splat(100.))),
+		(layer, click_target(DVec2::splat(10.), DVec2::splat(20.))),
+	]));
+	let expected = [DVec2::splat(10.), DVec2::splat(20.)];
+	assert_eq!(document_metadata.document_bounds_document_space(false), Some(expected));
+	assert_eq!(document_metadata.document_bounds_viewport_space_except_artboards(), Some(expected));
+
+	// Changing the click targets behind the cache's back shows that repeated calls don't recompute the bounds
+	Arc::make_mut(&mut document_metadata.click_targets).insert(added, click_target(DVec2::splat(-10.), DVec2::ZERO));
+	assert_eq!(document_metadata.document_bounds_document_space(false), Some(expected));
+	assert_eq!(document_metadata.document_bounds_viewport_space_except_artboards(), Some(expected));
+
+	// Any update batch invalidates the cache
+	document_metadata.merge_transforms(HashMap::from_iter([(99, (Footprint::default(), DAffine2::IDENTITY))]));
+	let expected = [DVec2::splat(-10.), DVec2::splat(20.)];
+	assert_eq!(document_metadata.document_bounds_document_space(false), Some(expected));
+	assert_eq!(document_metadata.document_bounds_viewport_space_except_artboards(), Some(expected));
+	assert_eq!(document_metadata.document_bounds_viewport_space(), Some([DVec2::splat(-10.), DVec2::splat(100.)]));
+
+	// As does moving the viewport
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(2.));
+	Arc::make_mut(&mut document_metadata.click_targets).remove(&added);
+	assert_eq!(document_metadata.document_bounds_viewport_space_except_artboards(), Some([DVec2::splat(20.), DVec2::splat(40.)]));
+
+	// And modifying the layer tree
+	added.delete(&mut document_metadata);
+	assert_eq!(document_metadata.document_bounds_document_space(false), Some([DVec2::splat(10.), DVec2::splat(20.)]));
+}
+
+#[test]
+fn layers_of_kind() {
+	use crate::test_utils::{layer_node, network, node};
+
+	// Each layer is fed by its content node and stacked above the layer below it
+	let network = network([
+		(0, node("Output", &[1])),
+		// Top level: artboard (1), text (3), raster (5), vector (7), other (9)
+		(1, layer_node(2, Some(3))),
+		(2, node("Artboard", &[11])),
+		(3, layer_node(4, Some(5))),
+		(4, node("Fill", &[40])),
+		(40, node("Text", &[])),
+		(5, layer_node(6, Some(7))),
+		(6, node("Image", &[])),
+		(7, layer_node(8, Some(9))),
+		(8, node("Shape", &[])),
+		(9, layer_node(10, None)),
+		(10, node("Rectangle Generator", &[])),
+		// Inside the artboard: folder (11) containing vector (13) and text (15)
+		(11, layer_node(12, None)),
+		(12, node("Transform", &[13])),
+		(13, layer_node(14, Some(15))),
+		(14, node("Shape", &[])),
+		(15, layer_node(16, None)),
+		(16, node("Text", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+
+	let layers = |document_metadata: &DocumentMetadata, kind| document_metadata.layers_of_kind(kind).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>();
+	assert_eq!(layers(&document_metadata, LayerClassification::Artboard), vec![1]);
+	assert_eq!(layers(&document_metadata, LayerClassification::Folder), vec![11]);
+	assert_eq!(layers(&document_metadata, LayerClassification::Text), vec![15, 3]);
+	assert_eq!(layers(&document_metadata, LayerClassification::Raster), vec![5]);
+	assert_eq!(layers(&document_metadata, LayerClassification::Vector), vec![13, 7]);
+	assert_eq!(layers(&document_metadata, LayerClassification::Other), vec![9]);
+	assert_eq!(document_metadata.classification(LayerNodeIdentifier::new_unchecked(13)), Some(LayerClassification::Vector));
+
+	let counts = document_metadata.layer_kind_counts();
+	let expected = LayerKindCounts {
+		artboards: 1,
+		folders: 1,
+		text: 2,
+		raster: 1,
+		vector: 2,
+		other: 1,
+	};
+	assert_eq!(counts, expected);
+	assert_eq!(counts.total(), document_metadata.all_layers().count());
+
+	LayerNodeIdentifier::new_unchecked(11).delete(&mut document_metadata);
+	assert_eq!(layers(&document_metadata, LayerClassification::Text), vec![3]);
+	assert_eq!(document_metadata.layer_kind_counts().get(LayerClassification::Vector), 1);
+}
+
+#[test]
+fn selection_quad_intersections() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_translation(DVec2::new(100., 0.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [crossed, inside, artboard] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [crossed, inside, artboard] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(crossed, rect_click_targets(DVec2::ZERO, DVec2::splat(10.))),
+		(inside, rect_click_targets(DVec2::splat(-25.), DVec2::splat(-20.))),
+		(artboard, rect_click_targets(DVec2::splat(-100.), DVec2::splat(100.))),
+	]));
+
+	let quad = Quad::from_box([DVec2::new(70., -30.), DVec2::new(115., 4.)]);
+	let intersections = document_metadata.selection_quad_intersections(quad);
+	assert_eq!(intersections.len(), 1);
+	let (layer, mut points) = intersections[0].clone();
+	assert_eq!(layer, crossed);
+	points.sort_by(|a, b| a.x.total_cmp(&b.x));
+	assert!(points[0].abs_diff_eq(DVec2::new(100., 4.), 1e-6) && points[1].abs_diff_eq(DVec2::new(110., 4.), 1e-6));
+	assert_eq!(points.len(), 2);
+}
+
+#[test]
+fn nth_child() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let folder = LayerNodeIdentifier::new_unchecked(1);
+	root.push_child(&mut document_metadata, folder);
+	for id in 100..150 {
+		folder.push_child(&mut document_metadata, LayerNodeIdentifier::new_unchecked(id));
+	}
+	for id in 200..220 {
+		folder.push_front_child(&mut document_metadata, LayerNodeIdentifier::new_unchecked(id));
+	}
+	let middle = LayerNodeIdentifier::new_unchecked(120);
+	middle.add_before(&mut document_metadata, LayerNodeIdentifier::new_unchecked(300));
+	middle.add_after(&mut document_metadata, LayerNodeIdentifier::new_unchecked(301));
+	LayerNodeIdentifier::new_unchecked(110).delete(&mut document_metadata);
+	LayerNodeIdentifier::new_unchecked(149).delete(&mut document_metadata);
+
+	let children = folder.children(&document_metadata).collect::<Vec<_>>();
+	assert_eq!(folder.child_count(&document_metadata), children.len());
+	assert_eq!(children.len(), 70);
+	for index in 0..=children.len() {
+		assert_eq!(folder.nth_child(&document_metadata, index), folder.children(&document_metadata).nth(index));
+	}
+
+	let reversed = folder.children_rev(&document_metadata).collect::<Vec<_>>();
+	assert_eq!(reversed, children.iter().rev().copied().collect::<Vec<_>>());
+
+	// Iterating from both ends meets in the middle without repeating a child
+	let mut iter = folder.children(&document_metadata);
+	let mut front_and_back = Vec::new();
+	while let (Some(front), back) = (iter.next(), iter.next_back()) {
+		front_and_back.push(front);
+		front_and_back.extend(back);
+	}
+	assert_eq!(front_and_back.len(), children.len());
+	assert_eq!(root.child_count(&document_metadata), 1);
+	assert_eq!(root.nth_child(&document_metadata, 0), Some(folder));
+	assert_eq!(LayerNodeIdentifier::new_unchecked(100).nth_child(&document_metadata, 0), None);
+}
+
+#[test]
+fn transform_delta() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_scale(DVec2::splat(2.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [folder, child, transformed_child, other] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	folder.push_child(&mut document_metadata, transformed_child);
+	root.push_child(&mut document_metadata, other);
+	let click_target = || rect_click_targets(DVec2::ZERO, DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([(child, click_target()), (transformed_child, click_target()), (other, click_target())]));
+	let footprint = Footprint {
+		transform: DAffine2::from_scale(DVec2::splat(2.)),
+		..Default::default()
+	};
+	document_metadata.update_transforms(HashMap::from_iter([(transformed_child.to_node(), (footprint, DAffine2::from_angle(0.5)))]));
+
+	let layers = [child, transformed_child, other];
+	let before = layers.map(|layer| document_metadata.bounding_box_viewport(layer).unwrap());
+	let snapshot = document_metadata.snapshot_transforms();
+
+	// Listing a child of a dragged folder doesn't move it twice
+	let delta = DVec2::new(15., -7.);
+	document_metadata.apply_transform_delta(&[folder, child], DAffine2::from_translation(delta));
+	let after = layers.map(|layer| document_metadata.bounding_box_viewport(layer).unwrap());
+	for (before, after) in before.iter().zip(after).take(2) {
+		assert!(after[0].abs_diff_eq(before[0] + delta, 1e-9) && after[1].abs_diff_eq(before[1] + delta, 1e-9));
+	}
+	assert_eq!(after[2], before[2]);
+
+	document_metadata.restore_transforms(snapshot);
+	assert_eq!(layers.map(|layer| document_metadata.bounding_box_viewport(layer).unwrap()), before);
+}
+
+#[test]
+fn bounding_box_in_layer_space() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [parent, child, collapsed] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, parent);
+	parent.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, collapsed);
+	document_metadata.update_click_targets(HashMap::from_iter([(child, rect_click_targets(DVec2::ZERO, DVec2::new(10., 20.)))]));
+
+	// The parent is rotated by 90 degrees and the child is offset within it
+	let parent_to_viewport = DAffine2::from_angle_translation(core::f64::consts::FRAC_PI_2, DVec2::new(100., 0.));
+	let child_to_viewport = parent_to_viewport * DAffine2::from_translation(DVec2::new(5., 5.));
+	document_metadata.update_transforms(HashMap::from_iter([
+		(parent.to_node(), (Footprint::default(), parent_to_viewport)),
+		(child.to_node(), (Footprint::default(), child_to_viewport)),
+		(collapsed.to_node(), (Footprint::default(), DAffine2::from_scale(DVec2::new(0., 1.)))),
+	]));
+
+	let [min, max] = document_metadata.bounding_box_in_layer_space(child, parent).unwrap();
+	assert!(min.abs_diff_eq(DVec2::new(5., 5.), 1e-9) && max.abs_diff_eq(DVec2::new(15., 25.), 1e-9));
+	let [min, max] = document_metadata.bounding_box_in_layer_space(child, root).unwrap();
+	assert!(min.abs_diff_eq(DVec2::new(75., 5.), 1e-9) && max.abs_diff_eq(DVec2::new(95., 15.), 1e-9));
+
+	let quad = document_metadata.bounding_quad_in_layer_space(child, parent).unwrap();
+	assert!(quad.0.iter().zip(Quad::from_box([DVec2::new(5., 5.), DVec2::new(15., 25.)]).0).all(|(a, b)| a.abs_diff_eq(b, 1e-9)));
+
+	assert!(document_metadata.bounding_box_in_layer_space(child, collapsed).is_none());
+}
+
+#[test]
+fn selection_summary() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [first_artboard, second_artboard, folder, path, text, image, unselected] = [1, 2, 3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, first_artboard);
+	root.push_child(&mut document_metadata, second_artboard);
+	first_artboard.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, path);
+	first_artboard.push_child(&mut document_metadata, text);
+	second_artboard.push_child(&mut document_metadata, image);
+	root.push_child(&mut document_metadata, unselected);
+	Arc::make_mut(&mut document_metadata.artboards).extend([first_artboard, second_artboard]);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	for (layer, kind) in [(path, LayerClassification::Vector), (text, LayerClassification::Text), (image, LayerClassification::Raster)] {
+		Arc::make_mut(&mut document_metadata.classifications).insert(layer, kind);
+	}
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(path, rect_click_targets(DVec2::new(10., 20.), DVec2::new(50., 60.))),
+		(text, rect_click_targets(DVec2::new(100., 40.), DVec2::new(250., 200.))),
+		(image, rect_click_targets(DVec2::new(500., 500.), DVec2::new(600., 600.))),
+		(unselected, rect_click_targets(DVec2::splat(-1000.), DVec2::splat(1000.))),
+	]));
+
+	let _ = document_metadata.set_selected_nodes([folder, path, text].map(LayerNodeIdentifier::to_node).to_vec());
+	let summary = document_metadata.selection_summary();
+	let expected = SelectionSummary {
+		layer_count: 3,
+		kinds: LayerKindCounts {
+			folders: 1,
+			text: 1,
+			vector: 1,
+			..Default::default()
+		},
+		bounds: Some([DVec2::new(10., 20.), DVec2::new(250., 200.)]),
+		size: Some(DVec2::new(240., 180.)),
+		spans_multiple_artboards: false,
+	};
+	assert_eq!(summary, expected);
+
+	// Selected artboards aren't counted as layers, but do count towards the artboards spanned
+	let _ = document_metadata.add_selected_nodes([image, second_artboard].map(LayerNodeIdentifier::to_node));
+	let summary = document_metadata.selection_summary();
+	assert_eq!(summary.layer_count, 4);
+	assert_eq!(summary.kinds.raster, 1);
+	assert_eq!(summary.kinds.artboards, 0);
+	assert_eq!(summary.size, Some(DVec2::new(590., 580.)));
+	assert!(summary.spans_multiple_artboards);
+
+	let _ = document_metadata.clear_selected_nodes();
+	assert_eq!(document_metadata.selection_summary(), SelectionSummary::default());
+}
+
+#[test]
+fn validated_selection() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [kept, deleted, never_existed] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, kept);
+	root.push_child(&mut document_metadata, deleted);
+	deleted.delete(&mut document_metadata);
+
+	let (_, rejected) = document_metadata.set_selected_layers(vec![deleted]);
+	assert_eq!(rejected, vec![deleted]);
+	assert!(!document_metadata.has_selected_nodes());
+	assert_eq!(document_metadata.selected_layers().count(), 0);
+
+	let (_, rejected) = document_metadata.add_selected_layers([kept, never_existed, root]);
+	assert_eq!(rejected, vec![never_existed, root]);
+	assert_eq!(document_metadata.selected_nodes, vec![kept.to_node()]);
+
+	// Selecting by node skips validation, but the selection can be pruned afterwards
+	let _ = document_metadata.add_selected_nodes([deleted.to_node()]);
+	kept.delete(&mut document_metadata);
+	let _ = document_metadata.prune_selection(&NodeNetwork::default());
+	assert!(!document_metadata.has_selected_nodes());
+
+	// Nodes in the graph which aren't layers stay selected, and nothing changes unless a layer is deselected
+	let node = |name: &str| DocumentNode {
+		name: name.to_string(),
+		..Default::default()
+	};
+	let graph = NodeNetwork {
+		nodes: HashMap::from_iter([(4, node("Transform")), (deleted.to_node(), node("Layer"))]),
+		..Default::default()
+	};
+	let _ = document_metadata.set_selected_nodes(vec![4, deleted.to_node()]);
+	let generation = document_metadata.selection_generation;
+	let _ = document_metadata.prune_selection(&graph);
+	assert_eq!(document_metadata.selected_nodes().copied().collect::<Vec<_>>(), [4]);
+	assert_eq!(document_metadata.selection_generation, generation + 1);
+	let _ = document_metadata.prune_selection(&graph);
+	assert_eq!(document_metadata.selection_generation, generation + 1);
+}
+
+#[test]
+fn layer_paths() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, group, first_duplicate, second_duplicate, unnamed] = [10, 11, 12, 13, 14].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	artboard.push_child(&mut document_metadata, group);
+	group.push_child(&mut document_metadata, first_duplicate);
+	group.push_child(&mut document_metadata, second_duplicate);
+	group.push_child(&mut document_metadata, unnamed);
+	for (layer, name) in [(artboard, "Artboard 1"), (group, "Group A"), (first_duplicate, "Rect"), (second_duplicate, "Rect")] {
+		document_metadata.set_layer_name(layer, name.to_string());
+	}
+
+	assert_eq!(document_metadata.layer_path_string(second_duplicate, "/"), "Artboard 1/Group A/Rect#13");
+	assert_eq!(document_metadata.layer_path_string(unnamed, " > "), "Artboard 1 > Group A > 14#14");
+
+	// Duplicate names resolve to the topmost sibling unless disambiguated by the id
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Rect"), Some(first_duplicate));
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Rect#13"), Some(second_duplicate));
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/14"), Some(unnamed));
+	for layer in [artboard, group, first_duplicate, second_duplicate, unnamed] {
+		assert_eq!(document_metadata.resolve_layer_path(&document_metadata.layer_path_string(layer, "/")), Some(layer));
+	}
+
+	// Names are case sensitive and the id must match the path
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/group a"), None);
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Rect#13"), None);
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Rect#99"), None);
+
+	// Names containing the separator or a `#` are escaped
+	let [numbered, slashed] = [15, 16].map(LayerNodeIdentifier::new_unchecked);
+	group.push_child(&mut document_metadata, numbered);
+	group.push_child(&mut document_metadata, slashed);
+	document_metadata.set_layer_name(numbered, "Layer #2".to_string());
+	document_metadata.set_layer_name(slashed, "A/B \\ C".to_string());
+	assert_eq!(document_metadata.layer_path_string(numbered, "/"), "Artboard 1/Group A/Layer \\#2#15");
+	assert_eq!(document_metadata.layer_path_string(slashed, "/"), "Artboard 1/Group A/A\\/B \\\\ C#16");
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Layer \\#2"), Some(numbered));
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/A\\/B \\\\ C"), Some(slashed));
+	for layer in [numbered, slashed] {
+		assert_eq!(document_metadata.resolve_layer_path(&document_metadata.layer_path_string(layer, "/")), Some(layer));
+	}
+	// Unescaped, the `#2` is taken as the id of a layer which doesn't match the path
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Layer #2"), None);
+	assert_eq!(document_metadata.resolve_layer_path("Artboard 1/Group A/Layer \\"), None);
+}
+
+#[test]
+fn layers_outside_artboards() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, group, nested, stray, stray_group, stray_child, last_artboard, last_child] = [1, 2, 3, 4, 5, 6, 7, 8].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	artboard.push_child(&mut document_metadata, group);
+	group.push_child(&mut document_metadata, nested);
+	root.push_child(&mut document_metadata, stray);
+	root.push_child(&mut document_metadata, stray_group);
+	stray_group.push_child(&mut document_metadata, stray_child);
+	root.push_child(&mut document_metadata, last_artboard);
+	last_artboard.push_child(&mut document_metadata, last_child);
+	Arc::make_mut(&mut document_metadata.artboards).extend([artboard, last_artboard]);
+
+	assert_eq!(
+		document_metadata.iter_skipping_artboard_subtrees().collect::<Vec<_>>(),
+		vec![artboard, stray, stray_group, stray_child, last_artboard]
+	);
+	assert_eq!(document_metadata.layers_outside_artboards().collect::<Vec<_>>(), vec![stray, stray_group, stray_child]);
+	assert_eq!(document_metadata.all_layers_except_artboards().count(), 6);
+}
+
+#[test]
+fn rotated_circle_bounds() {
+	// Using the handle length which puts the midpoint of each quarter on the circle, the approximation touches the circle at 45 degrees
+	const HANDLE_LENGTH: f64 = 4. / 3. * (core::f64::consts::SQRT_2 - 1.);
+	let quarter = |angle: f64| {
+		let [start, end] = [DVec2::from_angle(angle), DVec2::from_angle(angle + core::f64::consts::FRAC_PI_2)];
+		bezier_rs::Bezier::from_cubic_dvec2(start, start + start.perp() * HANDLE_LENGTH, end - end.perp() * HANDLE_LENGTH, end)
+	};
+	let circle = bezier_rs::Subpath::from_beziers(&[0., 1., 2., 3.].map(|quarter_index| quarter(quarter_index * core::f64::consts::FRAC_PI_2)), true);
+
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.update_click_targets(HashMap::from_iter([(layer, vec![ClickTarget { subpath: circle, stroke_width: 0. }])]));
+
+	let [min, max] = document_metadata.bounding_box_with_transform(layer, DAffine2::from_angle(core::f64::consts::FRAC_PI_4)).unwrap();
+	assert!(min.abs_diff_eq(DVec2::NEG_ONE, 1e-6) && max.abs_diff_eq(DVec2::ONE, 1e-6), "{min} {max}");
+	let exact = document_metadata.bounding_box_with_precision(layer, DAffine2::from_angle(core::f64::consts::FRAC_PI_4), BoundsPrecision::Exact);
+	let [min, max] = exact.unwrap();
+	assert!(min.abs_diff_eq(DVec2::NEG_ONE, 1e-6) && max.abs_diff_eq(DVec2::ONE, 1e-6), "{min} {max}");
+	// At other angles the approximation bulges outside the circle, which the exact bounds cover as closely as densely sampling the curves does
+	for angle in [0.3, 1.] {
+		let transform = DAffine2::from_angle(angle);
+		let samples =
+			(0..4).flat_map(|quarter_index| (0..=10_000).map(move |step| quarter(quarter_index as f64 * core::f64::consts::FRAC_PI_2).evaluate(bezier_rs::TValue::Parametric(step as f64 / 10_000.))));
+		let [sampled_min, sampled_max] = samples
+			.map(|point| transform.transform_point2(point))
+			.fold([DVec2::INFINITY, DVec2::NEG_INFINITY], |[min, max], point| [min.min(point), max.max(point)]);
+		let [min, max] = document_metadata.bounding_box_with_precision(layer, transform, BoundsPrecision::Exact).unwrap();
+		assert!(min.cmple(sampled_min).all() && max.cmpge(sampled_max).all());
+		assert!(min.abs_diff_eq(sampled_min, 1e-6) && max.abs_diff_eq(sampled_max, 1e-6), "{min} {max}");
+	}
+
+	let _ = document_metadata.set_selected_nodes(vec![layer.to_node()]);
+	document_metadata.update_transforms(HashMap::from_iter([(layer.to_node(), (Footprint::default(), DAffine2::from_angle(core::f64::consts::FRAC_PI_4)))]));
+	let [min, max] = document_metadata.selected_bounds_document_space(false).unwrap();
+	assert!(min.abs_diff_eq(DVec2::NEG_ONE, 1e-6) && max.abs_diff_eq(DVec2::ONE, 1e-6), "{min} {max}");
+}
+
+#[test]
+fn layers_using_node() {
+	use crate::test_utils::{layer_node, network, node};
+
+	// Both layers are filled with the same gradient node, which is fed to the secondary input of their fill nodes
+	let network = network([
+		(0, node("Output", &[1])),
+		(1, layer_node(2, Some(4))),
+		(2, node("Fill", &[3, 10])),
+		(3, node("Shape", &[])),
+		(4, layer_node(5, None)),
+		(5, node("Fill", &[6, 10])),
+		(6, node("Shape", &[])),
+		(10, node("Gradient", &[])),
+		(20, node("Unrelated", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	assert_eq!(document_metadata.layers_using_node(10).count(), 0);
+
+	document_metadata.set_node_usage_indexing(true);
+	let _ = document_metadata.load_structure(&network);
+	let layers = |node| document_metadata.layers_using_node(node).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>();
+	assert_eq!(layers(10), vec![1, 4]);
+	assert_eq!(layers(3), vec![1]);
+	assert_eq!(layers(6), vec![4]);
+	assert!(layers(20).is_empty());
+}
+
+#[test]
+fn measure_between() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [left, right, crossing] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [left, right, crossing] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(left, rect_click_targets(DVec2::new(0., 0.), DVec2::new(20., 40.))),
+		(right, rect_click_targets(DVec2::new(30., 10.), DVec2::new(60., 30.))),
+		(crossing, rect_click_targets(DVec2::new(10., -10.), DVec2::new(40., 5.))),
+	]));
+
+	let measurement = document_metadata.measure_between(left, right).unwrap();
+	assert_eq!(measurement.gap.x, 10.);
+	assert!(measurement.gap.y < 0.);
+	assert_eq!(measurement.center_delta, DVec2::new(35., 0.));
+	assert!((measurement.outline_distance.unwrap() - 10.).abs() < 1e-9);
+
+	assert!(document_metadata.measure_between(left, crossing).unwrap().gap.cmplt(DVec2::ZERO).all());
+	assert_eq!(document_metadata.outline_distance(left, crossing), Some(0.));
+	assert!(document_metadata.measure_between(left, LayerNodeIdentifier::new_unchecked(4)).is_none());
+}
+
+#[test]
+fn with_descendants_mut() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, target] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	let children = [3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, target);
+	for child in children {
+		folder.push_child(&mut document_metadata, child);
+	}
+	assert_eq!(folder.descendants_vec(&document_metadata), children);
+
+	let mut visited = Vec::new();
+	document_metadata.with_descendants_mut(folder, |document_metadata, layer| {
+		visited.push(layer);
+		layer.delete(document_metadata);
+		target.push_child(document_metadata, layer);
+	});
+	assert_eq!(visited, children);
+	assert_eq!(folder.child_count(&document_metadata), 0);
+	assert_eq!(target.children(&document_metadata).collect::<Vec<_>>(), children);
+	assert!(children.iter().all(|child| child.parent(&document_metadata) == Some(target)));
+
+	// The buffer is empty between calls
+	document_metadata.with_descendants_mut(folder, |_, _| panic!("Folder should have no descendants"));
+}
+
+#[test]
+fn layers_in_marquee() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [half_inside, inside, folder, nested_inside, nested_outside] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [half_inside, inside, folder] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	folder.push_child(&mut document_metadata, nested_inside);
+	folder.push_child(&mut document_metadata, nested_outside);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(half_inside, rect_click_targets(DVec2::new(0., 0.), DVec2::new(20., 20.))),
+		(inside, rect_click_targets(DVec2::new(25., 0.), DVec2::new(35., 10.))),
+		(nested_inside, rect_click_targets(DVec2::new(25., 10.), DVec2::new(35., 20.))),
+		(nested_outside, rect_click_targets(DVec2::new(80., 0.), DVec2::new(100., 20.))),
+	]));
+
+	let marquee = Quad::from_box([DVec2::new(5., -10.), DVec2::new(40., 30.)]);
+	assert_eq!(
+		document_metadata.layers_in_marquee(marquee, MarqueeMode::Touch, HitScope::All),
+		[half_inside, inside, folder, nested_inside]
+	);
+	assert_eq!(document_metadata.layers_in_marquee(marquee, MarqueeMode::Contain, HitScope::All), [inside, nested_inside]);
+	assert_eq!(
+		document_metadata.layers_in_marquee(marquee, MarqueeMode::CenterInside, HitScope::All),
+		[half_inside, inside, nested_inside]
+	);
+}
+
+#[test]
+fn axis_aligned_layer_transforms() {
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_scale_angle_translation(DVec2::splat(3.), 0.2, DVec2::new(4., 5.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [identity, translated, scaled, quarter_turn, rotated] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [identity, translated, scaled, quarter_turn, rotated] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let document_to_viewport = document_metadata.document_to_viewport;
+	let transforms = [
+		(translated, DAffine2::from_translation(DVec2::new(3., -8.))),
+		(scaled, DAffine2::from_scale(DVec2::new(2., -0.5))),
+		(quarter_turn, DAffine2::from_angle(std::f64::consts::FRAC_PI_2)),
+		(rotated, DAffine2::from_angle(30_f64.to_radians())),
+	];
+	document_metadata.update_transforms(HashMap::from_iter(transforms.map(|(layer, transform)| {
+		(
+			layer.to_node(),
+			(
+				Footprint {
+					transform: document_to_viewport,
+					..Default::default()
+				},
+				transform,
+			),
+		)
+	})));
+
+	let epsilon = 1e-9;
+	let axis_aligned = [identity, translated, scaled, quarter_turn, rotated].map(|layer| document_metadata.layer_transform_is_axis_aligned(layer, epsilon));
+	assert_eq!(axis_aligned, [true, true, true, false, false]);
+	let integer_translation = [identity, translated, scaled, quarter_turn, rotated].map(|layer| document_metadata.layer_transform_is_integer_translation(layer, epsilon));
+	assert_eq!(integer_translation, [true, true, false, false, false]);
+}
+
+#[test]
+fn transform_to_reveal() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_scale(DVec2::splat(2.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [visible, off_right, large] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [visible, off_right, large] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(visible, rect_click_targets(DVec2::new(10., 10.), DVec2::new(20., 20.))),
+		(off_right, rect_click_targets(DVec2::new(95., 20.), DVec2::new(105., 30.))),
+		(large, rect_click_targets(DVec2::new(0., 0.), DVec2::new(200., 50.))),
+	]));
+	let viewport_size = DVec2::new(200., 100.);
+	let document_to_viewport = document_metadata.document_to_viewport;
+
+	assert_eq!(document_metadata.transform_to_reveal(visible, viewport_size, 10.), Some(document_to_viewport));
+
+	// The layer ends at 210 in the viewport, so it needs to move 20 pixels left to end 10 pixels before the edge
+	let revealed = document_metadata.transform_to_reveal(off_right, viewport_size, 10.).unwrap();
+	assert_eq!(revealed, DAffine2::from_translation(DVec2::new(-20., 0.)) * document_to_viewport);
+
+	// The layer is 400 pixels wide in the viewport so it is zoomed out to fit in the 180 pixels between the margins
+	let revealed = document_metadata.transform_to_reveal(large, viewport_size, 10.).unwrap();
+	let fitted = (revealed * Quad::from_box([DVec2::new(0., 0.), DVec2::new(200., 50.)])).bounding_box();
+	assert!(fitted[0].abs_diff_eq(DVec2::new(10., 27.5), 1e-9) && fitted[1].abs_diff_eq(DVec2::new(190., 72.5), 1e-9));
+
+	assert_eq!(document_metadata.transform_to_reveal(LayerNodeIdentifier::new_unchecked(4), viewport_size, 10.), None);
+}
+
+#[test]
+fn layer_opacity() {
+	use crate::test_utils::{layer_node, network, node, node_with_value};
+
+	// Two nested folders at 50% opacity containing a multiplied shape
+	let network = network([
+		(0, node("Output", &[1])),
+		(1, layer_node(2, None)),
+		(2, node_with_value("Opacity", 3, TaggedValue::F32(50.))),
+		(3, layer_node(4, None)),
+		(4, node_with_value("Opacity", 5, TaggedValue::F32(50.))),
+		(5, layer_node(6, None)),
+		(6, node_with_value("Blend Mode", 7, TaggedValue::BlendMode(BlendMode::Multiply))),
+		(7, node("Shape", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [outer, inner, leaf] = [1, 3, 5].map(LayerNodeIdentifier::new_unchecked);
+	assert_eq!(leaf.parent(&document_metadata), Some(inner));
+
+	assert_eq!([outer, inner, leaf].map(|layer| document_metadata.layer_opacity(layer)), [0.5, 0.25, 0.25]);
+	assert_eq!(document_metadata.layer_blend_mode(leaf), BlendMode::Multiply);
+	assert_eq!(document_metadata.layer_blend_mode(inner), BlendMode::Normal);
+
+	document_metadata.set_layer_opacity_hint(outer, 1.);
+	assert_eq!(document_metadata.layer_opacity(leaf), 0.5);
+	let _ = document_metadata.load_structure(&network);
+	assert_eq!(document_metadata.layer_opacity(leaf), 0.25);
+}
+
+#[test]
+fn reader_snapshot() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	let click_target = |size: f64| rect_click_targets(DVec2::ZERO, DVec2::splat(size));
+	document_metadata.update_click_targets(HashMap::from_iter([(layer, click_target(10.))]));
+
+	let reader = document_metadata.reader();
+	assert!(Arc::ptr_eq(&reader.click_targets, &document_metadata.click_targets));
+	assert!(Arc::ptr_eq(&reader.structure, &document_metadata.structure));
+	assert!(Arc::ptr_eq(&reader.upstream_transforms, &document_metadata.upstream_transforms));
+
+	document_metadata.update_click_targets_partial(HashMap::from_iter([(layer, click_target(20.))]));
+	assert_eq!(reader.bounding_box_document(layer), Some([DVec2::ZERO, DVec2::splat(10.)]));
+	assert_eq!(document_metadata.bounding_box_document(layer), Some([DVec2::ZERO, DVec2::splat(20.)]));
+	assert!(!Arc::ptr_eq(&reader.click_targets, &document_metadata.click_targets));
+	// Only the modified data stopped being shared
+	assert!(Arc::ptr_eq(&reader.structure, &document_metadata.structure));
+}
+
+#[test]
+fn reorder_children() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, empty_folder] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, empty_folder);
+	let children = [3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	for child in children {
+		folder.push_child(&mut document_metadata, child);
+	}
+
+	let mut reversed = children;
+	reversed.reverse();
+	assert_eq!(document_metadata.reorder_children(folder, &reversed), Ok(()));
+	assert_eq!(folder.children(&document_metadata).collect::<Vec<_>>(), reversed);
+	assert_eq!(folder.children_rev(&document_metadata).collect::<Vec<_>>(), children);
+	assert_eq!(document_metadata.all_layers().collect::<Vec<_>>(), [&[folder][..], &reversed, &[empty_folder]].concat());
+
+	// Missing, unrelated and repeated layers are rejected without modifying the order
+	let invalid = [reversed[0], reversed[1], reversed[1], empty_folder];
+	let error = ReorderError {
+		missing: vec![reversed[2], reversed[3], reversed[4]],
+		extra: vec![reversed[1], empty_folder],
+	};
+	assert_eq!(document_metadata.reorder_children(folder, &invalid), Err(error));
+	assert_eq!(folder.children(&document_metadata).collect::<Vec<_>>(), reversed);
+
+	assert_eq!(document_metadata.reorder_children(empty_folder, &[]), Ok(()));
+	assert_eq!(empty_folder.child_count(&document_metadata), 0);
+}
+
+#[test]
+fn layer_outline_or_bounds() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [loaded, loading] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, loaded);
+	document_metadata.root().push_child(&mut document_metadata, loading);
+	let subpath = bezier_rs::Subpath::new_ellipse(DVec2::ZERO, DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(
+			loaded,
+			vec![ClickTarget {
+				subpath: subpath.clone(),
+				stroke_width: 0.,
+			}],
+		),
+		(loading, Vec::new()),
+	]));
+
+	assert!(document_metadata.has_click_targets(loaded));
+	assert_eq!(document_metadata.layer_outline_or_bounds(loaded).collect::<Vec<_>>(), [subpath]);
+
+	assert!(!document_metadata.has_click_targets(loading));
+	let outline = document_metadata.layer_outline_or_bounds(loading).collect::<Vec<_>>();
+	assert_eq!(outline.len(), 1);
+	assert!(outline[0].closed());
+	let anchors = outline[0].anchors();
+	assert_eq!(anchors.len(), 4);
+	let [min, max] = document_metadata.nonzero_bounding_box(loading);
+	assert_eq!(anchors, Quad::from_box([min, max]).0);
+	assert_eq!([min, max], [DVec2::ZERO, DVec2::ONE]);
+}
+
+#[test]
+fn find_unreachable_layers() {
+	use crate::test_utils::{layer_node, network, node};
+
+	let network = network([
+		(0, node("Output", &[1])),
+		(1, layer_node(2, None)),
+		(2, node("Shape", &[])),
+		(3, layer_node(4, None)),
+		(4, node("Shape", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+
+	assert_eq!(document_metadata.all_layers().map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), [1]);
+	assert_eq!(document_metadata.find_unreachable_layers(&network), [3]);
+}
+
+#[test]
+fn selection_centers() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, small, large] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [artboard, small, large] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(artboard, rect_click_targets(DVec2::splat(-100.), DVec2::splat(100.))),
+		(small, rect_click_targets(DVec2::new(0., 0.), DVec2::new(10., 10.))),
+		(large, rect_click_targets(DVec2::new(20., 0.), DVec2::new(60., 40.))),
+	]));
+
+	assert_eq!(document_metadata.selection_centroid_document(), None);
+	assert_eq!(document_metadata.selection_bounds_center_document(), None);
+
+	let _ = document_metadata.set_selected_nodes([artboard, small, large].map(LayerNodeIdentifier::to_node).to_vec());
+	assert_eq!(document_metadata.layer_center_document(large), Some(DVec2::new(40., 20.)));
+	assert_eq!(document_metadata.selection_centroid_document(), Some(DVec2::new(22.5, 12.5)));
+	assert_eq!(document_metadata.selection_bounds_center_document(), Some(DVec2::new(30., 20.)));
+}
+
+#[test]
+fn bake_transform_into_children() {
+	let mut document_metadata = DocumentMetadata {
+		document_to_viewport: DAffine2::from_scale(DVec2::splat(2.)),
+		..Default::default()
+	};
+	let root = document_metadata.root();
+	let [folder, transformed, inheriting, transformed_grandchild, inheriting_grandchild] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, transformed);
+	folder.push_child(&mut document_metadata, inheriting);
+	inheriting.push_child(&mut document_metadata, transformed_grandchild);
+	transformed.push_child(&mut document_metadata, inheriting_grandchild);
+
+	// Each cached footprint is the viewport transform of the parent
+	let footprint = |transform| Footprint { transform, ..Default::default() };
+	let folder_entry = (
+		footprint(document_metadata.document_to_viewport),
+		DAffine2::from_scale_angle_translation(DVec2::new(1.5, 0.5), 0.7, DVec2::new(3., -4.)),
+	);
+	let folder_to_viewport = folder_entry.0.transform * folder_entry.1;
+	let transformed_entry = (footprint(folder_to_viewport), DAffine2::from_angle_translation(-0.3, DVec2::new(10., 2.)));
+	let grandchild_entry = (footprint(folder_to_viewport), DAffine2::from_scale(DVec2::new(3., 1.)));
+	document_metadata.update_transforms(HashMap::from_iter([
+		(folder.to_node(), folder_entry),
+		(transformed.to_node(), transformed_entry),
+		(transformed_grandchild.to_node(), grandchild_entry),
+	]));
+
+	let descendants = [transformed, inheriting, transformed_grandchild, inheriting_grandchild];
+	let before = descendants.map(|layer| document_metadata.transform_to_viewport(layer));
+	document_metadata.bake_transform_into_children(folder);
+	let after = descendants.map(|layer| document_metadata.transform_to_viewport(layer));
+
+	assert_eq!(document_metadata.upstream_transform(folder.to_node()), DAffine2::IDENTITY);
+	assert_eq!(document_metadata.transform_to_viewport(folder), document_metadata.document_to_viewport);
+	for (before, after) in before.into_iter().zip(after) {
+		assert!(before.abs_diff_eq(after, 1e-9), "{before:?} != {after:?}");
+	}
+}
+
+#[test]
+fn nearest_layer_in_direction() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	// A 3x3 grid of squares in rows from the top left
+	let grid = [1, 2, 3, 4, 5, 6, 7, 8, 9].map(LayerNodeIdentifier::new_unchecked);
+	for layer in grid {
+		root.push_child(&mut document_metadata, layer);
+	}
+	document_metadata.update_click_targets(HashMap::from_iter(grid.iter().enumerate().map(|(index, &layer)| {
+		let start = DVec2::new((index % 3) as f64, (index / 3) as f64) * 20.;
+		(layer, rect_click_targets(start, start + DVec2::splat(10.)))
+	})));
+	let center = grid[4];
+	let max_angle = 60_f64.to_radians();
+
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::X, max_angle), Some(grid[5]));
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::NEG_X, max_angle), Some(grid[3]));
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::NEG_Y, max_angle), Some(grid[1]));
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::Y, max_angle), Some(grid[7]));
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::ONE, max_angle), Some(grid[8]));
+
+	// Nothing is further to the right of the right column
+	assert_eq!(document_metadata.nearest_layer_in_direction(grid[5], DVec2::X, max_angle), None);
+
+	// Hidden layers are skipped, leaving the diagonal neighbours which are tied so the topmost wins
+	Arc::make_mut(&mut document_metadata.hidden).insert(grid[5]);
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::X, max_angle), Some(grid[2]));
+	// Locked layers are skipped too
+	document_metadata.set_layer_locked(grid[2], true);
+	assert!(document_metadata.is_locked(grid[2]));
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::X, max_angle), Some(grid[8]));
+	document_metadata.set_layer_locked(grid[2], false);
+	assert_eq!(document_metadata.nearest_layer_in_direction(center, DVec2::X, max_angle), Some(grid[2]));
+}
+
+#[test]
+fn locked_layers() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, child, other, missing] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, other);
+
+	// Descendants are locked with their folder
+	document_metadata.set_layer_locked(folder, true);
+	assert!(document_metadata.is_locked(folder) && document_metadata.is_locked(child));
+	assert!(!document_metadata.is_locked(other));
+	document_metadata.set_layer_locked(missing, true);
+	document_metadata.set_layer_locked(root, true);
+	assert!(!document_metadata.is_locked(missing) && !document_metadata.is_locked(other));
+
+	// Copies are locked like the original, and deleting forgets the lock
+	let copy = document_metadata.duplicate_subtree(folder, &HashMap::from_iter([(1, 11), (2, 12)])).unwrap();
+	assert!(document_metadata.is_locked(copy));
+	folder.delete(&mut document_metadata);
+	assert!(document_metadata.audit_auxiliary_state().is_empty());
+	document_metadata.set_layer_locked(copy, false);
+	assert!(!document_metadata.is_locked(LayerNodeIdentifier::new_unchecked(12)));
+}
+
+#[test]
+fn culled_layers() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [inside, overlapping, outside, uncached] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [inside, overlapping, outside, uncached] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let click_target = || rect_click_targets(DVec2::ZERO, DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([inside, overlapping, outside, uncached].map(|layer| (layer, click_target()))));
+	let footprint = Footprint {
+		transform: DAffine2::from_scale(DVec2::splat(2.)),
+		resolution: glam::UVec2::new(100, 50),
+		..Default::default()
+	};
+	document_metadata.update_transforms(HashMap::from_iter([
+		(inside.to_node(), (footprint, DAffine2::from_translation(DVec2::new(10., 10.)))),
+		(overlapping.to_node(), (footprint, DAffine2::from_translation(DVec2::new(45., 20.)))),
+		(outside.to_node(), (footprint, DAffine2::from_translation(DVec2::new(10., 30.)))),
+	]));
+
+	let in_footprint = [inside, overlapping, outside, uncached].map(|layer| document_metadata.layer_was_in_footprint(layer));
+	assert_eq!(in_footprint, [Some(true), Some(true), Some(false), None]);
+	assert_eq!(document_metadata.culled_layers().collect::<Vec<_>>(), [outside]);
+}
+
+#[test]
+fn statistics() {
+	use crate::test_utils::rect_click_targets;
+
+	// The final tree from `test_tree`, with 9 as a folder containing 10
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, folder, nested] = [2, 9, 10].map(LayerNodeIdentifier::new_unchecked);
+	for id in [2, 3, 4, 5, 9] {
+		root.push_child(&mut document_metadata, LayerNodeIdentifier::new_unchecked(id));
+	}
+	folder.push_child(&mut document_metadata, nested);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	Arc::make_mut(&mut document_metadata.folders).extend([artboard, folder]);
+
+	let rectangle = Arc::new(rect_click_targets(DVec2::ZERO, DVec2::ONE));
+	let triangle = vec![ClickTarget {
+		subpath: bezier_rs::Subpath::from_anchors([DVec2::ZERO, DVec2::X, DVec2::Y], true),
+		stroke_width: 0.,
+	}];
+	// The rectangle is shared by two layers
+	document_metadata.update_click_targets_shared(HashMap::from_iter([
+		(LayerNodeIdentifier::new_unchecked(3), rectangle.clone()),
+		(LayerNodeIdentifier::new_unchecked(4), rectangle),
+		(nested, Arc::new(triangle)),
+	]));
+
+	let statistics = document_metadata.statistics();
+	assert_eq!(statistics.layer_count, 6);
+	assert_eq!(statistics.folder_count, 2);
+	assert_eq!(statistics.artboard_count, 1);
+	assert_eq!(statistics.max_depth, 2);
+	assert_eq!(statistics.click_target_anchor_count, 7);
+	assert!(statistics.memory_usage >= 7 * std::mem::size_of::<bezier_rs::ManipulatorGroup<ManipulatorGroupId>>() + 7 * std::mem::size_of::<NodeRelations>());
+
+	let reader = document_metadata.reader();
+	document_metadata.shrink_to_fit();
+	assert!(Arc::ptr_eq(&reader.structure, &document_metadata.structure));
+	assert_eq!(document_metadata.statistics(), statistics);
+	assert_eq!(DocumentMetadata::default().statistics().max_depth, 0);
+}
+
+#[test]
+fn click_targets_in_place() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [layer, other] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.root().push_child(&mut document_metadata, other);
+	let rectangle = |size: f64| rect_click_targets(DVec2::ZERO, DVec2::splat(size));
+	let shared = Arc::new(rectangle(10.));
+	document_metadata.update_click_targets_shared(HashMap::from_iter([(layer, shared.clone()), (other, shared.clone())]));
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(10.)]));
+
+	let generation = document_metadata.generation();
+	document_metadata.click_targets_mut(layer).unwrap().extend(rectangle(30.));
+	assert_eq!(document_metadata.layers_changed_since(generation), [layer]);
+	assert_eq!(document_metadata.click_target(layer).map(<[_]>::len), Some(2));
+	// The other layer sharing the click targets is unaffected
+	assert_eq!(document_metadata.click_target(other).map(<[_]>::len), Some(1));
+	assert_eq!(shared.len(), 1);
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(30.)]));
+
+	let generation = document_metadata.generation();
+	document_metadata.remove_click_targets(layer);
+	assert!(document_metadata.click_targets_mut(layer).is_none());
+	assert_eq!(document_metadata.layers_changed_since(generation), [layer]);
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(10.)]));
+
+	document_metadata.insert_click_targets(layer, rectangle(20.));
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(20.)]));
+}
+
+#[test]
+fn selection_sources() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [layer, other_layer] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.root().push_child(&mut document_metadata, other_layer);
+	let graph_node = 10;
+
+	let _ = document_metadata.set_selected_nodes_from(vec![layer.to_node()], SelectionSource::LayerPanel);
+	let _ = document_metadata.add_selected_nodes_from([graph_node, other_layer.to_node()], SelectionSource::NodeGraph);
+
+	// The node selected in the graph view is in the full selection but not the layer panel's
+	assert_eq!(document_metadata.selected_nodes().copied().collect::<Vec<_>>(), [layer.to_node(), graph_node, other_layer.to_node()]);
+	assert_eq!(document_metadata.selected_layers_from(SelectionSource::LayerPanel).collect::<Vec<_>>(), [layer]);
+	assert_eq!(
+		document_metadata.selected_nodes_from(SelectionSource::NodeGraph).copied().collect::<Vec<_>>(),
+		[graph_node, other_layer.to_node()]
+	);
+	assert_eq!(document_metadata.selected_layers().collect::<Vec<_>>(), [layer, other_layer]);
+
+	let _ = document_metadata.clear_selected_nodes_from(SelectionSource::NodeGraph);
+	assert_eq!(document_metadata.selected_nodes().copied().collect::<Vec<_>>(), [layer.to_node()]);
+	assert_eq!(document_metadata.selection_source(graph_node), None);
+
+	let _ = document_metadata.add_selected_nodes([other_layer.to_node()]);
+	assert_eq!(document_metadata.selection_source(other_layer.to_node()), Some(SelectionSource::Api));
+	let _ = document_metadata.clear_selected_nodes();
+	assert!(document_metadata.selected_layers_from(SelectionSource::LayerPanel).next().is_none());
+}
+
+#[test]
+fn combined_bounds_options() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, folder, nested, hidden, rotated] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [artboard, folder, hidden, rotated] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	folder.push_child(&mut document_metadata, nested);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	Arc::make_mut(&mut document_metadata.hidden).insert(hidden);
+	let circle = vec![ClickTarget {
+		subpath: bezier_rs::Subpath::new_ellipse(DVec2::splat(-1.), DVec2::splat(1.)),
+		stroke_width: 0.,
+	}];
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(artboard, rect_click_targets(DVec2::splat(-100.), DVec2::splat(100.))),
+		(folder, rect_click_targets(DVec2::new(0., 0.), DVec2::new(1., 1.))),
+		(nested, rect_click_targets(DVec2::new(0., 0.), DVec2::new(10., 1.))),
+		(hidden, rect_click_targets(DVec2::new(0., 0.), DVec2::new(1., 20.))),
+		(rotated, circle),
+	]));
+	let footprint = Footprint::default();
+	document_metadata.update_transforms(HashMap::from_iter([(rotated.to_node(), (footprint, DAffine2::from_angle(std::f64::consts::FRAC_PI_4)))]));
+
+	let bounds = |document_metadata: &DocumentMetadata, layers: &[LayerNodeIdentifier], options| document_metadata.combined_bounds_document(layers.iter().copied(), options);
+	let default = BoundsOptions::default();
+	assert_eq!(bounds(&document_metadata, &[folder], default), Some([DVec2::ZERO, DVec2::ONE]));
+	let subtree = BoundsOptions {
+		use_subtree_for_folders: true,
+		..default
+	};
+	assert_eq!(bounds(&document_metadata, &[folder], subtree), Some([DVec2::ZERO, DVec2::new(10., 1.)]));
+
+	assert_eq!(bounds(&document_metadata, &[folder, hidden], default), Some([DVec2::ZERO, DVec2::new(1., 20.)]));
+	let visible = BoundsOptions { include_hidden: false, ..default };
+	assert_eq!(bounds(&document_metadata, &[folder, hidden], visible), Some([DVec2::ZERO, DVec2::ONE]));
+
+	assert_eq!(bounds(&document_metadata, &[artboard, folder], default), Some([DVec2::splat(-100.), DVec2::splat(100.)]));
+	let except_artboards = BoundsOptions { include_artboards: false, ..default };
+	assert_eq!(bounds(&document_metadata, &[artboard, folder], except_artboards), Some([DVec2::ZERO, DVec2::ONE]));
+	assert_eq!(bounds(&document_metadata, &[artboard], except_artboards), None);
+
+	// A rotated circle keeps its size with tight bounds (up to the error of the bezier approximation), but the rotated square around it is larger
+	let [min, max] = bounds(&document_metadata, &[rotated], default).unwrap();
+	assert!(min.abs_diff_eq(DVec2::NEG_ONE, 1e-3) && max.abs_diff_eq(DVec2::ONE, 1e-3));
+	let loose = BoundsOptions { tight: false, ..default };
+	let [min, max] = bounds(&document_metadata, &[rotated], loose).unwrap();
+	assert!(min.abs_diff_eq(DVec2::splat(-std::f64::consts::SQRT_2), 1e-6) && max.abs_diff_eq(DVec2::splat(std::f64::consts::SQRT_2), 1e-6));
+}
+
+#[test]
+fn resolve_drop_target() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	// root
+	// ├── folder
+	// │   ├── a
+	// │   ├── nested
+	// │   │   └── b
+	// │   └── c
+	// └── d
+	let [folder, a, nested, b, c, d] = [1, 2, 3, 4, 5, 6].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, d);
+	folder.push_child(&mut document_metadata, a);
+	folder.push_child(&mut document_metadata, nested);
+	folder.push_child(&mut document_metadata, c);
+	nested.push_child(&mut document_metadata, b);
+	Arc::make_mut(&mut document_metadata.folders).extend([folder, nested]);
+
+	// Above and below the hovered layer, among its siblings
+	assert_eq!(document_metadata.resolve_drop_target(a, DropPosition::Above, &[d]), Some((folder, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(a, DropPosition::Below, &[d]), Some((folder, 1)));
+	assert_eq!(document_metadata.resolve_drop_target(c, DropPosition::Below, &[d]), Some((folder, 3)));
+	assert_eq!(document_metadata.resolve_drop_target(b, DropPosition::Above, &[d]), Some((nested, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(folder, DropPosition::Below, &[b]), Some((root, 1)));
+
+	// Inside a folder goes to the top, but inside anything else drops below it
+	assert_eq!(document_metadata.resolve_drop_target(nested, DropPosition::Inside, &[d]), Some((nested, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(root, DropPosition::Inside, &[b]), Some((root, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(a, DropPosition::Inside, &[d]), Some((folder, 1)));
+	assert_eq!(document_metadata.resolve_drop_target(d, DropPosition::Inside, &[a]), Some((root, 2)));
+
+	// Dragged siblings above the insertion point are removed first
+	assert_eq!(document_metadata.resolve_drop_target(c, DropPosition::Below, &[a]), Some((folder, 2)));
+	assert_eq!(document_metadata.resolve_drop_target(c, DropPosition::Above, &[a, nested]), Some((folder, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(c, DropPosition::Below, &[a, c]), Some((folder, 1)));
+	assert_eq!(document_metadata.resolve_drop_target(a, DropPosition::Above, &[c]), Some((folder, 0)));
+	assert_eq!(document_metadata.resolve_drop_target(d, DropPosition::Below, &[folder]), Some((root, 1)));
+
+	// Layers can't be moved into themselves or their descendants
+	assert_eq!(document_metadata.resolve_drop_target(nested, DropPosition::Inside, &[nested]), None);
+	assert_eq!(document_metadata.resolve_drop_target(b, DropPosition::Below, &[nested]), None);
+	assert_eq!(document_metadata.resolve_drop_target(b, DropPosition::Above, &[d, folder]), None);
+	assert_eq!(document_metadata.resolve_drop_target(nested, DropPosition::Inside, &[folder]), None);
+	assert_eq!(document_metadata.resolve_drop_target(a, DropPosition::Inside, &[folder]), None);
+	// but can be dropped next to themselves
+	assert_eq!(document_metadata.resolve_drop_target(nested, DropPosition::Above, &[nested]), Some((folder, 1)));
+
+	// The root has no siblings
+	assert_eq!(document_metadata.resolve_drop_target(root, DropPosition::Above, &[d]), None);
+}
+
+#[test]
+fn child_of_root() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, nested, leaf, sibling, other] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, nested);
+	nested.push_child(&mut document_metadata, leaf);
+	leaf.add_after(&mut document_metadata, sibling);
+	root.push_front_child(&mut document_metadata, other);
+
+	assert_eq!(root.child_of_root(&document_metadata), None);
+	assert_eq!(LayerNodeIdentifier::new_unchecked(6).child_of_root(&document_metadata), None);
+	for layer in [folder, nested, leaf, sibling] {
+		assert_eq!(layer.child_of_root(&document_metadata), Some(folder));
+	}
+	assert_eq!(other.child_of_root(&document_metadata), Some(other));
+	assert_eq!(document_metadata.top_level_ancestors([leaf, sibling, nested].into_iter()), HashSet::from([folder]));
+
+	// Moving a subtree updates it along with all of its descendants
+	nested.reparent(&mut document_metadata, other);
+	assert_eq!(other.child_of_root(&document_metadata), Some(other));
+	for layer in [nested, leaf, sibling] {
+		assert_eq!(layer.child_of_root(&document_metadata), Some(other));
+	}
+	assert_eq!(folder.child_of_root(&document_metadata), Some(folder));
+	assert_eq!(folder.children(&document_metadata).count(), 0);
+	assert_eq!(other.children(&document_metadata).collect::<Vec<_>>(), [nested]);
+	assert_eq!(document_metadata.top_level_ancestors([leaf, folder].into_iter()), HashSet::from([folder, other]));
+
+	// Moving to the root makes the layer top level
+	leaf.reparent(&mut document_metadata, root);
+	assert_eq!(leaf.child_of_root(&document_metadata), Some(leaf));
+	assert_eq!(sibling.child_of_root(&document_metadata), Some(other));
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [other, folder, leaf]);
+	assert_eq!(nested.children(&document_metadata).collect::<Vec<_>>(), [sibling]);
+}
+
+#[test]
+fn text_position_at_point() {
+	use crate::test_utils::empty_network;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [text, other] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, text);
+	root.push_child(&mut document_metadata, other);
+	// Two lines of ten units per character, with "hello " on the first and "world" on the second
+	let regions = vec![
+		TextHitRegion {
+			bounds: Quad::from_box([DVec2::new(0., 0.), DVec2::new(60., 20.)]),
+			char_range: 0..6,
+		},
+		TextHitRegion {
+			bounds: Quad::from_box([DVec2::new(0., 20.), DVec2::new(50., 40.)]),
+			char_range: 6..11,
+		},
+	];
+	document_metadata.insert_click_targets(text, Vec::new());
+	document_metadata.set_text_hit_regions(text, regions.clone());
+	let footprint = Footprint::default();
+	document_metadata.update_transforms(HashMap::from_iter([(text.to_node(), (footprint, DAffine2::from_translation(DVec2::new(100., 0.))))]));
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(2.));
+
+	let position = |document_metadata: &DocumentMetadata, point: DVec2| document_metadata.text_position_at_point(text, point);
+	assert_eq!(position(&document_metadata, DVec2::new(101., 5.)), Some(0));
+	assert_eq!(position(&document_metadata, DVec2::new(114., 5.)), Some(1));
+	assert_eq!(position(&document_metadata, DVec2::new(116., 5.)), Some(2));
+	assert_eq!(position(&document_metadata, DVec2::new(159., 19.)), Some(6));
+	assert_eq!(position(&document_metadata, DVec2::new(100., 30.)), Some(6));
+	assert_eq!(position(&document_metadata, DVec2::new(126., 30.)), Some(9));
+	assert_eq!(position(&document_metadata, DVec2::new(149., 39.)), Some(11));
+	// Outside of the lines, or a layer without regions
+	assert_eq!(position(&document_metadata, DVec2::new(155., 30.)), None);
+	assert_eq!(position(&document_metadata, DVec2::new(99., 5.)), None);
+	assert_eq!(document_metadata.text_position_at_point(other, DVec2::new(1., 5.)), None);
+	assert_eq!(document_metadata.text_hit_regions(text).len(), 2);
+
+	// Modifying in place leaves the regions, but replacing the click targets or reloading clears them
+	let _ = document_metadata.click_targets_mut(text);
+	assert_eq!(document_metadata.text_hit_regions(text).len(), 2);
+	document_metadata.insert_click_targets(text, Vec::new());
+	assert_eq!(position(&document_metadata, DVec2::new(101., 5.)), None);
+
+	document_metadata.set_text_hit_regions(text, regions);
+	let network = empty_network();
+	let _ = document_metadata.load_structure(&network);
+	assert!(document_metadata.text_hit_regions(text).is_empty());
+}
+
+#[test]
+fn content_outside_artboards() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [first_artboard, second_artboard, inside_first, inside_second, stray, hidden] = [1, 2, 3, 4, 5, 6].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, first_artboard);
+	root.push_child(&mut document_metadata, second_artboard);
+	first_artboard.push_child(&mut document_metadata, inside_first);
+	second_artboard.push_child(&mut document_metadata, inside_second);
+	root.push_child(&mut document_metadata, stray);
+	root.push_child(&mut document_metadata, hidden);
+	Arc::make_mut(&mut document_metadata.hidden).insert(hidden);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(first_artboard, rect_click_targets(DVec2::new(0., 0.), DVec2::new(100., 100.))),
+		(second_artboard, rect_click_targets(DVec2::new(200., 0.), DVec2::new(300., 100.))),
+		(inside_first, rect_click_targets(DVec2::new(0., 0.), DVec2::new(100., 50.))),
+		(inside_second, rect_click_targets(DVec2::new(250., 50.), DVec2::new(260., 60.))),
+		(hidden, rect_click_targets(DVec2::new(500., 500.), DVec2::new(600., 600.))),
+	]));
+
+	// Without artboards there is nothing to fit in
+	assert_eq!(document_metadata.content_outside_artboards_bounds(), None);
+	Arc::make_mut(&mut document_metadata.artboards).extend([first_artboard, second_artboard]);
+	assert_eq!(document_metadata.content_outside_artboards_bounds(), None);
+	assert!(document_metadata.is_within_some_artboard(inside_first));
+	assert!(document_metadata.is_within_some_artboard(inside_second));
+	assert!(document_metadata.is_within_some_artboard(stray));
+
+	// A shape between the artboards is outside of both, even though it is within their combined bounds
+	document_metadata.insert_click_targets(stray, rect_click_targets(DVec2::new(90., 10.), DVec2::new(210., 20.)));
+	assert!(!document_metadata.is_within_some_artboard(stray));
+	assert!(!document_metadata.is_within_some_artboard(hidden));
+	assert_eq!(document_metadata.content_outside_artboards_bounds(), Some([DVec2::new(90., 10.), DVec2::new(210., 20.)]));
+
+	// Rounding error at the edge of an artboard still fits
+	document_metadata.insert_click_targets(stray, rect_click_targets(DVec2::new(-1e-9, 0.), DVec2::new(100. + 1e-9, 100.)));
+	assert!(document_metadata.is_within_some_artboard(stray));
+	assert_eq!(document_metadata.content_outside_artboards_bounds(), None);
+}
+
+#[test]
+fn transforms_to_viewport() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, transformed_child, other] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, other);
+	let siblings = (10..210).map(LayerNodeIdentifier::new_unchecked).collect::<Vec<_>>();
+	for &sibling in &siblings {
+		folder.push_child(&mut document_metadata, sibling);
+	}
+	folder.push_child(&mut document_metadata, transformed_child);
+	let footprint = Footprint {
+		transform: DAffine2::from_scale(DVec2::splat(2.)),
+		..Default::default()
+	};
+	document_metadata.update_transforms(HashMap::from_iter([
+		(folder.to_node(), (footprint, DAffine2::from_translation(DVec2::new(10., 0.)))),
+		(transformed_child.to_node(), (footprint, DAffine2::from_angle(1.))),
+	]));
+	document_metadata.document_to_viewport = DAffine2::from_translation(DVec2::new(0., 5.));
+	let click_target = rect_click_targets(DVec2::ZERO, DVec2::ONE);
+	document_metadata.update_click_targets(siblings.iter().map(|&sibling| (sibling, click_target.clone())).collect::<HashMap<_, _>>());
+
+	let layers = || siblings.iter().copied().chain([folder, transformed_child, other, root]);
+	let transforms = document_metadata.transforms_to_viewport(layers());
+	assert_eq!(transforms.len(), siblings.len() + 4);
+	for layer in layers() {
+		assert_eq!(transforms[&layer], document_metadata.transform_to_viewport(layer), "{layer}");
+	}
+
+	// The folder is looked up for the first of its children, then reused for the rest
+	let mut lookups = HashMap::<NodeId, usize>::new();
+	document_metadata.transforms_to_viewport_with(layers(), |node| {
+		*lookups.entry(node).or_default() += 1;
+		document_metadata.cached_transform_to_viewport(node)
+	});
+	assert_eq!(lookups[&folder.to_node()], 1);
+	assert!(siblings.iter().all(|sibling| lookups[&sibling.to_node()] == 1));
+	assert_eq!(lookups[&root.to_node()], 1);
+
+	let bounds = document_metadata.bounding_boxes_viewport(layers());
+	assert_eq!(bounds.len(), siblings.len());
+	assert_eq!(bounds[&siblings[0]], [DVec2::new(20., 0.), DVec2::new(22., 2.)]);
+}
+
+#[test]
+fn stale_transforms_after_partial_evaluation() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, child, grandchild, other] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	child.push_child(&mut document_metadata, grandchild);
+	root.push_child(&mut document_metadata, other);
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(2.));
+	let d2v = document_metadata.document_to_viewport;
+
+	// Evaluating the whole graph, where the footprint of each node accumulates the transforms downstream of it
+	let evaluate = |folder_transform: DAffine2, child_transform: DAffine2| {
+		let folder_footprint = Footprint { transform: d2v, ..Default::default() };
+		let child_footprint = Footprint {
+			transform: d2v * folder_transform,
+			..Default::default()
+		};
+		HashMap::from_iter([
+			(folder.to_node(), (folder_footprint, folder_transform)),
+			(child.to_node(), (child_footprint, child_transform)),
+			(other.to_node(), (folder_footprint, DAffine2::from_translation(DVec2::new(0., 7.)))),
+		])
+	};
+	let moved = DAffine2::from_translation(DVec2::new(10., 0.));
+	document_metadata.update_transforms(evaluate(DAffine2::IDENTITY, DAffine2::IDENTITY));
+	assert_eq!(document_metadata.transform_to_viewport(child), d2v);
+
+	// Only the folder is evaluated again after it is moved, leaving the child's cached footprint from before
+	let mut partial = evaluate(moved, DAffine2::IDENTITY);
+	partial.retain(|&node, _| node == folder.to_node());
+	let before = document_metadata.generation();
+	document_metadata.merge_transforms(partial);
+	let mut full = document_metadata.clone();
+	full.update_transforms(evaluate(moved, DAffine2::IDENTITY));
+	for layer in [folder, child, grandchild, other] {
+		assert_eq!(document_metadata.transform_to_viewport(layer), full.transform_to_viewport(layer), "{layer}");
+	}
+	assert_eq!(document_metadata.transform_to_viewport(grandchild), d2v * moved);
+	assert_eq!(document_metadata.layers_changed_since(before), [folder, child, grandchild]);
+	let transforms = document_metadata.transforms_to_viewport([grandchild, child, folder, other].into_iter());
+	assert!(transforms.iter().all(|(&layer, &transform)| transform == full.transform_to_viewport(layer)));
+
+	// Evaluating the child afterwards makes its own cached transform the newest again
+	let child_transform = DAffine2::from_angle(0.5);
+	let mut partial = evaluate(moved, child_transform);
+	partial.retain(|&node, _| node == child.to_node());
+	document_metadata.merge_transforms(partial);
+	full.update_transforms(evaluate(moved, child_transform));
+	for layer in [folder, child, grandchild, other] {
+		assert_eq!(document_metadata.transform_to_viewport(layer), full.transform_to_viewport(layer), "{layer}");
+	}
+	assert_eq!(document_metadata.transform_to_viewport(grandchild), d2v * moved * child_transform);
+	// The downstream transform is that of the footprint the child was evaluated with
+	assert_eq!(document_metadata.downstream_transform_to_viewport(child), d2v * moved);
+}
+
+#[test]
+fn artboard_export_info() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [outer, nested, empty, inside_outer, folder, clipped, inside_nested, stray] = [1, 2, 3, 4, 5, 6, 7, 8].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, outer);
+	root.push_child(&mut document_metadata, empty);
+	root.push_child(&mut document_metadata, stray);
+	outer.push_child(&mut document_metadata, inside_outer);
+	outer.push_child(&mut document_metadata, nested);
+	outer.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, clipped);
+	nested.push_child(&mut document_metadata, inside_nested);
+	Arc::make_mut(&mut document_metadata.artboards).extend([outer, nested, empty]);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(outer, rect_click_targets(DVec2::new(0., 0.), DVec2::new(100., 100.))),
+		(nested, rect_click_targets(DVec2::new(10., 10.), DVec2::new(20., 20.))),
+		(empty, rect_click_targets(DVec2::new(200., 0.), DVec2::new(300., 100.))),
+		(inside_outer, rect_click_targets(DVec2::new(50., 50.), DVec2::new(60., 60.))),
+		// Within the outer artboard but beyond the nested one
+		(inside_nested, rect_click_targets(DVec2::new(15., 15.), DVec2::new(30., 30.))),
+		(clipped, rect_click_targets(DVec2::new(90., 90.), DVec2::new(110., 95.))),
+		(stray, rect_click_targets(DVec2::new(500., 500.), DVec2::new(510., 510.))),
+	]));
+
+	let info = document_metadata.artboard_export_info();
+	assert_eq!(info.iter().map(|info| info.artboard).collect::<Vec<_>>(), [outer, nested, empty]);
+	assert_eq!(info[0].bounds_document, Some([DVec2::new(0., 0.), DVec2::new(100., 100.)]));
+	assert_eq!(info[0].content_layers, [inside_outer, folder, clipped]);
+	assert!(info[0].has_clipped_content);
+	assert_eq!(info[1].content_layers, [inside_nested]);
+	assert!(info[1].has_clipped_content);
+	assert_eq!(info[2].bounds_document, Some([DVec2::new(200., 0.), DVec2::new(300., 100.)]));
+	assert!(info[2].content_layers.is_empty());
+	assert!(!info[2].has_clipped_content);
+
+	// Content that fits is not clipped
+	document_metadata.insert_click_targets(clipped, rect_click_targets(DVec2::new(90., 90.), DVec2::new(100., 95.)));
+	document_metadata.insert_click_targets(inside_nested, rect_click_targets(DVec2::new(15., 15.), DVec2::new(20., 20.)));
+	assert!(document_metadata.artboard_export_info().iter().all(|info| !info.has_clipped_content));
+}
+
+#[test]
+fn layer_tags() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, child, untagged, other] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	folder.push_child(&mut document_metadata, untagged);
+	root.push_child(&mut document_metadata, other);
+	document_metadata.set_layer_tag(folder, Some(LayerTag::Red));
+	document_metadata.set_layer_tag(child, Some(LayerTag::Custom("Review".to_string())));
+	document_metadata.set_layer_tag(other, Some(LayerTag::Red));
+	document_metadata.set_layer_tag(LayerNodeIdentifier::new_unchecked(99), Some(LayerTag::Red));
+	assert_eq!(document_metadata.layer_tag(folder), Some(&LayerTag::Red));
+	assert_eq!(document_metadata.layer_tag(untagged), None);
+	assert_eq!(document_metadata.layers_with_tag(&LayerTag::Red).collect::<Vec<_>>(), [folder, other]);
+
+	// The copy of the folder is placed above it, and its copies carry the tags
+	let id_map = HashMap::from_iter([(1, 11), (2, 12), (3, 13)]);
+	let copy = document_metadata.duplicate_subtree(folder, &id_map).unwrap();
+	let [child_copy, untagged_copy] = [12, 13].map(LayerNodeIdentifier::new_unchecked);
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [copy, folder, other]);
+	assert_eq!(copy.children(&document_metadata).collect::<Vec<_>>(), [child_copy, untagged_copy]);
+	assert_eq!(document_metadata.layer_tag(copy), Some(&LayerTag::Red));
+	assert_eq!(document_metadata.layer_tag(child_copy), Some(&LayerTag::Custom("Review".to_string())));
+	assert_eq!(document_metadata.layer_tag(untagged_copy), None);
+	assert_eq!(document_metadata.layers_with_tag(&LayerTag::Red).collect::<Vec<_>>(), [copy, folder, other]);
+	// Duplicating again to the same ids or with a missing id does nothing
+	assert_eq!(document_metadata.duplicate_subtree(folder, &id_map), None);
+	assert_eq!(document_metadata.duplicate_subtree(folder, &HashMap::from_iter([(1, 21), (2, 22)])), None);
+	assert_eq!(root.child_count(&document_metadata), 3);
+
+	// Deleting the original only removes its own tags
+	folder.delete(&mut document_metadata);
+	assert_eq!(document_metadata.layer_tag(folder), None);
+	assert_eq!(document_metadata.layer_tag(child), None);
+	assert_eq!(document_metadata.layer_tag(copy), Some(&LayerTag::Red));
+	assert_eq!(document_metadata.layer_tags().len(), 3);
+	assert_eq!(document_metadata.layers_with_tag(&LayerTag::Red).collect::<Vec<_>>(), [copy, other]);
+
+	// Tags are restored from a saved document for the layers that still exist
+	let saved = HashMap::from_iter([(copy, LayerTag::Blue), (folder, LayerTag::Blue)]);
+	document_metadata.set_layer_tags(saved);
+	assert_eq!(document_metadata.layers_with_tag(&LayerTag::Blue).collect::<Vec<_>>(), [copy]);
+	document_metadata.set_layer_tag(copy, None);
+	assert!(document_metadata.layer_tags().is_empty());
+}
+
+#[test]
+fn persistent_state_round_trip() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [folder, child, deleted] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	folder.push_child(&mut document_metadata, deleted);
+	document_metadata.set_layer_tag(folder, Some(LayerTag::Green));
+	document_metadata.set_layer_tag(child, Some(LayerTag::Custom("#2 Review".to_string())));
+	document_metadata.set_layer_tag(deleted, Some(LayerTag::Gray));
+	document_metadata.set_collapsed(folder, true);
+	document_metadata.set_layer_locked(child, true);
+	let _ = document_metadata.set_selected_nodes(vec![1, 2]);
+	document_metadata.save_selection("folder".to_string());
+	let _ = document_metadata.set_selected_nodes(vec![2, 3]);
+	document_metadata.save_selection("contents".to_string());
+	let guide = document_metadata.add_guide(Guide {
+		axis: GuideAxis::Horizontal,
+		position_document: 40.,
+	});
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(2.));
+	document_metadata.save_view("zoomed".to_string());
+	let saved = serde_json::to_string(&document_metadata.persistent_state()).unwrap();
+
+	// The state is restored before the layers are loaded, then pruned by the load
+	let mut restored = DocumentMetadata::default();
+	restored.restore_persistent_state(serde_json::from_str(&saved).unwrap());
+	assert_eq!(restored.persistent_state(), document_metadata.persistent_state());
+	restored.root().push_child(&mut restored, folder);
+	folder.push_child(&mut restored, child);
+	assert_eq!(restored.prune_auxiliary_state().removed(AuxiliaryState::Tags), 1);
+	assert_eq!(restored.layer_tag(folder), Some(&LayerTag::Green));
+	assert_eq!(restored.layer_tag(child), Some(&LayerTag::Custom("#2 Review".to_string())));
+	assert_eq!(restored.layer_tag(deleted), None);
+	assert!(restored.is_collapsed(folder));
+	assert!(restored.is_locked(child) && !restored.is_locked(folder));
+	// Saved selections keep the missing layer until restored
+	assert_eq!(restored.saved_selections().collect::<Vec<_>>(), [("folder", 2), ("contents", 1)]);
+	assert_eq!(restored.saved_selection_list()[1].layers, [child, deleted]);
+	assert!(restored.restore_selection("contents").is_some());
+	assert_eq!(restored.selected_nodes().copied().collect::<Vec<_>>(), [2]);
+	// Guides keep their identifiers
+	assert_eq!(restored.guides().iter().map(|&(id, guide)| (id, guide.position_document)).collect::<Vec<_>>(), [(guide, 40.)]);
+	assert!(restored.restore_view("zoomed"));
+	assert_eq!(restored.document_to_viewport, DAffine2::from_scale(DVec2::splat(2.)));
+
+	// Documents saved without the state load with none
+	assert_eq!(serde_json::from_str::<PersistentMetadata>("{}").unwrap(), PersistentMetadata::default());
+}
+
+#[test]
+fn deepest_common_ancestor_filtered() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	// root
+	// ├── artboard
+	// │   ├── folder
+	// │   │   ├── a
+	// │   │   └── b
+	// │   └── c
+	// └── other_artboard
+	//     └── d
+	let [artboard, folder, a, b, c, other_artboard, d] = [1, 2, 3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	root.push_child(&mut document_metadata, other_artboard);
+	artboard.push_child(&mut document_metadata, folder);
+	artboard.push_child(&mut document_metadata, c);
+	folder.push_child(&mut document_metadata, a);
+	folder.push_child(&mut document_metadata, b);
+	other_artboard.push_child(&mut document_metadata, d);
+	Arc::make_mut(&mut document_metadata.artboards).extend([artboard, other_artboard]);
+	Arc::make_mut(&mut document_metadata.folders).extend([artboard, other_artboard, folder]);
+	let is_artboard = |layer| document_metadata.is_artboard(layer);
+
+	let selections: [&[LayerNodeIdentifier]; 6] = [&[a, b], &[a, c], &[c], &[a, d], &[folder], &[]];
+	for selection in selections {
+		for include_self in [false, true] {
+			let unfiltered = document_metadata.deepest_common_ancestor(selection.iter().copied(), include_self);
+			assert_eq!(document_metadata.deepest_common_ancestor_filtered(selection.iter().copied(), include_self, |_| false), unfiltered);
+		}
+	}
+
+	// Inside an artboard, the artboard is skipped for its parent
+	assert_eq!(document_metadata.deepest_common_ancestor([a, c].into_iter(), false), Some(artboard));
+	assert_eq!(document_metadata.deepest_common_ancestor_filtered([a, c].into_iter(), false, is_artboard), Some(root));
+	assert_eq!(document_metadata.deepest_common_ancestor_filtered([a, b].into_iter(), false, is_artboard), Some(folder));
+	// Across artboards the root is already shared
+	assert_eq!(document_metadata.deepest_common_ancestor_filtered([a, d].into_iter(), false, is_artboard), Some(root));
+	assert_eq!(document_metadata.deepest_common_ancestor_filtered([a, d].into_iter(), false, |_| true), None);
+
+	assert_eq!(document_metadata.deepest_common_folder([a, b].into_iter()), Some(folder));
+	assert_eq!(document_metadata.deepest_common_folder([a].into_iter()), Some(folder));
+	assert_eq!(document_metadata.deepest_common_folder([folder, a].into_iter()), Some(folder));
+	assert_eq!(document_metadata.deepest_common_folder([c].into_iter()), Some(root));
+	assert_eq!(document_metadata.deepest_common_folder([a, c].into_iter()), Some(root));
+	assert_eq!(document_metadata.deepest_common_folder([d].into_iter()), Some(root));
+	assert_eq!(document_metadata.deepest_common_folder(std::iter::empty()), None);
+}
+
+#[test]
+fn min_area_bounding_quad() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [rotated, line, point, empty] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [rotated, line, point, empty] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let polyline = |points: &[DVec2], closed| {
+		vec![ClickTarget {
+			subpath: bezier_rs::Subpath::from_anchors(points.iter().copied(), closed),
+			stroke_width: 0.,
+		}]
+	};
+	// A 40 by 10 rectangle, with extra anchors inside and along its edges
+	let rectangle = [
+		DVec2::new(0., 0.),
+		DVec2::new(20., 0.),
+		DVec2::new(40., 0.),
+		DVec2::new(40., 10.),
+		DVec2::new(10., 5.),
+		DVec2::new(0., 10.),
+	];
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(rotated, polyline(&rectangle, true)),
+		(line, polyline(&[DVec2::new(0., 0.), DVec2::new(5., 0.), DVec2::new(10., 0.)], false)),
+		(point, polyline(&[DVec2::new(3., 4.)], false)),
+		(empty, Vec::new()),
+	]));
+	let angle = std::f64::consts::FRAC_PI_4;
+	let transform = DAffine2::from_translation(DVec2::new(100., 50.)) * DAffine2::from_angle(angle);
+	document_metadata.update_transforms(HashMap::from_iter([(rotated.to_node(), (Footprint::default(), transform))]));
+
+	let (quad, quad_angle) = document_metadata.min_area_bounding_quad(rotated).unwrap();
+	let sides = [quad.0[1].distance(quad.0[0]), quad.0[2].distance(quad.0[1])];
+	let [short, long] = [sides[0].min(sides[1]), sides[0].max(sides[1])];
+	assert!((short - 10.).abs() < 1e-9 && (long - 40.).abs() < 1e-9, "{short} {long}");
+	assert!((quad_angle.abs() - angle).abs() < 1e-9, "{quad_angle}");
+	let corners = [DVec2::new(0., 0.), DVec2::new(40., 0.), DVec2::new(40., 10.), DVec2::new(0., 10.)].map(|corner| transform.transform_point2(corner));
+	assert!(corners.iter().all(|corner| quad.0.iter().any(|point| point.abs_diff_eq(*corner, 1e-9))));
+	// The axis aligned box of the rotated rectangle is much larger
+	let [min, max] = document_metadata.bounding_box_document(rotated).unwrap();
+	assert!((max - min).x * (max - min).y > 2. * 400.);
+
+	// Too few points for an area fall back to the axis aligned bounds with a nonzero size
+	assert_eq!(
+		document_metadata.min_area_bounding_quad(line).map(|(quad, angle)| (quad.0, angle)),
+		Some((Quad::from_box([DVec2::ZERO, DVec2::new(10., 1.)]).0, 0.))
+	);
+	assert_eq!(
+		document_metadata.min_area_bounding_quad(point).map(|(quad, _)| quad.0),
+		Some(Quad::from_box([DVec2::new(3., 4.), DVec2::new(4., 5.)]).0)
+	);
+	assert!(document_metadata.min_area_bounding_quad(empty).is_none());
+	assert!(document_metadata.min_area_bounding_quad(LayerNodeIdentifier::new_unchecked(5)).is_none());
+}
+
+#[test]
+fn selected_layer_ids() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [first, second] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, first);
+	root.push_child(&mut document_metadata, second);
+	// 3 is a layer node in the graph that isn't connected to the output, so isn't in the layer tree, and 4 is any other node
+	let _ = document_metadata.set_selected_nodes(vec![4, second.to_node(), 3, first.to_node()]);
+
+	assert_eq!(document_metadata.selected_layer_ids().collect::<Vec<_>>(), [second, first]);
+	assert_eq!(document_metadata.selected_layers().collect::<Vec<_>>(), [first, second]);
+	assert_eq!(document_metadata.selected_non_layer_nodes().collect::<Vec<_>>(), [4, 3]);
+	assert_eq!(document_metadata.selected_nodes().count(), 4);
+	let partitioned = document_metadata.selected_layer_ids().count() + document_metadata.selected_non_layer_nodes().count();
+	assert_eq!(partitioned, document_metadata.selected_nodes().count());
+
+	// The root isn't a layer that can be selected
+	let _ = document_metadata.set_selected_nodes(vec![root.to_node()]);
+	assert_eq!(document_metadata.selected_layer_ids().count(), 0);
+	assert_eq!(document_metadata.selected_layers().count(), 0);
+	assert_eq!(document_metadata.selected_non_layer_nodes().collect::<Vec<_>>(), [root.to_node()]);
+}
+
+#[test]
+fn change_observers() {
+	use crate::test_utils::empty_network;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, child, other] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, other);
+
+	let events = Arc::new(Mutex::new(Vec::new()));
+	let recorded = events.clone();
+	let all = document_metadata.on_change(
+		ChangeMask::ALL,
+		Box::new(move |event, document_metadata| {
+			// Observers can read the metadata after the change was made
+			if let ChangeEvent::SelectionChanged { .. } = event {
+				assert!(document_metadata.selected_nodes().count() <= 2);
+			}
+			recorded.lock().unwrap().push(event.clone());
+		}),
+	);
+	let selection_events = Arc::new(Mutex::new(0));
+	let counter = selection_events.clone();
+	let selection = document_metadata.on_change(ChangeMask::SELECTION, Box::new(move |_, _| *counter.lock().unwrap() += 1));
+	let take_events = || std::mem::take(&mut *events.lock().unwrap());
+
+	let _ = document_metadata.set_selected_nodes(vec![1, 3]);
+	let _ = document_metadata.add_selected_nodes([3]);
+	let _ = document_metadata.retain_selected_nodes(|&node| node != 1);
+	assert_eq!(
+		take_events(),
+		[
+			ChangeEvent::SelectionChanged { added: vec![1, 3], removed: vec![] },
+			ChangeEvent::SelectionChanged { added: vec![], removed: vec![1] },
+		]
+	);
+
+	let transform = (Footprint::default(), DAffine2::from_translation(DVec2::X));
+	document_metadata.update_transforms(HashMap::from_iter([(child.to_node(), transform)]));
+	document_metadata.update_transforms(HashMap::from_iter([(child.to_node(), transform)]));
+	document_metadata.merge_transforms(HashMap::from_iter([(folder.to_node(), transform)]));
+	folder.delete(&mut document_metadata);
+	assert_eq!(
+		take_events(),
+		[
+			ChangeEvent::TransformsUpdated(vec![child.to_node()]),
+			ChangeEvent::TransformsUpdated(vec![folder.to_node()]),
+			ChangeEvent::LayersDeleted(vec![folder, child]),
+		]
+	);
+
+	// Reloading empties the layer tree, deselecting the node that is no longer in the graph
+	let network = empty_network();
+	let _ = document_metadata.load_structure(&network);
+	assert_eq!(take_events(), [ChangeEvent::StructureReloaded, ChangeEvent::SelectionChanged { added: vec![], removed: vec![3] }]);
+	assert_eq!(*selection_events.lock().unwrap(), 3);
+
+	// Removed observers and clones aren't notified
+	assert!(document_metadata.remove_observer(selection));
+	assert!(!document_metadata.remove_observer(selection));
+	let mut clone = document_metadata.clone();
+	let _ = clone.set_selected_nodes(vec![5]);
+	let _ = document_metadata.set_selected_nodes(vec![4]);
+	assert_eq!(take_events(), [ChangeEvent::SelectionChanged { added: vec![4], removed: vec![] }]);
+	assert_eq!(*selection_events.lock().unwrap(), 3);
+
+	// Observers moved over to a replacement, such as when undoing, are notified of its changes instead
+	clone.take_observers_from(&mut document_metadata);
+	let _ = document_metadata.set_selected_nodes(vec![6]);
+	assert!(take_events().is_empty());
+	let _ = clone.set_selected_nodes(vec![7]);
+	assert_eq!(take_events(), [ChangeEvent::SelectionChanged { added: vec![7], removed: vec![5] }]);
+	assert!(!document_metadata.remove_observer(all));
+	assert!(clone.remove_observer(all));
+	let _ = clone.clear_selected_nodes();
+	assert!(take_events().is_empty());
+}
+
+#[test]
+fn saved_selections() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [first, second, third] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [first, second, third] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let _ = document_metadata.set_selected_nodes(vec![1, 2, 3]);
+	document_metadata.save_selection("icons".to_string());
+	let _ = document_metadata.set_selected_nodes(vec![3]);
+	document_metadata.save_selection("background".to_string());
+	document_metadata.save_selection("unused".to_string());
+	assert!(document_metadata.delete_saved_selection("unused"));
+	assert!(!document_metadata.delete_saved_selection("unused"));
+	assert_eq!(document_metadata.saved_selections().collect::<Vec<_>>(), [("icons", 3), ("background", 1)]);
+
+	// Deleted layers are only forgotten once the selection is restored
+	first.delete(&mut document_metadata);
+	third.delete(&mut document_metadata);
+	assert_eq!(document_metadata.saved_selections().collect::<Vec<_>>(), [("icons", 1), ("background", 0)]);
+	assert_eq!(document_metadata.saved_selection_list()[0].layers, [first, second, third]);
+
+	let _ = document_metadata.clear_selected_nodes();
+	assert!(document_metadata.restore_selection("icons").is_some());
+	assert_eq!(document_metadata.selected_nodes().copied().collect::<Vec<_>>(), [2]);
+	assert_eq!(document_metadata.saved_selection_list()[0].layers, [second]);
+	assert!(document_metadata.restore_selection("background").is_some());
+	assert_eq!(document_metadata.selected_nodes().count(), 0);
+	assert!(document_metadata.restore_selection("missing").is_none());
+
+	// Saving under an existing name replaces it in place
+	let _ = document_metadata.set_selected_nodes(vec![2]);
+	document_metadata.save_selection("background".to_string());
+	assert_eq!(document_metadata.saved_selections().collect::<Vec<_>>(), [("icons", 1), ("background", 1)]);
+	let saved = serde_json::to_value(document_metadata.saved_selection_list()).unwrap();
+	// The identifiers serialize as one more than the node id
+	assert_eq!(saved, serde_json::json!([{ "name": "icons", "layers": [3] }, { "name": "background", "layers": [3] }]));
+}
+
+#[test]
+fn layer_tile_coverage() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	Arc::make_mut(&mut document_metadata.click_targets).insert(layer, Arc::new(rect_click_targets(DVec2::new(-20., -10.), DVec2::new(30., 40.))));
+
+	// The rectangle straddles the corner of four 100 by 100 tiles at the origin
+	let coverage = document_metadata.layer_tile_coverage(layer, DVec2::splat(100.), DVec2::ZERO);
+	assert_eq!(
+		coverage,
+		[
+			(IVec2::new(-1, -1), [DVec2::new(80., 90.), DVec2::splat(100.)]),
+			(IVec2::new(0, -1), [DVec2::new(0., 90.), DVec2::new(30., 100.)]),
+			(IVec2::new(-1, 0), [DVec2::new(80., 0.), DVec2::new(100., 40.)]),
+			(IVec2::new(0, 0), [DVec2::ZERO, DVec2::new(30., 40.)]),
+		]
+	);
+	// Larger than a tile
+	assert_eq!(document_metadata.layer_tile_coverage(layer, DVec2::splat(20.), DVec2::ZERO).len(), 3 * 3);
+	assert!(document_metadata.layer_tile_coverage(LayerNodeIdentifier::new_unchecked(2), DVec2::splat(100.), DVec2::ZERO).is_empty());
+}
+
+#[test]
+fn isolation_scope() {
+	use crate::test_utils::{empty_network, rect_click_targets};
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [folder, inside, outside] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, outside);
+	folder.push_child(&mut document_metadata, inside);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	let click_target = |start: DVec2| rect_click_targets(start, start + DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([(inside, click_target(DVec2::ZERO)), (outside, click_target(DVec2::splat(20.)))]));
+
+	assert!(document_metadata.is_in_isolation_scope(outside));
+	document_metadata.set_isolation(Some(folder));
+	assert_eq!(document_metadata.isolation_root(), Some(folder));
+	assert!(document_metadata.is_in_isolation_scope(folder) && document_metadata.is_in_isolation_scope(inside));
+	assert!(!document_metadata.is_in_isolation_scope(outside) && !document_metadata.is_in_isolation_scope(root));
+
+	let point = DVec2::splat(25.);
+	assert_eq!(document_metadata.nearest_layer(point, 0., HitScope::Isolation, HitTestPolicy::Topmost), None);
+	assert_eq!(document_metadata.nearest_layer(point, 0., HitScope::All, HitTestPolicy::Topmost), Some((outside, 0.)));
+	let marquee = Quad::from_box([DVec2::splat(-5.), DVec2::splat(35.)]);
+	assert_eq!(document_metadata.layers_in_marquee(marquee, MarqueeMode::Contain, HitScope::Isolation), [folder, inside]);
+	assert_eq!(document_metadata.layers_in_marquee(marquee, MarqueeMode::Contain, HitScope::All), [folder, inside, outside]);
+	// Bounds are unaffected
+	assert_eq!(document_metadata.bounding_box_document(outside), Some([DVec2::splat(20.), DVec2::splat(30.)]));
+
+	// Isolating a missing layer, or reloading a structure without the isolated folder, stops isolation
+	document_metadata.set_isolation(Some(LayerNodeIdentifier::new_unchecked(4)));
+	assert_eq!(document_metadata.isolation_root(), None);
+	document_metadata.set_isolation(Some(folder));
+	let network = empty_network();
+	let _ = document_metadata.load_structure(&network);
+	assert_eq!(document_metadata.isolation_root(), None);
+}
+
+#[test]
+fn iterate_and_index() {
+	let mut document_metadata = DocumentMetadata::default();
+	assert!(document_metadata.is_empty());
+	let root = document_metadata.root();
+	let [folder, child, other] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	root.push_child(&mut document_metadata, other);
+	folder.push_child(&mut document_metadata, child);
+
+	let mut layers = Vec::new();
+	for layer in &document_metadata {
+		layers.push(layer);
+	}
+	assert_eq!(layers, document_metadata.all_layers().collect::<Vec<_>>());
+	assert_eq!(layers, [folder, child, other]);
+	assert_eq!(document_metadata.len(), 3);
+	assert!(!document_metadata.is_empty());
+
+	assert_eq!(document_metadata[folder].first_child(), Some(child));
+	assert_eq!(document_metadata[folder].next_sibling(), Some(other));
+	assert_eq!(document_metadata[child].parent(), Some(folder));
+	assert_eq!(document_metadata[root].child_count(), 2);
+	let missing = LayerNodeIdentifier::new_unchecked(4);
+	assert!(document_metadata.relations(missing).is_none());
+	let panic = std::panic::catch_unwind(|| document_metadata[missing].child_count()).unwrap_err();
+	assert_eq!(panic.downcast_ref::<String>().map(String::as_str), Some("LayerNodeIdentifier(4) is not in the layer tree"));
+}
+
+#[test]
+fn bounds_relative_to_artboard() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, inside, outside] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	root.push_child(&mut document_metadata, outside);
+	artboard.push_child(&mut document_metadata, inside);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	Arc::make_mut(&mut document_metadata.folders).insert(artboard);
+	let rect = |min: DVec2, max: DVec2| rect_click_targets(min, max);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(artboard, rect(DVec2::new(100., 50.), DVec2::new(300., 250.))),
+		(inside, rect(DVec2::new(120., 60.), DVec2::new(140., 90.))),
+		(outside, rect(DVec2::new(-10., -20.), DVec2::ZERO)),
+	]));
+
+	let (bounds, containing) = document_metadata.bounds_relative_to_artboard(inside).unwrap();
+	assert_eq!(containing, artboard);
+	assert_eq!(bounds, [DVec2::new(20., 10.), DVec2::new(40., 40.)]);
+	assert_eq!(document_metadata.point_relative_to_artboard(inside, DVec2::new(150., 150.)), Some(DVec2::new(50., 100.)));
+	assert_eq!(document_metadata.bounds_relative_to_artboard(outside), Some(([DVec2::new(-10., -20.), DVec2::ZERO], root)));
+	assert_eq!(document_metadata.point_relative_to_artboard(outside, DVec2::ONE), Some(DVec2::ONE));
+
+	// Moving the artboard and its contents together keeps the relative position
+	let translation = DAffine2::from_translation(DVec2::new(-500., 30.));
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(artboard.to_node(), (Footprint::default(), translation))]));
+	let (bounds, _) = document_metadata.bounds_relative_to_artboard(inside).unwrap();
+	assert!(bounds[0].abs_diff_eq(DVec2::new(20., 10.), 1e-9) && bounds[1].abs_diff_eq(DVec2::new(40., 40.), 1e-9));
+	assert!(document_metadata.point_relative_to_artboard(inside, DVec2::new(-400., 80.)).unwrap().abs_diff_eq(DVec2::ZERO, 1e-9));
+}
+
+#[test]
+fn hit_test_policy() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [overlay, folder, icon, card] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [overlay, folder, card] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	folder.push_child(&mut document_metadata, icon);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	// A large overlay on top of a small icon, in a folder above a card the same size as the overlay
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(overlay, rect_click_targets(DVec2::ZERO, DVec2::splat(100.))),
+		(icon, rect_click_targets(DVec2::splat(40.), DVec2::splat(50.))),
+		(card, rect_click_targets(DVec2::ZERO, DVec2::splat(100.))),
+	]));
+
+	let hit = |point: DVec2, policy| document_metadata.nearest_layer(point, 0., HitScope::All, policy).map(|(layer, _)| layer);
+	let on_icon = DVec2::splat(45.);
+	assert_eq!(hit(on_icon, HitTestPolicy::Topmost), Some(overlay));
+	assert_eq!(hit(on_icon, HitTestPolicy::SmallestArea), Some(icon));
+	assert_eq!(hit(on_icon, HitTestPolicy::DeepestInTree), Some(icon));
+	// Away from the icon, the overlay and card are tied so the topmost wins
+	let off_icon = DVec2::splat(10.);
+	assert_eq!(hit(off_icon, HitTestPolicy::SmallestArea), Some(overlay));
+	assert_eq!(hit(off_icon, HitTestPolicy::DeepestInTree), Some(overlay));
+	assert_eq!(hit(DVec2::splat(200.), HitTestPolicy::SmallestArea), None);
+}
+
+#[test]
+fn structure_transactions() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [a, b, c, folder] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [a, b, c] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	let _ = document_metadata.set_selected_nodes(vec![b.to_node()]);
+	let tree = |document_metadata: &DocumentMetadata| ((*document_metadata.structure).clone(), (*document_metadata.folders).clone(), document_metadata.selected_nodes.clone());
+	let before = tree(&document_metadata);
+
+	// The missing layer is only found after the folder is added and the first layer moved into it
+	let missing = LayerNodeIdentifier::new_unchecked(9);
+	assert_eq!(document_metadata.group_layers(&[b, missing], folder), Err(StructureError::MissingLayer(missing)));
+	assert_eq!(tree(&document_metadata), before);
+	// A failed modification closes its transaction
+	let transaction = document_metadata.begin_structure_transaction().unwrap();
+	assert!(matches!(document_metadata.begin_structure_transaction(), Err(StructureError::TransactionInProgress)));
+	assert_eq!(document_metadata.group_layers(&[b], folder), Err(StructureError::TransactionInProgress));
+	transaction.commit(&mut document_metadata);
+
+	document_metadata.group_layers(&[c, a], folder).unwrap();
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [b, folder]);
+	assert_eq!(folder.children(&document_metadata).collect::<Vec<_>>(), [c, a]);
+	assert_eq!(a.child_of_root(&document_metadata), Some(folder));
+	assert_eq!(document_metadata.selected_nodes().copied().collect::<Vec<_>>(), [folder.to_node()]);
+
+	// Dropping layers keeps their order and rolls back if any is missing
+	let grouped = tree(&document_metadata);
+	assert_eq!(document_metadata.drop_layers(b, DropPosition::Above, &[a, missing]), Err(StructureError::MissingLayer(missing)));
+	assert_eq!(tree(&document_metadata), grouped);
+	document_metadata.drop_layers(b, DropPosition::Above, &[a, c]).unwrap();
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [a, c, b, folder]);
+	assert_eq!(folder.children(&document_metadata).count(), 0);
+	assert_eq!(a.child_of_root(&document_metadata), Some(a));
+	document_metadata.drop_layers(folder, DropPosition::Inside, &[b, a]).unwrap();
+	assert_eq!(folder.children(&document_metadata).collect::<Vec<_>>(), [b, a]);
+	assert_eq!(document_metadata.drop_layers(a, DropPosition::Below, &[folder]), Err(StructureError::InvalidMove(a)));
+
+	assert_eq!(document_metadata.ungroup(c), Err(StructureError::NotAFolder(c)));
+	assert_eq!(document_metadata.ungroup(folder), Ok(vec![b, a]));
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [c, b, a]);
+	assert!(!document_metadata.layer_exists(folder) && !document_metadata.is_folder(folder));
+	assert_eq!(b.child_of_root(&document_metadata), Some(b));
+	assert_eq!(root.children(&document_metadata).count(), document_metadata[root].child_count());
+
+	// A dragged layer inside a dragged folder stays inside it
+	document_metadata.group_layers(&[b, a], folder).unwrap();
+	document_metadata.drop_layers(c, DropPosition::Above, &[a, folder]).unwrap();
+	assert_eq!(root.children(&document_metadata).collect::<Vec<_>>(), [folder, c]);
+	assert_eq!(folder.children(&document_metadata).collect::<Vec<_>>(), [b, a]);
+	assert_eq!(root.children(&document_metadata).count(), document_metadata[root].child_count());
+
+	// Dropping a transaction closes it, and clones don't share the open transaction
+	let transaction = document_metadata.begin_structure_transaction().unwrap();
+	let mut clone = document_metadata.clone();
+	let clone_transaction = clone.begin_structure_transaction().unwrap();
+	assert!(matches!(document_metadata.begin_structure_transaction(), Err(StructureError::TransactionInProgress)));
+	b.delete(&mut clone);
+	// Rolling back a transaction on metadata it wasn't begun on does nothing
+	transaction.rollback(&mut clone);
+	assert!(!clone.layer_exists(b));
+	clone_transaction.rollback(&mut clone);
+	assert!(clone.layer_exists(b));
+	assert!(document_metadata.begin_structure_transaction().is_ok());
+	assert!(document_metadata.begin_structure_transaction().is_ok());
+}
+
+#[test]
+fn structure_transaction_restores_deleted_layers() {
+	use crate::test_utils::{rect_click_targets, NetworkBuilder};
+
+	let (network, ids) = NetworkBuilder::new().folder("Folder", |folder| folder.layer("Child")).layer("Other").build();
+	let [folder, child, other] = ["Folder", "Child", "Other"].map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	document_metadata.set_layer_tag(child, Some(LayerTag::Red));
+	document_metadata.set_layer_locked(folder, true);
+	document_metadata.set_isolation(Some(folder));
+	document_metadata.update_click_targets(HashMap::from_iter([(child, rect_click_targets(DVec2::ZERO, DVec2::ONE))]));
+	let events = Arc::new(Mutex::new(Vec::new()));
+	let recorded = events.clone();
+	document_metadata.on_change(ChangeMask::DELETION, Box::new(move |event, _| recorded.lock().unwrap().push(event.clone())));
+
+	let transaction = document_metadata.begin_structure_transaction().unwrap();
+	folder.delete(&mut document_metadata);
+	document_metadata.set_layer_tag(other, Some(LayerTag::Red));
+	assert!(document_metadata.layer_name(child).is_none() && document_metadata.click_target(child).is_none());
+	transaction.rollback(&mut document_metadata);
+
+	assert_eq!(document_metadata.layer_name(child), Some("Child"));
+	assert_eq!(document_metadata.layer_tag(child), Some(&LayerTag::Red));
+	assert!(document_metadata.is_locked(child));
+	assert_eq!(document_metadata.isolation_root(), Some(folder));
+	assert_eq!(document_metadata.click_target(child).map(<[_]>::len), Some(1));
+	assert_eq!(document_metadata.classification(child), Some(LayerClassification::Vector));
+	// The data of layers that weren't deleted is kept as it was modified
+	assert_eq!(document_metadata.layer_tag(other), Some(&LayerTag::Red));
+	assert_eq!(
+		*events.lock().unwrap(),
+		[ChangeEvent::LayersDeleted(vec![folder, child]), ChangeEvent::LayersRestored(vec![folder, child])]
+	);
+}
+
+#[test]
+fn find_layer() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	// Layers 1 to 7 in z-order, with 4 and 5 inside folder 3
+	let layers = [1, 2, 3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	let [_, _, folder, nested, _, _, _] = layers;
+	for layer in [layers[0], layers[1], folder, layers[5], layers[6]] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	folder.push_child(&mut document_metadata, nested);
+	folder.push_child(&mut document_metadata, layers[4]);
+	Arc::make_mut(&mut document_metadata.hidden).insert(layers[5]);
+	document_metadata.set_layer_locked(folder, true);
+	let ids = |layers: Vec<LayerNodeIdentifier>| layers.into_iter().map(LayerNodeIdentifier::to_node).collect::<Vec<_>>();
+	let all = |options| ids(document_metadata.find_all_layers(options, |_| true, None));
+
+	assert_eq!(all(SearchOptions::default()), [1, 2, 3, 4, 5, 6, 7]);
+	let bottom_up = SearchOptions {
+		direction: SearchDirection::BottomUp,
+		..Default::default()
+	};
+	assert_eq!(all(bottom_up), [7, 6, 5, 4, 3, 2, 1]);
+	assert_eq!(all(SearchOptions { within: Some(folder), ..bottom_up }), [5, 4]);
+	assert_eq!(
+		all(SearchOptions {
+			skip_hidden: true,
+			..Default::default()
+		}),
+		[1, 2, 3, 4, 5, 7]
+	);
+	let unlocked = SearchOptions {
+		skip_locked: true,
+		..Default::default()
+	};
+	assert_eq!(all(unlocked), [1, 2, 6, 7]);
+	assert_eq!(all(SearchOptions { skip_hidden: true, ..unlocked }), [1, 2, 7]);
+
+	// Expanding outwards from the hint, in the search direction first and continuing on one side once the other runs out
+	let near = SearchOptions {
+		near: Some(layers[1]),
+		..Default::default()
+	};
+	assert_eq!(all(near), [2, 3, 1, 4, 5, 6, 7]);
+	assert_eq!(all(SearchOptions { near: Some(nested), ..bottom_up }), [4, 3, 5, 2, 6, 1, 7]);
+	assert_eq!(
+		all(SearchOptions {
+			near: Some(layers[6]),
+			..Default::default()
+		}),
+		[7, 6, 5, 4, 3, 2, 1]
+	);
+	// A hint outside of the searched layers is ignored
+	assert_eq!(all(SearchOptions { within: Some(folder), ..near }), [4, 5]);
+
+	let mut visited = Vec::new();
+	let found = document_metadata.find_layer(near, |layer| {
+		visited.push(layer.to_node());
+		layer.to_node() >= 4
+	});
+	assert_eq!(found, Some(nested));
+	assert_eq!(visited, [2, 3, 1, 4]);
+	assert_eq!(ids(document_metadata.find_all_layers(bottom_up, |layer| layer.to_node() % 2 == 1, Some(2))), [7, 5]);
+	assert_eq!(document_metadata.find_layer(SearchOptions::default(), |_| false), None);
+}
+
+#[test]
+fn folders_sorted_by_nesting() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	// root
+	// ├── 1
+	// │   ├── 2
+	// │   │   └── 3
+	// │   └── 4
+	// ├── 5
+	// └── 6
+	//     └── 7
+	let layers = [1, 2, 3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	let [first, nested, deepest, sibling, leaf, last, last_child] = layers;
+	for layer in [first, leaf, last] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	first.push_child(&mut document_metadata, nested);
+	first.push_child(&mut document_metadata, sibling);
+	nested.push_child(&mut document_metadata, deepest);
+	last.push_child(&mut document_metadata, last_child);
+	Arc::make_mut(&mut document_metadata.folders).extend([first, nested, deepest, sibling, last, last_child]);
+	assert_eq!(layers.map(|layer| layer.depth(&document_metadata)), [1, 2, 3, 2, 1, 1, 2]);
+	assert_eq!(root.depth(&document_metadata), 0);
+
+	// The order from a hash set doesn't matter
+	let input = HashSet::from(layers);
+	let most_nested = document_metadata.folders_sorted_by_most_nested(input.iter().copied());
+	assert_eq!(most_nested, [deepest, nested, sibling, last_child, first, last]);
+	for (index, folder) in most_nested.iter().enumerate() {
+		assert!(most_nested[index + 1..].iter().all(|later| !later.starts_with(*folder, &document_metadata)));
+	}
+	let mut reversed = layers;
+	reversed.reverse();
+	assert_eq!(document_metadata.folders_sorted_by_most_nested(reversed.into_iter()), most_nested);
+	assert_eq!(document_metadata.folders_sorted_by_least_nested(input.into_iter()), [first, last, nested, sibling, last_child, deepest]);
+
+	// Moving a folder updates the cached depths of its descendants
+	nested.reparent(&mut document_metadata, last_child);
+	assert_eq!([nested, deepest].map(|layer| layer.depth(&document_metadata)), [3, 4]);
+	assert_eq!(document_metadata.folders_sorted_by_most_nested(layers.into_iter()), [deepest, nested, sibling, last_child, first, last]);
+}
+
+#[test]
+fn load_structure_duplicates() {
+	use crate::test_utils::{empty_network, layer_node, network, node};
+
+	// Layer 5 is both below layer 3 and inside layer 3
+	let network = network([
+		(0, node("Output", &[1])),
+		(1, layer_node(2, Some(3))),
+		(2, node("Rectangle Generator", &[])),
+		(3, layer_node(4, Some(5))),
+		(4, node("Transform", &[5])),
+		(5, layer_node(6, Some(7))),
+		(6, node("Rectangle Generator", &[])),
+		(7, layer_node(8, None)),
+		(8, node("Rectangle Generator", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let report = document_metadata.load_structure(&network);
+	assert_eq!(report.duplicates, [5]);
+	let root = document_metadata.root();
+	assert_eq!(root.children(&document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), [1, 3, 5, 7]);
+	assert_eq!(LayerNodeIdentifier::new_unchecked(3).children(&document_metadata).count(), 0);
+
+	assert_eq!(document_metadata.load_structure(&empty_network()), LoadStructureReport::default());
+}
+
+#[test]
+fn load_structure_cycle() {
+	use crate::test_utils::{layer_node, network, node};
+
+	// The bottom layer loops back to the top of the stack
+	let network = network([
+		(0, node("Output", &[1])),
+		(1, layer_node(2, Some(3))),
+		(2, node("Rectangle Generator", &[])),
+		(3, layer_node(4, Some(1))),
+		(4, node("Rectangle Generator", &[])),
+	]);
+	let mut document_metadata = DocumentMetadata::default();
+	let report = document_metadata.load_structure(&network);
+	assert_eq!(report.duplicates, [1]);
+	let root = document_metadata.root();
+	assert_eq!(root.children(&document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), [1, 3]);
+}
+
+#[test]
+fn viewport_px_tolerance() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.update_click_targets(HashMap::from_iter([(layer, rect_click_targets(DVec2::ZERO, DVec2::splat(10.)))]));
+	for zoom in [0.1, 1., 10.] {
+		document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(zoom));
+		// The layer is stretched to twice the width within the document, and evaluated with a footprint of the viewport
+		let footprint = Footprint {
+			transform: document_metadata.document_to_viewport,
+			..Default::default()
+		};
+		document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(layer.to_node(), (footprint, DAffine2::from_scale(DVec2::new(2., 1.))))]));
+		assert!((document_metadata.viewport_px_to_document_units(5.) - 5. / zoom).abs() < 1e-9);
+		assert!((document_metadata.viewport_px_to_layer_units(layer, 5.) - 5. / (zoom * 2_f64.sqrt())).abs() < 1e-9);
+
+		// A point 4 pixels to the right of the layer is within 5 pixels, but one 6 pixels away isn't
+		let right_edge = document_metadata.transform_to_viewport(layer).transform_point2(DVec2::new(10., 5.));
+		let hit = |offset: f64| document_metadata.nearest_layer(right_edge + DVec2::new(offset, 0.), 5., HitScope::All, HitTestPolicy::Topmost);
+		assert!(hit(4.).is_some_and(|(_, distance)| (distance - 4.).abs() < 1e-6), "{zoom}");
+		assert_eq!(hit(6.), None, "{zoom}");
+	}
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::new(1., 0.));
+	assert_eq!(document_metadata.viewport_px_to_document_units(5.), f64::INFINITY);
+}
+
+#[test]
+fn chrome_click_targets() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [artboard, content] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, artboard);
+	artboard.push_child(&mut document_metadata, content);
+	Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+	let rect = |min: DVec2, max: DVec2| rect_click_targets(min, max);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(artboard, rect(DVec2::ZERO, DVec2::splat(100.))),
+		(content, rect(DVec2::splat(10.), DVec2::splat(20.))),
+	]));
+
+	// The label is drawn above the top left corner of the artboard
+	let label = Quad::from_box([DVec2::new(0., -20.), DVec2::new(60., -4.)]);
+	document_metadata.set_chrome_click_targets(artboard, vec![label]);
+	let hit = |document_metadata: &DocumentMetadata, point| document_metadata.nearest_layer(point, 0., HitScope::All, HitTestPolicy::Topmost).map(|(layer, _)| layer);
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(30., -10.)), Some(artboard));
+	assert_eq!(hit(&document_metadata, DVec2::new(30., -10.)), Some(artboard));
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(30., -2.)), None);
+	assert_eq!(hit(&document_metadata, DVec2::new(30., -2.)), None);
+	assert_eq!(hit(&document_metadata, DVec2::new(61., -10.)), None);
+	// Click targets inside the artboard are unaffected
+	assert_eq!(hit(&document_metadata, DVec2::new(15., 15.)), Some(content));
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(15., 15.)), None);
+
+	// Moving the viewport forgets the regions until they are registered again
+	document_metadata.document_to_viewport = DAffine2::from_translation(DVec2::new(0., 30.));
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(30., -10.)), None);
+	document_metadata.set_chrome_click_targets(content, vec![label + DVec2::new(0., 30.)]);
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(30., 20.)), Some(content));
+	document_metadata.set_chrome_click_targets(content, Vec::new());
+	assert_eq!(document_metadata.chrome_region_at_point(DVec2::new(30., 20.)), None);
+}
+
+#[test]
+fn previous_frame_bounds() {
+	use crate::test_utils::{empty_network, rect_click_targets};
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [layer, empty] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.root().push_child(&mut document_metadata, empty);
+	let click_targets = rect_click_targets(DVec2::ZERO, DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([(layer, click_targets)]));
+	let set_offset = |document_metadata: &mut DocumentMetadata, offset: DVec2| {
+		document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(layer.to_node(), (Footprint::default(), DAffine2::from_translation(offset)))]));
+	};
+
+	// Nothing has been committed yet
+	assert_eq!(document_metadata.previous_bounding_box_viewport(layer), None);
+	assert!(document_metadata.bounds_changed_significantly(layer, 0.5));
+	assert!(!document_metadata.bounds_changed_significantly(empty, 0.5));
+
+	document_metadata.commit_frame();
+	assert_eq!(document_metadata.previous_bounding_box_viewport(layer), Some([DVec2::ZERO, DVec2::splat(10.)]));
+	assert!(!document_metadata.bounds_changed_significantly(layer, 0.5));
+
+	set_offset(&mut document_metadata, DVec2::new(0.3, -0.2));
+	assert!(!document_metadata.bounds_changed_significantly(layer, 0.5));
+	set_offset(&mut document_metadata, DVec2::new(0.3, 0.8));
+	assert!(document_metadata.bounds_changed_significantly(layer, 0.5));
+	assert_eq!(document_metadata.previous_bounding_box_viewport(layer), Some([DVec2::ZERO, DVec2::splat(10.)]));
+
+	// The new bounds are compared against from the next frame
+	document_metadata.commit_frame();
+	assert!(!document_metadata.bounds_changed_significantly(layer, 0.5));
+	assert_eq!(document_metadata.previous_bounding_box_viewport(layer), Some([DVec2::new(0.3, 0.8), DVec2::new(10.3, 10.8)]));
+
+	// Reloading the structure forgets the previous frame
+	let _ = document_metadata.load_structure(&empty_network());
+	assert_eq!(document_metadata.previous_bounding_box_viewport(layer), None);
+}
+
+#[test]
+fn snap_candidates() {
+	let mut document_metadata = DocumentMetadata::default();
+	let vertical = document_metadata.add_guide(Guide {
+		axis: GuideAxis::Vertical,
+		position_document: 100.,
+	});
+	let grid = GridSettings {
+		origin: DVec2::ZERO,
+		spacing: DVec2::splat(10.),
+	};
+	let of_source = |candidates: &[SnapCandidate], source: SnapSource| candidates.iter().filter(|candidate| candidate.source == source).map(|candidate| candidate.point).collect::<Vec<_>>();
+
+	// The query rectangle may be given in any winding
+	let candidates = document_metadata.snap_candidates([DVec2::new(125., 75.), DVec2::new(75., 125.)], Some(grid));
+	let grid_points = of_source(&candidates, SnapSource::Grid);
+	assert_eq!(grid_points.len(), 25);
+	assert_eq!(grid_points.first(), Some(&DVec2::new(80., 80.)));
+	assert_eq!(grid_points.last(), Some(&DVec2::new(120., 120.)));
+	let expected = [80., 90., 100., 110., 120.].map(|y| DVec2::new(100., y));
+	assert_eq!(of_source(&candidates, SnapSource::GuideOnGrid(vertical)), expected);
+	assert_eq!(candidates.len(), 30);
+
+	// Without a grid, a further horizontal guide crosses the vertical one
+	let horizontal = document_metadata.add_guide(Guide {
+		axis: GuideAxis::Horizontal,
+		position_document: 95.,
+	});
+	let candidates = document_metadata.snap_candidates([DVec2::new(75., 75.), DVec2::new(125., 125.)], None);
+	assert_eq!(
+		candidates,
+		[SnapCandidate {
+			point: DVec2::new(100., 95.),
+			source: SnapSource::GuideIntersection(vertical, horizontal)
+		}]
+	);
+	// A guide outside the rectangle doesn't cross the grid within it
+	assert!(of_source(&document_metadata.snap_candidates([DVec2::ZERO, DVec2::splat(50.)], Some(grid)), SnapSource::GuideOnGrid(vertical)).is_empty());
+
+	assert_eq!(document_metadata.remove_guide(vertical).map(|guide| guide.position_document), Some(100.));
+	assert_eq!(document_metadata.remove_guide(vertical), None);
+	assert_eq!(document_metadata.guides().iter().map(|&(id, _)| id).collect::<Vec<_>>(), [horizontal]);
+
+	// Restored guides aren't given the identifiers of new ones
+	let saved = document_metadata.guides().to_vec();
+	let mut restored = DocumentMetadata::default();
+	restored.set_guides(saved);
+	assert_ne!(
+		restored.add_guide(Guide {
+			axis: GuideAxis::Vertical,
+			position_document: 0.
+		}),
+		horizontal
+	);
+
+	// Only the lines nearest the center of a huge rectangle are enumerated
+	let huge = [DVec2::splat(-1e12), DVec2::splat(1e12)];
+	let grid_points = of_source(&restored.snap_candidates(huge, Some(grid)), SnapSource::Grid);
+	assert_eq!(grid_points.len(), DocumentMetadata::MAX_GRID_SNAP_LINES.pow(2));
+	assert!(grid_points.iter().all(|point| point.abs().max_element() <= 10. * DocumentMetadata::MAX_GRID_SNAP_LINES as f64));
+	assert_eq!(
+		of_source(&restored.snap_candidates(huge, Some(grid)), SnapSource::GuideOnGrid(horizontal)).len(),
+		DocumentMetadata::MAX_GRID_SNAP_LINES
+	);
+	// The grid is left out where its lines can't be told apart, or the rectangle isn't finite
+	let far = [DVec2::splat(1e300), DVec2::splat(1e300 + 1e290)];
+	assert!(of_source(&restored.snap_candidates(far, Some(grid)), SnapSource::Grid).is_empty());
+	let infinite = [DVec2::splat(f64::NEG_INFINITY), DVec2::splat(f64::INFINITY)];
+	assert_eq!(of_source(&restored.snap_candidates(infinite, Some(grid)), SnapSource::Grid).len(), 0);
+}
+
+#[test]
+fn scoped_metadata() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [symbol, inside, nested, outside] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, symbol);
+	symbol.push_child(&mut document_metadata, inside);
+	symbol.push_child(&mut document_metadata, nested);
+	root.push_child(&mut document_metadata, outside);
+	Arc::make_mut(&mut document_metadata.folders).insert(symbol);
+	let rect = || rect_click_targets(DVec2::ZERO, DVec2::splat(10.));
+	document_metadata.update_click_targets(HashMap::from_iter([(inside, rect()), (nested, rect()), (outside, rect())]));
+	let symbol_transform = DAffine2::from_scale_angle_translation(DVec2::splat(2.), 0., DVec2::new(100., 0.));
+	let transforms = [
+		(symbol, symbol_transform),
+		(inside, symbol_transform * DAffine2::from_translation(DVec2::new(5., 5.))),
+		(nested, symbol_transform * DAffine2::from_translation(DVec2::new(20., 0.))),
+		(outside, DAffine2::IDENTITY),
+	];
+	document_metadata.upstream_transforms = Arc::new(transforms.into_iter().map(|(layer, transform)| (layer.to_node(), (Footprint::default(), transform))).collect());
+	let scoped = document_metadata.scoped(symbol);
+
+	assert_eq!(scoped.all_layers().collect::<Vec<_>>(), [inside, nested]);
+	assert!(scoped.contains(nested) && !scoped.contains(symbol) && !scoped.contains(outside));
+
+	// Transforms and bounds are relative to the scope root
+	assert!(scoped.transform_to_document(inside).unwrap().abs_diff_eq(DAffine2::from_translation(DVec2::new(5., 5.)), 1e-10));
+	assert_eq!(scoped.transform_to_document(outside), None);
+	assert_eq!(scoped.bounding_box_document(inside), Some([DVec2::splat(5.), DVec2::splat(15.)]));
+	assert_eq!(document_metadata.bounding_box_document(inside), Some([DVec2::new(110., 10.), DVec2::new(130., 30.)]));
+	assert_eq!(scoped.bounding_box_document(outside), None);
+	assert_eq!(scoped.document_bounds_document_space(true), Some([DVec2::new(5., 0.), DVec2::new(30., 15.)]));
+
+	// The viewport space queries match the global ones filtered to the subtree
+	let filtered = document_metadata
+		.all_layers()
+		.filter(|&layer| layer.ancestors(&document_metadata).skip(1).any(|ancestor| ancestor == symbol))
+		.filter_map(|layer| document_metadata.bounding_box_viewport(layer))
+		.reduce(Quad::combine_bounds);
+	assert_eq!(scoped.document_bounds_viewport_space(), filtered);
+	for point in [DVec2::new(115., 15.), DVec2::new(145., 5.), DVec2::new(5., 5.)] {
+		let global = document_metadata
+			.nearest_layer(point, 0., HitScope::All, HitTestPolicy::Topmost)
+			.filter(|&(layer, _)| scoped.contains(layer));
+		assert_eq!(scoped.nearest_layer(point, 0., HitTestPolicy::Topmost), global);
+	}
+	assert_eq!(scoped.nearest_layer(DVec2::new(5., 5.), 0., HitTestPolicy::Topmost), None);
+	let marquee = Quad::from_box([DVec2::splat(-10.), DVec2::splat(200.)]);
+	assert_eq!(scoped.layers_in_marquee(marquee, MarqueeMode::Contain), [inside, nested]);
+
+	let _ = document_metadata.set_selected_nodes(vec![outside.to_node(), nested.to_node()]);
+	assert_eq!(document_metadata.scoped(symbol).selected_layers().collect::<Vec<_>>(), [nested]);
+}
+
+#[test]
+fn click_targets_outside_structure() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [layer, unknown, other_unknown] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	let click_target = || rect_click_targets(DVec2::ZERO, DVec2::ONE);
+
+	let rejected = document_metadata.update_click_targets(HashMap::from_iter([(other_unknown, click_target()), (layer, click_target()), (unknown, click_target())]));
+	assert_eq!(rejected, [unknown, other_unknown]);
+	assert!(document_metadata.click_target(layer).is_some());
+	assert!(document_metadata.click_target(unknown).is_none());
+
+	let rejected = document_metadata.update_click_targets_partial(HashMap::from_iter([(unknown, click_target())]));
+	assert_eq!(rejected, [unknown]);
+	assert!(document_metadata.click_target(unknown).is_none());
+	assert!(document_metadata.nearest_layer(DVec2::splat(0.5), 0., HitScope::All, HitTestPolicy::Topmost).is_some());
+
+	// Once the structure catches up the click targets are accepted
+	document_metadata.root().push_child(&mut document_metadata, unknown);
+	assert!(document_metadata.update_click_targets_partial(HashMap::from_iter([(unknown, click_target())])).is_empty());
+	assert!(document_metadata.click_target(unknown).is_some());
+
+	// Deleting a layer forgets its click targets
+	unknown.delete(&mut document_metadata);
+	assert!(document_metadata.update_click_targets_partial(HashMap::from_iter([(layer, click_target())])).is_empty());
+	assert!(document_metadata.click_target(unknown).is_none());
+}
+
+#[test]
+fn load_structure_with_remap() {
+	use crate::test_utils::{layer_node, network, node};
+
+	// Two layers with the ids, filled by their own shapes
+	let network = |[top, bottom]: [NodeId; 2]| {
+		network([
+			(0, node("Output", &[top])),
+			(top, layer_node(10, Some(bottom))),
+			(10, node("Shape", &[])),
+			(bottom, layer_node(20, None)),
+			(20, node("Shape", &[])),
+		])
+	};
+
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network([1, 2]));
+	let [top, bottom, replacement] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	assert_eq!(document_metadata.all_layers().collect::<Vec<_>>(), [top, bottom]);
+	let _ = document_metadata.set_selected_nodes(vec![2]);
+	document_metadata.set_layer_tag(bottom, Some(LayerTag::Red));
+
+	// The bottom layer is regenerated with a new id
+	let _ = document_metadata.load_structure_with_remap(&network([1, 3]), &HashMap::from_iter([(2, 3)]));
+	assert_eq!(document_metadata.all_layers().collect::<Vec<_>>(), [top, replacement]);
+	assert_eq!(document_metadata.selected_layers().collect::<Vec<_>>(), [replacement]);
+	assert_eq!(document_metadata.layer_tag(replacement), Some(&LayerTag::Red));
+	assert_eq!(document_metadata.layer_tag(bottom), None);
+
+	// Without the remap the selection of a replaced layer is dropped
+	let _ = document_metadata.load_structure(&network([1, 2]));
+	assert_eq!(document_metadata.selected_layers().count(), 0);
+	assert_eq!(document_metadata.layer_tag(bottom), None);
+}
+
+#[test]
+fn non_rendering_bounds() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [visible, transparent, folder, child] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [visible, transparent, folder] {
+		root.push_child(&mut document_metadata, layer);
+	}
+	folder.push_child(&mut document_metadata, child);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	let rect = |min: DVec2, max: DVec2| rect_click_targets(min, max);
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(visible, rect(DVec2::ZERO, DVec2::splat(10.))),
+		(transparent, rect(DVec2::splat(-1000.), DVec2::splat(1000.))),
+		(child, rect(DVec2::ZERO, DVec2::splat(50.))),
+	]));
+	document_metadata.set_layer_opacity_hint(transparent, 0.);
+	document_metadata.set_layer_opacity_hint(folder, 0.5);
+
+	let layers = [visible, transparent, folder];
+	let included = BoundsOptions {
+		use_subtree_for_folders: true,
+		..Default::default()
+	};
+	let excluded = BoundsOptions {
+		exclude_non_rendering: true,
+		..included
+	};
+	assert_eq!(
+		document_metadata.combined_bounds_document(layers.into_iter(), included),
+		Some([DVec2::splat(-1000.), DVec2::splat(1000.)])
+	);
+	assert_eq!(document_metadata.combined_bounds_document(layers.into_iter(), excluded), Some([DVec2::ZERO, DVec2::splat(50.)]));
+	assert_eq!(document_metadata.rendered_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(50.)]));
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::splat(-1000.), DVec2::splat(1000.)]));
+	assert_eq!(document_metadata.non_rendering_layers().collect::<Vec<_>>(), [transparent]);
+
+	// The opacity of a folder applies to its contents
+	document_metadata.set_layer_opacity_hint(folder, 0.);
+	assert_eq!(document_metadata.non_rendering_layers().collect::<Vec<_>>(), [transparent, folder, child]);
+	assert_eq!(document_metadata.rendered_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(10.)]));
+	assert_eq!(document_metadata.combined_bounds_document(layers.into_iter(), excluded), Some([DVec2::ZERO, DVec2::splat(10.)]));
+
+	// Layers blended to only mask what is below them, along with their contents
+	document_metadata.set_layer_opacity_hint(folder, 1.);
+	Arc::make_mut(&mut document_metadata.blend_modes).insert(folder, BlendMode::Erase);
+	assert!(document_metadata.is_mask_only(folder) && !document_metadata.is_mask_only(child));
+	assert_eq!(document_metadata.non_rendering_layers().collect::<Vec<_>>(), [transparent, folder, child]);
+	assert_eq!(document_metadata.combined_bounds_document(layers.into_iter(), excluded), Some([DVec2::ZERO, DVec2::splat(10.)]));
+}
+
+#[test]
+fn nonzero_bounding_box_with_min() {
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	// A horizontal line, which has no height
+	let line = vec![ClickTarget {
+		subpath: bezier_rs::Subpath::from_anchors([DVec2::ZERO, DVec2::new(10., 0.)], false),
+		stroke_width: 0.,
+	}];
+	document_metadata.update_click_targets(HashMap::from_iter([(layer, line)]));
+	assert_eq!(document_metadata.nonzero_bounding_box(layer), [DVec2::ZERO, DVec2::new(10., 1.)]);
+
+	for zoom in [0.01, 1., 64.] {
+		for layer_scale in [DVec2::splat(0.001), DVec2::ONE, DVec2::new(1000., 3.)] {
+			let document_to_viewport = DAffine2::from_scale(DVec2::splat(zoom));
+			document_metadata.document_to_viewport = document_to_viewport;
+			let footprint = Footprint {
+				transform: document_to_viewport,
+				..Default::default()
+			};
+			let layer_transform = DAffine2::from_scale_angle_translation(layer_scale, 0.5, DVec2::new(3., 4.));
+			document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(layer.to_node(), (footprint, layer_transform))]));
+
+			let [min, max] = document_metadata.nonzero_bounding_box_with_min(layer, 8.);
+			assert_eq!((min, max.x), (DVec2::ZERO, 10.));
+			let on_screen = document_metadata.transform_to_viewport(layer).transform_vector2(DVec2::new(0., max.y - min.y)).length();
+			assert!((on_screen - 8.).abs() < 1e-9, "{on_screen} at zoom {zoom} and layer scale {layer_scale}");
+
+			// A pivot in the middle of the bounds is drawn half of the minimum size from the line, rather than half a unit of the layer's scale
+			let [min, max] = document_metadata.nonzero_bounding_box_with_min(layer, 1.);
+			let to_viewport = document_metadata.transform_to_viewport(layer);
+			let pivot = to_viewport.transform_point2(min + (max - min) * 0.5);
+			let line_middle = to_viewport.transform_point2(DVec2::new(5., 0.));
+			assert!((pivot.distance(line_middle) - 0.5).abs() < 1e-9, "pivot {pivot} at zoom {zoom} and layer scale {layer_scale}");
+		}
+	}
+}
+
+#[test]
+fn shallowest_unique_layers_matches_paths() {
+	// The previous implementation, which sorted the paths from the root and removed those starting with another
+	fn by_sorted_paths(document_metadata: &DocumentMetadata, layers: &[LayerNodeIdentifier]) -> HashSet<LayerNodeIdentifier> {
+		let mut paths = layers
+			.iter()
+			.map(|layer| {
+				let mut path = layer.ancestors(document_metadata).collect::<Vec<_>>();
+				path.reverse();
+				path
+			})
+			.collect::<Vec<_>>();
+		paths.sort();
+		paths.dedup_by(|a, b| a.starts_with(b));
+		paths.iter().filter_map(|path| path.last().copied()).collect()
+	}
+
+	let mut state = 0x2545_f491_4f6c_dd1d_u64;
+	let mut random = |below: usize| {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		(state % below as u64) as usize
+	};
+	for _ in 0..200 {
+		let mut document_metadata = DocumentMetadata::default();
+		let mut layers = vec![LayerNodeIdentifier::ROOT];
+		for id in 1..=1 + random(40) as u64 {
+			let layer = LayerNodeIdentifier::new_unchecked(id);
+			layers[random(layers.len())].push_child(&mut document_metadata, layer);
+			layers.push(layer);
+		}
+		// Layers may be given more than once, and include some that aren't in the tree
+		let selection = (0..random(20))
+			.map(|_| {
+				if random(10) == 0 {
+					LayerNodeIdentifier::new_unchecked(1000)
+				} else {
+					layers[1 + random(layers.len() - 1)]
+				}
+			})
+			.collect::<Vec<_>>();
+
+		let shallowest = document_metadata.shallowest_unique_layers(selection.iter().copied());
+		assert_eq!(shallowest.iter().copied().collect::<HashSet<_>>(), by_sorted_paths(&document_metadata, &selection));
+		assert_eq!(shallowest.len(), shallowest.iter().collect::<HashSet<_>>().len());
+		// The layers are in the order of their first appearance in the input
+		let mut first_appearances = selection.clone();
+		let mut seen = HashSet::new();
+		first_appearances.retain(|layer| shallowest.contains(layer) && seen.insert(*layer));
+		assert_eq!(shallowest, first_appearances);
+	}
+}
+
+#[test]
+fn artboard_info() {
+	use crate::test_utils::NetworkBuilder;
+
+	let (mut network, ids) = NetworkBuilder::new()
+		.artboard_at("Artboard", IVec2::new(10, 20), IVec2::new(1920, 1080), |artboard| artboard.layer("Content"))
+		.build();
+	let NodeInput::Node { node_id: artboard_node, .. } = network.nodes[&ids["Artboard"]].inputs[0] else {
+		panic!("the artboard layer is fed by its Artboard node");
+	};
+
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [artboard, content] = [ids["Artboard"], ids["Content"]].map(LayerNodeIdentifier::new_unchecked);
+	assert!(document_metadata.is_artboard(artboard));
+	assert_eq!(content.parent(&document_metadata), Some(artboard));
+	let expected = ArtboardInfo {
+		origin_document: DVec2::new(10., 20.),
+		size: DVec2::new(1920., 1080.),
+		background: Some(Color::WHITE),
+		clip_content: false,
+	};
+	assert_eq!(document_metadata.artboard_info(artboard), Some(&expected));
+	// The export bounds come from the parameters, even before there are click targets
+	assert_eq!(document_metadata.artboard_export_info()[0].bounds_document, Some([DVec2::new(10., 20.), DVec2::new(1930., 1100.)]));
+
+	// Parameters changed without a structural change are read again for just the artboard
+	let artboard_inputs = &mut network.nodes.get_mut(&artboard_node).unwrap().inputs;
+	artboard_inputs[1] = NodeInput::value(TaggedValue::IVec2(IVec2::new(-5, 0)), false);
+	artboard_inputs[4] = NodeInput::value(TaggedValue::Bool(true), false);
+	assert_eq!(document_metadata.artboard_info(artboard), Some(&expected));
+	let generation = document_metadata.layer_generation(artboard);
+	assert!(document_metadata.refresh_artboard_info(artboard, &network));
+	assert!(!document_metadata.refresh_artboard_info(artboard, &network));
+	assert_ne!(document_metadata.layer_generation(artboard), generation);
+	let info = document_metadata.artboard_info(artboard).unwrap();
+	assert_eq!((info.origin_document, info.clip_content), (DVec2::new(-5., 0.), true));
+	assert_eq!(document_metadata.point_relative_to_artboard(content, DVec2::new(0., 10.)), Some(DVec2::new(5., 10.)));
+
+	// Layers that aren't artboards have no parameters
+	assert!(!document_metadata.refresh_artboard_info(content, &network));
+	assert_eq!(document_metadata.artboard_info(content), None);
+}
+
+#[test]
+fn folders_with_selected_descendants() {
+	let mut document_metadata = DocumentMetadata::default();
+	let root = document_metadata.root();
+	let [outer, middle, inner, deep, sibling, other_folder, other] = [1, 2, 3, 4, 5, 6, 7].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, outer);
+	outer.push_child(&mut document_metadata, middle);
+	middle.push_child(&mut document_metadata, inner);
+	inner.push_child(&mut document_metadata, deep);
+	outer.push_child(&mut document_metadata, sibling);
+	root.push_child(&mut document_metadata, other_folder);
+	other_folder.push_child(&mut document_metadata, other);
+	assert!(document_metadata.folders_with_selected_descendants().is_empty());
+
+	let _ = document_metadata.set_selected_nodes(vec![deep.to_node()]);
+	assert_eq!(document_metadata.folders_with_selected_descendants(), HashSet::from_iter([outer, middle, inner]));
+	assert!(document_metadata.has_selected_descendant(middle));
+	assert!(!document_metadata.has_selected_descendant(deep) && !document_metadata.has_selected_descendant(other_folder));
+
+	// Deselecting clears exactly the folders of the deselected layer
+	let _ = document_metadata.add_selected_nodes([other.to_node()]);
+	assert_eq!(document_metadata.folders_with_selected_descendants(), HashSet::from_iter([outer, middle, inner, other_folder]));
+	let _ = document_metadata.retain_selected_nodes(|&node| node != deep.to_node());
+	assert_eq!(document_metadata.folders_with_selected_descendants(), HashSet::from_iter([other_folder]));
+
+	// The contents of a selected folder are selected along with it
+	let _ = document_metadata.set_selected_nodes(vec![middle.to_node(), sibling.to_node()]);
+	assert_eq!(document_metadata.folders_with_selected_descendants(), HashSet::from_iter([outer, middle]));
+
+	// Moving a layer in the tree is reflected without changing the selection
+	let _ = document_metadata.set_selected_nodes(vec![sibling.to_node()]);
+	assert!(document_metadata.has_selected_descendant(outer));
+	sibling.delete(&mut document_metadata);
+	other_folder.push_child(&mut document_metadata, sibling);
+	assert_eq!(document_metadata.folders_with_selected_descendants(), HashSet::from_iter([other_folder]));
+}
+
+#[test]
+fn resolve_overlap() {
+	use crate::test_utils::{rect_click_targets, NetworkBuilder};
+
+	let (network, ids) = NetworkBuilder::new().layer("A").layer("B").layer("C").build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [a, b, c] = ["A", "B", "C"].map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	document_metadata.update_click_targets(HashMap::from_iter([
+		(a, rect_click_targets(DVec2::ZERO, DVec2::splat(10.))),
+		(b, rect_click_targets(DVec2::ZERO, DVec2::new(10., 4.))),
+		(c, rect_click_targets(DVec2::ZERO, DVec2::splat(4.))),
+	]));
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	let rotated = DAffine2::from_translation(DVec2::new(11., 11.)) * DAffine2::from_angle(std::f64::consts::FRAC_PI_4) * DAffine2::from_translation(DVec2::splat(-2.));
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([
+		(b.to_node(), (footprint, DAffine2::from_translation(DVec2::new(7., 3.)))),
+		(c.to_node(), (footprint, rotated)),
+	]));
 
-		if let Some(next_sibling) = next_sibling.map(|node| document_metadata.get_structure_mut(node)) {
-			next_sibling.previous_sibling = previous_sibling;
-		}
-		let mut parent = self.parent(document_metadata).map(|parent| document_metadata.get_structure_mut(parent));
-		if let Some(structure) = parent.as_mut().filter(|structure| structure.first_child == Some(self)) {
-			structure.first_child = next_sibling;
-		}
-		if let Some(structure) = parent.as_mut().filter(|structure| structure.last_child == Some(self)) {
-			structure.last_child = previous_sibling;
-		}
+	assert!(document_metadata.resolve_overlap(a, b).is_some_and(|translation| translation.abs_diff_eq(DVec2::new(-3., 0.), 1e-9)));
+	assert!(document_metadata.resolve_overlap(b, a).is_some_and(|translation| translation.abs_diff_eq(DVec2::new(3., 0.), 1e-9)));
+	// The rotated layer overlaps the corner of the first, so it is pushed out diagonally
+	assert!(document_metadata
+		.resolve_overlap(c, a)
+		.is_some_and(|translation| translation.abs_diff_eq(DVec2::splat(std::f64::consts::SQRT_2 - 1.), 1e-9)));
+	assert_eq!(document_metadata.resolve_overlap(b, c), None);
+	assert_eq!(document_metadata.resolve_overlap(a, LayerNodeIdentifier::new_unchecked(1000)), None);
+}
 
-		let mut delete = vec![self];
-		delete.extend(self.decendants(document_metadata));
-		for node in delete {
-			document_metadata.structure.remove(&node);
+#[test]
+fn scratch_buffers() {
+	use crate::test_utils::{rect_click_targets, NetworkBuilder};
+
+	let (network, ids) = NetworkBuilder::new()
+		.folder("A", |folder| folder.layer("A1").layer("A2"))
+		.folder("B", |folder| folder.layer("B1").layer("B2"))
+		.build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [folder_a, folder_b, a1, a2, b1, b2] = ["A", "B", "A1", "A2", "B1", "B2"].map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	let rect = |offset: f64| Arc::new(rect_click_targets(DVec2::splat(offset), DVec2::splat(offset + 10.)));
+	Arc::make_mut(&mut document_metadata.click_targets).extend([(a1, rect(0.)), (a2, rect(20.)), (b1, rect(40.)), (b2, rect(60.))]);
+
+	let queries = |document_metadata: &DocumentMetadata, point: DVec2| {
+		assert_eq!(document_metadata.shallowest_unique_layers([a1, folder_a, b2, a2, folder_a].into_iter()), vec![folder_a, b2]);
+		assert_eq!(document_metadata.deepest_common_ancestor([b1, b2].into_iter(), false), Some(folder_b));
+		assert_eq!(document_metadata.transforms_to_viewport([a1, b2].into_iter()).len(), 2);
+		for policy in [HitTestPolicy::Topmost, HitTestPolicy::SmallestArea] {
+			assert!(document_metadata.nearest_layer(point, 5., HitScope::All, policy).is_some());
 		}
+	};
+	let points = [DVec2::splat(5.), DVec2::splat(25.), DVec2::splat(45.), DVec2::splat(65.)];
+	for point in points {
+		queries(&document_metadata, point);
 	}
+	let warm = document_metadata.scratch_stats();
+	assert!(warm.acquisitions > 0);
 
-	pub fn exists(&self, document_metadata: &DocumentMetadata) -> bool {
-		document_metadata.get_relations(*self).is_some()
+	// Once the buffers have grown, moving the pointer around reuses them rather than growing new ones
+	for point in points {
+		queries(&document_metadata, point);
 	}
+	let steady = document_metadata.scratch_stats();
+	assert_eq!(steady.acquisitions, warm.acquisitions * 2);
+	assert_eq!(steady.allocations, warm.allocations);
 
-	pub fn starts_with(&self, other: Self, document_metadata: &DocumentMetadata) -> bool {
-		self.ancestors(document_metadata).any(|parent| parent == other)
+	// A query nested inside another that holds a buffer takes its own buffer instead of sharing one
+	let nested = document_metadata.scratch.layers.with(|outer| {
+		outer.push(a1);
+		let nested = document_metadata.deepest_common_ancestor([a1, a2].into_iter(), false);
+		(nested, outer.clone())
+	});
+	assert_eq!(nested, (Some(folder_a), vec![a1]));
+
+	// Clones start with empty pools
+	assert_eq!(document_metadata.clone().scratch_stats(), ScratchStats::default());
+}
+
+#[test]
+fn subtree_intervals() {
+	use crate::test_utils::NetworkBuilder;
+
+	// A folder containing a layer and another folder, which contains one more layer, above a layer at the top level
+	let (network, ids) = NetworkBuilder::new()
+		.folder("Folder", |folder| folder.layer("Inner").folder("Inner folder", |inner_folder| inner_folder.layer("Nested")))
+		.layer("Outer")
+		.build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [folder, inner, inner_folder, nested, outer] = ["Folder", "Inner", "Inner folder", "Nested", "Outer"].map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	assert_eq!(document_metadata.subtree_interval(folder), None);
+
+	document_metadata.set_subtree_interval_indexing(true);
+	let intervals = |document_metadata: &DocumentMetadata| [LayerNodeIdentifier::ROOT, folder, inner, inner_folder, nested, outer].map(|layer| document_metadata.subtree_interval(layer));
+	assert_eq!(intervals(&document_metadata), [Some((0, 5)), Some((1, 4)), Some((2, 2)), Some((3, 4)), Some((4, 4)), Some((5, 5))]);
+	assert!(nested.starts_with(folder, &document_metadata) && nested.starts_with(nested, &document_metadata) && !outer.starts_with(folder, &document_metadata));
+	assert!(folder.is_ancestor_of(nested, &document_metadata) && !nested.is_ancestor_of(nested, &document_metadata) && !inner.is_ancestor_of(nested, &document_metadata));
+	assert!(document_metadata.scoped(inner_folder).contains(nested) && !document_metadata.scoped(inner_folder).contains(inner_folder));
+	assert_eq!(document_metadata.subtree_interval(LayerNodeIdentifier::new_unchecked(1000)), None);
+
+	// Moving the inner folder to the top level leaves the numbers stale until they are next needed
+	inner_folder.reparent(&mut document_metadata, LayerNodeIdentifier::ROOT);
+	assert!(document_metadata.valid_subtree_intervals().is_none());
+	assert!(!nested.starts_with(folder, &document_metadata) && nested.starts_with(inner_folder, &document_metadata));
+	assert!(!document_metadata.scoped(folder).contains(nested));
+	assert_eq!(intervals(&document_metadata), [Some((0, 5)), Some((1, 2)), Some((2, 2)), Some((4, 5)), Some((5, 5)), Some((3, 3))]);
+	assert!(document_metadata.valid_subtree_intervals().is_some());
+	assert!(inner_folder.is_ancestor_of(nested, &document_metadata) && !folder.is_ancestor_of(nested, &document_metadata) && !outer.starts_with(inner_folder, &document_metadata));
+
+	// Reloading numbers the layers straight away
+	let _ = document_metadata.load_structure(&network);
+	assert!(document_metadata.valid_subtree_intervals().is_some());
+	assert_eq!(intervals(&document_metadata)[3], Some((3, 4)));
+
+	// Hit tests look the intervals up once for all layers, which agrees with testing each layer on its own
+	document_metadata.set_isolation(Some(inner_folder));
+	let check_hit_scopes = |document_metadata: &DocumentMetadata| {
+		for scope in [HitScope::All, HitScope::Isolation, HitScope::Subtree(folder), HitScope::Subtree(LayerNodeIdentifier::ROOT)] {
+			let in_scope = document_metadata.hit_scope_filter(scope);
+			for layer in [LayerNodeIdentifier::ROOT, folder, inner, inner_folder, nested, outer] {
+				assert_eq!(in_scope(layer), document_metadata.is_in_hit_scope(layer, scope), "{layer:?} in {scope:?}");
+			}
+		}
+	};
+	check_hit_scopes(&document_metadata);
+	document_metadata.set_subtree_interval_indexing(false);
+	assert_eq!(document_metadata.subtree_interval(folder), None);
+	check_hit_scopes(&document_metadata);
+}
+
+#[test]
+fn subpath_windings() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [donut, ring, open, mirrored] = [1, 2, 3, 4].map(LayerNodeIdentifier::new_unchecked);
+	let outer = bezier_rs::Subpath::new_rect(DVec2::ZERO, DVec2::splat(30.));
+	let inner = bezier_rs::Subpath::new_rect(DVec2::splat(10.), DVec2::splat(20.));
+	let click_targets = |subpaths: Vec<bezier_rs::Subpath<ManipulatorGroupId>>| Arc::new(subpaths.into_iter().map(|subpath| ClickTarget { subpath, stroke_width: 0. }).collect::<Vec<_>>());
+	let line = bezier_rs::Subpath::from_anchors([DVec2::ZERO, DVec2::new(30., 0.), DVec2::splat(30.)], false);
+	Arc::make_mut(&mut document_metadata.click_targets).extend([
+		(donut, click_targets(vec![outer.clone(), inner.reverse()])),
+		(ring, click_targets(vec![outer.clone(), inner.clone()])),
+		(open, click_targets(vec![line, bezier_rs::Subpath::new_ellipse(DVec2::ZERO, DVec2::splat(30.))])),
+		(mirrored, click_targets(vec![outer.clone(), inner.reverse()])),
+	]);
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	let mirror = DAffine2::from_translation(DVec2::new(30., 0.)) * DAffine2::from_scale(DVec2::new(-1., 1.));
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(mirrored.to_node(), (footprint, mirror))]));
+
+	assert_eq!(document_metadata.subpath_windings(donut), vec![Winding::CounterClockwise, Winding::Clockwise]);
+	assert_eq!(document_metadata.subpath_windings(ring), vec![Winding::CounterClockwise, Winding::CounterClockwise]);
+	assert_eq!(document_metadata.subpath_windings(open), vec![Winding::None, Winding::CounterClockwise]);
+	assert_eq!(document_metadata.subpath_windings(mirrored), vec![Winding::Clockwise, Winding::CounterClockwise]);
+	assert!(document_metadata.subpath_windings(LayerNodeIdentifier::new_unchecked(5)).is_empty());
+	assert!((document_metadata.click_targets[&open][1].subpath.iter().map(|bezier| cubic_signed_area(&bezier)).sum::<f64>() - 225. * std::f64::consts::PI).abs() < 1.);
+
+	let [body, hole, outside] = [DVec2::splat(5.), DVec2::splat(15.), DVec2::splat(35.)];
+	for layer in [donut, mirrored] {
+		for rule in [FillRule::NonZero, FillRule::EvenOdd] {
+			assert!(document_metadata.point_fill_test(layer, body, rule));
+			assert!(!document_metadata.point_fill_test(layer, hole, rule));
+			assert!(!document_metadata.point_fill_test(layer, outside, rule));
+		}
 	}
+	// Subpaths winding the same way only leave a hole with the even-odd rule
+	assert!(document_metadata.point_fill_test(ring, hole, FillRule::NonZero));
+	assert!(!document_metadata.point_fill_test(ring, hole, FillRule::EvenOdd));
+	// The open line would enclose the lower right half if it were closed
+	assert!(!document_metadata.point_fill_test(open, DVec2::new(29., 2.), FillRule::NonZero));
+	assert!(document_metadata.point_fill_test(open, DVec2::splat(15.), FillRule::NonZero));
+}
 
-	pub fn child_of_root(&self, document_metadata: &DocumentMetadata) -> Self {
-		self.ancestors(document_metadata)
-			.filter(|&layer| layer != LayerNodeIdentifier::ROOT)
-			.last()
-			.expect("There should be a layer before the root")
+#[test]
+fn layer_panel_slice() {
+	use crate::test_utils::NetworkBuilder;
+
+	// Two folders of three layers each, then two layers at the top level
+	let (network, ids) = NetworkBuilder::new()
+		.folder("First folder", |folder| folder.layer("A").layer("B").layer("C"))
+		.folder("Second folder", |folder| folder.layer("D").layer("E").layer("F"))
+		.layer("Top")
+		.layer("Bottom")
+		.build();
+	let layer = |name: &str| LayerNodeIdentifier::new_unchecked(ids[name]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [first_folder, second_folder, top] = [layer("First folder"), layer("Second folder"), layer("Top")];
+	document_metadata.set_layer_name(layer("B"), "Named".to_string());
+	let _ = document_metadata.set_selected_nodes(vec![ids["B"], ids["Top"]]);
+
+	// Overlapping windows agree on the rows they share
+	let check = |document_metadata: &DocumentMetadata, options: PanelSliceOptions| {
+		let all = document_metadata.layer_panel_slice(0, usize::MAX, options);
+		for (start, count) in [(0, 3), (2, 4), (5, 10), (7, 2), (20, 5)] {
+			let slice = document_metadata.layer_panel_slice(start, count, options);
+			assert_eq!(slice.total_rows, all.total_rows);
+			assert_eq!(slice.start_flattened_index, start);
+			assert_eq!(slice.rows[..], all.rows[start.min(all.rows.len())..(start + count).min(all.rows.len())]);
+		}
+		all
+	};
+	let all = check(&document_metadata, PanelSliceOptions::default());
+	assert_eq!(all.total_rows, document_metadata.visible_tree_iter().count());
+	assert_eq!(all.rows.iter().map(|row| row.id).collect::<Vec<_>>(), document_metadata.all_layers().collect::<Vec<_>>());
+	let named = &all.rows[2];
+	assert_eq!((named.depth, named.has_children, named.selected, named.name.as_deref()), (2, false, true, Some("Named")));
+	assert_eq!((all.rows[0].depth, all.rows[0].has_children, all.rows[0].kind), (1, true, Some(LayerClassification::Folder)));
+
+	// Collapsing a folder hides its contents until it is expanded again
+	document_metadata.set_collapsed(second_folder, true);
+	let collapsed = check(&document_metadata, PanelSliceOptions::default());
+	assert_eq!((collapsed.total_rows, document_metadata.visible_tree_iter().count()), (7, 7));
+	assert!(collapsed.rows[4].collapsed && collapsed.rows[4].id == second_folder && collapsed.rows[5].id == top);
+	let expanded = check(&document_metadata, PanelSliceOptions { expand_all: true });
+	assert_eq!(expanded.rows.len(), 10);
+	assert!(expanded.rows[4].collapsed);
+
+	// Changing the tree finds the rows again
+	top.reparent(&mut document_metadata, first_folder);
+	let moved = check(&document_metadata, PanelSliceOptions::default());
+	assert_eq!(moved.total_rows, document_metadata.visible_tree_iter().count());
+	assert_eq!((moved.rows[4].id, moved.rows[4].depth), (top, 2));
+	document_metadata.set_collapsed(first_folder, true);
+	assert_eq!(document_metadata.layer_panel_slice(0, 10, PanelSliceOptions::default()).total_rows, 3);
+}
+
+#[test]
+fn geometry_fingerprint() {
+	use crate::test_utils::NetworkBuilder;
+
+	// Five layers at the top level
+	let names = ["Original", "Copy", "Scaled", "Other shape", "Empty"];
+	let (network, ids) = names.iter().fold(NetworkBuilder::new(), |builder, name| builder.layer(name)).build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [original, copy, scaled, other_shape, empty] = names.map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	let click_targets = |subpath: bezier_rs::Subpath<ManipulatorGroupId>| Arc::new(vec![ClickTarget { subpath, stroke_width: 0. }]);
+	let star = |scale: f64| {
+		let points = (0..10).map(|index| DVec2::from_angle(index as f64 * std::f64::consts::TAU / 10.) * scale * if index % 2 == 0 { 10. } else { 4. });
+		bezier_rs::Subpath::from_anchors(points, true)
+	};
+	Arc::make_mut(&mut document_metadata.click_targets).extend([
+		(original, click_targets(star(1.))),
+		(copy, click_targets(star(1.))),
+		(scaled, click_targets(star(2.))),
+		(other_shape, click_targets(bezier_rs::Subpath::new_ellipse(DVec2::ZERO, DVec2::splat(20.)))),
+		(empty, Arc::new(Vec::new())),
+	]);
+	// The copy is moved elsewhere by its transform, which isn't part of the geometry
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(copy.to_node(), (footprint, DAffine2::from_translation(DVec2::new(100., 40.))))]));
+
+	assert!(document_metadata.geometry_fingerprint(original).is_some());
+	assert_eq!(document_metadata.geometry_fingerprint(original), document_metadata.geometry_fingerprint(copy));
+	assert_ne!(document_metadata.geometry_fingerprint(original), document_metadata.geometry_fingerprint(scaled));
+	assert_eq!(document_metadata.geometry_fingerprint(empty), None);
+	assert_eq!(document_metadata.layers_matching_geometry(original), vec![copy]);
+	assert_eq!(document_metadata.layers_matching_geometry(copy), vec![original]);
+	assert!(document_metadata.layers_matching_geometry(scaled).is_empty());
+	assert!(document_metadata.layers_matching_geometry(empty).is_empty());
+
+	// Floating point noise is rounded away
+	let noisy = bezier_rs::Subpath::from_anchors(star(1.).anchors().into_iter().map(|point| point + DVec2::splat(1e-9)), true);
+	document_metadata.insert_click_targets(scaled, click_targets(noisy));
+	assert_eq!(document_metadata.layers_matching_geometry(original), vec![copy, scaled]);
+
+	// Click targets modified in place are fingerprinted again
+	document_metadata.click_targets_mut(copy).unwrap()[0].subpath.apply_transform(DAffine2::from_scale(DVec2::splat(2.)));
+	assert_eq!(document_metadata.layers_matching_geometry(original), vec![scaled]);
+	assert_eq!(document_metadata.geometry_fingerprint(copy), {
+		let mut document_metadata = DocumentMetadata::default();
+		Arc::make_mut(&mut document_metadata.click_targets).insert(original, click_targets(star(2.)));
+		document_metadata.geometry_fingerprint(original)
+	});
+}
+
+#[test]
+fn layer_extent_along() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [square, circle] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	let click_targets = |subpath: bezier_rs::Subpath<ManipulatorGroupId>| Arc::new(vec![ClickTarget { subpath, stroke_width: 0. }]);
+	Arc::make_mut(&mut document_metadata.click_targets).extend([
+		(square, click_targets(bezier_rs::Subpath::new_rect(DVec2::ZERO, DVec2::ONE))),
+		(circle, click_targets(bezier_rs::Subpath::new_ellipse(DVec2::splat(-1.), DVec2::splat(1.)))),
+	]);
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(square.to_node(), (footprint, DAffine2::from_translation(DVec2::new(3., 0.))))]));
+	let close = |extent: Option<(f64, f64)>, expected: (f64, f64)| extent.is_some_and(|(min, max)| (min - expected.0).abs() < 1e-9 && (max - expected.1).abs() < 1e-9);
+
+	// The unit square is as wide as its diagonal along the diagonal, either way
+	let diagonal = DVec2::ONE;
+	let offset = 3. / std::f64::consts::SQRT_2;
+	for tight in [true, false] {
+		assert!(close(document_metadata.layer_extent_along(square, diagonal, tight), (offset, offset + std::f64::consts::SQRT_2)));
+		assert!(close(document_metadata.layer_extent_along(square, DVec2::new(0., 2.), tight), (0., 1.)));
 	}
+	// The circle is the same width along any axis, but the corners of its bounding box stick out along the diagonal
+	let (min, max) = document_metadata.layer_extent_along(circle, diagonal, true).unwrap();
+	assert!((min + 1.).abs() < 1e-3 && (max - 1.).abs() < 1e-3);
+	assert!(close(
+		document_metadata.layer_extent_along(circle, diagonal, false),
+		(-std::f64::consts::SQRT_2, std::f64::consts::SQRT_2)
+	));
+
+	assert_eq!(document_metadata.layer_extent_along(square, DVec2::ZERO, true), None);
+	assert_eq!(document_metadata.layer_extent_along(LayerNodeIdentifier::new_unchecked(3), DVec2::X, false), None);
 }
 
-impl From<NodeId> for LayerNodeIdentifier {
-	fn from(node_id: NodeId) -> Self {
-		Self::new_unchecked(node_id)
+#[test]
+fn relative_offset_between() {
+	let mut document_metadata = DocumentMetadata::default();
+	let [original, duplicate, collapsed] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	let placement = DAffine2::from_scale_angle_translation(DVec2::new(2., 0.5), 0.4, DVec2::new(-7., 12.));
+	let offset = DAffine2::from_translation(DVec2::new(10., 0.)) * DAffine2::from_angle(15_f64.to_radians());
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([
+		(original.to_node(), (footprint, placement)),
+		(duplicate.to_node(), (footprint, offset * placement)),
+		(collapsed.to_node(), (footprint, DAffine2::from_scale(DVec2::new(1., 0.)))),
+	]));
+
+	assert!(document_metadata.relative_offset_between(original, duplicate).unwrap().abs_diff_eq(offset, 1e-9));
+	assert!(document_metadata.relative_offset_between(duplicate, original).unwrap().abs_diff_eq(offset.inverse(), 1e-9));
+	let (translation, rotation, scale) = document_metadata.relative_translation_rotation_scale(original, duplicate).unwrap();
+	assert!(translation.abs_diff_eq(DVec2::new(10., 0.), 1e-9));
+	assert!((rotation - 15_f64.to_radians()).abs() < 1e-9);
+	assert!(scale.abs_diff_eq(DVec2::ONE, 1e-9));
+
+	// Applying the offset to the duplicate gives where a repeated duplicate would go
+	let repeated = document_metadata.relative_offset_between(original, duplicate).unwrap() * document_metadata.transform_to_document(duplicate);
+	assert!(repeated.abs_diff_eq(offset * offset * placement, 1e-9));
+
+	assert_eq!(document_metadata.relative_offset_between(collapsed, original), None);
+	assert!(document_metadata.relative_offset_between(original, collapsed).is_some());
+}
+
+#[test]
+fn prune_auxiliary_state() {
+	use crate::test_utils::{rect_click_targets, NetworkBuilder};
+
+	// A folder containing two layers, above a layer at the top level
+	let (network, ids) = NetworkBuilder::new().folder("Folder", |folder| folder.layer("Inner").layer("Other inner")).layer("Outer").build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	assert!(document_metadata.audit_auxiliary_state().is_empty());
+	let [folder, inner, other_inner, outer] = ["Folder", "Inner", "Other inner", "Outer"].map(|name| LayerNodeIdentifier::new_unchecked(ids[name]));
+	document_metadata.set_collapsed(folder, true);
+	document_metadata.set_layer_tag(inner, Some(LayerTag::Red));
+	document_metadata.set_layer_name(other_inner, "Named".to_string());
+	document_metadata.set_layer_opacity_hint(other_inner, 0.5);
+	document_metadata.insert_click_targets(inner, rect_click_targets(DVec2::ZERO, DVec2::ONE));
+	assert!(document_metadata.geometry_fingerprint(inner).is_some());
+	document_metadata.set_collapsed(outer, true);
+	document_metadata.set_isolation(Some(folder));
+	document_metadata.save_selection("Saved".to_string());
+
+	// Deleting the folder leaves behind what deleting doesn't forget itself
+	folder.delete(&mut document_metadata);
+	let mut leaks = document_metadata.audit_auxiliary_state();
+	leaks.sort_by_key(|leak| (leak.layer, leak.state as u8));
+	let [folder_generation, inner_generation, other_generation] = [folder, inner, other_inner].map(|layer| AuxiliaryLeak {
+		state: AuxiliaryState::LayerGenerations,
+		layer,
+	});
+	let collapsed = AuxiliaryLeak {
+		state: AuxiliaryState::Collapsed,
+		layer: folder,
+	};
+	let fingerprint = AuxiliaryLeak {
+		state: AuxiliaryState::GeometryFingerprints,
+		layer: inner,
+	};
+	let mut expected = vec![folder_generation, collapsed, inner_generation, fingerprint, other_generation];
+	expected.sort_by_key(|leak| (leak.layer, leak.state as u8));
+	assert_eq!(leaks, expected);
+
+	let report = document_metadata.prune_auxiliary_state();
+	assert_eq!(
+		(
+			report.removed(AuxiliaryState::Collapsed),
+			report.removed(AuxiliaryState::GeometryFingerprints),
+			report.removed(AuxiliaryState::LayerGenerations)
+		),
+		(1, 1, 3)
+	);
+	assert_eq!(report.removed(AuxiliaryState::Tags), 0);
+	assert!(document_metadata.audit_auxiliary_state().is_empty());
+	assert!(document_metadata.prune_auxiliary_state().is_empty());
+	// State of the layers still in the tree is kept
+	assert!(document_metadata.is_collapsed(outer));
+
+	// Entries added for layers not in the tree are pruned when the structure is loaded
+	Arc::make_mut(&mut document_metadata.tags).insert(LayerNodeIdentifier::new_unchecked(1000), LayerTag::Blue);
+	assert_eq!(document_metadata.audit_auxiliary_state().len(), 1);
+	let _ = document_metadata.load_structure(&network);
+	assert!(document_metadata.audit_auxiliary_state().is_empty());
+	assert!(document_metadata.layer_tag(LayerNodeIdentifier::new_unchecked(1000)).is_none());
+}
+
+#[test]
+fn selection_pixel_rect() {
+	use crate::test_utils::{rect_click_targets, NetworkBuilder};
+
+	let (network, ids) = NetworkBuilder::new().layer("A").layer("B").build();
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+	let [a, b] = [ids["A"], ids["B"]].map(LayerNodeIdentifier::new_unchecked);
+	let rect = |min: DVec2, max: DVec2| rect_click_targets(min, max);
+	document_metadata.insert_click_targets(a, rect(DVec2::new(0.25, 0.5), DVec2::new(3.5, 2.)));
+	document_metadata.insert_click_targets(b, rect(DVec2::new(5., 1.), DVec2::new(6.75, 4.25)));
+	document_metadata.document_to_viewport = DAffine2::from_translation(DVec2::new(10., 20.));
+
+	assert_eq!(document_metadata.selection_pixel_rect(1., PixelRounding::Enclose), None);
+	let _ = document_metadata.set_selected_nodes(vec![a.to_node(), b.to_node()]);
+	// The selection covers 10.25, 20.5 to 16.75, 24.25 in the viewport
+	assert_eq!(document_metadata.selection_pixel_rect(1., PixelRounding::Enclose), Some(([10, 20], [7, 5])));
+	assert_eq!(document_metadata.selection_pixel_rect(2., PixelRounding::Round), Some(([21, 41], [13, 8])));
+	assert_eq!(document_metadata.selection_pixel_rect(1.5, PixelRounding::Enclose), Some(([15, 30], [11, 7])));
+}
+
+#[test]
+fn new_layer_insertion_target() {
+	let mut document_metadata = DocumentMetadata::default();
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::ZERO)), (LayerNodeIdentifier::ROOT, 0));
+
+	// Two overlapping artboards, the first containing a folder and a layer, above a layer at the top level
+	let root = document_metadata.root();
+	let [first, content, folder, nested, second, stray] = [1, 2, 4, 5, 3, 7].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, first);
+	first.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, nested);
+	first.push_child(&mut document_metadata, content);
+	root.push_child(&mut document_metadata, second);
+	root.push_child(&mut document_metadata, stray);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	for (artboard, origin) in [(first, DVec2::ZERO), (second, DVec2::splat(50.))] {
+		Arc::make_mut(&mut document_metadata.artboards).insert(artboard);
+		let info = ArtboardInfo {
+			origin_document: origin,
+			size: DVec2::splat(100.),
+			background: None,
+			clip_content: false,
+		};
+		Arc::make_mut(&mut document_metadata.artboard_info).insert(artboard, info);
 	}
+
+	// Without a selection the topmost artboard under the cursor is used, otherwise the active artboard
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::splat(75.))), (first, 0));
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::splat(120.))), (second, 0));
+	let active = document_metadata.active_artboard();
+	assert!(document_metadata.is_artboard(active));
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::splat(500.))), (active, 0));
+	assert_eq!(document_metadata.new_layer_insertion_target(None), (active, 0));
+
+	// Selected layers that aren't in the tree are ignored
+	let _ = document_metadata.set_selected_nodes(vec![99]);
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::splat(120.))), (second, 0));
+
+	// The new layer goes directly above the topmost selected layer
+	let _ = document_metadata.set_selected_nodes(vec![7, 2]);
+	assert_eq!(document_metadata.new_layer_insertion_target(Some(DVec2::splat(120.))), (first, 1));
+	let _ = document_metadata.set_selected_nodes(vec![7, 5]);
+	assert_eq!(document_metadata.new_layer_insertion_target(None), (folder, 0));
+	let _ = document_metadata.set_selected_nodes(vec![7]);
+	assert_eq!(document_metadata.new_layer_insertion_target(None), (root, 2));
+
+	// The deepest selected folder wins over any other selected layers, with ties won by the topmost
+	let _ = document_metadata.set_selected_nodes(vec![2, 3, 4, 1]);
+	assert_eq!(document_metadata.new_layer_insertion_target(None), (folder, 0));
+	let _ = document_metadata.set_selected_nodes(vec![3, 7, 1]);
+	assert_eq!(document_metadata.new_layer_insertion_target(None), (first, 0));
 }
 
-impl From<LayerNodeIdentifier> for NodeId {
-	fn from(identifier: LayerNodeIdentifier) -> Self {
-		identifier.to_node()
+#[test]
+fn render_padding() {
+	let mut document_metadata = DocumentMetadata::default();
+	let rectangle = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, rectangle);
+	let click_target = ClickTarget {
+		subpath: bezier_rs::Subpath::new_rect(DVec2::ZERO, DVec2::new(100., 50.)),
+		stroke_width: 10.,
+	};
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([(rectangle, vec![click_target.clone()])]));
+	let geometric = [DVec2::ZERO, DVec2::new(100., 50.)];
+	let padded = BoundsOptions {
+		include_render_padding: true,
+		..Default::default()
+	};
+
+	// The stroke spreads half its width beyond the geometry on every side
+	document_metadata.set_layer_render_padding(rectangle, 5.);
+	assert_eq!(document_metadata.combined_bounds_document(std::iter::once(rectangle), BoundsOptions::default()), Some(geometric));
+	assert_eq!(
+		document_metadata.combined_bounds_document(std::iter::once(rectangle), padded),
+		Some([DVec2::splat(-5.), DVec2::new(105., 55.)])
+	);
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some(geometric));
+	assert_eq!(document_metadata.rendered_bounds_document_space(true), Some([DVec2::splat(-5.), DVec2::new(105., 55.)]));
+
+	// Padding is scaled along with the layer, whether the bounds are tight or not
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(rectangle.to_node(), (Footprint::default(), DAffine2::from_scale(DVec2::new(2., 1.))))]));
+	let expected = Some([DVec2::new(-10., -5.), DVec2::new(210., 55.)]);
+	assert_eq!(document_metadata.combined_bounds_document(std::iter::once(rectangle), padded), expected);
+	assert_eq!(
+		document_metadata.combined_bounds_document(std::iter::once(rectangle), BoundsOptions { tight: false, ..padded }),
+		expected
+	);
+
+	// Padding is laid out around the rotated layer, so a stroke reaches as far beyond the rotated geometry as beyond the unrotated geometry
+	let rotation = DAffine2::from_angle(std::f64::consts::FRAC_PI_4);
+	document_metadata.upstream_transforms = Arc::new(HashMap::from_iter([(rectangle.to_node(), (Footprint::default(), rotation))]));
+	let [rotated_min, rotated_max] = document_metadata.combined_bounds_document(std::iter::once(rectangle), BoundsOptions::default()).unwrap();
+	for tight in [true, false] {
+		let [min, max] = document_metadata.combined_bounds_document(std::iter::once(rectangle), BoundsOptions { tight, ..padded }).unwrap();
+		assert!(min.abs_diff_eq(rotated_min - 5., 1e-6) && max.abs_diff_eq(rotated_max + 5., 1e-6), "{min} {max}");
 	}
+
+	// Each side can be padded separately, such as for a shadow offset down and to the right
+	document_metadata.upstream_transforms = Arc::default();
+	document_metadata.set_layer_render_padding_sides(rectangle, [0., 1., 8., -3.]);
+	assert_eq!(document_metadata.layer_render_padding(rectangle), [0., 1., 8., 0.]);
+	assert_eq!(
+		document_metadata.combined_bounds_document(std::iter::once(rectangle), padded),
+		Some([DVec2::new(0., -1.), DVec2::new(108., 50.)])
+	);
+
+	// Replacing the click targets clears the padding unless it is supplied again
+	document_metadata.insert_click_targets(rectangle, vec![click_target]);
+	assert_eq!(document_metadata.layer_render_padding(rectangle), [0.; 4]);
+	assert_eq!(document_metadata.combined_bounds_document(std::iter::once(rectangle), padded), Some(geometric));
 }
 
-/// Iterator over specified axis.
-#[derive(Clone)]
-pub struct AxisIter<'a> {
-	layer_node: Option<LayerNodeIdentifier>,
-	next_node: fn(LayerNodeIdentifier, &DocumentMetadata) -> Option<LayerNodeIdentifier>,
-	document_metadata: &'a DocumentMetadata,
+#[test]
+fn collapsed_document_to_viewport() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [layer, untransformed] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	document_metadata.root().push_child(&mut document_metadata, untransformed);
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([
+		(layer, rect_click_targets(DVec2::ZERO, DVec2::splat(10.))),
+		(untransformed, rect_click_targets(DVec2::splat(-3.), DVec2::ZERO)),
+	]));
+	let evaluate = |document_metadata: &mut DocumentMetadata| {
+		let footprint = Footprint {
+			transform: document_metadata.document_to_viewport,
+			..Default::default()
+		};
+		document_metadata.update_transforms(HashMap::from_iter([(layer.to_node(), (footprint, DAffine2::from_translation(DVec2::new(5., 0.))))]));
+	};
+	let expected = Some([DVec2::splat(-3.), DVec2::new(15., 10.)]);
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::splat(2.));
+	evaluate(&mut document_metadata);
+	assert_eq!(document_metadata.document_bounds_document_space(true), expected);
+
+	// Collapsing the viewport transform leaves the document space bounds from the last evaluation
+	document_metadata.document_to_viewport = DAffine2::from_scale(DVec2::ZERO);
+	assert_eq!(document_metadata.bounding_box_document(layer), Some([DVec2::new(5., 0.), DVec2::new(15., 10.)]));
+	assert_eq!(document_metadata.document_bounds_document_space(true), expected);
+
+	// An evaluation while collapsed has no document space placement, but nothing is kept from it once the viewport recovers
+	evaluate(&mut document_metadata);
+	assert!(document_metadata.document_bounds_document_space(true).into_iter().flatten().all(|corner| corner.is_finite()));
+	document_metadata.document_to_viewport = DAffine2::from_translation(DVec2::new(7., 7.));
+	evaluate(&mut document_metadata);
+	assert_eq!(document_metadata.document_bounds_document_space(true), expected);
+	assert_eq!(document_metadata.transform_to_document(untransformed), DAffine2::IDENTITY);
 }
 
-impl<'a> Iterator for AxisIter<'a> {
-	type Item = LayerNodeIdentifier;
+#[test]
+fn thumbnail_bounds() {
+	use crate::test_utils::rect_click_targets;
 
-	fn next(&mut self) -> Option<Self::Item> {
-		let layer_node = self.layer_node.take();
-		self.layer_node = layer_node.and_then(|node| (self.next_node)(node, self.document_metadata));
-		layer_node
+	let mut document_metadata = DocumentMetadata::default();
+	let [wide, point] = [1, 2].map(LayerNodeIdentifier::new_unchecked);
+	document_metadata.root().push_child(&mut document_metadata, wide);
+	document_metadata.root().push_child(&mut document_metadata, point);
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([
+		(wide, rect_click_targets(DVec2::new(0., 40.), DVec2::new(200., 60.))),
+		(point, rect_click_targets(DVec2::splat(3.), DVec2::splat(3.))),
+	]));
+
+	// The wide rectangle is framed by a square around its center, grown by a tenth of the side on each edge
+	assert_eq!(document_metadata.thumbnail_bounds_document(wide, 0.), Some([DVec2::new(0., -50.), DVec2::new(200., 150.)]));
+	assert_eq!(document_metadata.thumbnail_bounds_document(wide, 0.1), Some([DVec2::new(-20., -70.), DVec2::new(220., 170.)]));
+	assert_eq!(document_metadata.thumbnail_bounds_document(point, 0.), Some([DVec2::splat(2.5), DVec2::splat(3.5)]));
+	assert_eq!(document_metadata.thumbnail_bounds_document(LayerNodeIdentifier::new_unchecked(9), 0.), None);
+
+	// The framed square of 220 units is rendered into 110 pixels
+	let footprint = document_metadata.thumbnail_footprint(wide, 110).unwrap();
+	assert_eq!(footprint.resolution, glam::UVec2::splat(110));
+	assert!((footprint.transform.matrix2.x_axis.x - 0.5).abs() < 1e-9 && (footprint.transform.matrix2.y_axis.y - 0.5).abs() < 1e-9);
+	assert!(footprint.transform.transform_point2(DVec2::new(-10., -60.)).abs_diff_eq(DVec2::ZERO, 1e-9));
+	assert!(footprint.transform.transform_point2(DVec2::new(210., 160.)).abs_diff_eq(DVec2::splat(110.), 1e-9));
+}
+
+#[test]
+fn selection_gizmo_quad() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [first, second, untransformed] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [first, second, untransformed] {
+		document_metadata.root().push_child(&mut document_metadata, layer);
+	}
+	let square = rect_click_targets(DVec2::ZERO, DVec2::splat(10.));
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([(first, square.clone()), (second, square.clone()), (untransformed, square)]));
+	document_metadata.document_to_viewport = DAffine2::from_translation(DVec2::new(100., 0.));
+	let footprint = Footprint {
+		transform: document_metadata.document_to_viewport,
+		..Default::default()
+	};
+	let first_rotation = std::f64::consts::FRAC_PI_6;
+	let second_rotation = -std::f64::consts::FRAC_PI_4;
+	document_metadata.update_transforms(HashMap::from_iter([
+		(first.to_node(), (footprint, DAffine2::from_angle(first_rotation))),
+		(second.to_node(), (footprint, DAffine2::from_translation(DVec2::new(30., 0.)) * DAffine2::from_angle(second_rotation))),
+	]));
+	let _ = document_metadata.set_selected_nodes(vec![1, 2]);
+
+	let angle = |quad: Quad| {
+		let edge = quad.0[1] - quad.0[0];
+		edge.y.atan2(edge.x)
+	};
+	let encloses_selection = |quad: Quad| {
+		[first, second].into_iter().all(|layer| {
+			let corners = document_metadata.transform_to_viewport(layer) * Quad::from_box([DVec2::ZERO, DVec2::splat(10.)]);
+			let to_frame = DAffine2::from_angle(angle(quad)).inverse();
+			let [min, max] = (to_frame * quad).bounding_box();
+			(to_frame * corners).0.iter().all(|&corner| corner.cmpge(min - 1e-9).all() && corner.cmple(max + 1e-9).all())
+		})
+	};
+
+	let viewport = document_metadata.selection_gizmo_quad(GizmoBasis::Viewport).unwrap();
+	assert!(angle(viewport).abs() < 1e-9);
+	assert_eq!(
+		viewport.bounding_box(),
+		document_metadata.combined_bounds_viewport(document_metadata.selected_layers(), BoundsOptions::default()).unwrap()
+	);
+	for (basis, rotation) in [
+		(GizmoBasis::FirstSelected, first_rotation),
+		(GizmoBasis::LastSelected, second_rotation),
+		(GizmoBasis::ActiveLayer(second), second_rotation),
+	] {
+		let quad = document_metadata.selection_gizmo_quad(basis).unwrap();
+		assert!((angle(quad) - rotation).abs() < 1e-9, "{basis:?}");
+		assert!(encloses_selection(quad), "{basis:?}");
 	}
+
+	// The order of selection decides which layer is first
+	let _ = document_metadata.set_selected_nodes(vec![2, 1]);
+	assert!((angle(document_metadata.selection_gizmo_quad(GizmoBasis::FirstSelected).unwrap()) - second_rotation).abs() < 1e-9);
+
+	// A basis layer without a cached transform leaves the gizmo axis aligned
+	let quad = document_metadata.selection_gizmo_quad(GizmoBasis::ActiveLayer(untransformed)).unwrap();
+	assert!(angle(quad).abs() < 1e-9);
+	let _ = document_metadata.set_selected_nodes(vec![]);
+	assert!(document_metadata.selection_gizmo_quad(GizmoBasis::Viewport).is_none());
 }
 
-#[derive(Clone)]
-pub struct DecendantsIter<'a> {
-	front: Option<LayerNodeIdentifier>,
-	back: Option<LayerNodeIdentifier>,
-	document_metadata: &'a DocumentMetadata,
+#[test]
+fn load_structure_from_built_network() {
+	use crate::test_utils::NetworkBuilder;
+
+	let (network, ids) = NetworkBuilder::new()
+		.artboard("Artboard", |artboard| artboard.layer("Rectangle").folder("Group", |group| group.layer("First").layer("Second")))
+		.folder("Empty", |empty| empty)
+		.layer("Background")
+		.hidden()
+		.build();
+	let layer = |name: &str| LayerNodeIdentifier::new_unchecked(ids[name]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+
+	// Layers are found from the top of the layer tree to the bottom, with each folder before its contents
+	let order = ["Artboard", "Rectangle", "Group", "First", "Second", "Empty", "Background"];
+	assert_eq!(document_metadata.all_layers().collect::<Vec<_>>(), order.map(layer));
+	assert_eq!(
+		document_metadata.root().children(&document_metadata).collect::<Vec<_>>(),
+		["Artboard", "Empty", "Background"].map(layer)
+	);
+	assert_eq!(layer("Group").children(&document_metadata).collect::<Vec<_>>(), ["First", "Second"].map(layer));
+	assert_eq!(layer("Second").parent(&document_metadata), Some(layer("Group")));
+	assert_eq!(layer("First").depth(&document_metadata), 3);
+
+	// Sibling chains are followed through the second input of each layer
+	assert_eq!(layer("Rectangle").next_sibling(&document_metadata), Some(layer("Group")));
+	assert_eq!(layer("Group").previous_sibling(&document_metadata), Some(layer("Rectangle")));
+	assert_eq!(layer("Background").next_sibling(&document_metadata), None);
+	assert_eq!(layer("Artboard").previous_sibling(&document_metadata), None);
+
+	// Names and hidden layers are read from the graph
+	assert_eq!(document_metadata.layer_name(layer("Group")), Some("Group"));
+	assert!(document_metadata.is_hidden(layer("Background")));
+	assert!(!document_metadata.is_hidden(layer("Group")));
 }
 
-impl<'a> Iterator for DecendantsIter<'a> {
-	type Item = LayerNodeIdentifier;
+#[test]
+fn folder_and_artboard_detection() {
+	use crate::test_utils::NetworkBuilder;
 
-	fn next(&mut self) -> Option<Self::Item> {
-		if self.front == self.back {
-			self.back = None;
-			self.front.take()
-		} else {
-			let layer_node = self.front.take();
-			if let Some(layer_node) = layer_node {
-				self.front = layer_node
-					.first_child(self.document_metadata)
-					.or_else(|| layer_node.ancestors(self.document_metadata).find_map(|ancestor| ancestor.next_sibling(self.document_metadata)));
-			}
-			layer_node
-		}
+	let (network, ids) = NetworkBuilder::new()
+		.artboard("Artboard", |artboard| artboard.layer("Content"))
+		.artboard("Empty artboard", |artboard| artboard)
+		.folder("Folder", |folder| folder.folder("Nested", |nested| nested.layer("Leaf")))
+		.folder("Empty folder", |folder| folder)
+		.layer("Shape")
+		.build();
+	let layer = |name: &str| LayerNodeIdentifier::new_unchecked(ids[name]);
+	let mut document_metadata = DocumentMetadata::default();
+	let _ = document_metadata.load_structure(&network);
+
+	for name in ["Artboard", "Empty artboard"] {
+		assert!(is_artboard(layer(name), &network), "{name}");
+		assert!(document_metadata.is_artboard(layer(name)), "{name}");
+	}
+	for name in ["Artboard", "Empty artboard", "Folder", "Nested", "Empty folder"] {
+		assert!(is_folder(layer(name), &network), "{name}");
+		assert!(document_metadata.is_folder(layer(name)), "{name}");
 	}
+	for name in ["Content", "Leaf", "Shape"] {
+		assert!(!is_artboard(layer(name), &network), "{name}");
+		assert!(!is_folder(layer(name), &network), "{name}");
+		assert_eq!(document_metadata.classification(layer(name)), Some(LayerClassification::Vector), "{name}");
+	}
+	assert!(!is_artboard(layer("Folder"), &network));
+	assert_eq!(document_metadata.classification(layer("Folder")), Some(LayerClassification::Folder));
+	assert_eq!(document_metadata.classification(layer("Artboard")), Some(LayerClassification::Artboard));
+	assert_eq!(
+		document_metadata.artboard_info(layer("Artboard")).map(|info| (info.origin_document, info.size)),
+		Some((DVec2::ZERO, DVec2::new(1920., 1080.)))
+	);
 }
-impl<'a> DoubleEndedIterator for DecendantsIter<'a> {
-	fn next_back(&mut self) -> Option<Self::Item> {
-		if self.front == self.back {
-			self.front = None;
-			self.back.take()
-		} else {
-			let layer_node = self.back.take();
-			if let Some(layer_node) = layer_node {
-				self.back = layer_node
-					.previous_sibling(self.document_metadata)
-					.and_then(|sibling| sibling.last_children(self.document_metadata).last())
-					.or_else(|| layer_node.parent(self.document_metadata));
-			}
 
-			layer_node
-		}
+#[test]
+fn lasso_select() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let [square, far, triangle, folder, nested] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	for layer in [square, far, triangle, folder] {
+		document_metadata.root().push_child(&mut document_metadata, layer);
 	}
-}
+	folder.push_child(&mut document_metadata, nested);
+	Arc::make_mut(&mut document_metadata.folders).insert(folder);
+	// A triangle whose bounds reach into the gap of the lasso, but whose curves stay out of it
+	let triangle_target = vec![ClickTarget {
+		subpath: bezier_rs::Subpath::from_anchors([DVec2::new(0., 40.), DVec2::new(30., 40.), DVec2::new(0., 55.)], true),
+		stroke_width: 0.,
+	}];
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([
+		(square, rect_click_targets(DVec2::splat(10.), DVec2::splat(20.))),
+		(far, rect_click_targets(DVec2::splat(500.), DVec2::splat(510.))),
+		(triangle, triangle_target),
+		(nested, rect_click_targets(DVec2::new(200., 0.), DVec2::new(210., 10.))),
+	]));
 
-#[derive(Debug, Clone, Copy, Default)]
-pub struct NodeRelations {
-	parent: Option<LayerNodeIdentifier>,
-	previous_sibling: Option<LayerNodeIdentifier>,
-	next_sibling: Option<LayerNodeIdentifier>,
-	first_child: Option<LayerNodeIdentifier>,
-	last_child: Option<LayerNodeIdentifier>,
+	// A C shape open to the right, whose gap runs from x = 25 to the right at heights 40 to 50
+	let c_shape = [
+		DVec2::new(-10., 0.),
+		DVec2::new(100., 0.),
+		DVec2::new(100., 40.),
+		DVec2::new(25., 40.),
+		DVec2::new(25., 50.),
+		DVec2::new(100., 50.),
+		DVec2::new(100., 100.),
+		DVec2::new(-10., 100.),
+	];
+	assert_eq!(document_metadata.lasso_select(&c_shape, MarqueeMode::Touch), [square, triangle]);
+	assert_eq!(document_metadata.lasso_select(&c_shape, MarqueeMode::Contain), [square]);
+	assert_eq!(document_metadata.lasso_select(&c_shape, MarqueeMode::CenterInside), [square, triangle]);
+
+	// The triangle's far corner pokes into the gap, so it is only touched once its curves are tested
+	let gap_only = [DVec2::new(10., 44.), DVec2::new(40., 44.), DVec2::new(40., 46.), DVec2::new(10., 46.)];
+	assert_eq!(document_metadata.lasso_select(&gap_only, MarqueeMode::Touch), [triangle]);
+	let beside_edge = [DVec2::new(25., 50.), DVec2::new(40., 50.), DVec2::new(40., 54.), DVec2::new(25., 54.)];
+	assert!(document_metadata.lasso_select(&beside_edge, MarqueeMode::Touch).is_empty());
+
+	// A bow tie crossing itself, where the middle of each loop is inside and the folder's contents are tested as one rectangle
+	let bow_tie = [DVec2::new(190., -10.), DVec2::new(230., 20.), DVec2::new(230., -10.), DVec2::new(190., 20.)];
+	assert_eq!(document_metadata.lasso_select(&bow_tie, MarqueeMode::Touch), [folder, nested]);
+	assert!(document_metadata.lasso_select(&bow_tie, MarqueeMode::Contain).is_empty());
+	assert!(document_metadata.lasso_select(&bow_tie[..2], MarqueeMode::Touch).is_empty());
 }
 
-fn is_layer_node(node: NodeId, network: &NodeNetwork) -> bool {
-	node == LayerNodeIdentifier::ROOT.to_node() || network.nodes.get(&node).is_some_and(|node| node.is_layer())
+#[test]
+fn saved_views() {
+	use crate::test_utils::rect_click_targets;
+
+	let mut document_metadata = DocumentMetadata::default();
+	let layer = LayerNodeIdentifier::new_unchecked(1);
+	document_metadata.root().push_child(&mut document_metadata, layer);
+	let _ = document_metadata.update_click_targets(HashMap::from_iter([(layer, rect_click_targets(DVec2::ZERO, DVec2::splat(10.)))]));
+	let start = DAffine2::from_translation(DVec2::new(100., 50.));
+	document_metadata.document_to_viewport = start;
+	document_metadata.save_view("View 1".to_string());
+	assert_eq!(document_metadata.document_bounds_viewport_space(), Some([DVec2::new(100., 50.), DVec2::new(110., 60.)]));
+
+	// Panning needn't be remembered, but zooming to fit is a jump the editor remembers before making
+	let panned = DAffine2::from_translation(DVec2::new(-30., 0.));
+	document_metadata.document_to_viewport = panned;
+	let zoomed = DAffine2::from_scale(DVec2::splat(4.));
+	document_metadata.remember_view();
+	document_metadata.document_to_viewport = zoomed;
+	document_metadata.save_view("View 2".to_string());
+	assert_eq!(document_metadata.document_bounds_viewport_space(), Some([DVec2::ZERO, DVec2::splat(40.)]));
+
+	// Restoring jumps to the saved transform, and the viewport bounds are found again with it
+	assert!(document_metadata.restore_view("View 1"));
+	assert_eq!(document_metadata.document_to_viewport, start);
+	assert_eq!(document_metadata.document_bounds_viewport_space(), Some([DVec2::new(100., 50.), DVec2::new(110., 60.)]));
+	assert_eq!(document_metadata.document_bounds_document_space(true), Some([DVec2::ZERO, DVec2::splat(10.)]));
+	assert!(!document_metadata.restore_view("View 3"));
+	assert_eq!(document_metadata.document_to_viewport, start);
+	assert_eq!(document_metadata.views().collect::<Vec<_>>(), ["View 1", "View 2"]);
+
+	// Each jump can be stepped back through, back to the view before the zoom but not the pan
+	assert!(document_metadata.restore_previous_view());
+	assert_eq!(document_metadata.document_to_viewport, zoomed);
+	assert_eq!(document_metadata.document_bounds_viewport_space(), Some([DVec2::ZERO, DVec2::splat(40.)]));
+	assert!(document_metadata.restore_previous_view());
+	assert_eq!(document_metadata.document_to_viewport, panned);
+	assert!(!document_metadata.restore_previous_view());
+	assert_eq!(document_metadata.document_to_viewport, panned);
+
+	// Undo replaces the metadata with an older copy, which keeps the views of the one it replaces
+	assert!(document_metadata.restore_view("View 2"));
+	let mut undone = DocumentMetadata::default();
+	undone.take_views_from(&mut document_metadata);
+	assert_eq!(undone.views().collect::<Vec<_>>(), ["View 1", "View 2"]);
+	assert!(undone.restore_previous_view());
+	assert_eq!(undone.document_to_viewport, panned);
+
+	// The saved views are saved with the document, and restored without any views to return to
+	assert!(undone.restore_view("View 1"));
+	let mut reopened = DocumentMetadata::default();
+	reopened.remember_view();
+	reopened.restore_persistent_state(undone.persistent_state());
+	assert_eq!(reopened.views().collect::<Vec<_>>(), ["View 1", "View 2"]);
+	assert!(reopened.restore_view("View 2"));
+	assert_eq!(reopened.document_to_viewport, zoomed);
+	assert!(reopened.restore_previous_view());
+	assert_eq!(reopened.document_to_viewport, DAffine2::IDENTITY);
+	assert!(!reopened.restore_previous_view());
 }
 
 #[test]
-fn test_tree() {
+fn layer_relative_transform() {
 	let mut document_metadata = DocumentMetadata::default();
 	let root = document_metadata.root();
-	let document_metadata = &mut document_metadata;
-	root.push_child(document_metadata, LayerNodeIdentifier::new_unchecked(3));
-	assert_eq!(root.children(document_metadata).collect::<Vec<_>>(), vec![LayerNodeIdentifier::new_unchecked(3)]);
-	root.push_child(document_metadata, LayerNodeIdentifier::new_unchecked(6));
-	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![3, 6]);
-	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![3, 6]);
-	LayerNodeIdentifier::new_unchecked(3).add_after(document_metadata, LayerNodeIdentifier::new_unchecked(4));
-	LayerNodeIdentifier::new_unchecked(3).add_before(document_metadata, LayerNodeIdentifier::new_unchecked(2));
-	LayerNodeIdentifier::new_unchecked(6).add_before(document_metadata, LayerNodeIdentifier::new_unchecked(5));
-	LayerNodeIdentifier::new_unchecked(6).add_after(document_metadata, LayerNodeIdentifier::new_unchecked(9));
-	LayerNodeIdentifier::new_unchecked(6).push_child(document_metadata, LayerNodeIdentifier::new_unchecked(8));
-	LayerNodeIdentifier::new_unchecked(6).push_front_child(document_metadata, LayerNodeIdentifier::new_unchecked(7));
-	root.push_front_child(document_metadata, LayerNodeIdentifier::new_unchecked(1));
-	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 9]);
-	assert_eq!(
-		root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(),
-		vec![1, 2, 3, 4, 5, 6, 7, 8, 9]
-	);
+	let [folder, child, top_level, uncached_folder, uncached_child] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	root.push_child(&mut document_metadata, folder);
+	folder.push_child(&mut document_metadata, child);
+	root.push_child(&mut document_metadata, top_level);
+	root.push_child(&mut document_metadata, uncached_folder);
+	uncached_folder.push_child(&mut document_metadata, uncached_child);
 	assert_eq!(
-		root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).rev().collect::<Vec<_>>(),
-		vec![9, 8, 7, 6, 5, 4, 3, 2, 1]
+		document_metadata.layer_relative_transform(child),
+		LayerRelativeTransform {
+			transform: DAffine2::IDENTITY,
+			relative_to_parent: true
+		}
 	);
-	assert!(root.children(document_metadata).all(|child| child.parent(document_metadata) == Some(root)));
-	LayerNodeIdentifier::new_unchecked(6).delete(document_metadata);
-	LayerNodeIdentifier::new_unchecked(1).delete(document_metadata);
-	LayerNodeIdentifier::new_unchecked(9).push_child(document_metadata, LayerNodeIdentifier::new_unchecked(10));
-	assert_eq!(root.children(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![2, 3, 4, 5, 9]);
-	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).collect::<Vec<_>>(), vec![2, 3, 4, 5, 9, 10]);
-	assert_eq!(root.decendants(document_metadata).map(LayerNodeIdentifier::to_node).rev().collect::<Vec<_>>(), vec![10, 9, 5, 4, 3, 2]);
+
+	document_metadata.document_to_viewport = DAffine2::from_scale_angle_translation(DVec2::splat(1.5), 0.25, DVec2::new(100., 50.));
+	let d2v = document_metadata.document_to_viewport;
+	let scaled = DAffine2::from_scale(DVec2::splat(2.));
+	let moved = DAffine2::from_translation(DVec2::new(10., 0.));
+	let cached = |footprint: DAffine2, transform: DAffine2| {
+		(
+			Footprint {
+				transform: footprint,
+				..Default::default()
+			},
+			transform,
+		)
+	};
+	document_metadata.update_transforms(HashMap::from_iter([
+		(folder.to_node(), cached(d2v, scaled)),
+		(child.to_node(), cached(d2v * scaled, moved)),
+		(top_level.to_node(), cached(d2v, moved)),
+		(uncached_child.to_node(), cached(d2v * scaled, moved)),
+	]));
+
+	// The child is moved by 10 units in the folder, which is 20 units in the document
+	let relative = document_metadata.layer_relative_transform(child);
+	assert!(relative.relative_to_parent);
+	assert!(relative.transform.abs_diff_eq(moved, 1e-9), "{:?}", relative.transform);
+	assert!(document_metadata.transform_to_document(child).translation.abs_diff_eq(DVec2::new(20., 0.), 1e-9));
+	let relative = document_metadata.layer_relative_transform(folder);
+	assert!(relative.relative_to_parent && relative.transform.abs_diff_eq(scaled, 1e-9));
+	let relative = document_metadata.layer_relative_transform(top_level);
+	assert!(relative.relative_to_parent && relative.transform.abs_diff_eq(moved, 1e-9));
+
+	// Without the parent's cached transform, its scale can't be removed
+	let relative = document_metadata.layer_relative_transform(uncached_child);
+	assert!(!relative.relative_to_parent);
+	assert!(relative.transform.abs_diff_eq(scaled * moved, 1e-9));
+	assert_eq!(relative.transform, document_metadata.transform_to_document(uncached_child));
 }
@@ -0,0 +1,562 @@
+//! A journal of the modifications of the layer tree and selection, which can be sent to another [`DocumentMetadata`] and replayed there to keep the two in sync.
+
+use super::{DocumentMetadata, LayerNodeIdentifier, NodeRelations};
+use graph_craft::document::NodeId;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// A modification of the layer tree or selection recorded by a [`DocumentMetadata`] with journaling enabled, see [`DocumentMetadata::set_journaling`].
+///
+/// Positions are given by the sibling directly below rather than an index, so layers which are moved one after another to the same place end up in the same order when replayed.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MetadataOp {
+	/// Every layer was removed, before the structure was loaded again.
+	ClearStructure,
+	/// A layer without children was added to the parent, directly above `below`, or at the bottom if it is `None`, as a folder or artboard if it is one.
+	AddLayer {
+		layer: LayerNodeIdentifier,
+		parent: LayerNodeIdentifier,
+		below: Option<LayerNodeIdentifier>,
+		is_folder: bool,
+		is_artboard: bool,
+	},
+	/// A layer was moved along with its descendants to the parent, directly above `below`, or at the bottom if it is `None`.
+	MoveLayer {
+		layer: LayerNodeIdentifier,
+		parent: LayerNodeIdentifier,
+		below: Option<LayerNodeIdentifier>,
+	},
+	/// A layer was deleted along with its descendants.
+	DeleteLayer { layer: LayerNodeIdentifier },
+	/// The children of the parent were restacked into this order from top to bottom.
+	ReorderChildren { parent: LayerNodeIdentifier, order: Vec<LayerNodeIdentifier> },
+	/// The selected nodes, in the order they were selected.
+	SetSelection { nodes: Vec<NodeId> },
+	/// The whole layer tree, replacing the one before, with each layer in depth first order, and the selection.
+	///
+	/// This is recorded when the modifications since the last one can't be replayed, such as when a [`super::StructureTransaction`] is rolled back or the journal is full.
+	Resync { layers: Vec<ResyncLayer>, selection: Vec<NodeId> },
+}
+
+/// A layer of the layer tree in a [`MetadataOp::Resync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ResyncLayer {
+	pub layer: LayerNodeIdentifier,
+	pub parent: LayerNodeIdentifier,
+	pub is_folder: bool,
+	pub is_artboard: bool,
+}
+
+/// Why [`DocumentMetadata::apply_ops`] failed, in which case the layer tree and selection were left as they were before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyError {
+	/// Another [`super::StructureTransaction`] is still open.
+	TransactionInProgress,
+	/// The operation at the index refers to a layer, such as the parent or the sibling below, which isn't in the layer tree.
+	UnknownLayer { index: usize, layer: LayerNodeIdentifier },
+	/// The operation at the index adds a layer which is already in the layer tree.
+	DuplicateLayer { index: usize, layer: LayerNodeIdentifier },
+	/// The operation at the index moves a layer where it can't go, such as into itself.
+	InvalidMove { index: usize, layer: LayerNodeIdentifier },
+	/// The operation at the index restacks the children of the parent into an order which isn't a permutation of them.
+	InvalidOrder { index: usize, parent: LayerNodeIdentifier },
+}
+
+/// The operations recorded since the journal was last drained.
+#[derive(Debug, Clone)]
+pub(super) struct Journal {
+	ops: VecDeque<MetadataOp>,
+	capacity: usize,
+}
+
+impl Journal {
+	const CAPACITY: usize = 4096;
+
+	fn with_capacity(capacity: usize) -> Self {
+		Self {
+			ops: VecDeque::new(),
+			capacity: capacity.max(1),
+		}
+	}
+}
+
+impl DocumentMetadata {
+	/// Start or stop recording the modifications of the layer tree and selection, to be taken with [`Self::drain_journal`]. Stopping discards any which haven't been drained.
+	///
+	/// If more than a few thousand modifications are recorded without being drained, they are replaced by a single [`MetadataOp::Resync`].
+	pub fn set_journaling(&mut self, enabled: bool) {
+		match (enabled, self.journal.is_some()) {
+			(true, false) => self.journal = Some(Journal::with_capacity(Journal::CAPACITY)),
+			(false, true) => self.journal = None,
+			_ => {}
+		}
+	}
+
+	pub fn is_journaling(&self) -> bool {
+		self.journal.is_some()
+	}
+
+	/// Take the operations recorded since the journal was last drained, oldest first.
+	pub fn drain_journal(&mut self) -> Vec<MetadataOp> {
+		self.journal.as_mut().map(|journal| journal.ops.drain(..).collect()).unwrap_or_default()
+	}
+
+	/// Replay the operations drained from another [`DocumentMetadata`], in order. The operations aren't recorded in this one's journal, so peers don't echo them back.
+	///
+	/// If any of the operations can't be applied, none of them are.
+	pub fn apply_ops(&mut self, ops: &[MetadataOp]) -> Result<(), ApplyError> {
+		let transaction = self.begin_structure_transaction().map_err(|_| ApplyError::TransactionInProgress)?;
+		let journal = self.journal.take();
+		let result = ops.iter().enumerate().try_for_each(|(index, op)| self.apply_op(index, op));
+		match result {
+			Ok(()) => transaction.commit(self),
+			Err(_) => transaction.rollback(self),
+		}
+		self.journal = journal;
+		result
+	}
+
+	fn apply_op(&mut self, index: usize, op: &MetadataOp) -> Result<(), ApplyError> {
+		let unknown = |layer| ApplyError::UnknownLayer { index, layer };
+		match op {
+			MetadataOp::ClearStructure => self.clear_layer_tree(),
+			&MetadataOp::AddLayer {
+				layer,
+				parent,
+				below,
+				is_folder,
+				is_artboard,
+			} => {
+				if layer == LayerNodeIdentifier::ROOT || self.layer_exists(layer) {
+					return Err(ApplyError::DuplicateLayer { index, layer });
+				}
+				self.validate_position(parent, below).map_err(unknown)?;
+				match below {
+					Some(below) => below.add_before(self, layer),
+					None => parent.push_child(self, layer),
+				}
+				self.set_layer_kind(layer, is_folder, is_artboard);
+			}
+			&MetadataOp::MoveLayer { layer, parent, below } => {
+				if layer == LayerNodeIdentifier::ROOT || !self.layer_exists(layer) {
+					return Err(unknown(layer));
+				}
+				self.validate_position(parent, below).map_err(unknown)?;
+				if parent.starts_with(layer, self) || below == Some(layer) {
+					return Err(ApplyError::InvalidMove { index, layer });
+				}
+				layer.detach(self);
+				match below {
+					Some(below) => below.link_before(self, layer),
+					None => parent.link_last_child(self, layer),
+				}
+				layer.update_descendant_relations(self);
+			}
+			&MetadataOp::DeleteLayer { layer } => {
+				if layer == LayerNodeIdentifier::ROOT || !self.layer_exists(layer) {
+					return Err(unknown(layer));
+				}
+				layer.delete(self);
+			}
+			&MetadataOp::ReorderChildren { parent, ref order } => {
+				if !self.layer_exists(parent) {
+					return Err(unknown(parent));
+				}
+				self.reorder_children(parent, order).map_err(|_| ApplyError::InvalidOrder { index, parent })?;
+			}
+			MetadataOp::SetSelection { nodes } => {
+				let _ = self.set_selected_nodes(nodes.clone());
+			}
+			MetadataOp::Resync { layers, selection } => {
+				self.clear_layer_tree();
+				for &ResyncLayer {
+					layer,
+					parent,
+					is_folder,
+					is_artboard,
+				} in layers
+				{
+					if layer == LayerNodeIdentifier::ROOT || self.layer_exists(layer) {
+						return Err(ApplyError::DuplicateLayer { index, layer });
+					}
+					if !self.layer_exists(parent) {
+						return Err(unknown(parent));
+					}
+					parent.push_child(self, layer);
+					self.set_layer_kind(layer, is_folder, is_artboard);
+				}
+				let _ = self.set_selected_nodes(selection.clone());
+			}
+		}
+		Ok(())
+	}
+
+	/// Check that the parent is in the layer tree, and that the sibling below is one of its children, returning the layer which isn't.
+	fn validate_position(&self, parent: LayerNodeIdentifier, below: Option<LayerNodeIdentifier>) -> Result<(), LayerNodeIdentifier> {
+		if !self.layer_exists(parent) {
+			return Err(parent);
+		}
+		match below {
+			Some(below) if below.parent(self) != Some(parent) => Err(below),
+			_ => Ok(()),
+		}
+	}
+
+	fn clear_layer_tree(&mut self) {
+		self.structure = Arc::new(HashMap::from_iter([(LayerNodeIdentifier::ROOT, NodeRelations::default())]));
+		self.structure_generation += 1;
+		self.folders = Arc::default();
+		self.artboards = Arc::default();
+	}
+
+	fn set_layer_kind(&mut self, layer: LayerNodeIdentifier, is_folder: bool, is_artboard: bool) {
+		if is_folder {
+			Arc::make_mut(&mut self.folders).insert(layer);
+		}
+		if is_artboard {
+			Arc::make_mut(&mut self.artboards).insert(layer);
+		}
+	}
+
+	/// Record the operation if journaling is enabled, replacing everything recorded so far with a [`MetadataOp::Resync`] if the journal is full.
+	fn record(&mut self, op: impl FnOnce(&Self) -> MetadataOp) {
+		let Some(full) = self.journal.as_ref().map(|journal| journal.ops.len() >= journal.capacity) else {
+			return;
+		};
+		let op = if full { self.resync_op() } else { op(self) };
+		if let Some(journal) = &mut self.journal {
+			if full {
+				journal.ops.clear();
+			}
+			journal.ops.push_back(op);
+		}
+	}
+
+	fn resync_op(&self) -> MetadataOp {
+		MetadataOp::Resync {
+			layers: self
+				.all_layers()
+				.map(|layer| ResyncLayer {
+					layer,
+					parent: layer.parent(self).unwrap_or(LayerNodeIdentifier::ROOT),
+					is_folder: self.is_folder(layer),
+					is_artboard: self.is_artboard(layer),
+				})
+				.collect(),
+			selection: self.selected_nodes.clone(),
+		}
+	}
+
+	pub(super) fn record_added(&mut self, layer: LayerNodeIdentifier) {
+		self.record(|document_metadata| MetadataOp::AddLayer {
+			layer,
+			parent: layer.parent(document_metadata).unwrap_or(LayerNodeIdentifier::ROOT),
+			below: layer.next_sibling(document_metadata),
+			is_folder: document_metadata.is_folder(layer),
+			is_artboard: document_metadata.is_artboard(layer),
+		});
+	}
+
+	pub(super) fn record_moved(&mut self, layer: LayerNodeIdentifier) {
+		self.record(|document_metadata| MetadataOp::MoveLayer {
+			layer,
+			parent: layer.parent(document_metadata).unwrap_or(LayerNodeIdentifier::ROOT),
+			below: layer.next_sibling(document_metadata),
+		});
+	}
+
+	pub(super) fn record_deleted(&mut self, layer: LayerNodeIdentifier) {
+		self.record(|_| MetadataOp::DeleteLayer { layer });
+	}
+
+	pub(super) fn record_reordered(&mut self, parent: LayerNodeIdentifier) {
+		self.record(|document_metadata| MetadataOp::ReorderChildren {
+			parent,
+			order: parent.children(document_metadata).collect(),
+		});
+	}
+
+	pub(super) fn record_selection(&mut self) {
+		self.record(|document_metadata| MetadataOp::SetSelection {
+			nodes: document_metadata.selected_nodes.clone(),
+		});
+	}
+
+	pub(super) fn record_cleared(&mut self) {
+		self.record(|_| MetadataOp::ClearStructure);
+	}
+
+	pub(super) fn record_resync(&mut self) {
+		self.record(Self::resync_op);
+	}
+}
+
+#[cfg(test)]
+use super::DropPosition;
+
+/// A small deterministic pseudo random number generator, so failures can be reproduced from the seed.
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n.max(1) as u64) as usize
+	}
+
+	fn pick<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+		(!items.is_empty()).then(|| items[self.below(items.len())])
+	}
+}
+
+#[cfg(test)]
+fn assert_converged(source: &DocumentMetadata, replica: &DocumentMetadata) {
+	let tree = |document_metadata: &DocumentMetadata| {
+		document_metadata
+			.all_layers()
+			.map(|layer| {
+				let kind = (document_metadata.is_folder(layer), document_metadata.is_artboard(layer));
+				(layer, layer.parent(document_metadata), layer.depth(document_metadata), layer.child_of_root(document_metadata), kind)
+			})
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(tree(replica), tree(source));
+	assert_eq!(replica.len(), source.len());
+	assert_eq!(replica.selected_nodes, source.selected_nodes);
+}
+
+/// Modify the layer tree and selection in one of the ways the editor does, chosen at random.
+#[cfg(test)]
+fn random_modification(document_metadata: &mut DocumentMetadata, rng: &mut XorShift, next_id: &mut NodeId) {
+	let layers = document_metadata.all_layers().collect::<Vec<_>>();
+	let parents = std::iter::once(LayerNodeIdentifier::ROOT).chain(layers.iter().copied()).collect::<Vec<_>>();
+	let mut new_layer = || {
+		*next_id += 1;
+		LayerNodeIdentifier::new_unchecked(*next_id)
+	};
+	match rng.below(10) {
+		0 | 1 => {
+			let parent = rng.pick(&parents).unwrap();
+			match (rng.below(4), rng.pick(&parent.children(document_metadata).collect::<Vec<_>>())) {
+				(0, _) => parent.push_front_child(document_metadata, new_layer()),
+				(1, Some(sibling)) => sibling.add_before(document_metadata, new_layer()),
+				(2, Some(sibling)) => sibling.add_after(document_metadata, new_layer()),
+				_ => parent.push_child(document_metadata, new_layer()),
+			}
+		}
+		2 => {
+			if let Some(layer) = rng.pick(&layers) {
+				layer.delete(document_metadata);
+			}
+		}
+		3 => {
+			let (Some(layer), Some(parent)) = (rng.pick(&layers), rng.pick(&parents)) else { return };
+			if !parent.starts_with(layer, document_metadata) {
+				layer.reparent(document_metadata, parent);
+			}
+		}
+		4 => {
+			let Some(hovered) = rng.pick(&layers) else { return };
+			let position = [DropPosition::Above, DropPosition::Below, DropPosition::Inside][rng.below(3)];
+			let dragged = (0..1 + rng.below(3)).filter_map(|_| rng.pick(&layers)).collect::<Vec<_>>();
+			let _ = document_metadata.drop_layers(hovered, position, &dragged);
+		}
+		5 => {
+			// Includes the root and repeated layers sometimes, so some groupings fail and are rolled back
+			let grouped = (0..1 + rng.below(3)).filter_map(|_| rng.pick(&parents)).collect::<Vec<_>>();
+			let _ = document_metadata.group_layers(&grouped, new_layer());
+		}
+		6 => {
+			if let Some(folder) = rng.pick(&layers.iter().copied().filter(|&layer| document_metadata.is_folder(layer)).collect::<Vec<_>>()) {
+				let _ = document_metadata.ungroup(folder);
+			}
+		}
+		7 => {
+			let parent = rng.pick(&parents).unwrap();
+			let mut order = parent.children(document_metadata).collect::<Vec<_>>();
+			for index in (1..order.len()).rev() {
+				order.swap(index, rng.below(index + 1));
+			}
+			document_metadata.reorder_children(parent, &order).unwrap();
+		}
+		8 => {
+			let selected = layers.iter().filter(|_| rng.below(3) == 0).map(|layer| layer.to_node()).collect();
+			let _ = document_metadata.set_selected_nodes(selected);
+		}
+		_ => {
+			if let Some(layer) = rng.pick(&layers) {
+				let _ = document_metadata.add_selected_nodes([layer.to_node()]);
+			}
+		}
+	}
+}
+
+#[test]
+fn journal_round_trip() {
+	for seed in 1..=20_u64 {
+		let mut rng = XorShift(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+		let mut source = DocumentMetadata::default();
+		let mut replica = DocumentMetadata::default();
+		source.set_journaling(true);
+		replica.set_journaling(true);
+		let mut next_id = 0;
+		for step in 0..400 {
+			random_modification(&mut source, &mut rng, &mut next_id);
+			if step % 25 == 24 {
+				let ops = source.drain_journal();
+				assert_eq!(replica.apply_ops(&ops), Ok(()), "seed {seed}, step {step}");
+				assert_converged(&source, &replica);
+				// The replayed operations aren't journaled again by the replica
+				assert!(replica.drain_journal().is_empty());
+			}
+		}
+		let ops = source.drain_journal();
+		assert_eq!(replica.apply_ops(&ops), Ok(()), "seed {seed}");
+		assert_converged(&source, &replica);
+	}
+}
+
+#[test]
+fn journal_round_trip_through_reload() {
+	use crate::test_utils::NetworkBuilder;
+
+	let (network, ids) = NetworkBuilder::new()
+		.artboard("Artboard", |artboard| artboard.layer("Rectangle").folder("Group", |group| group.layer("Child")))
+		.layer("Ellipse")
+		.build();
+	let layer = |name: &str| LayerNodeIdentifier::new_unchecked(ids[name]);
+	let mut source = DocumentMetadata::default();
+	source.set_journaling(true);
+	LayerNodeIdentifier::ROOT.push_child(&mut source, LayerNodeIdentifier::new_unchecked(1000));
+	let _ = source.set_selected_nodes(vec![ids["Child"], ids["Ellipse"], 1000]);
+	let _ = source.load_structure(&network);
+	layer("Ellipse").reparent(&mut source, layer("Group"));
+
+	let ops = source.drain_journal();
+	assert_eq!(ops.iter().filter(|op| **op == MetadataOp::ClearStructure).count(), 1);
+	let mut replica = DocumentMetadata::default();
+	replica.apply_ops(&ops).unwrap();
+	assert_converged(&source, &replica);
+	assert_eq!(replica.selected_nodes, [ids["Child"], ids["Ellipse"]]);
+	assert_eq!(layer("Ellipse").parent(&replica), Some(layer("Group")));
+	assert!(replica.is_artboard(layer("Artboard")));
+	assert!(replica.is_folder(layer("Group")) && !replica.is_artboard(layer("Group")));
+	assert!(!replica.is_folder(layer("Child")) && !replica.is_artboard(layer("Child")));
+
+	// A resync carries the folders and artboards too
+	let mut resynced = DocumentMetadata::default();
+	resynced.apply_ops(&[source.resync_op()]).unwrap();
+	assert_converged(&source, &resynced);
+	assert!(resynced.is_artboard(layer("Artboard")) && resynced.is_folder(layer("Group")));
+}
+
+#[test]
+fn journal_overflow_resyncs() {
+	let mut source = DocumentMetadata::default();
+	let mut replica = DocumentMetadata::default();
+	source.journal = Some(Journal::with_capacity(4));
+	let layers = (1..=10).map(LayerNodeIdentifier::new_unchecked).collect::<Vec<_>>();
+	for &layer in &layers {
+		LayerNodeIdentifier::ROOT.push_front_child(&mut source, layer);
+	}
+	layers[0].reparent(&mut source, layers[9]);
+	let _ = source.set_selected_nodes(vec![layers[3].to_node()]);
+
+	// The early operations were dropped, so the journal starts again from the whole layer tree
+	let ops = source.drain_journal();
+	assert!(ops.len() <= 4);
+	assert!(matches!(ops[0], MetadataOp::Resync { .. }));
+	replica.apply_ops(&ops).unwrap();
+	assert_converged(&source, &replica);
+
+	// Stopping journaling discards what wasn't drained
+	layers[1].delete(&mut source);
+	source.set_journaling(false);
+	assert!(!source.is_journaling());
+	assert!(source.drain_journal().is_empty());
+}
+
+#[test]
+fn journal_rollback_resyncs() {
+	let mut source = DocumentMetadata::default();
+	let mut replica = DocumentMetadata::default();
+	let [first, second, folder] = [1, 2, 3].map(LayerNodeIdentifier::new_unchecked);
+	source.set_journaling(true);
+	LayerNodeIdentifier::ROOT.push_child(&mut source, first);
+	LayerNodeIdentifier::ROOT.push_child(&mut source, second);
+
+	// The folder and first move are recorded before the grouping fails on the root
+	assert!(source.group_layers(&[first, LayerNodeIdentifier::ROOT], folder).is_err());
+	let ops = source.drain_journal();
+	assert!(ops.iter().any(|op| matches!(op, MetadataOp::AddLayer { layer, .. } if *layer == folder)));
+	assert!(ops.iter().any(|op| matches!(op, MetadataOp::Resync { .. })));
+	replica.apply_ops(&ops).unwrap();
+	assert_converged(&source, &replica);
+	assert!(!replica.layer_exists(folder));
+}
+
+#[test]
+fn apply_ops_validation() {
+	let mut replica = DocumentMetadata::default();
+	let [first, second, third, fourth, missing] = [1, 2, 3, 4, 5].map(LayerNodeIdentifier::new_unchecked);
+	LayerNodeIdentifier::ROOT.push_child(&mut replica, first);
+	first.push_child(&mut replica, second);
+	let _ = replica.set_selected_nodes(vec![second.to_node()]);
+	let before = replica.clone();
+	let add = |layer, parent, below| MetadataOp::AddLayer {
+		layer,
+		parent,
+		below,
+		is_folder: false,
+		is_artboard: false,
+	};
+
+	// Operations before the invalid one are undone
+	let ops = [add(third, LayerNodeIdentifier::ROOT, None), MetadataOp::SetSelection { nodes: vec![] }, add(fourth, missing, None)];
+	assert_eq!(replica.apply_ops(&ops), Err(ApplyError::UnknownLayer { index: 2, layer: missing }));
+	assert_converged(&before, &replica);
+	assert!(!replica.layer_exists(third));
+
+	assert_eq!(
+		replica.apply_ops(&[add(second, LayerNodeIdentifier::ROOT, None)]),
+		Err(ApplyError::DuplicateLayer { index: 0, layer: second })
+	);
+	assert_eq!(
+		replica.apply_ops(&[add(third, LayerNodeIdentifier::ROOT, Some(second))]),
+		Err(ApplyError::UnknownLayer { index: 0, layer: second })
+	);
+	let move_into_child = MetadataOp::MoveLayer {
+		layer: first,
+		parent: second,
+		below: None,
+	};
+	assert_eq!(replica.apply_ops(&[move_into_child]), Err(ApplyError::InvalidMove { index: 0, layer: first }));
+	let reorder = MetadataOp::ReorderChildren { parent: first, order: vec![] };
+	assert_eq!(replica.apply_ops(&[reorder]), Err(ApplyError::InvalidOrder { index: 0, parent: first }));
+	assert_eq!(replica.apply_ops(&[MetadataOp::DeleteLayer { layer: third }]), Err(ApplyError::UnknownLayer { index: 0, layer: third }));
+	let root_layer = |layer| ResyncLayer {
+		layer,
+		parent: LayerNodeIdentifier::ROOT,
+		is_folder: false,
+		is_artboard: false,
+	};
+	let resync = MetadataOp::Resync {
+		layers: vec![root_layer(first), root_layer(first)],
+		selection: vec![],
+	};
+	assert_eq!(replica.apply_ops(&[resync]), Err(ApplyError::DuplicateLayer { index: 0, layer: first }));
+	assert_converged(&before, &replica);
+
+	let transaction = replica.begin_structure_transaction().unwrap();
+	assert_eq!(replica.apply_ops(&[]), Err(ApplyError::TransactionInProgress));
+	transaction.commit(&mut replica);
+	assert_eq!(replica.apply_ops(&[]), Ok(()));
+}
@@ -11,6 +11,8 @@ pub mod intersection;
 pub mod layers;
 pub mod operation;
 pub mod response;
+#[cfg(test)]
+pub mod test_utils;
 
 pub use document::LayerId;
 pub use error::DocumentError;
@@ -0,0 +1,195 @@
+use graph_craft::document::value::TaggedValue;
+use graph_craft::document::{DocumentNode, NodeId, NodeInput, NodeNetwork, NodeOutput};
+use graphene_core::raster::color::Color;
+use graphene_core::renderer::ClickTarget;
+
+use glam::{DVec2, IVec2};
+use std::collections::HashMap;
+
+/// Builds a [`NodeNetwork`] of layers for tests, wired like the document's graph: the output is fed by the topmost layer, each layer's second input is fed by the layer below it,
+/// and the first input is fed by its content. The content is a Shape node for layers, the topmost child layer for folders, and an Artboard node fed by the topmost child layer for artboards.
+///
+/// Layers are given their name as their alias. Node ids are handed out from 1 as nodes are created, with the Output node as 0, so layers are best looked up by name.
+///
+/// ```ignore
+/// let (network, ids) = NetworkBuilder::new().artboard("Artboard", |artboard| artboard.layer("Rectangle").folder("Group", |group| group.layer("Child"))).build();
+/// ```
+#[derive(Debug)]
+pub struct NetworkBuilder {
+	nodes: HashMap<NodeId, DocumentNode>,
+	ids: HashMap<String, NodeId>,
+	next_id: NodeId,
+	/// The layers of the stack being built, from the top.
+	stack: Vec<NodeId>,
+	disabled: Vec<NodeId>,
+}
+
+impl Default for NetworkBuilder {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl NetworkBuilder {
+	pub const OUTPUT: NodeId = 0;
+
+	pub fn new() -> Self {
+		Self {
+			nodes: HashMap::new(),
+			ids: HashMap::new(),
+			next_id: Self::OUTPUT + 1,
+			stack: Vec::new(),
+			disabled: Vec::new(),
+		}
+	}
+
+	/// Add a layer with vector content below the layers added so far.
+	pub fn layer(mut self, name: &str) -> Self {
+		let content = self.add_node(DocumentNode {
+			name: "Shape".to_string(),
+			inputs: vec![NodeInput::value(TaggedValue::None, false)],
+			..Default::default()
+		});
+		self.push_layer(name, Some(content))
+	}
+
+	/// Add a folder below the layers added so far, containing the layers added by `children`. A folder without children is fed by nothing.
+	pub fn folder(self, name: &str, children: impl FnOnce(Self) -> Self) -> Self {
+		let (builder, first_child) = self.nested(children);
+		builder.push_layer(name, first_child)
+	}
+
+	/// Add a 1920x1080 artboard at the origin below the layers added so far, containing the layers added by `children`.
+	pub fn artboard(self, name: &str, children: impl FnOnce(Self) -> Self) -> Self {
+		self.artboard_at(name, IVec2::ZERO, IVec2::new(1920, 1080), children)
+	}
+
+	/// Add an artboard with the location and dimensions below the layers added so far, containing the layers added by `children`.
+	pub fn artboard_at(self, name: &str, location: IVec2, dimensions: IVec2, children: impl FnOnce(Self) -> Self) -> Self {
+		let (mut builder, first_child) = self.nested(children);
+		let artboard = builder.add_node(DocumentNode {
+			name: "Artboard".to_string(),
+			inputs: vec![
+				first_child.map_or(NodeInput::value(TaggedValue::None, false), |child| NodeInput::node(child, 0)),
+				NodeInput::value(TaggedValue::IVec2(location), false),
+				NodeInput::value(TaggedValue::IVec2(dimensions), false),
+				NodeInput::value(TaggedValue::Color(Color::WHITE), false),
+				NodeInput::value(TaggedValue::Bool(false), false),
+			],
+			..Default::default()
+		});
+		builder.push_layer(name, Some(artboard))
+	}
+
+	/// Disable the most recently added layer in the graph.
+	pub fn hidden(mut self) -> Self {
+		let layer = *self.stack.last().expect("a layer to hide");
+		self.disabled.push(layer);
+		self
+	}
+
+	/// The network with the Output node fed by the topmost layer, and the id of each layer by its name.
+	pub fn build(mut self) -> (NodeNetwork, HashMap<String, NodeId>) {
+		let top = self.wire_stack();
+		let output = DocumentNode {
+			name: "Output".to_string(),
+			inputs: vec![top.map_or(NodeInput::value(TaggedValue::None, false), |top| NodeInput::node(top, 0))],
+			..Default::default()
+		};
+		self.nodes.insert(Self::OUTPUT, output);
+		let network = NodeNetwork {
+			outputs: vec![NodeOutput::new(Self::OUTPUT, 0)],
+			nodes: self.nodes,
+			disabled: self.disabled,
+			..Default::default()
+		};
+		(network, self.ids)
+	}
+
+	fn add_node(&mut self, node: DocumentNode) -> NodeId {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.nodes.insert(id, node);
+		id
+	}
+
+	fn push_layer(mut self, name: &str, content: Option<NodeId>) -> Self {
+		let layer = self.add_node(DocumentNode {
+			name: "Layer".to_string(),
+			alias: name.to_string(),
+			inputs: vec![
+				content.map_or(NodeInput::value(TaggedValue::None, false), |content| NodeInput::node(content, 0)),
+				NodeInput::value(TaggedValue::None, false),
+			],
+			..Default::default()
+		});
+		let previous = self.ids.insert(name.to_string(), layer);
+		assert!(previous.is_none(), "layer names must be unique, but {name} was added twice");
+		self.stack.push(layer);
+		self
+	}
+
+	/// Build the stack of children in a new builder sharing the nodes and ids, returning the topmost child.
+	fn nested(mut self, children: impl FnOnce(Self) -> Self) -> (Self, Option<NodeId>) {
+		let outer_stack = std::mem::take(&mut self.stack);
+		let mut builder = children(self);
+		let first_child = builder.wire_stack();
+		builder.stack = outer_stack;
+		(builder, first_child)
+	}
+
+	/// Feed each layer of the stack by the one below it, returning the topmost.
+	fn wire_stack(&mut self) -> Option<NodeId> {
+		for pair in self.stack.windows(2) {
+			self.nodes.get_mut(&pair[0]).expect("layer node").inputs[1] = NodeInput::node(pair[1], 0);
+		}
+		self.stack.first().copied()
+	}
+}
+
+/// The click targets of a layer drawn as a rectangle between the corners, without a stroke.
+pub fn rect_click_targets(min: DVec2, max: DVec2) -> Vec<ClickTarget> {
+	vec![ClickTarget {
+		subpath: bezier_rs::Subpath::new_rect(min, max),
+		stroke_width: 0.,
+	}]
+}
+
+/// A network of the nodes by their ids, with the Output node as [`NetworkBuilder::OUTPUT`], for graphs wired by hand which [`NetworkBuilder`] doesn't build.
+pub fn network(nodes: impl IntoIterator<Item = (NodeId, DocumentNode)>) -> NodeNetwork {
+	NodeNetwork {
+		outputs: vec![NodeOutput::new(NetworkBuilder::OUTPUT, 0)],
+		nodes: HashMap::from_iter(nodes),
+		..Default::default()
+	}
+}
+
+/// The network of a document without any layers, with only an Output node fed by nothing.
+pub fn empty_network() -> NodeNetwork {
+	NetworkBuilder::new().build().0
+}
+
+/// A node with the name, fed by the first output of each of the nodes in order.
+pub fn node(name: &str, inputs: &[NodeId]) -> DocumentNode {
+	DocumentNode {
+		name: name.to_string(),
+		inputs: inputs.iter().map(|&input| NodeInput::node(input, 0)).collect(),
+		..Default::default()
+	}
+}
+
+/// A node with the name, fed by the first output of the node and then by the value, such as an Opacity node.
+pub fn node_with_value(name: &str, input: NodeId, value: TaggedValue) -> DocumentNode {
+	DocumentNode {
+		inputs: vec![NodeInput::node(input, 0), NodeInput::value(value, false)],
+		..node(name, &[])
+	}
+}
+
+/// A Layer node fed by its content, stacked above the layer below it or at the bottom if there is none.
+pub fn layer_node(content: NodeId, below: Option<NodeId>) -> DocumentNode {
+	DocumentNode {
+		inputs: vec![NodeInput::node(content, 0), below.map_or(NodeInput::value(TaggedValue::None, false), |below| NodeInput::node(below, 0))],
+		..node("Layer", &[])
+	}
+}
@@ -47,6 +47,8 @@ pub const SELECTION_DRAG_ANGLE: f64 = 90.;
 pub const PIVOT_OUTER_OUTLINE_THICKNESS: f64 = 1.;
 pub const PIVOT_OUTER: f64 = 9.;
 pub const PIVOT_INNER: f64 = 3.;
+/// The size in viewport pixels given to the pivot's bounds along an axis where the layer has no size, such as the height of a horizontal line.
+pub const PIVOT_MIN_BOUNDS_SIZE: f64 = 1.;
 
 // Transformation cage
 pub const BOUNDS_SELECT_THRESHOLD: f64 = 10.;
@@ -123,8 +123,15 @@ pub enum DocumentMessage {
 	RenderDocument,
 	RenderRulers,
 	RenderScrollbars,
+	RestorePreviousView,
+	RestoreView {
+		name: String,
+	},
 	RollbackTransaction,
 	SaveDocument,
+	SaveView {
+		name: String,
+	},
 	SelectAllLayers,
 	SelectedLayersLower,
 	SelectedLayersLowerToBack,
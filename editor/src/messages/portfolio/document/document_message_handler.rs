@@ -44,8 +44,9 @@ pub struct DocumentMessageHandler {
 	pub version: String,
 	#[serde(default)]
 	pub commit_hash: String,
-	#[serde(default)]
-	pub collapsed_folders: Vec<LayerNodeIdentifier>,
+	/// Only read from documents saved before the collapsed folders were kept in the document metadata, see [`Self::deserialize_document`].
+	#[serde(default, skip_serializing)]
+	collapsed_folders: Vec<LayerNodeIdentifier>,
 	pub document_mode: DocumentMode,
 	pub view_mode: ViewMode,
 	#[serde(skip)]
@@ -194,14 +195,13 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 						document: &mut self.document_legacy,
 						document_id,
 						document_name: self.name.as_str(),
-						collapsed_folders: &mut self.collapsed_folders,
 						input: ipp,
 						graph_view_overlay_open,
 					},
 				);
 			}
 			#[remain::unsorted]
-			GraphOperation(message) => GraphOperationMessageHandler.process_message(message, responses, (&mut self.document_legacy, &mut self.collapsed_folders, &mut self.node_graph_handler)),
+			GraphOperation(message) => GraphOperationMessageHandler.process_message(message, responses, (&mut self.document_legacy, &mut self.node_graph_handler)),
 
 			// Messages
 			AbortTransaction => {
@@ -301,10 +301,8 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 				self.backup(responses);
 
 				responses.add_front(BroadcastEvent::SelectionChanged);
-				for path in self.metadata().shallowest_unique_layers(self.metadata().selected_layers()) {
-					responses.add_front(DocumentMessage::DeleteLayer {
-						layer_path: path.last().unwrap().to_path(),
-					});
+				for layer in self.metadata().shallowest_unique_layers(self.metadata().selected_layers()) {
+					responses.add_front(DocumentMessage::DeleteLayer { layer_path: layer.to_path() });
 				}
 
 				responses.add(BroadcastEvent::DocumentIsDirty);
@@ -610,6 +608,18 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 					multiplier: scrollbar_multiplier.into(),
 				});
 			}
+			RestorePreviousView => {
+				if self.document_legacy.metadata.restore_previous_view() {
+					let document_to_viewport = self.document_legacy.metadata.document_to_viewport;
+					responses.add(NavigationMessage::SetView { document_to_viewport });
+				}
+			}
+			RestoreView { name } => {
+				if self.document_legacy.metadata.restore_view(&name) {
+					let document_to_viewport = self.document_legacy.metadata.document_to_viewport;
+					responses.add(NavigationMessage::SetView { document_to_viewport });
+				}
+			}
 			RollbackTransaction => {
 				self.rollback(responses);
 				responses.extend([RenderDocument.into(), DocumentStructureChanged.into()]);
@@ -629,6 +639,11 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 					name,
 				})
 			}
+			SaveView { name } => {
+				self.document_legacy.metadata.save_view(name);
+				self.set_save_state(false);
+				responses.add(PortfolioMessage::UpdateOpenDocumentsList);
+			}
 			SelectAllLayers => {
 				let all = self.metadata().all_layers_except_artboards().map(|layer| layer.to_node()).collect();
 				responses.add(NodeGraphMessage::SelectedNodesSet { nodes: all });
@@ -781,11 +796,8 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 			StartTransaction => self.backup(responses),
 			ToggleLayerExpansion { layer } => {
 				let layer = LayerNodeIdentifier::new(layer, self.network());
-				if self.collapsed_folders.contains(&layer) {
-					self.collapsed_folders.retain(|&collapsed_layer| collapsed_layer != layer);
-				} else {
-					self.collapsed_folders.push(layer);
-				}
+				let collapsed = self.metadata().is_collapsed(layer);
+				self.document_legacy.metadata.set_collapsed(layer, !collapsed);
 				responses.add(NodeGraphMessage::RunDocumentGraph);
 			}
 			Undo => {
@@ -840,6 +852,7 @@ impl MessageHandler<DocumentMessage, DocumentInputs<'_>> for DocumentMessageHand
 			}
 			ZoomCanvasToFitAll => {
 				if let Some(bounds) = self.metadata().document_bounds_document_space(true) {
+					self.document_legacy.metadata.remember_view();
 					responses.add(NavigationMessage::FitViewportToBounds {
 						bounds,
 						padding_scale_factor: Some(VIEWPORT_ZOOM_TO_FIT_PADDING_SCALE_FACTOR),
@@ -910,7 +923,11 @@ impl DocumentMessageHandler {
 	pub fn deserialize_document(serialized_content: &str) -> Result<Self, DocumentError> {
 		let deserialized_result: Result<Self, DocumentError> = serde_json::from_str(serialized_content).map_err(|e| DocumentError::InvalidFile(e.to_string()));
 		match deserialized_result {
-			Ok(document) => {
+			Ok(mut document) => {
+				let legacy_collapsed = std::mem::take(&mut document.collapsed_folders);
+				if !legacy_collapsed.is_empty() {
+					document.document_legacy.metadata.set_collapsed_folders(legacy_collapsed);
+				}
 				if document.version == GRAPHITE_DOCUMENT_VERSION {
 					Ok(document)
 				} else {
@@ -1010,7 +1027,7 @@ impl DocumentMessageHandler {
 		for layer_node in folder.children(self.metadata()) {
 			data.push(layer_node.to_node());
 			space += 1;
-			if layer_node.has_children(self.metadata()) && !self.collapsed_folders.contains(&layer_node) {
+			if layer_node.has_children(self.metadata()) && !self.metadata().is_collapsed(layer_node) {
 				path.push(layer_node.to_node());
 
 				// TODO: Skip if folder is not expanded.
@@ -1157,8 +1174,14 @@ impl DocumentMessageHandler {
 	pub fn replace_document(&mut self, DocumentSave { document, layer_metadata }: DocumentSave) -> DocumentSave {
 		// Keeping the root is required if the bounds of the viewport have changed during the operation
 		let old_root = self.metadata().document_to_viewport;
-		let document = std::mem::replace(&mut self.document_legacy, document);
+		// Collapsing folders isn't undone
+		let collapsed: Vec<_> = self.metadata().collapsed_folders().collect();
+		let mut document = std::mem::replace(&mut self.document_legacy, document);
 		self.document_legacy.metadata.document_to_viewport = old_root;
+		self.document_legacy.metadata.set_collapsed_folders(collapsed);
+		// Observers stay with the document being displayed, and changing the view isn't undone
+		self.document_legacy.metadata.take_observers_from(&mut document.metadata);
+		self.document_legacy.metadata.take_views_from(&mut document.metadata);
 		self.document_legacy.root.cache_dirty = true;
 
 		let layer_metadata = std::mem::replace(&mut self.layer_metadata, layer_metadata);
@@ -1,7 +1,7 @@
 use crate::messages::input_mapper::utility_types::input_keyboard::Key;
 use crate::messages::prelude::*;
 
-use glam::DVec2;
+use glam::{DAffine2, DVec2};
 use serde::{Deserialize, Serialize};
 
 #[remain::sorted]
@@ -36,6 +36,9 @@ pub enum NavigationMessage {
 	SetCanvasZoom {
 		zoom_factor: f64,
 	},
+	SetView {
+		document_to_viewport: DAffine2,
+	},
 	TransformCanvasEnd {
 		abort_transform: bool,
 	},
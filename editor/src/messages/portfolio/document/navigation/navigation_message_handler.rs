@@ -239,6 +239,26 @@ impl MessageHandler<NavigationMessage, (&Document, Option<[DVec2; 2]>, &InputPre
 				responses.add(PortfolioMessage::UpdateDocumentWidgets);
 				self.create_document_transform(ipp.viewport_bounds.center(), responses);
 			}
+			SetView { document_to_viewport } => {
+				let x_axis = document_to_viewport.matrix2.x_axis;
+				let zoom = x_axis.length();
+				if !zoom.is_finite() || zoom <= f64::EPSILON {
+					warn!("Tried to set a view that can't be navigated to: {document_to_viewport:?}");
+					return;
+				}
+
+				// Undo the scale, offset to the viewport center and rotation in the reverse order of `calculate_offset_transform`
+				self.zoom = zoom;
+				self.tilt = x_axis.y.atan2(x_axis.x);
+				let offset = (document_to_viewport.translation - ipp.viewport_bounds.center()) / zoom;
+				self.pan = DAffine2::from_angle(-self.tilt).transform_vector2(offset);
+
+				responses.add(BroadcastEvent::CanvasTransformed);
+				responses.add(BroadcastEvent::DocumentIsDirty);
+				responses.add(DocumentMessage::DirtyRenderDocumentInOutlineView);
+				responses.add(PortfolioMessage::UpdateDocumentWidgets);
+				self.create_document_transform(ipp.viewport_bounds.center(), responses);
+			}
 			TransformCanvasEnd { abort_transform } => {
 				if abort_transform {
 					match self.transform_operation {
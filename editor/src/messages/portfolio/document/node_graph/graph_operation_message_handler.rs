@@ -571,12 +571,12 @@ impl<'a> ModifyInputsContext<'a> {
 	}
 }
 
-impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIdentifier>, &mut NodeGraphMessageHandler)> for GraphOperationMessageHandler {
+impl MessageHandler<GraphOperationMessage, (&mut Document, &mut NodeGraphMessageHandler)> for GraphOperationMessageHandler {
 	fn process_message(
 		&mut self,
 		message: GraphOperationMessage,
 		responses: &mut VecDeque<Message>,
-		(document, collapsed_folders, node_graph): (&mut Document, &mut Vec<LayerNodeIdentifier>, &mut NodeGraphMessageHandler),
+		(document, node_graph): (&mut Document, &mut NodeGraphMessageHandler),
 	) {
 		match message {
 			GraphOperationMessage::FillSet { layer, fill } => {
@@ -657,7 +657,7 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 				if let Some(layer) = modify_inputs.create_layer(id, modify_inputs.network.original_outputs()[0].node_id, 0, 0) {
 					modify_inputs.insert_artboard(artboard, layer);
 				}
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 			GraphOperationMessage::NewBitmapLayer {
 				id,
@@ -710,14 +710,14 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 					modify_inputs.responses.add(NodeGraphMessage::SendGraph { should_rerender: true });
 				}
 
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 			GraphOperationMessage::NewVectorLayer { id, subpaths, parent, insert_index } => {
 				let mut modify_inputs = ModifyInputsContext::new(document, node_graph, responses);
 				if let Some(layer) = modify_inputs.create_layer_with_insert_index(id, insert_index, parent) {
 					modify_inputs.insert_vector_data(subpaths, layer);
 				}
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 			GraphOperationMessage::NewTextLayer {
 				id,
@@ -731,7 +731,7 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 				if let Some(layer) = modify_inputs.create_layer_with_insert_index(id, insert_index, parent) {
 					modify_inputs.insert_text(text, font, size, layer);
 				}
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 			GraphOperationMessage::ResizeArtboard { id, location, dimensions } => {
 				if let Some(mut modify_inputs) = ModifyInputsContext::new_with_layer(&[id], document, node_graph, responses) {
@@ -741,7 +741,7 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 			GraphOperationMessage::DeleteLayer { id } => {
 				let mut modify_inputs = ModifyInputsContext::new(document, node_graph, responses);
 				modify_inputs.delete_layer(id);
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 			GraphOperationMessage::ClearArtboards => {
 				let mut modify_inputs = ModifyInputsContext::new(document, node_graph, responses);
@@ -751,7 +751,7 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 						modify_inputs.delete_layer(layer);
 					}
 				}
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 			}
 		}
 	}
@@ -761,7 +761,9 @@ impl MessageHandler<GraphOperationMessage, (&mut Document, &mut Vec<LayerNodeIde
 	}
 }
 
-pub fn load_network_structure(document: &mut Document, collapsed_folders: &mut Vec<LayerNodeIdentifier>) {
-	document.metadata.load_structure(&document.document_network);
-	collapsed_folders.retain(|&layer| document.metadata.layer_exists(layer));
+pub fn load_network_structure(document: &mut Document) {
+	let report = document.metadata.load_structure(&document.document_network);
+	if !report.duplicates.is_empty() {
+		warn!("Layer nodes {:?} are in the layer stacks more than once, so only their first positions are in the layer tree", report.duplicates);
+	}
 }
@@ -1,3 +1,4 @@
+use crate::consts::PIVOT_MIN_BOUNDS_SIZE;
 use crate::messages::portfolio::document::node_graph::VectorDataModification;
 
 use bezier_rs::{ManipulatorGroup, Subpath};
@@ -56,7 +57,7 @@ impl LayerBounds {
 	pub fn new(document: &Document, layer: &[u64]) -> Self {
 		let layer = LayerNodeIdentifier::new(*layer.last().unwrap(), &document.document_network);
 		Self {
-			bounds: document.metadata.nonzero_bounding_box(layer),
+			bounds: document.metadata.nonzero_bounding_box_with_min(layer, PIVOT_MIN_BOUNDS_SIZE),
 			bounds_transform: DAffine2::IDENTITY,
 			layer_transform: document.metadata.transform_to_document(layer),
 		}
@@ -350,7 +350,7 @@ impl NodeGraphMessageHandler {
 	fn update_selected(&mut self, document: &mut Document, responses: &mut VecDeque<Message>) {
 		self.update_selection_action_buttons(document, responses);
 		responses.add(FrontendMessage::UpdateNodeGraphSelection {
-			selected: document.metadata.selected_nodes_ref().clone(),
+			selected: document.metadata.selected_nodes().copied().collect(),
 		});
 	}
 
@@ -455,7 +455,6 @@ pub struct NodeGraphHandlerData<'a> {
 	pub document: &'a mut Document,
 	pub document_id: u64,
 	pub document_name: &'a str,
-	pub collapsed_folders: &'a mut Vec<LayerNodeIdentifier>,
 	pub input: &'a InputPreprocessorMessageHandler,
 	pub graph_view_overlay_open: bool,
 }
@@ -465,7 +464,6 @@ impl<'a> MessageHandler<NodeGraphMessage, NodeGraphHandlerData<'a>> for NodeGrap
 		let NodeGraphHandlerData {
 			document,
 			document_id,
-			collapsed_folders,
 			graph_view_overlay_open,
 			..
 		} = data;
@@ -476,7 +474,7 @@ impl<'a> MessageHandler<NodeGraphMessage, NodeGraphHandlerData<'a>> for NodeGrap
 					on: BroadcastEvent::SelectionChanged,
 					send: Box::new(NodeGraphMessage::SelectedNodesUpdated.into()),
 				});
-				load_network_structure(document, collapsed_folders);
+				load_network_structure(document);
 				responses.add(DocumentMessage::DocumentStructureChanged);
 			}
 			NodeGraphMessage::SelectedNodesUpdated => {
@@ -818,7 +816,7 @@ impl<'a> MessageHandler<NodeGraphMessage, NodeGraphHandlerData<'a>> for NodeGrap
 						let structure_changed = node_input.as_node().is_some() || input.as_node().is_some();
 						*node_input = input;
 						if structure_changed {
-							load_network_structure(document, collapsed_folders);
+							load_network_structure(document);
 						}
 					}
 				}
@@ -936,10 +934,13 @@ impl<'a> MessageHandler<NodeGraphMessage, NodeGraphHandlerData<'a>> for NodeGrap
 			NodeGraphMessage::SetNameImpl { node_id, name } => {
 				if let Some(network) = document.document_network.nested_network_mut(&self.network) {
 					if let Some(node) = network.nodes.get_mut(&node_id) {
-						node.alias = name;
+						node.alias = name.clone();
 						responses.add(NodeGraphMessage::SendGraph { should_rerender: false });
 					}
 				}
+				if self.network.is_empty() {
+					document.metadata.set_layer_name(LayerNodeIdentifier::new_unchecked(node_id), name);
+				}
 			}
 			NodeGraphMessage::TogglePreview { node_id } => {
 				responses.add(DocumentMessage::StartTransaction);
@@ -404,9 +404,7 @@ impl<'a> Selected<'a> {
 	pub fn apply_transformation(&mut self, transformation: DAffine2) {
 		if !self.selected.is_empty() {
 			// TODO: Cache the result of `shallowest_unique_layers` to avoid this heavy computation every frame of movement, see https://github.com/GraphiteEditor/Graphite/pull/481
-			for layer_ancestors in self.document.metadata.shallowest_unique_layers(self.selected.iter().copied()) {
-				let layer = *layer_ancestors.last().unwrap();
-
+			for layer in self.document.metadata.shallowest_unique_layers(self.selected.iter().copied()) {
 				match &self.original_transforms {
 					OriginalTransforms::Layer(layer_transforms) => Self::transform_layer(self.document, layer, layer_transforms.get(&layer), transformation, self.responses),
 					OriginalTransforms::Path(path_transforms) => Self::transform_path(self.document, layer, path_transforms.get(&layer), transformation, self.responses),
@@ -178,11 +178,7 @@ impl MessageHandler<PortfolioMessage, (&InputPreprocessorMessageHandler, &Prefer
 				};
 
 				let copy_val = |buffer: &mut Vec<CopyBufferEntry>| {
-					for layer_path in active_document.metadata().shallowest_unique_layers(active_document.metadata().selected_layers()) {
-						let Some(layer) = layer_path.last().copied() else {
-							continue;
-						};
-
+					for layer in active_document.metadata().shallowest_unique_layers(active_document.metadata().selected_layers()) {
 						let node = layer.to_node();
 						let Some(node) = active_document.network().nodes.get(&node).and_then(|node| node.inputs.first()).and_then(|input| input.as_node()) else {
 							continue;
@@ -720,7 +716,7 @@ impl PortfolioMessageHandler {
 		};
 
 		self.executor
-			.poll_node_graph_evaluation(&mut active_document.document_legacy, &mut active_document.collapsed_folders, responses)
+			.poll_node_graph_evaluation(&mut active_document.document_legacy, responses)
 			.unwrap_or_else(|e| {
 				log::error!("Error while evaluating node graph: {e}");
 			});
@@ -1,3 +1,4 @@
+use crate::consts::PIVOT_MIN_BOUNDS_SIZE;
 use crate::messages::portfolio::document::node_graph::VectorDataModification;
 use crate::messages::prelude::*;
 
@@ -78,13 +79,13 @@ pub fn get_pivot(layer: LayerNodeIdentifier, document: &Document) -> Option<DVec
 }
 
 pub fn get_document_pivot(layer: LayerNodeIdentifier, document: &Document) -> DVec2 {
-	let [min, max] = document.metadata.nonzero_bounding_box(layer);
+	let [min, max] = document.metadata.nonzero_bounding_box_with_min(layer, PIVOT_MIN_BOUNDS_SIZE);
 	let pivot = get_pivot(layer, document).unwrap_or(DVec2::splat(0.5));
 	document.metadata.transform_to_document(layer).transform_point2(min + (max - min) * pivot)
 }
 
 pub fn get_viewport_pivot(layer: LayerNodeIdentifier, document: &Document) -> DVec2 {
-	let [min, max] = document.metadata.nonzero_bounding_box(layer);
+	let [min, max] = document.metadata.nonzero_bounding_box_with_min(layer, PIVOT_MIN_BOUNDS_SIZE);
 	let pivot = get_pivot(layer, document).unwrap_or(DVec2::splat(0.5));
 	document.metadata.transform_to_viewport(layer).transform_point2(min + (max - min) * pivot)
 }
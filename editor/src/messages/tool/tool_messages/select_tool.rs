@@ -301,8 +301,7 @@ impl SelectToolData {
 		self.not_duplicated_layers = Some(self.layers_dragging.clone());
 
 		// Duplicate each previously selected layer and select the new ones.
-		for layer_ancestors in document.metadata().shallowest_unique_layers(self.layers_dragging.iter().copied()) {
-			let layer = layer_ancestors.last().unwrap();
+		for layer in document.metadata().shallowest_unique_layers(self.layers_dragging.iter().copied()) {
 			// Moves the original back to its starting position.
 			responses.add_front(GraphOperationMessage::TransformChange {
 				layer: layer.to_path(),
@@ -352,16 +351,14 @@ impl SelectToolData {
 		responses.add(DocumentMessage::DeselectAllLayers);
 
 		// Delete the duplicated layers
-		for layer_ancestors in document.metadata().shallowest_unique_layers(self.layers_dragging.iter().copied()) {
-			responses.add(GraphOperationMessage::DeleteLayer {
-				id: layer_ancestors.last().unwrap().to_node(),
-			});
+		for layer in document.metadata().shallowest_unique_layers(self.layers_dragging.iter().copied()) {
+			responses.add(GraphOperationMessage::DeleteLayer { id: layer.to_node() });
 		}
 
 		// Move the original to under the mouse
-		for layer_ancestors in document.metadata().shallowest_unique_layers(originals.iter().copied()) {
+		for layer in document.metadata().shallowest_unique_layers(originals.iter().copied()) {
 			responses.add_front(GraphOperationMessage::TransformChange {
-				layer: layer_ancestors.last().unwrap().to_path(),
+				layer: layer.to_path(),
 				transform: DAffine2::from_translation(self.drag_current - self.drag_start),
 				transform_in: TransformIn::Viewport,
 				skip_rerender: true,
@@ -604,9 +601,9 @@ impl Fsm for SelectToolFsmState {
 
 				let closest_move = tool_data.snap_manager.snap_layers(responses, document, snap, mouse_delta);
 				// TODO: Cache the result of `shallowest_unique_layers` to avoid this heavy computation every frame of movement, see https://github.com/GraphiteEditor/Graphite/pull/481
-				for layer_ancestors in document.metadata().shallowest_unique_layers(tool_data.layers_dragging.iter().copied()) {
+				for layer in document.metadata().shallowest_unique_layers(tool_data.layers_dragging.iter().copied()) {
 					responses.add_front(GraphOperationMessage::TransformChange {
-						layer: layer_ancestors.last().unwrap().to_path(),
+						layer: layer.to_path(),
 						transform: DAffine2::from_translation(mouse_delta + closest_move),
 						transform_in: TransformIn::Viewport,
 						skip_rerender: false,
@@ -914,7 +911,7 @@ fn drag_shallowest_manipulation(responses: &mut VecDeque<Message>, selected: Vec
 	let layer = selected[0];
 	let ancestor = layer.ancestors(document.metadata()).find(|&ancestor| document.metadata().selected_layers_contains(ancestor));
 
-	let new_selected = ancestor.unwrap_or_else(|| layer.child_of_root(document.metadata()));
+	let new_selected = ancestor.or_else(|| layer.child_of_root(document.metadata())).unwrap_or(layer);
 
 	tool_data.layers_dragging = vec![new_selected];
 	responses.add(NodeGraphMessage::SelectedNodesSet {
@@ -534,7 +534,7 @@ impl NodeGraphExecutor {
 
 		// Calculate the bounding box of the region to be exported
 		let bounds = match export_config.bounds {
-			ExportBounds::AllArtwork => document.metadata().document_bounds_document_space(!export_config.transparent_background),
+			ExportBounds::AllArtwork => document.metadata().rendered_bounds_document_space(!export_config.transparent_background),
 			ExportBounds::Selection => document.metadata().selected_bounds_document_space(!export_config.transparent_background),
 			ExportBounds::Artboard(id) => document.metadata().bounding_box_document(id),
 		}
@@ -595,7 +595,7 @@ impl NodeGraphExecutor {
 		Ok(())
 	}
 
-	pub fn poll_node_graph_evaluation(&mut self, document: &mut DocumentLegacy, collapsed_folders: &mut Vec<LayerNodeIdentifier>, responses: &mut VecDeque<Message>) -> Result<(), String> {
+	pub fn poll_node_graph_evaluation(&mut self, document: &mut DocumentLegacy, responses: &mut VecDeque<Message>) -> Result<(), String> {
 		let results = self.receiver.try_iter().collect::<Vec<_>>();
 		for response in results {
 			match response {
@@ -634,7 +634,7 @@ impl NodeGraphExecutor {
 									LayerDataTypeDiscriminant::Layer
 								},
 								layer_metadata: LayerMetadata {
-									expanded: layer.has_children(&document.metadata) && !collapsed_folders.contains(&layer),
+									expanded: layer.has_children(&document.metadata) && !document.metadata.is_collapsed(layer),
 									selected: document.metadata.selected_layers_contains(layer),
 								},
 								path: vec![node_id],
@@ -644,7 +644,11 @@ impl NodeGraphExecutor {
 					}
 					self.thumbnails = new_thumbnails;
 					document.metadata.update_transforms(new_upstream_transforms);
-					document.metadata.update_click_targets(new_click_targets);
+					let rejected = document.metadata.update_click_targets(new_click_targets);
+					if !rejected.is_empty() {
+						// A later evaluation sends these again once the layer tree has caught up with the graph
+						warn!("Click targets for layers missing from the layer tree were dropped: {rejected:?}");
+					}
 					responses.extend(updates);
 					self.process_node_graph_output(node_graph_output, execution_context.layer_path.clone(), transform, responses)?;
 					responses.add(DocumentMessage::LayerChanged {
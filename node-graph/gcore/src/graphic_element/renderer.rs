@@ -3,7 +3,7 @@ mod quad;
 use crate::raster::{BlendMode, Image, ImageFrame};
 use crate::uuid::{generate_uuid, ManipulatorGroupId};
 use crate::{vector::VectorData, Artboard, Color, GraphicElement, GraphicGroup};
-pub use quad::Quad;
+pub use quad::{HandleId, PixelRounding, Quad};
 
 use bezier_rs::Subpath;
 
@@ -1,10 +1,21 @@
-use glam::{DAffine2, DVec2};
+use crate::uuid::ManipulatorGroupId;
+
+use glam::{DAffine2, DVec2, IVec2};
+
+/// Intersection points closer together than this are considered to be the same point.
+const INTERSECTION_EPSILON: f64 = 1e-6;
+
+/// The smallest size along each of its axes that [`Quad::resize_by_handle`] shrinks a quad to.
+const MIN_RESIZE_SIZE: f64 = 1e-6;
 
 #[derive(Debug, Clone, Default, Copy)]
 /// A quad defined by four vertices.
 pub struct Quad(pub [DVec2; 4]);
 
 impl Quad {
+	/// The most tiles that [`Self::split_bounds_into_tiles`] splits bounds across, so huge bounds or tiny tiles can't produce an unbounded number of parts.
+	pub const MAX_TILES: usize = 4096;
+
 	/// Create a zero sized quad at the point
 	pub fn from_point(point: DVec2) -> Self {
 		Self([point; 4])
@@ -16,6 +27,21 @@ impl Quad {
 		Self([bbox[0], bbox[0] + size * DVec2::X, bbox[1], bbox[0] + size * DVec2::Y])
 	}
 
+	/// Convert a box defined by two opposite corner points in any order to a quad, starting from the minimum corner with counter-clockwise winding (when the y axis points up).
+	pub fn from_box_normalized(bbox: [DVec2; 2]) -> Self {
+		Self::from_box(Self::sort_bounds(bbox))
+	}
+
+	/// Reorder the two corner points of an axis aligned rectangle so that the first is the minimum and the second is the maximum on each axis.
+	pub fn sort_bounds([a, b]: [DVec2; 2]) -> [DVec2; 2] {
+		[a.min(b), a.max(b)]
+	}
+
+	/// Are both corners finite, with the first no greater than the second on each axis.
+	pub fn bounds_are_valid([min, max]: [DVec2; 2]) -> bool {
+		min.is_finite() && max.is_finite() && min.cmple(max).all()
+	}
+
 	/// Get all the edges in the quad.
 	pub fn bezier_lines(&self) -> impl Iterator<Item = bezier_rs::Bezier> + '_ {
 		[[self.0[0], self.0[1]], [self.0[1], self.0[2]], [self.0[2], self.0[3]], [self.0[3], self.0[0]]]
@@ -28,6 +54,52 @@ impl Quad {
 		crate::vector::Subpath::from_points(self.0.into_iter(), true)
 	}
 
+	/// Generates a closed [crate::vector::Subpath] of the quad with every corner rounded by a circular arc of the radius.
+	///
+	/// The radius is reduced where needed so that the arcs don't extend past half of the shorter edge at each corner.
+	pub fn rounded_subpath(&self, radius: f64) -> crate::vector::Subpath {
+		self.per_corner_radii([radius; 4])
+	}
+
+	/// Generates a closed [crate::vector::Subpath] of the quad with each corner, in the same order as the vertices, rounded by its own radius.
+	pub fn per_corner_radii(&self, radii: [f64; 4]) -> crate::vector::Subpath {
+		if radii.iter().all(|&radius| radius <= 0.) {
+			return self.subpath();
+		}
+
+		let mut manipulator_groups: Vec<bezier_rs::ManipulatorGroup<ManipulatorGroupId>> = Vec::new();
+		let mut push = |anchor: DVec2, in_handle: Option<DVec2>, out_handle: Option<DVec2>| match manipulator_groups.last_mut() {
+			// Where the arcs of adjacent corners meet in the middle of an edge, they share a manipulator group
+			Some(previous) if previous.anchor.abs_diff_eq(anchor, 1e-10) => previous.out_handle = out_handle,
+			_ => manipulator_groups.push(bezier_rs::ManipulatorGroup::new(anchor, in_handle, out_handle)),
+		};
+		for (index, radius) in radii.into_iter().enumerate() {
+			let [previous, corner, next] = [self.0[(index + 3) % 4], self.0[index], self.0[(index + 1) % 4]];
+			let (incoming, outgoing) = (corner - previous, next - corner);
+			let [incoming_direction, outgoing_direction] = [incoming.normalize_or_zero(), outgoing.normalize_or_zero()];
+
+			// The arc sweeps through the angle the path turns by at the corner, and starts and ends where it touches the edges
+			let sweep = incoming_direction.angle_between(outgoing_direction).abs();
+			let tangent_length = (radius.max(0.) * (sweep / 2.).tan()).min(incoming.length().min(outgoing.length()) / 2.);
+			if !(tangent_length > 0. && sweep > 1e-10 && sweep < core::f64::consts::PI - 1e-10) {
+				push(corner, None, None);
+				continue;
+			}
+			let radius = tangent_length / (sweep / 2.).tan();
+			let handle_length = radius * 4. / 3. * (sweep / 4.).tan();
+
+			let [start, end] = [corner - incoming_direction * tangent_length, corner + outgoing_direction * tangent_length];
+			push(start, None, Some(start + incoming_direction * handle_length));
+			push(end, Some(end - outgoing_direction * handle_length), None);
+		}
+		if manipulator_groups.len() > 1 && manipulator_groups[0].anchor.abs_diff_eq(manipulator_groups[manipulator_groups.len() - 1].anchor, 1e-10) {
+			let last = manipulator_groups.pop().expect("There are manipulator groups");
+			manipulator_groups[0].in_handle = last.in_handle;
+		}
+
+		crate::vector::Subpath::from_bezier_rs([&bezier_rs::Subpath::new(manipulator_groups, true)])
+	}
+
 	/// Generates the axis aligned bounding box of the quad
 	pub fn bounding_box(&self) -> [DVec2; 2] {
 		[
@@ -36,42 +108,397 @@ impl Quad {
 		]
 	}
 
+	/// The smallest and largest projections of the corners onto the direction of the axis, which is `(0., 0.)` for an axis with no direction.
+	pub fn extent_along(&self, axis: DVec2) -> (f64, f64) {
+		let axis = axis.normalize_or_zero();
+		self.0
+			.iter()
+			.map(|corner| corner.dot(axis))
+			.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| (min.min(x), max.max(x)))
+	}
+
+	/// The size of the quad along the direction of the axis, see [`Self::extent_along`].
+	pub fn width_along(&self, axis: DVec2) -> f64 {
+		let (min, max) = self.extent_along(axis);
+		max - min
+	}
+
+	/// The axis aligned bounds of the quad in physical pixels when scaled by the device pixel ratio, as the minimum corner and the size.
+	///
+	/// The size is never negative, and is at least one pixel in each axis with [`PixelRounding::Enclose`]. Returns `None` if any corner isn't finite.
+	pub fn to_pixel_rect(&self, device_pixel_ratio: f64, mode: PixelRounding) -> Option<([i64; 2], [i64; 2])> {
+		// The bounding box skips over NaN, so the corners are checked first
+		if !self.0.iter().all(|corner| corner.is_finite()) {
+			return None;
+		}
+		let [min, max] = self.bounding_box().map(|corner| corner * device_pixel_ratio);
+		if !min.is_finite() || !max.is_finite() {
+			return None;
+		}
+		let (min, max) = match mode {
+			PixelRounding::Enclose => {
+				let min = min.floor();
+				(min, max.ceil().max(min + DVec2::ONE))
+			}
+			PixelRounding::Round => (min.round(), max.round()),
+		};
+		let size = (max - min).max(DVec2::ZERO);
+		Some(([min.x as i64, min.y as i64], [size.x as i64, size.y as i64]))
+	}
+
 	/// Gets the center of a quad
 	pub fn center(&self) -> DVec2 {
 		self.0.iter().sum::<DVec2>() / 4.
 	}
 
+	/// Apply a function to each of the four corners of the quad.
+	pub fn map(self, f: impl Fn(DVec2) -> DVec2) -> Self {
+		Self(self.0.map(f))
+	}
+
 	/// Take the outside bounds of two axis aligned rectangles, which are defined by two corner points.
 	pub fn combine_bounds(a: [DVec2; 2], b: [DVec2; 2]) -> [DVec2; 2] {
 		[a[0].min(b[0]), a[1].max(b[1])]
 	}
 
-	/// Expand a quad by a certain amount on all sides.
+	/// Split the bounding box of the quad at the edges of a grid of tiles, giving the part in each tile it overlaps in the local space of that tile, by rows then columns.
+	///
+	/// Parts of a quad that isn't axis aligned are the parts of its bounding box, so they may cover more than the quad itself.
+	pub fn wrap_into_tile(&self, tile_size: DVec2, origin: DVec2) -> Vec<Quad> {
+		Self::split_bounds_into_tiles(self.bounding_box(), tile_size, origin)
+			.into_iter()
+			.map(|(_, bounds)| Quad::from_box(bounds))
+			.collect()
+	}
+
+	/// Split an axis aligned rectangle at the edges of a grid of tiles with a corner at `origin`, giving the index of each tile it overlaps and its part in that tile relative to the tile's minimum corner.
 	///
-	/// Not currently very optimised
+	/// The tiles are ordered by rows then columns. Bounds touching a tile edge aren't counted as overlapping the tile beyond it, unless they have no size on that axis.
+	/// Bounds overlapping more than [`Self::MAX_TILES`] tiles are only split across that many, from the first rows and columns. Returns nothing if the tiles or bounds
+	/// aren't valid, or the tile indices aren't finite or don't fit in an `i32`.
+	pub fn split_bounds_into_tiles(bounds: [DVec2; 2], tile_size: DVec2, origin: DVec2) -> Vec<(IVec2, [DVec2; 2])> {
+		if !Self::bounds_are_valid(bounds) || !tile_size.is_finite() || tile_size.cmple(DVec2::ZERO).any() || !origin.is_finite() {
+			return Vec::new();
+		}
+		let [min, max] = bounds;
+		let first = ((min - origin) / tile_size).floor();
+		let last = (((max - origin) / tile_size).ceil() - DVec2::ONE).max(first);
+		if !first.is_finite() || !last.is_finite() {
+			return Vec::new();
+		}
+		let max_tiles = Self::MAX_TILES as f64;
+		let columns = (last.x - first.x + 1.).min(max_tiles);
+		let rows = (last.y - first.y + 1.).min((max_tiles / columns).floor());
+		let last = first + DVec2::new(columns, rows) - DVec2::ONE;
+		if first.min(last).min_element() < i32::MIN as f64 || first.max(last).max_element() > i32::MAX as f64 {
+			return Vec::new();
+		}
+		let [first, last] = [first, last].map(|index| IVec2::new(index.x as i32, index.y as i32));
+
+		let mut tiles = Vec::new();
+		for y in first.y..=last.y {
+			for x in first.x..=last.x {
+				let index = IVec2::new(x, y);
+				let tile_min = origin + DVec2::new(x as f64, y as f64) * tile_size;
+				tiles.push((index, [min.max(tile_min) - tile_min, max.min(tile_min + tile_size) - tile_min]));
+			}
+		}
+		tiles
+	}
+
+	/// Interpolate each corner linearly towards the same corner of the other quad, giving exactly `self` at `t = 0` and `other` at `t = 1`.
+	pub fn lerp(&self, other: Quad, t: f64) -> Quad {
+		Self([0, 1, 2, 3].map(|index| self.0[index] * (1. - t) + other.0[index] * t))
+	}
+
+	/// Interpolate the center, rotation and shape of two parallelograms separately, so a box keeps its size while rotating rather than shrinking through the midpoint.
+	///
+	/// The rotation is of the first edge and takes the shorter way around. If either quad isn't a parallelogram or has a first edge of zero length, this falls back to [`Quad::lerp`].
+	pub fn lerp_rigid(&self, other: Quad, t: f64) -> Quad {
+		/// The center, the first edge, and the last edge relative to the rotation of the first edge
+		fn decompose(quad: &Quad) -> Option<(DVec2, DVec2, DVec2)> {
+			let [a, b, c, d] = quad.0;
+			let [x_axis, y_axis] = [b - a, d - a];
+			// Relative to the size of the quad
+			let tolerance = 1e-6 * x_axis.length().max(y_axis.length()).max(1.);
+			if x_axis.length() <= tolerance || !(a + c).abs_diff_eq(b + d, tolerance) {
+				return None;
+			}
+			let unit = x_axis.normalize();
+			Some(((a + c) / 2., x_axis, DVec2::new(unit.dot(y_axis), unit.perp_dot(y_axis))))
+		}
+		let (Some((start_center, start_x, start_y)), Some((end_center, end_x, end_y))) = (decompose(self), decompose(&other)) else {
+			return self.lerp(other, t);
+		};
+
+		let direction = DVec2::from_angle(start_x.angle_between(end_x) * t).rotate(start_x.normalize());
+		let x_axis = direction * (start_x.length() * (1. - t) + end_x.length() * t);
+		let y_axis = direction.rotate(start_y * (1. - t) + end_y * t);
+		let corner = start_center * (1. - t) + end_center * t - (x_axis + y_axis) / 2.;
+		Self([corner, corner + x_axis, corner + x_axis + y_axis, corner + y_axis])
+	}
+
+	/// Move the handle at a corner or edge midpoint towards the new position, scaling the quad along its own edges so this works for rotated quads.
+	///
+	/// The quad is treated as the parallelogram spanned by its first and last edges from the first corner. The opposite handle stays in place, or the center does
+	/// if `from_center` is set. An edge handle only follows the new position along the axis across that edge, and with `constrain_aspect` the other axis is scaled
+	/// by the same amount about its middle. A corner handle with `constrain_aspect` scales both axes by whichever of them was scaled the most.
+	///
+	/// Dragging a handle past the opposite side doesn't flip the quad, it is instead kept at a tiny size. A quad with no area is returned unchanged.
+	pub fn resize_by_handle(&self, handle: HandleId, new_handle_position: DVec2, constrain_aspect: bool, from_center: bool) -> Quad {
+		let [origin, x_end, _, y_end] = self.0;
+		let frame = DAffine2::from_cols(x_end - origin, y_end - origin, origin);
+		let axis_lengths = DVec2::new(frame.matrix2.x_axis.length(), frame.matrix2.y_axis.length());
+		if frame.matrix2.determinant().abs() <= f64::EPSILON * axis_lengths.x * axis_lengths.y || !frame.is_finite() {
+			return *self;
+		}
+
+		// In the frame the quad spans from zero to one on each axis
+		let handle = handle.position_in_box();
+		let anchor = if from_center { DVec2::splat(0.5) } else { DVec2::ONE - handle };
+		let target = frame.inverse().transform_point2(new_handle_position);
+		let movable = handle.to_array().map(|position| position != 0.5);
+		let min_scale = MIN_RESIZE_SIZE / axis_lengths;
+		let scale_along = |axis: usize| {
+			if movable[axis] {
+				((target[axis] - anchor[axis]) / (handle[axis] - anchor[axis])).max(min_scale[axis])
+			} else {
+				1.
+			}
+		};
+		let mut scale = DVec2::new(scale_along(0), scale_along(1));
+		if constrain_aspect {
+			let uniform = match movable {
+				[true, true] => scale.max_element(),
+				[true, false] => scale.x,
+				_ => scale.y,
+			};
+			scale = DVec2::splat(uniform).max(min_scale);
+		}
+
+		let resize = DAffine2::from_translation(anchor) * DAffine2::from_scale(scale) * DAffine2::from_translation(-anchor);
+		frame * resize * frame.inverse() * *self
+	}
+
+	/// Expand a quad by a certain amount on all sides, whichever way around its corners are.
+	///
+	/// Each edge is moved outwards along its normal and the new corners are where adjacent moved edges meet. This is done relative to the center
+	/// so precision isn't lost for quads far from the origin.
 	pub fn inflate(&self, offset: f64) -> Quad {
-		let offset = |index_before, index, index_after| {
-			let [point_before, point, point_after]: [DVec2; 3] = [self.0[index_before], self.0[index], self.0[index_after]];
-			let [line_in, line_out] = [point - point_before, point_after - point];
-			let angle = line_in.angle_between(-line_out);
-			let offset_length = offset / (std::f64::consts::FRAC_PI_2 - angle / 2.).cos();
-			point + (line_in.perp().normalize_or_zero() + line_out.perp().normalize_or_zero()).normalize_or_zero() * offset_length
+		let center = self.center();
+		let points = self.0.map(|point| point - center);
+		let edges = [0, 1, 2, 3].map(|index| points[(index + 1) % 4] - points[index]);
+		// The sign of the area gives the winding, which decides which side of the edges is outside
+		let area = edges.iter().zip(points).map(|(edge, point)| point.perp_dot(*edge)).sum::<f64>();
+		let outwards = if area >= 0. { -1. } else { 1. };
+		let normals = edges.map(|edge| edge.perp().normalize_or_zero() * outwards);
+
+		let corner = |index: usize| {
+			let before = (index + 3) % 4;
+			let [edge_in, edge_out] = [edges[before], edges[index]];
+			let [start_in, start_out] = [points[before] + normals[before] * offset, points[index] + normals[index] * offset];
+			let denominator = edge_in.perp_dot(edge_out);
+			// Parallel edges (or edges with no length) don't meet, so the corner is just moved along the normals
+			if denominator.abs() <= f64::EPSILON * edge_in.length() * edge_out.length() {
+				return points[index] + (normals[before] + normals[index]).normalize_or_zero() * offset;
+			}
+			start_in + edge_in * ((start_out - start_in).perp_dot(edge_out) / denominator)
 		};
-		Self([offset(3, 0, 1), offset(0, 1, 2), offset(1, 2, 3), offset(2, 3, 0)])
+		Self([0, 1, 2, 3].map(|index| corner(index) + center))
 	}
 
 	/// Does this quad contain a point
 	///
+	/// This uses the even-odd rule so the result doesn't depend on the order of the corners. Both triangles of a self intersecting (bowtie) quad are
+	/// inside, and for four corners the nonzero rule would give the same answers. See [`Quad::untangle`] to instead test the area of the simple polygon.
+	///
 	/// Code from https://wrfranklin.org/Research/Short_Notes/pnpoly.html
 	pub fn contains(&self, p: DVec2) -> bool {
+		Self::contains_with_edges(&self.edge_equations(), p)
+	}
+
+	/// Do opposite edges of the quad cross, such as from dragging a corner past the others, making a bowtie shape?
+	pub fn is_self_intersecting(&self) -> bool {
+		let [a, b, c, d] = self.0;
+		segments_cross([a, b], [c, d]) || segments_cross([b, c], [d, a])
+	}
+
+	/// Reorder the corners of a self intersecting quad so its edges form a simple polygon around the same four points, otherwise returning it unchanged.
+	pub fn untangle(&self) -> Quad {
+		let [a, b, c, d] = self.0;
+		if segments_cross([a, b], [c, d]) {
+			Self([a, c, b, d])
+		} else if segments_cross([b, c], [d, a]) {
+			Self([a, b, d, c])
+		} else {
+			*self
+		}
+	}
+
+	/// Are the edges of the quad parallel to the axes, such that it is the same as its bounding box?
+	pub fn is_axis_aligned(&self) -> bool {
+		let [a, b, c, d] = self.0;
+		(a.x == b.x && b.y == c.y && c.x == d.x && d.y == a.y) || (a.y == b.y && b.x == c.x && c.y == d.y && d.x == a.x)
+	}
+
+	/// Which of the points this quad contains, written to `out` after clearing it. This gives the same results as [`Quad::contains`] for each point, but only prepares the quad once.
+	pub fn contains_many(&self, points: &[DVec2], out: &mut Vec<bool>) {
+		out.clear();
+		out.reserve(points.len());
+		if self.is_axis_aligned() {
+			// Matches the half open intervals of the edge crossing test
+			let [min, max] = self.bounding_box();
+			out.extend(points.iter().map(|point| point.cmpge(min).all() && point.cmplt(max).all()));
+		} else {
+			let edges = self.edge_equations();
+			out.extend(points.iter().map(|&point| Self::contains_with_edges(&edges, point)));
+		}
+	}
+
+	/// The indices of the points this quad contains.
+	pub fn filter_contained(&self, points: impl Iterator<Item = (usize, DVec2)>) -> Vec<usize> {
+		if self.is_axis_aligned() {
+			let [min, max] = self.bounding_box();
+			points.filter(|(_, point)| point.cmpge(min).all() && point.cmplt(max).all()).map(|(index, _)| index).collect()
+		} else {
+			let edges = self.edge_equations();
+			points.filter(|&(_, point)| Self::contains_with_edges(&edges, point)).map(|(index, _)| index).collect()
+		}
+	}
+
+	/// The closest point to `point` on the edges of the quad, whether the point is inside or outside.
+	pub fn closest_point(&self, point: DVec2) -> DVec2 {
+		let segments = self.edge_segments();
+		let closest = segments.map(|segment| Self::closest_on_segment(segment, point));
+		closest
+			.into_iter()
+			.reduce(|a, b| if b.distance_squared(point) < a.distance_squared(point) { b } else { a })
+			.unwrap_or_default()
+	}
+
+	/// The distance from the point to the edges of the quad, which is negative inside it as given by [`Quad::contains`].
+	///
+	/// A quad with no area is never inside, so this is the distance to the segment or point it has collapsed to.
+	pub fn signed_distance(&self, point: DVec2) -> f64 {
+		let distance = self.closest_point(point).distance(point);
+		if self.contains(point) {
+			-distance
+		} else {
+			distance
+		}
+	}
+
+	/// The [`Quad::signed_distance`] of each of the points, written to `out` after clearing it, only preparing the edges of the quad once.
+	pub fn signed_distances(&self, points: &[DVec2], out: &mut Vec<f64>) {
+		let segments = self.edge_segments();
+		let edges = self.edge_equations();
+		out.clear();
+		out.extend(points.iter().map(|&point| {
+			let distance = segments.iter().map(|&segment| Self::closest_on_segment(segment, point).distance(point)).fold(f64::INFINITY, f64::min);
+			if Self::contains_with_edges(&edges, point) {
+				-distance
+			} else {
+				distance
+			}
+		}));
+	}
+
+	/// The axis aligned bounding box of the quad and the point, starting from the minimum corner like [`Quad::from_box_normalized`], for building bounds one point at a time from [`Quad::from_point`].
+	pub fn expand_to_include(&self, point: DVec2) -> Quad {
+		Self::from_box(Self::combine_bounds(self.bounding_box(), [point, point]))
+	}
+
+	/// The shortest translation of this quad that stops it overlapping the other, or `None` if they don't overlap (including when they only touch).
+	///
+	/// This uses the separating axis theorem, so each quad is treated as the convex hull of its corners, and quads with no area as the segment or point they collapse to.
+	pub fn separation_vector(&self, other: Quad) -> Option<DVec2> {
+		// The directions between each pair of corners give the normals of the edges of the convex hull, whichever way around the corners are.
+		// Testing extra axes can't give a shorter translation than the true one, and the axes of the plane cover points and collinear segments.
+		let pairs = [(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (1, 3)];
+		let directions = [self, &other].into_iter().flat_map(|quad| pairs.map(|(a, b)| (quad.0[b] - quad.0[a]).perp()));
+		let axes = directions.chain([DVec2::X, DVec2::Y]).filter_map(|direction| direction.try_normalize());
+
+		let project = |quad: &Quad, axis: DVec2| {
+			quad.0
+				.iter()
+				.map(|corner| corner.dot(axis))
+				.fold([f64::INFINITY, f64::NEG_INFINITY], |[min, max], x| [min.min(x), max.max(x)])
+		};
+		let mut best: Option<DVec2> = None;
+		for axis in axes {
+			let ([self_min, self_max], [other_min, other_max]) = (project(self, axis), project(&other, axis));
+			let [forwards, backwards] = [other_max - self_min, self_max - other_min];
+			if forwards <= 0. || backwards <= 0. {
+				return None;
+			}
+			let translation = if forwards < backwards { axis * forwards } else { -axis * backwards };
+			if !best.is_some_and(|best| translation.length_squared() >= best.length_squared()) {
+				best = Some(translation);
+			}
+		}
+		best
+	}
+
+	/// For each edge, its start, its change from start to end and the reciprocal of its squared length, which is zero for an edge with no length.
+	fn edge_segments(&self) -> [(DVec2, DVec2, f64); 4] {
+		[0, 1, 2, 3].map(|index| {
+			let [start, end] = [self.0[index], self.0[(index + 1) % 4]];
+			let length_squared = start.distance_squared(end);
+			(start, end - start, if length_squared > 0. { length_squared.recip() } else { 0. })
+		})
+	}
+
+	fn closest_on_segment((start, delta, recip_length_squared): (DVec2, DVec2, f64), point: DVec2) -> DVec2 {
+		let t = ((point - start).dot(delta) * recip_length_squared).clamp(0., 1.);
+		start + delta * t
+	}
+
+	/// For each edge, the y values of its start and end, the x value of its start and its change in x per unit of y.
+	fn edge_equations(&self) -> [[f64; 4]; 4] {
+		[(0, 3), (1, 0), (2, 1), (3, 2)].map(|(i, j)| {
+			let [start, end] = [self.0[i], self.0[j]];
+			[start.y, end.y, start.x, (end.x - start.x) / (end.y - start.y)]
+		})
+	}
+
+	/// Count the edges crossed by a ray from the point towards positive x
+	fn contains_with_edges(edges: &[[f64; 4]; 4], p: DVec2) -> bool {
 		let mut inside = false;
-		for (i, j) in (0..4).zip([3, 0, 1, 2]) {
-			if (self.0[i].y > p.y) != (self.0[j].y > p.y) && p.x < (self.0[j].x - self.0[i].x * (p.y - self.0[i].y) / (self.0[j].y - self.0[i].y) + self.0[i].x) {
+		for &[start_y, end_y, start_x, x_per_y] in edges {
+			if (start_y > p.y) != (end_y > p.y) && p.x < x_per_y * (p.y - start_y) + start_x {
 				inside = !inside;
 			}
 		}
 		inside
 	}
+
+	/// Find the points where the edges of this quad cross the subpath, with the transform applied to the subpath first.
+	///
+	/// Points closer together than [`INTERSECTION_EPSILON`], such as where the subpath passes through a corner of the quad, are only included once.
+	pub fn intersections_with_subpath(&self, subpath: &bezier_rs::Subpath<ManipulatorGroupId>, transform: DAffine2) -> Vec<DVec2> {
+		let beziers = subpath.iter().map(|bezier| bezier.apply_transformation(|point| transform.transform_point2(point))).collect::<Vec<_>>();
+		let mut points: Vec<DVec2> = Vec::new();
+		for edge in self.bezier_lines() {
+			for bezier in &beziers {
+				// Intersecting against the edge as the linear curve uses an exact root finding method rather than subdivision
+				for t in bezier.intersections(&edge, None, None) {
+					let point = bezier.evaluate(bezier_rs::TValue::Parametric(t));
+					if !points.iter().any(|existing| existing.distance_squared(point) < INTERSECTION_EPSILON * INTERSECTION_EPSILON) {
+						points.push(point);
+					}
+				}
+			}
+		}
+		points
+	}
+}
+
+/// Do the two line segments cross at a single point inside both of them? Segments that only touch or are collinear don't.
+fn segments_cross([a, b]: [DVec2; 2], [c, d]: [DVec2; 2]) -> bool {
+	let side = |start: DVec2, end: DVec2, point: DVec2| (end - start).perp_dot(point - start);
+	let [side_c, side_d] = [side(a, b, c), side(a, b, d)];
+	let [side_a, side_b] = [side(c, d, a), side(c, d, b)];
+	side_c * side_d < 0. && side_a * side_b < 0.
 }
 
 impl core::ops::Mul<Quad> for DAffine2 {
@@ -81,6 +508,83 @@ impl core::ops::Mul<Quad> for DAffine2 {
 		Quad(rhs.0.map(|point| self.transform_point2(point)))
 	}
 }
+
+/// Translate the quad by an offset.
+impl core::ops::Add<DVec2> for Quad {
+	type Output = Quad;
+
+	fn add(self, rhs: DVec2) -> Self::Output {
+		self.map(|point| point + rhs)
+	}
+}
+
+/// Translate the quad by the negative of an offset.
+impl core::ops::Sub<DVec2> for Quad {
+	type Output = Quad;
+
+	fn sub(self, rhs: DVec2) -> Self::Output {
+		self.map(|point| point - rhs)
+	}
+}
+
+/// Uniformly scale the quad about the origin (not about its center).
+impl core::ops::Mul<f64> for Quad {
+	type Output = Quad;
+
+	fn mul(self, rhs: f64) -> Self::Output {
+		self.map(|point| point * rhs)
+	}
+}
+
+/// Uniformly scale the quad down about the origin (not about its center).
+impl core::ops::Div<f64> for Quad {
+	type Output = Quad;
+
+	fn div(self, rhs: f64) -> Self::Output {
+		self.map(|point| point / rhs)
+	}
+}
+
+/// A resize handle at a corner or the midpoint of an edge of a quad, see [`Quad::resize_by_handle`].
+///
+/// The names are for a quad made by [`Quad::from_box`] from its minimum corner with the y axis pointing down, so the first corner is the top left and the corners go clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HandleId {
+	TopLeft,
+	Top,
+	TopRight,
+	Right,
+	BottomRight,
+	Bottom,
+	BottomLeft,
+	Left,
+}
+
+impl HandleId {
+	/// Where the handle is on a unit box starting from the first corner of the quad, with the second corner along the x axis and the last along the y axis.
+	fn position_in_box(self) -> DVec2 {
+		match self {
+			Self::TopLeft => DVec2::new(0., 0.),
+			Self::Top => DVec2::new(0.5, 0.),
+			Self::TopRight => DVec2::new(1., 0.),
+			Self::Right => DVec2::new(1., 0.5),
+			Self::BottomRight => DVec2::new(1., 1.),
+			Self::Bottom => DVec2::new(0.5, 1.),
+			Self::BottomLeft => DVec2::new(0., 1.),
+			Self::Left => DVec2::new(0., 0.5),
+		}
+	}
+}
+
+/// How [`Quad::to_pixel_rect`] fits fractional bounds to whole pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelRounding {
+	/// Cover every pixel the bounds touch, so nothing within them is cut off.
+	#[default]
+	Enclose,
+	/// Round each edge to the nearest pixel edge.
+	Round,
+}
 #[test]
 fn offset_quad() {
 	fn eq(a: Quad, b: Quad) -> bool {
@@ -95,6 +599,26 @@ fn offset_quad() {
 	));
 }
 #[test]
+fn offset_quad_far_from_origin() {
+	let offset = 2.;
+	for center in [DVec2::new(1e9, -1e9), DVec2::splat(3e9)] {
+		for winding in [DVec2::ONE, DVec2::new(-1., 1.)] {
+			let local = DAffine2::from_angle(0.3) * DAffine2::from_scale(winding) * Quad::from_box([DVec2::new(-50., -30.), DVec2::new(50., 30.)]);
+			let quad = DAffine2::from_translation(center) * local;
+			let inflated = quad.inflate(offset);
+			for index in 0..4 {
+				let [start, end] = [quad.0[index], quad.0[(index + 1) % 4]];
+				let direction = (end - start).normalize();
+				for point in [inflated.0[index], inflated.0[(index + 1) % 4]] {
+					// The inflated edge lies outside of the original edge at the offset distance
+					assert!((direction.perp_dot(point - start).abs() - offset).abs() < 1e-6, "{index} {point} {center} {winding}");
+					assert!(!quad.contains(point));
+				}
+			}
+		}
+	}
+}
+#[test]
 fn quad_contains() {
 	assert!(Quad::from_box([DVec2::ZERO, DVec2::ONE]).contains(DVec2::splat(0.5)));
 	assert!(Quad::from_box([DVec2::ONE, DVec2::ZERO]).contains(DVec2::splat(0.5)));
@@ -104,3 +628,405 @@ fn quad_contains() {
 	assert!(!Quad::from_box([DVec2::ONE, DVec2::ZERO]).contains(DVec2::new(0.5, -0.01)));
 	assert!(!(DAffine2::from_scale(DVec2::new(-1., 1.)) * Quad::from_box([DVec2::ZERO, DVec2::ONE])).contains(DVec2::splat(0.5)));
 }
+#[test]
+fn quad_arithmetic() {
+	let quad = Quad::from_box([DVec2::ZERO, DVec2::ONE]);
+	let offset = DVec2::new(2., 3.);
+
+	assert_eq!((quad + offset).0, Quad::from_box([offset, offset + DVec2::ONE]).0);
+	assert_eq!((quad + offset - offset).0, quad.0);
+
+	// Scaling is about the origin, so the center moves unless the quad is centered on the origin
+	assert_eq!((quad * 2.).0, Quad::from_box([DVec2::ZERO, DVec2::splat(2.)]).0);
+	assert_eq!((quad * 2.).center(), DVec2::ONE);
+	assert_eq!((quad * 2. / 2.).0, quad.0);
+
+	// Translating then scaling also scales the offset, whereas scaling then translating does not
+	assert_eq!(((quad + offset) * 2.).0, Quad::from_box([offset * 2., offset * 2. + DVec2::splat(2.)]).0);
+	assert_eq!((quad * 2. + offset).0, Quad::from_box([offset, offset + DVec2::splat(2.)]).0);
+
+	assert_eq!(
+		quad.map(|point| point.perp()).0,
+		(DAffine2::from_angle(core::f64::consts::FRAC_PI_2) * quad).map(|point| point.round()).0
+	);
+}
+#[test]
+fn quad_subpath_intersections() {
+	let rectangle = bezier_rs::Subpath::<ManipulatorGroupId>::new_rect(DVec2::ZERO, DVec2::new(10., 10.));
+	let sort = |mut points: Vec<DVec2>| {
+		points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+		points
+	};
+	let close = |a: Vec<DVec2>, b: Vec<DVec2>| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.abs_diff_eq(b, 1e-6));
+
+	// Only the bottom edge of the quad crosses the rectangle, through its left and right sides
+	let quad = Quad::from_box([DVec2::new(-5., -5.), DVec2::new(15., 4.)]);
+	assert!(close(
+		sort(quad.intersections_with_subpath(&rectangle, DAffine2::IDENTITY)),
+		vec![DVec2::new(0., 4.), DVec2::new(10., 4.)]
+	));
+
+	// The transform is applied to the subpath before intersecting
+	let translated = quad.intersections_with_subpath(&rectangle, DAffine2::from_translation(DVec2::new(2., 0.)));
+	assert!(close(sort(translated), vec![DVec2::new(2., 4.), DVec2::new(12., 4.)]));
+
+	// A quad entirely inside or outside doesn't cross the outline
+	assert!(Quad::from_box([DVec2::splat(2.), DVec2::splat(8.)])
+		.intersections_with_subpath(&rectangle, DAffine2::IDENTITY)
+		.is_empty());
+	assert!(Quad::from_box([DVec2::splat(20.), DVec2::splat(30.)])
+		.intersections_with_subpath(&rectangle, DAffine2::IDENTITY)
+		.is_empty());
+}
+#[test]
+fn quad_contains_many() {
+	// Points on a grid with an irrational step, so none lie exactly on the edges of the quads
+	let points = (0..10_000)
+		.map(|index| DVec2::new((index % 100) as f64, (index / 100) as f64) * core::f64::consts::E / 20. - 1.)
+		.collect::<Vec<_>>();
+	let unit = Quad::from_box([DVec2::ZERO, DVec2::ONE]);
+	let quads = [
+		DAffine2::from_scale_angle_translation(DVec2::new(3., 2.), 0.7, DVec2::new(5., 7.)) * unit,
+		DAffine2::from_scale_angle_translation(DVec2::new(-4., 6.), 2.5, DVec2::new(8., 2.)) * unit,
+		DAffine2::from_cols(DVec2::new(5., 1.), DVec2::new(2., 4.), DVec2::new(1., 1.)) * unit,
+		Quad::from_box([DVec2::new(2., 3.), DVec2::new(9., 5.)]),
+		Quad::from_box([DVec2::new(9., 5.), DVec2::new(2., 3.)]),
+	];
+
+	let mut batch = Vec::new();
+	for quad in quads {
+		let scalar = points.iter().map(|&point| quad.contains(point)).collect::<Vec<_>>();
+		assert!(scalar.iter().any(|&inside| inside) && !scalar.iter().all(|&inside| inside));
+
+		quad.contains_many(&points, &mut batch);
+		assert_eq!(batch, scalar);
+
+		let indices = quad.filter_contained(points.iter().copied().enumerate());
+		assert_eq!(indices, (0..points.len()).filter(|&index| scalar[index]).collect::<Vec<_>>());
+	}
+	assert!(!quads[0].is_axis_aligned());
+	assert!(quads[3].is_axis_aligned() && quads[4].is_axis_aligned());
+
+	// A rotated square is contained within its circumscribed circle but contains its inscribed circle
+	let diamond = DAffine2::from_angle(core::f64::consts::FRAC_PI_4) * Quad::from_box([DVec2::splat(-1.), DVec2::ONE]);
+	for angle in (0..64).map(|step| step as f64 / 64. * core::f64::consts::TAU) {
+		assert!(diamond.contains(DVec2::from_angle(angle) * 0.99));
+		assert!(!diamond.contains(DVec2::from_angle(angle) * 1.42));
+	}
+}
+#[test]
+fn quad_rounded_subpath() {
+	let close = |a: [DVec2; 2], b: [DVec2; 2]| a[0].abs_diff_eq(b[0], 1e-6) && a[1].abs_diff_eq(b[1], 1e-6);
+	let bounds = |subpath: crate::vector::Subpath| subpath.bounding_box().unwrap();
+	let rectangle = Quad::from_box([DVec2::new(10., 20.), DVec2::new(50., 30.)]);
+
+	assert_eq!(rectangle.rounded_subpath(0.), rectangle.subpath());
+	assert_eq!(rectangle.per_corner_radii([0.; 4]), rectangle.subpath());
+
+	// Rounding never changes the bounds of a rectangle, even once the radius is clamped to half of the shorter side to make a stadium
+	for radius in [2., 5., 100.] {
+		assert!(close(bounds(rectangle.rounded_subpath(radius)), rectangle.bounding_box()));
+	}
+	assert!(close(bounds(rectangle.per_corner_radii([0., 3., 100., 1.])), rectangle.bounding_box()));
+
+	// A fully rounded square is a circle, even when rotated
+	let square = DAffine2::from_angle(core::f64::consts::FRAC_PI_4) * Quad::from_box([DVec2::splat(-1.), DVec2::ONE]);
+	assert!(close(bounds(square.rounded_subpath(1.)), [DVec2::splat(-1.), DVec2::ONE]));
+	let rotated_rounded = bounds(square.rounded_subpath(0.5));
+	let rotated_sharp = square.bounding_box();
+	assert!(rotated_rounded[0].cmpgt(rotated_sharp[0]).all() && rotated_rounded[1].cmplt(rotated_sharp[1]).all());
+}
+
+#[test]
+fn quad_bowtie() {
+	// Edges 0-1 and 2-3 cross at (5, 5), leaving triangles on the left and right with gaps above and below
+	let bowtie = Quad([DVec2::new(0., 0.), DVec2::new(10., 10.), DVec2::new(10., 0.), DVec2::new(0., 10.)]);
+	assert!(bowtie.is_self_intersecting());
+	assert!(!Quad::from_box([DVec2::ZERO, DVec2::splat(10.)]).is_self_intersecting());
+
+	let [left_lobe, right_lobe, hole] = [DVec2::new(2., 5.), DVec2::new(8., 5.), DVec2::new(5., 2.)];
+	// The answers are the same for every order of the corners
+	for rotation in 0..4 {
+		let mut corners = bowtie.0;
+		corners.rotate_left(rotation);
+		for quad in [Quad(corners), Quad([corners[3], corners[2], corners[1], corners[0]])] {
+			assert!(quad.contains(left_lobe) && quad.contains(right_lobe) && !quad.contains(hole));
+		}
+	}
+
+	let untangled = bowtie.untangle();
+	assert!(!untangled.is_self_intersecting());
+	assert_eq!(untangled.bounding_box(), bowtie.bounding_box());
+	assert!(untangled.contains(left_lobe) && untangled.contains(right_lobe) && untangled.contains(hole));
+
+	// The other pair of edges crossing
+	let bowtie = Quad([DVec2::new(0., 0.), DVec2::new(10., 0.), DVec2::new(0., 10.), DVec2::new(10., 10.)]);
+	assert!(bowtie.is_self_intersecting() && !bowtie.untangle().is_self_intersecting());
+	assert!(bowtie.untangle().contains(DVec2::new(2., 5.)));
+}
+
+#[test]
+fn quad_lerp() {
+	let start = Quad::from_box([DVec2::ZERO, DVec2::new(10., 4.)]);
+	let end = Quad([DVec2::new(3.3, 1.7), DVec2::new(-2.1, 8.9), DVec2::new(0.1, -0.7), DVec2::new(7., 7.)]);
+	assert_eq!(start.lerp(end, 0.).0, start.0);
+	assert_eq!(start.lerp(end, 1.).0, end.0);
+	assert_eq!(start.lerp(end, 0.5).0[0], DVec2::new(1.65, 0.85));
+
+	// Rotating a rectangle by 90 degrees about its center
+	let rotation = DAffine2::from_translation(DVec2::new(5., 2.)) * DAffine2::from_angle(std::f64::consts::FRAC_PI_2) * DAffine2::from_translation(DVec2::new(-5., -2.));
+	let end = rotation * start;
+	for t in [0., 1.] {
+		let rigid = start.lerp_rigid(end, t);
+		let expected = if t == 0. { start } else { end };
+		assert!(rigid.0.iter().zip(expected.0).all(|(corner, expected)| corner.abs_diff_eq(expected, 1e-9)), "{rigid:?}");
+	}
+	let halfway = start.lerp_rigid(end, 0.5);
+	let [a, b, _, d] = halfway.0;
+	assert!(((b - a).length() - 10.).abs() < 1e-9 && ((d - a).length() - 4.).abs() < 1e-9);
+	assert!(halfway.center().abs_diff_eq(DVec2::new(5., 2.), 1e-9));
+	assert!((DVec2::X.angle_between(b - a) - std::f64::consts::FRAC_PI_4).abs() < 1e-9);
+	// Whereas the corners move through the center
+	let naive = start.lerp(end, 0.5);
+	assert!((naive.0[1] - naive.0[0]).length() < 10.);
+
+	// Quads that aren't parallelograms fall back to the corner interpolation
+	let kite = Quad([DVec2::ZERO, DVec2::new(4., 1.), DVec2::new(5., 5.), DVec2::new(1., 3.)]);
+	assert_eq!(kite.lerp_rigid(start, 0.25).0, kite.lerp(start, 0.25).0);
+}
+
+#[test]
+fn normalized_box() {
+	let expected = [DVec2::new(1., 2.), DVec2::new(5., 2.), DVec2::new(5., 6.), DVec2::new(1., 6.)];
+	// Already ordered, reversed, and with only the x axis swapped
+	for bbox in [
+		[DVec2::new(1., 2.), DVec2::new(5., 6.)],
+		[DVec2::new(5., 6.), DVec2::new(1., 2.)],
+		[DVec2::new(5., 2.), DVec2::new(1., 6.)],
+	] {
+		assert_eq!(Quad::from_box_normalized(bbox).0, expected);
+		assert_eq!(Quad::sort_bounds(bbox), [DVec2::new(1., 2.), DVec2::new(5., 6.)]);
+		assert!(Quad::bounds_are_valid(Quad::sort_bounds(bbox)));
+	}
+	assert_eq!(Quad::from_box([DVec2::new(5., 2.), DVec2::new(1., 6.)]).0[0], DVec2::new(5., 2.));
+
+	assert!(!Quad::bounds_are_valid([DVec2::new(5., 2.), DVec2::new(1., 6.)]));
+	assert!(!Quad::bounds_are_valid([DVec2::ZERO, DVec2::new(f64::NAN, 1.)]));
+	assert!(!Quad::bounds_are_valid([DVec2::NEG_INFINITY, DVec2::ZERO]));
+	assert!(Quad::bounds_are_valid([DVec2::ONE, DVec2::ONE]));
+}
+
+#[test]
+fn split_into_tiles() {
+	let tile_size = DVec2::new(10., 20.);
+	let origin = DVec2::new(5., 0.);
+	// Straddling the corner shared by the tiles in the first two rows and columns from the origin
+	let quad = Quad::from_box([DVec2::new(12., 15.), DVec2::new(18., 30.)]);
+	let tiles = Quad::split_bounds_into_tiles(quad.bounding_box(), tile_size, origin);
+	assert_eq!(
+		tiles,
+		[
+			(IVec2::new(0, 0), [DVec2::new(7., 15.), DVec2::new(10., 20.)]),
+			(IVec2::new(1, 0), [DVec2::new(0., 15.), DVec2::new(3., 20.)]),
+			(IVec2::new(0, 1), [DVec2::new(7., 0.), DVec2::new(10., 10.)]),
+			(IVec2::new(1, 1), [DVec2::new(0., 0.), DVec2::new(3., 10.)]),
+		]
+	);
+	let parts = quad.wrap_into_tile(tile_size, origin);
+	assert_eq!(parts.len(), 4);
+	assert_eq!(parts[3].0, Quad::from_box([DVec2::ZERO, DVec2::new(3., 10.)]).0);
+
+	// Ending on a tile edge, before the origin, and with no width
+	assert_eq!(Quad::split_bounds_into_tiles([DVec2::new(5., 0.), DVec2::new(15., 20.)], tile_size, origin).len(), 1);
+	assert_eq!(Quad::split_bounds_into_tiles([DVec2::new(-6., -1.), DVec2::new(-5., 0.)], tile_size, origin)[0].0, IVec2::new(-2, -1));
+	assert_eq!(Quad::split_bounds_into_tiles([DVec2::new(15., 5.), DVec2::new(15., 25.)], tile_size, origin).len(), 2);
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::ONE], DVec2::new(0., 1.), origin).is_empty());
+
+	// Non-finite bounds, tiles or origins, and tiles so small that the indices overflow
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::new(f64::NAN, 1.)], tile_size, origin).is_empty());
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::new(f64::INFINITY, 1.)], tile_size, origin).is_empty());
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::ONE], DVec2::new(f64::INFINITY, 1.), origin).is_empty());
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::ONE], tile_size, DVec2::NAN).is_empty());
+	assert!(Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::splat(1e300)], DVec2::splat(1e-300), DVec2::ZERO).is_empty());
+	assert!(Quad::split_bounds_into_tiles([DVec2::splat(1e12), DVec2::splat(1e12 + 1.)], DVec2::ONE, DVec2::ZERO).is_empty());
+
+	// Huge bounds are clamped to the first rows and columns
+	let tiles = Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::splat(1e6)], DVec2::ONE, DVec2::ZERO);
+	assert_eq!(tiles.len(), Quad::MAX_TILES);
+	assert_eq!(tiles[0].0, IVec2::ZERO);
+	assert_eq!(tiles.last().unwrap().0, IVec2::new(Quad::MAX_TILES as i32 - 1, 0));
+	let tiles = Quad::split_bounds_into_tiles([DVec2::ZERO, DVec2::new(10., 1e6)], DVec2::ONE, DVec2::ZERO);
+	assert_eq!(tiles.len(), Quad::MAX_TILES / 10 * 10);
+	assert_eq!(tiles.last().unwrap().0, IVec2::new(9, Quad::MAX_TILES as i32 / 10 - 1));
+}
+
+#[test]
+fn quad_signed_distance() {
+	let unit = Quad::from_box([DVec2::ZERO, DVec2::ONE]);
+	assert_eq!(unit.signed_distance(DVec2::splat(0.5)), -0.5);
+	assert_eq!(unit.closest_point(DVec2::new(0.5, 0.2)), DVec2::new(0.5, 0.));
+	for distance in [0.25, 1., 10.] {
+		assert!((unit.signed_distance(DVec2::new(1. + distance, 0.5)) - distance).abs() < 1e-12);
+		assert!((unit.signed_distance(DVec2::new(0.5, -distance)) - distance).abs() < 1e-12);
+	}
+	// Outside a corner the distance is to the corner itself
+	assert!((unit.signed_distance(DVec2::new(4., 5.)) - 5.).abs() < 1e-12);
+
+	// Continuous across the boundary, whichever way around the corners are
+	let rotated = DAffine2::from_scale_angle_translation(DVec2::new(-3., 2.), 0.4, DVec2::new(2., 1.)) * unit;
+	for quad in [unit, rotated] {
+		let [start, end] = [quad.0[1], quad.0[2]];
+		let outwards = (end - start).perp().normalize() * if quad.contains((start + end) / 2. + (end - start).perp() * 1e-3) { -1. } else { 1. };
+		let on_edge = (start + end) / 2.;
+		let [inside, outside] = [quad.signed_distance(on_edge - outwards * 1e-9), quad.signed_distance(on_edge + outwards * 1e-9)];
+		assert!(inside < 0. && outside > 0. && (outside - inside).abs() < 1e-8);
+	}
+
+	// Degenerate quads are the distance to the segment or point they collapse to
+	let segment = Quad([DVec2::ZERO, DVec2::X * 4., DVec2::X * 4., DVec2::ZERO]);
+	assert_eq!(segment.signed_distance(DVec2::new(2., 3.)), 3.);
+	assert_eq!(segment.signed_distance(DVec2::new(-3., 4.)), 5.);
+	assert_eq!(Quad::from_point(DVec2::ONE).signed_distance(DVec2::new(4., 5.)), 5.);
+
+	let points = (0..400).map(|index| DVec2::new((index % 20) as f64, (index / 20) as f64) * 0.37 - 2.).collect::<Vec<_>>();
+	let mut batch = Vec::new();
+	for quad in [unit, rotated, segment] {
+		quad.signed_distances(&points, &mut batch);
+		assert_eq!(batch, points.iter().map(|&point| quad.signed_distance(point)).collect::<Vec<_>>());
+	}
+}
+
+#[test]
+fn quad_expand_to_include() {
+	let bounds = [DVec2::new(3., -1.), DVec2::new(-2., 4.), DVec2::new(1., 1.)]
+		.into_iter()
+		.fold(Quad::from_point(DVec2::ZERO), |quad, point| quad.expand_to_include(point));
+	assert_eq!(bounds.0, Quad::from_box([DVec2::new(-2., -1.), DVec2::new(3., 4.)]).0);
+	assert!(bounds.is_axis_aligned());
+}
+
+#[test]
+fn quad_separation_vector() {
+	let close = |a: Option<DVec2>, b: DVec2| a.is_some_and(|a| a.abs_diff_eq(b, 1e-9));
+	let square = Quad::from_box([DVec2::ZERO, DVec2::splat(10.)]);
+
+	// The shortest way out of an overlap with an axis aligned rectangle is along the axis it overlaps least on
+	let overlapping = Quad::from_box([DVec2::new(8., 3.), DVec2::new(20., 5.)]);
+	assert!(close(square.separation_vector(overlapping), DVec2::new(-2., 0.)));
+	assert!(close(overlapping.separation_vector(square), DVec2::new(2., 0.)));
+	let below = Quad::from_box([DVec2::new(-5., 9.), DVec2::new(15., 30.)]);
+	assert!(close(square.separation_vector(below), DVec2::new(0., -1.)));
+	assert!(close(square.separation_vector(square + DVec2::new(3., 0.)), DVec2::new(-7., 0.)));
+	// Applying the translation leaves the quads touching, which isn't an overlap
+	let translation = square.separation_vector(overlapping).unwrap();
+	assert_eq!((square + translation).separation_vector(overlapping), None);
+
+	assert_eq!(square.separation_vector(square + DVec2::new(10., 0.)), None);
+	assert_eq!(square.separation_vector(square + DVec2::new(20., 20.)), None);
+
+	// A diamond overlapping the corner of the square is pushed out along the normal of its own edge
+	let diamond = DAffine2::from_translation(DVec2::new(11., 11.)) * DAffine2::from_angle(core::f64::consts::FRAC_PI_4) * Quad::from_box([DVec2::splat(-2.), DVec2::splat(2.)]);
+	assert!(close(diamond.separation_vector(square), DVec2::splat(core::f64::consts::SQRT_2 - 1.)));
+	// Diagonally separated, but overlapping on both axes of the plane
+	let near_corner = DAffine2::from_translation(DVec2::new(12.5, 12.5)) * DAffine2::from_angle(core::f64::consts::FRAC_PI_4) * Quad::from_box([DVec2::splat(-2.), DVec2::splat(2.)]);
+	assert_eq!(near_corner.separation_vector(square), None);
+
+	// Quads with no area are treated as segments and points
+	let segment = Quad([DVec2::new(-5., 4.), DVec2::new(15., 4.), DVec2::new(15., 4.), DVec2::new(-5., 4.)]);
+	assert!(close(segment.separation_vector(square), DVec2::new(0., -4.)));
+	assert!(close(Quad::from_point(DVec2::new(9., 5.)).separation_vector(square), DVec2::new(1., 0.)));
+	assert_eq!(Quad::from_point(DVec2::new(11., 5.)).separation_vector(square), None);
+	let collinear = Quad([DVec2::new(20., 4.), DVec2::new(30., 4.), DVec2::new(30., 4.), DVec2::new(20., 4.)]);
+	assert_eq!(segment.separation_vector(collinear), None);
+	// Collinear segments that share a stretch cover no area, so they only touch
+	assert_eq!(segment.separation_vector(collinear - DVec2::new(6., 0.)), None);
+}
+
+#[test]
+fn quad_extent_along() {
+	let unit = Quad::from_box([DVec2::ZERO, DVec2::ONE]);
+	let diagonal = DVec2::ONE / core::f64::consts::SQRT_2;
+	let (min, max) = unit.extent_along(diagonal);
+	assert!(min.abs() < 1e-12 && (max - core::f64::consts::SQRT_2).abs() < 1e-12);
+	assert!((unit.width_along(DVec2::new(-3., -3.)) - core::f64::consts::SQRT_2).abs() < 1e-12);
+	assert_eq!(unit.extent_along(DVec2::new(0., 5.)), (0., 1.));
+	assert_eq!((unit + DVec2::new(2., 0.)).extent_along(-DVec2::X), (-3., -2.));
+	assert_eq!(unit.extent_along(DVec2::ZERO), (0., 0.));
+}
+
+#[test]
+fn quad_to_pixel_rect() {
+	let quad = Quad::from_box([DVec2::new(10.25, -3.5), DVec2::new(20.5, 4.75)]);
+	assert_eq!(quad.to_pixel_rect(1., PixelRounding::Enclose), Some(([10, -4], [11, 9])));
+	// Halfway edges round away from zero
+	assert_eq!(quad.to_pixel_rect(1., PixelRounding::Round), Some(([10, -4], [11, 9])));
+	// Scaled to 15.375, -5.25 and 30.75, 7.125
+	assert_eq!(quad.to_pixel_rect(1.5, PixelRounding::Enclose), Some(([15, -6], [16, 14])));
+	assert_eq!(quad.to_pixel_rect(1.5, PixelRounding::Round), Some(([15, -5], [16, 12])));
+	assert_eq!(quad.to_pixel_rect(2., PixelRounding::Enclose), Some(([20, -7], [21, 17])));
+	assert_eq!(quad.to_pixel_rect(2., PixelRounding::Round), Some(([21, -7], [20, 17])));
+
+	// Rotated quads are fitted by their bounding box
+	let diamond = DAffine2::from_translation(DVec2::splat(5.)) * DAffine2::from_angle(core::f64::consts::FRAC_PI_4) * Quad::from_box([DVec2::splat(-1.), DVec2::splat(1.)]);
+	assert_eq!(diamond.to_pixel_rect(1., PixelRounding::Enclose), Some(([3, 3], [4, 4])));
+
+	// Thin quads still enclose a pixel, but can round away to nothing
+	let sliver = Quad::from_box([DVec2::new(4.2, 1.), DVec2::new(4.3, 3.)]);
+	assert_eq!(sliver.to_pixel_rect(1., PixelRounding::Enclose), Some(([4, 1], [1, 2])));
+	assert_eq!(sliver.to_pixel_rect(1.5, PixelRounding::Round), Some(([6, 2], [0, 3])));
+	assert_eq!(Quad::from_point(DVec2::splat(2.)).to_pixel_rect(2., PixelRounding::Enclose), Some(([4, 4], [1, 1])));
+	assert_eq!(Quad::from_point(DVec2::new(f64::NAN, 0.)).to_pixel_rect(1., PixelRounding::Enclose), None);
+	let mut one_nan_corner = quad;
+	one_nan_corner.0[2] = DVec2::new(f64::NAN, 1.);
+	assert_eq!(one_nan_corner.to_pixel_rect(1., PixelRounding::Enclose), None);
+	assert_eq!(one_nan_corner.to_pixel_rect(1., PixelRounding::Round), None);
+	assert_eq!(quad.to_pixel_rect(f64::INFINITY, PixelRounding::Round), None);
+}
+
+#[test]
+fn quad_resize_by_handle() {
+	fn eq(a: Quad, b: Quad) -> bool {
+		a.0.iter().zip(b.0).all(|(a, b)| a.abs_diff_eq(b, 1e-9))
+	}
+	let boxed = |min: (f64, f64), max: (f64, f64)| Quad::from_box([min.into(), max.into()]);
+	let original = boxed((0., 0.), (100., 50.));
+
+	// Each case is checked on the box and on the box rotated by 30° about a point away from the origin, dragging to the rotated position
+	let rotation = DAffine2::from_translation(DVec2::new(40., -25.)) * DAffine2::from_angle(30_f64.to_radians());
+	let check = |handle: HandleId, position: (f64, f64), constrain_aspect: bool, from_center: bool, expected: Quad| {
+		let resized = original.resize_by_handle(handle, position.into(), constrain_aspect, from_center);
+		assert!(eq(resized, expected), "{handle:?} with {constrain_aspect} {from_center} gave {resized:?}");
+		let rotated = (rotation * original).resize_by_handle(handle, rotation.transform_point2(position.into()), constrain_aspect, from_center);
+		assert!(eq(rotated, rotation * expected), "rotated {handle:?} with {constrain_aspect} {from_center} gave {rotated:?}");
+	};
+
+	// Corners scale by 1.5 and 2 about the opposite corner, or 2 and 3 about the center
+	check(HandleId::BottomRight, (150., 100.), false, false, boxed((0., 0.), (150., 100.)));
+	check(HandleId::BottomRight, (150., 100.), true, false, boxed((0., 0.), (200., 100.)));
+	check(HandleId::BottomRight, (150., 100.), false, true, boxed((-50., -50.), (150., 100.)));
+	check(HandleId::BottomRight, (150., 100.), true, true, boxed((-100., -50.), (200., 100.)));
+	check(HandleId::TopLeft, (-50., 10.), false, false, boxed((-50., 10.), (100., 50.)));
+	check(HandleId::TopLeft, (-50., 10.), true, false, boxed((-50., -25.), (100., 50.)));
+
+	// Edges only follow the position across them, with the other axis scaled about its middle to keep the aspect
+	check(HandleId::Right, (120., 999.), false, false, boxed((0., 0.), (120., 50.)));
+	check(HandleId::Right, (120., 999.), true, false, boxed((0., -5.), (120., 55.)));
+	check(HandleId::Right, (120., 999.), false, true, boxed((-20., 0.), (120., 50.)));
+	check(HandleId::Right, (120., 999.), true, true, boxed((-20., -10.), (120., 60.)));
+	check(HandleId::Top, (-30., -50.), false, false, boxed((0., -50.), (100., 50.)));
+	check(HandleId::Top, (-30., -50.), true, false, boxed((-50., -50.), (150., 50.)));
+
+	// Dragging past the opposite side keeps a tiny quad rather than flipping it
+	for (handle, position) in [(HandleId::BottomRight, (-10., -10.)), (HandleId::Left, (300., 25.)), (HandleId::Bottom, (50., -1.))] {
+		for constrain_aspect in [false, true] {
+			let resized = original.resize_by_handle(handle, position.into(), constrain_aspect, false);
+			let (width, height) = (resized.0[1] - resized.0[0], resized.0[3] - resized.0[0]);
+			assert!(width.x > 0. && height.y > 0. && width.y == 0. && height.x == 0., "{handle:?} gave {resized:?}");
+			assert!(width.x.min(height.y) < 1e-3, "{handle:?} gave {resized:?}");
+		}
+	}
+
+	// A quad without area has no frame to resize in
+	let line = Quad::from_box([DVec2::ZERO, DVec2::new(10., 0.)]);
+	assert!(eq(line.resize_by_handle(HandleId::BottomRight, DVec2::splat(20.), false, false), line));
+}